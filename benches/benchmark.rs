@@ -1,24 +1,94 @@
-use apkdoctor;
+use apkdoctor::dex_file::DexFile;
+use apkdoctor::dex_model::DexModel;
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 
+const DEX_PATH: &str = "./tests/assets/classes.dex";
+
 fn deserialize_benchmark(c: &mut Criterion) {
     let mut group = c.benchmark_group("deserialize-group");
     group.bench_function("deserialize", |b| {
-        b.iter(|| {
-            black_box(apkdoctor::deserialize(
-                "./tests/assets/classes.dex".to_string(),
-            ))
-        })
+        b.iter(|| black_box(apkdoctor::deserialize(DEX_PATH)))
     });
     group.bench_function("deserialize_serialize", |b| {
         b.iter(|| {
             black_box(apkdoctor::serialize(
-                apkdoctor::deserialize("./tests/assets/classes.dex".to_string()).unwrap(),
+                apkdoctor::deserialize(DEX_PATH).unwrap(),
             ))
         })
     });
     group.finish();
 }
 
-criterion_group!(benches, deserialize_benchmark);
+/// Compares the eager `DexModel` with the lazy `DexFile` view on the same
+/// in-memory bytes, so file reads are not part of the measurement.
+fn lazy_benchmark(c: &mut Criterion) {
+    let bytes = std::fs::read(DEX_PATH).unwrap();
+    let mut group = c.benchmark_group("lazy-vs-eager");
+    group.bench_function("method_ids/eager", |b| {
+        b.iter(|| {
+            let dex = DexModel::from_bytes(&bytes).unwrap();
+            black_box(dex.method_ids.iter().map(|m| m.name_idx).sum::<u32>())
+        })
+    });
+    group.bench_function("method_ids/lazy", |b| {
+        b.iter(|| {
+            let dex = DexFile::new(&bytes).unwrap();
+            black_box(dex.method_ids().map(|m| m.name_idx).sum::<u32>())
+        })
+    });
+    group.bench_function("instructions/eager", |b| {
+        b.iter(|| {
+            let dex = DexModel::from_bytes(&bytes).unwrap();
+            black_box(dex.code_items.iter().map(|c| c.insns.len()).sum::<usize>())
+        })
+    });
+    group.bench_function("instructions/lazy", |b| {
+        b.iter(|| {
+            let dex = DexFile::new(&bytes).unwrap();
+            let mut count = 0;
+            for class_def in dex.class_defs() {
+                let class_data = match dex.class_data(&class_def) {
+                    Some(class_data) => class_data,
+                    None => continue,
+                };
+                let methods = class_data
+                    .direct_methods
+                    .iter()
+                    .chain(class_data.virtual_methods.iter());
+                for method in methods {
+                    if let Some(code) = dex.code(method.code_off) {
+                        count += code.instructions().count();
+                    }
+                }
+            }
+            black_box(count)
+        })
+    });
+    group.finish();
+}
+
+/// Decodes the same bytes with and without the deferred sections spread
+/// over the rayon pool. Run with `cargo bench --features parallel`.
+#[cfg(feature = "parallel")]
+fn parallel_benchmark(c: &mut Criterion) {
+    let bytes = std::fs::read(DEX_PATH).unwrap();
+    let mut group = c.benchmark_group("serial-vs-parallel");
+    group.bench_function("deserialize/serial", |b| {
+        b.iter(|| black_box(apkdoctor::deserialize_with(&bytes, false).unwrap()))
+    });
+    group.bench_function("deserialize/parallel", |b| {
+        b.iter(|| black_box(apkdoctor::deserialize_with(&bytes, true).unwrap()))
+    });
+    group.finish();
+}
+
+#[cfg(not(feature = "parallel"))]
+criterion_group!(benches, deserialize_benchmark, lazy_benchmark);
+#[cfg(feature = "parallel")]
+criterion_group!(
+    benches,
+    deserialize_benchmark,
+    lazy_benchmark,
+    parallel_benchmark
+);
 criterion_main!(benches);
@@ -0,0 +1,711 @@
+//! Android App Bundles (`.aab`): the per-module layout, the protobuf
+//! manifests and resource tables written by aapt2, and `BundleConfig.pb`.
+
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display, Formatter},
+    path::Path,
+};
+
+use serde::Serialize;
+
+use crate::{
+    apk::{is_dex_entry, sort_dex_entries, Apk, ApkError},
+    arsc::{
+        ResourceConfig, ResourceEntry, ResourcePackage, ResourceTable, ResourceType,
+        ResourceTypeChunk, ResourceValue,
+    },
+    axml::{framework_attribute_name, AxmlError, Value, XmlAttribute, XmlDocument, XmlElement},
+    dex_model::DexModel,
+    manifest::{Manifest, ManifestError},
+    protobuf::{Message, ProtobufError},
+};
+
+pub const BUNDLE_CONFIG: &str = "BundleConfig.pb";
+pub const BASE_MODULE: &str = "base";
+
+/// Keys of array elements and plural quantities, as in `resources.arsc`.
+const ARRAY_KEY_BASE: u32 = 0x02000000;
+const PLURAL_OTHER_KEY: u32 = 0x01000004;
+const PLURAL_ZERO_KEY: u32 = 0x01000005;
+
+/// Decodes a compiled `Item` into the equivalent `Res_value`.
+fn item_value(item: &Message) -> Result<Value, AxmlError> {
+    if item.has(1) {
+        let reference = item.message(1)?;
+        let id = reference.uint32(2);
+        return Ok(match reference.varint(1) {
+            1 => Value::Attribute(id),
+            _ => Value::Reference(id),
+        });
+    }
+    // String, raw string, styled string and file reference all keep their
+    // text in field 1.
+    for number in 2..=5 {
+        if item.has(number) {
+            return Ok(Value::String(item.message(number)?.string(1)));
+        }
+    }
+    if item.has(6) {
+        return Ok(Value::Boolean(false));
+    }
+    let primitive = item.message(7)?;
+    let value = if primitive.has(3) {
+        Value::Float(f32::from_bits(primitive.fixed32(3)))
+    } else if primitive.has(4) {
+        Value::Dimension(primitive.fixed32(4))
+    } else if primitive.has(5) {
+        Value::Fraction(primitive.fixed32(5))
+    } else if primitive.has(6) {
+        Value::Int(primitive.varint(6) as i32)
+    } else if primitive.has(7) {
+        Value::Hex(primitive.uint32(7))
+    } else if primitive.has(8) {
+        Value::Boolean(primitive.bool(8))
+    } else if let Some(number) = (9..=12).find(|n| primitive.has(*n)) {
+        Value::Color(primitive.uint32(number))
+    } else if primitive.has(13) {
+        Value::Dimension(primitive.uint32(13))
+    } else if primitive.has(14) {
+        Value::Fraction(primitive.uint32(14))
+    } else {
+        Value::Null
+    };
+    return Ok(value);
+}
+
+fn xml_element(node: &Message) -> Result<XmlElement, AxmlError> {
+    let element = node.message(1)?;
+    let namespace = element.string(2);
+    let mut attributes = vec![];
+    for attribute in element.messages(4)? {
+        let namespace = attribute.string(1);
+        let resource_id = attribute.uint32(5);
+        let mut name = attribute.string(2);
+        if name.is_empty() {
+            name = framework_attribute_name(resource_id)
+                .unwrap_or_default()
+                .to_string();
+        }
+        let value = if attribute.has(6) {
+            item_value(&attribute.message(6)?)?
+        } else {
+            Value::String(attribute.string(3))
+        };
+        attributes.push(XmlAttribute {
+            namespace: (!namespace.is_empty()).then_some(namespace),
+            name,
+            resource_id: (resource_id != 0).then_some(resource_id),
+            value,
+        });
+    }
+    let mut children = vec![];
+    let mut text: Option<String> = None;
+    for child in element.messages(5)? {
+        if child.has(1) {
+            children.push(xml_element(&child)?);
+        } else if child.has(2) {
+            text.get_or_insert_with(String::new)
+                .push_str(&child.string(2));
+        }
+    }
+    return Ok(XmlElement {
+        namespace: (!namespace.is_empty()).then_some(namespace),
+        name: element.string(3),
+        line: node.message(3)?.uint32(1),
+        attributes,
+        children,
+        text,
+    });
+}
+
+/// Decodes a protobuf `XmlNode`, the form of `AndroidManifest.xml` and of
+/// XML resources inside a bundle module.
+pub fn parse_xml(bytes: &[u8]) -> Result<XmlDocument, AxmlError> {
+    let node = Message::parse(bytes)?;
+    if !node.has(1) {
+        return Err(AxmlError::InvalidProtobuf(ProtobufError::InvalidMessage(
+            0,
+            "not an XML element".to_string(),
+        )));
+    }
+    let namespaces = node
+        .message(1)?
+        .messages(1)?
+        .iter()
+        .map(|namespace| (namespace.string(1), namespace.string(2)))
+        .collect();
+    return Ok(XmlDocument {
+        namespaces,
+        root: xml_element(&node)?,
+    });
+}
+
+/// Language and region of a locale such as `fr-CA` or `b+sr+Latn+RS`.
+fn split_locale(locale: &str) -> (String, String) {
+    let parts = match locale.strip_prefix("b+") {
+        Some(tag) => tag.split('+').collect::<Vec<_>>(),
+        None => locale.split('-').collect(),
+    };
+    let language = parts.first().copied().unwrap_or("").to_string();
+    let region = parts
+        .iter()
+        .skip(1)
+        .find(|part| part.len() == 2)
+        .map(|part| part.to_string())
+        .unwrap_or_default();
+    return (language, region);
+}
+
+fn resource_config(config: &Message) -> ResourceConfig {
+    let (language, country) = split_locale(&config.string(3));
+    let night = match config.varint(17) {
+        1 => 0x20,
+        2 => 0x10,
+        _ => 0,
+    };
+    return ResourceConfig {
+        mcc: config.uint32(1) as u16,
+        mnc: config.uint32(2) as u16,
+        language,
+        country,
+        orientation: config.uint32(15) as u8,
+        density: config.uint32(18) as u16,
+        sdk_version: config.uint32(24) as u16,
+        ui_mode: config.uint32(16) as u8 | night,
+        smallest_screen_width_dp: config.uint32(9) as u16,
+        screen_width_dp: config.uint32(7) as u16,
+        screen_height_dp: config.uint32(8) as u16,
+    };
+}
+
+fn resource_value(value: &Message) -> Result<ResourceValue, AxmlError> {
+    if value.has(4) {
+        return Ok(ResourceValue::Simple(item_value(&value.message(4)?)?));
+    }
+    if !value.has(5) {
+        return Ok(ResourceValue::Simple(Value::Null));
+    }
+    let compound = value.message(5)?;
+    let mut parent = 0;
+    let mut values = vec![];
+    if compound.has(2) {
+        let style = compound.message(2)?;
+        parent = style.message(1)?.uint32(2);
+        for entry in style.messages(3)? {
+            values.push((entry.message(3)?.uint32(2), item_value(&entry.message(4)?)?));
+        }
+    } else if compound.has(3) {
+        for entry in compound.message(3)?.messages(1)? {
+            values.push((entry.message(3)?.uint32(2), Value::Null));
+        }
+    } else if compound.has(4) {
+        for (i, element) in compound.message(4)?.messages(1)?.iter().enumerate() {
+            values.push((ARRAY_KEY_BASE + i as u32, item_value(&element.message(3)?)?));
+        }
+    } else if compound.has(5) {
+        for entry in compound.message(5)?.messages(1)? {
+            // Arity runs ZERO, ONE, TWO, FEW, MANY, OTHER.
+            let key = match entry.uint32(3) {
+                5 => PLURAL_OTHER_KEY,
+                arity => PLURAL_ZERO_KEY + arity,
+            };
+            values.push((key, item_value(&entry.message(4)?)?));
+        }
+    }
+    return Ok(ResourceValue::Complex { parent, values });
+}
+
+/// Decodes a protobuf `ResourceTable` (`resources.pb`). Values are grouped
+/// by configuration as in `resources.arsc`; the chunk sizes are zero since
+/// the table has no binary chunks.
+pub fn parse_resource_table(bytes: &[u8]) -> Result<ResourceTable, AxmlError> {
+    let table = Message::parse(bytes)?;
+    let mut resources = ResourceTable::default();
+    for package in table.messages(2)? {
+        let mut types = vec![];
+        for resource_type in package.messages(3)? {
+            let mut chunks: BTreeMap<ResourceConfig, Vec<ResourceEntry>> = BTreeMap::new();
+            for entry in resource_type.messages(3)? {
+                let index = entry.message(1)?.uint32(1) as u16;
+                let key = entry.string(2);
+                for config_value in entry.messages(6)? {
+                    chunks
+                        .entry(resource_config(&config_value.message(1)?))
+                        .or_default()
+                        .push(ResourceEntry {
+                            index,
+                            key: key.clone(),
+                            value: resource_value(&config_value.message(2)?)?,
+                        });
+                }
+            }
+            types.push(ResourceType {
+                id: resource_type.message(1)?.uint32(1) as u8,
+                name: resource_type.string(2),
+                spec_flags: vec![],
+                chunks: chunks
+                    .into_iter()
+                    .map(|(config, entries)| ResourceTypeChunk {
+                        config,
+                        entries,
+                        size: 0,
+                    })
+                    .collect(),
+            });
+        }
+        resources.packages.push(ResourcePackage {
+            id: package.message(1)?.uint32(1) as u8,
+            name: package.string(2),
+            types,
+        });
+    }
+    return Ok(resources);
+}
+
+const SPLIT_DIMENSIONS: [&str; 10] = [
+    "unspecified",
+    "abi",
+    "screen_density",
+    "language",
+    "texture_compression_format",
+    "graphics_api",
+    "device_tier",
+    "country_set",
+    "ai_model_version",
+    "device_group",
+];
+
+/// A dimension APKs are split by when generated from the bundle.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SplitDimension {
+    pub dimension: String,
+    /// Whether splitting by the dimension is disabled.
+    pub negate: bool,
+}
+
+/// Build settings recorded by bundletool in `BundleConfig.pb`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct BundleConfig {
+    pub bundletool_version: String,
+    /// `regular`, `apex` or `asset_only`.
+    pub bundle_type: String,
+    pub split_dimensions: Vec<SplitDimension>,
+    pub uncompress_native_libraries: bool,
+    pub uncompress_dex_files: bool,
+    /// Globs of files kept uncompressed in the generated APKs.
+    pub uncompressed_globs: Vec<String>,
+}
+
+impl BundleConfig {
+    pub fn parse(bytes: &[u8]) -> Result<BundleConfig, AxmlError> {
+        let config = Message::parse(bytes)?;
+        let optimizations = config.message(2)?;
+        let split_dimensions = optimizations
+            .message(1)?
+            .messages(1)?
+            .iter()
+            .map(|dimension| SplitDimension {
+                dimension: SPLIT_DIMENSIONS
+                    .get(dimension.varint(1) as usize)
+                    .map_or_else(|| dimension.varint(1).to_string(), |d| d.to_string()),
+                negate: dimension.bool(2),
+            })
+            .collect();
+        return Ok(BundleConfig {
+            bundletool_version: config.message(1)?.string(2),
+            bundle_type: match config.varint(7) {
+                1 => "apex",
+                2 => "asset_only",
+                _ => "regular",
+            }
+            .to_string(),
+            split_dimensions,
+            uncompress_native_libraries: optimizations.message(2)?.bool(1),
+            uncompress_dex_files: optimizations.message(3)?.bool(1),
+            uncompressed_globs: config.message(3)?.strings(1),
+        });
+    }
+
+    pub fn to_json(&self) -> String {
+        return serde_json::to_string_pretty(self).unwrap();
+    }
+}
+
+impl Display for BundleConfig {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, "bundletool {}", self.bundletool_version)?;
+        writeln!(f, "type: {}", self.bundle_type)?;
+        let dimensions = self
+            .split_dimensions
+            .iter()
+            .map(|d| match d.negate {
+                true => format!("!{}", d.dimension),
+                false => d.dimension.clone(),
+            })
+            .collect::<Vec<_>>();
+        writeln!(f, "split dimensions: {}", dimensions.join(", "))?;
+        writeln!(
+            f,
+            "uncompressed: native libraries {}, dex {}",
+            self.uncompress_native_libraries, self.uncompress_dex_files
+        )?;
+        return Ok(());
+    }
+}
+
+/// An Android App Bundle. Each module (`base` and every dynamic feature)
+/// is a directory with `manifest/AndroidManifest.xml`, `dex/classesN.dex`
+/// and, optionally, `resources.pb`.
+pub struct Aab {
+    archive: Apk,
+}
+
+impl Aab {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Aab, ApkError> {
+        return Ok(Aab::from_apk(Apk::open(path)?));
+    }
+
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Aab, ApkError> {
+        return Ok(Aab::from_apk(Apk::from_bytes(bytes)?));
+    }
+
+    pub fn from_apk(archive: Apk) -> Aab {
+        return Aab { archive };
+    }
+
+    /// The underlying zip archive.
+    pub fn archive(&mut self) -> &mut Apk {
+        return &mut self.archive;
+    }
+
+    pub fn into_archive(self) -> Apk {
+        return self.archive;
+    }
+
+    /// Module names, `base` first and then the feature modules by name.
+    pub fn modules(&self) -> Vec<String> {
+        let mut modules = self
+            .archive
+            .entry_names()
+            .iter()
+            .filter_map(|name| name.strip_suffix("/manifest/AndroidManifest.xml"))
+            .filter(|module| !module.contains('/'))
+            .map(String::from)
+            .collect::<Vec<_>>();
+        modules.sort_by_key(|module| (module != BASE_MODULE, module.clone()));
+        return modules;
+    }
+
+    pub fn bundle_config(&mut self) -> Result<BundleConfig, ApkError> {
+        let bytes = self.archive.read_entry(BUNDLE_CONFIG)?;
+        return BundleConfig::parse(&bytes)
+            .map_err(|err| ApkError::ResourceError(BUNDLE_CONFIG.to_string(), err));
+    }
+
+    /// The decoded `AndroidManifest.xml` of a module.
+    pub fn manifest_document(&mut self, module: &str) -> Result<XmlDocument, ApkError> {
+        let name = format!("{}/manifest/AndroidManifest.xml", module);
+        let bytes = self.archive.read_entry(&name)?;
+        return parse_xml(&bytes).map_err(|err| ApkError::ResourceError(name, err));
+    }
+
+    pub fn manifest(&mut self, module: &str) -> Result<Manifest, ManifestError> {
+        return Manifest::from_document(&self.manifest_document(module)?);
+    }
+
+    /// The resource table of a module, from its `resources.pb`.
+    pub fn resources(&mut self, module: &str) -> Result<ResourceTable, ApkError> {
+        let name = format!("{}/resources.pb", module);
+        let bytes = self.archive.read_entry(&name)?;
+        return parse_resource_table(&bytes).map_err(|err| ApkError::ResourceError(name, err));
+    }
+
+    /// Names of the `dex/classesN.dex` entries of a module, in multidex
+    /// order.
+    pub fn dex_entry_names(&self, module: &str) -> Vec<String> {
+        let prefix = format!("{}/dex/", module);
+        let mut names = self
+            .archive
+            .entry_names()
+            .into_iter()
+            .filter(|name| name.strip_prefix(&prefix).is_some_and(is_dex_entry))
+            .collect::<Vec<_>>();
+        sort_dex_entries(&mut names);
+        return names;
+    }
+
+    /// The dex entry names of every module, module by module.
+    pub fn all_dex_entry_names(&self) -> Vec<String> {
+        return self
+            .modules()
+            .iter()
+            .flat_map(|module| self.dex_entry_names(module))
+            .collect();
+    }
+
+    /// Fails unless the archive is a bundle with a module named `module`.
+    pub fn check_module(&self, module: &str) -> Result<(), ApkError> {
+        if !self.archive.has_entry(BUNDLE_CONFIG) {
+            return Err(ApkError::NotABundle);
+        }
+        if !self.modules().iter().any(|m| m == module) {
+            return Err(ApkError::ModuleNotFound(module.to_string()));
+        }
+        return Ok(());
+    }
+
+    /// Parses the `dex/classesN.dex` entries of a module, in multidex order.
+    pub fn dex_files(&mut self, module: &str) -> Result<Vec<(String, DexModel)>, ApkError> {
+        self.check_module(module)?;
+        let names = self.dex_entry_names(module);
+        return self.archive.parse_dex_entries(names);
+    }
+
+    /// The dex files of every module, module by module.
+    pub fn all_dex_files(&mut self) -> Result<Vec<(String, DexModel)>, ApkError> {
+        let names = self.all_dex_entry_names();
+        return self.archive.parse_dex_entries(names);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        apk::load_dex_files,
+        axml::ANDROID_NAMESPACE,
+        doctor::AppContext,
+        serialize,
+        test_utils::{build_dex, build_zip, ProtoBuilder, TestClass},
+    };
+
+    fn attribute(name: &str, value: &str, item: Option<&ProtoBuilder>) -> ProtoBuilder {
+        let mut attribute = ProtoBuilder::new();
+        attribute
+            .string(1, ANDROID_NAMESPACE)
+            .string(2, name)
+            .string(3, value);
+        if let Some(item) = item {
+            attribute.message(6, item);
+        }
+        return attribute;
+    }
+
+    fn manifest(package: &str, split: Option<&str>) -> Vec<u8> {
+        let mut namespace = ProtoBuilder::new();
+        namespace.string(1, "android").string(2, ANDROID_NAMESPACE);
+        let mut package_attribute = ProtoBuilder::new();
+        package_attribute.string(2, "package").string(3, package);
+        let mut int = ProtoBuilder::new();
+        int.varint(6, 42);
+        let mut primitive = ProtoBuilder::new();
+        primitive.message(7, &int);
+        let mut reference = ProtoBuilder::new();
+        reference.varint(2, 0x7f010000);
+        let mut label = ProtoBuilder::new();
+        label.message(1, &reference);
+
+        let mut application = ProtoBuilder::new();
+        application
+            .string(3, "application")
+            .message(4, &attribute("label", "@string/app_name", Some(&label)));
+        let mut application_node = ProtoBuilder::new();
+        application_node.message(1, &application);
+
+        let mut root = ProtoBuilder::new();
+        root.message(1, &namespace)
+            .string(3, "manifest")
+            .message(4, &package_attribute)
+            .message(4, &attribute("versionCode", "42", Some(&primitive)));
+        if let Some(split) = split {
+            let mut split_attribute = ProtoBuilder::new();
+            split_attribute.string(2, "split").string(3, split);
+            root.message(4, &split_attribute);
+        }
+        root.message(5, &application_node);
+        let mut line = ProtoBuilder::new();
+        line.varint(1, 2);
+        let mut node = ProtoBuilder::new();
+        node.message(1, &root).message(3, &line);
+        return node.build();
+    }
+
+    fn string_value(value: &str, locale: Option<&str>) -> ProtoBuilder {
+        let mut string = ProtoBuilder::new();
+        string.string(1, value);
+        let mut item = ProtoBuilder::new();
+        item.message(2, &string);
+        let mut value = ProtoBuilder::new();
+        value.message(4, &item);
+        let mut config = ProtoBuilder::new();
+        if let Some(locale) = locale {
+            config.string(3, locale);
+        }
+        let mut config_value = ProtoBuilder::new();
+        config_value.message(1, &config).message(2, &value);
+        return config_value;
+    }
+
+    fn resources() -> Vec<u8> {
+        let id = |id: u64| {
+            let mut message = ProtoBuilder::new();
+            message.varint(1, id);
+            message
+        };
+        let mut entry = ProtoBuilder::new();
+        entry
+            .message(1, &id(0))
+            .string(2, "app_name")
+            .message(6, &string_value("Example", None))
+            .message(6, &string_value("Exemple", Some("fr-CA")));
+        let mut string_type = ProtoBuilder::new();
+        string_type
+            .message(1, &id(1))
+            .string(2, "string")
+            .message(3, &entry);
+        let mut package = ProtoBuilder::new();
+        package
+            .message(1, &id(0x7f))
+            .string(2, "com.example")
+            .message(3, &string_type);
+        let mut table = ProtoBuilder::new();
+        table.message(2, &package);
+        return table.build();
+    }
+
+    fn bundle_config() -> Vec<u8> {
+        let mut bundletool = ProtoBuilder::new();
+        bundletool.string(2, "1.15.6");
+        let mut abi = ProtoBuilder::new();
+        abi.varint(1, 1);
+        let mut density = ProtoBuilder::new();
+        density.varint(1, 2).varint(2, 1);
+        let mut splits = ProtoBuilder::new();
+        splits.message(1, &abi).message(1, &density);
+        let mut enabled = ProtoBuilder::new();
+        enabled.varint(1, 1);
+        let mut optimizations = ProtoBuilder::new();
+        optimizations.message(1, &splits).message(3, &enabled);
+        let mut compression = ProtoBuilder::new();
+        compression.string(1, "assets/**");
+        let mut config = ProtoBuilder::new();
+        config
+            .message(1, &bundletool)
+            .message(2, &optimizations)
+            .message(3, &compression);
+        return config.build();
+    }
+
+    fn bundle() -> Vec<u8> {
+        let dex = |class: &'static str| serialize(build_dex(vec![TestClass::new(class)], &[]));
+        let (base, base2, feature) = (dex("La/Base;"), dex("La/Base2;"), dex("La/Feature;"));
+        let (base_manifest, feature_manifest) = (
+            manifest("com.example", None),
+            manifest("com.example", Some("feature")),
+        );
+        let (resources, config) = (resources(), bundle_config());
+        return build_zip(&[
+            ("BundleConfig.pb", &config),
+            ("feature/manifest/AndroidManifest.xml", &feature_manifest),
+            ("feature/dex/classes.dex", &feature),
+            ("base/manifest/AndroidManifest.xml", &base_manifest),
+            ("base/dex/classes2.dex", &base2),
+            ("base/dex/classes.dex", &base),
+            ("base/resources.pb", &resources),
+        ]);
+    }
+
+    #[test]
+    fn test_modules_and_dex_files() {
+        let mut aab = Aab::from_bytes(bundle()).unwrap();
+        assert_eq!(aab.modules(), vec!["base", "feature"]);
+        let names = |dexes: Vec<(String, DexModel)>| {
+            dexes.into_iter().map(|(name, _)| name).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names(aab.dex_files("base").unwrap()),
+            vec!["base/dex/classes.dex", "base/dex/classes2.dex"]
+        );
+        assert_eq!(
+            aab.dex_entry_names("feature"),
+            vec!["feature/dex/classes.dex"]
+        );
+        assert_eq!(
+            names(aab.all_dex_files().unwrap()),
+            vec![
+                "base/dex/classes.dex",
+                "base/dex/classes2.dex",
+                "feature/dex/classes.dex"
+            ]
+        );
+
+        let path = std::env::temp_dir().join("apkdoctor_test_bundle.aab");
+        std::fs::write(&path, bundle()).unwrap();
+        let loaded = load_dex_files(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.len(), 3);
+        assert_eq!(loaded[2].0, "feature/dex/classes.dex");
+
+        let context =
+            AppContext::from_aab_module(Aab::from_bytes(bundle()).unwrap(), "feature").unwrap();
+        assert_eq!(names(context.dexes), vec!["feature/dex/classes.dex"]);
+        assert_eq!(context.manifest.unwrap().package, "com.example");
+        assert!(matches!(
+            AppContext::from_aab_module(Aab::from_bytes(bundle()).unwrap(), "nope"),
+            Err(ManifestError::ApkError(ApkError::ModuleNotFound(_)))
+        ));
+        let apk = build_zip(&[("base/dex/classes.dex", &serialize(build_dex(vec![], &[])))]);
+        assert!(matches!(
+            Aab::from_bytes(apk).unwrap().dex_files("base"),
+            Err(ApkError::NotABundle)
+        ));
+    }
+
+    #[test]
+    fn test_manifest_and_resources() {
+        let mut aab = Aab::from_bytes(bundle()).unwrap();
+        let document = aab.manifest_document("feature").unwrap();
+        assert_eq!(document.namespaces[0].0, "android");
+        assert_eq!(document.root.line, 2);
+        assert_eq!(
+            document.root.attribute("split"),
+            Some(&Value::String("feature".to_string()))
+        );
+
+        let manifest = aab.manifest("base").unwrap();
+        assert_eq!(manifest.package, "com.example");
+        assert_eq!(manifest.version_code, Some(42));
+        assert_eq!(manifest.label.as_deref(), Some("@0x7f010000"));
+
+        let table = aab.resources("base").unwrap();
+        assert_eq!(table.name(0x7f010000).as_deref(), Some("string/app_name"));
+        assert_eq!(table.resolve_str("@0x7f010000"), "Example");
+        let values = table.values(0x7f010000);
+        assert_eq!(values[1].0.qualifiers(), "fr-rCA");
+        assert!(aab.resources("feature").is_err());
+    }
+
+    #[test]
+    fn test_bundle_config() {
+        let config = Aab::from_bytes(bundle()).unwrap().bundle_config().unwrap();
+        assert_eq!(config.bundletool_version, "1.15.6");
+        assert_eq!(config.bundle_type, "regular");
+        assert_eq!(
+            config.split_dimensions,
+            vec![
+                SplitDimension {
+                    dimension: "abi".to_string(),
+                    negate: false
+                },
+                SplitDimension {
+                    dimension: "screen_density".to_string(),
+                    negate: true
+                }
+            ]
+        );
+        assert!(config.uncompress_dex_files);
+        assert!(!config.uncompress_native_libraries);
+        assert_eq!(config.uncompressed_globs, vec!["assets/**"]);
+    }
+}
@@ -0,0 +1,954 @@
+//! Annotations with every index resolved to a name or value.
+//!
+//! `ClassAnnotations` joins the annotations directory of a class with its
+//! annotation sets and parameter lists. `SystemAnnotations` decodes the
+//! `dalvik.annotation.*` annotations that compilers emit for metadata the
+//! dex format has no field for, such as generic signatures and inner class
+//! names, and `GenericSignature` parses those signatures.
+
+use std::fmt::{self, Display, Formatter};
+
+use serde::Serialize;
+
+use crate::{
+    dex_structs::{ClassDefItem, EncodedAnnotation, EncodedValue},
+    resolver::{descriptor_to_java, DexResolver},
+};
+
+const SIGNATURE: &str = "Ldalvik/annotation/Signature;";
+const INNER_CLASS: &str = "Ldalvik/annotation/InnerClass;";
+const ENCLOSING_CLASS: &str = "Ldalvik/annotation/EnclosingClass;";
+const ENCLOSING_METHOD: &str = "Ldalvik/annotation/EnclosingMethod;";
+const MEMBER_CLASSES: &str = "Ldalvik/annotation/MemberClasses;";
+const THROWS: &str = "Ldalvik/annotation/Throws;";
+const METHOD_PARAMETERS: &str = "Ldalvik/annotation/MethodParameters;";
+const SOURCE_DEBUG_EXTENSION: &str = "Ldalvik/annotation/SourceDebugExtension;";
+const ANNOTATION_DEFAULT: &str = "Ldalvik/annotation/AnnotationDefault;";
+
+/// Kinds of `method_handle_item`, by `method_handle_type`.
+const METHOD_HANDLE_KINDS: [&str; 9] = [
+    "static-put",
+    "static-get",
+    "instance-put",
+    "instance-get",
+    "invoke-static",
+    "invoke-instance",
+    "invoke-constructor",
+    "invoke-direct",
+    "invoke-interface",
+];
+
+/// An `EncodedValue` with its index resolved. Types are descriptors, and
+/// fields, methods and enum constants smali signatures.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", content = "value", rename_all = "kebab-case")]
+pub enum Value {
+    Byte(i8),
+    Short(i16),
+    Char(u16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    /// A method prototype such as `(I)V`.
+    MethodType(String),
+    /// The kind and target of a method handle, e.g.
+    /// `invoke-static@Lcom/Foo;->bar()V`.
+    MethodHandle(String),
+    String(String),
+    Type(String),
+    Field(String),
+    Method(String),
+    Enum(String),
+    Array(Vec<Value>),
+    Annotation(Annotation),
+    Null,
+    Boolean(bool),
+}
+
+impl Value {
+    pub fn new(resolver: &DexResolver, value: &EncodedValue) -> Value {
+        return match value {
+            EncodedValue::ValueByte(v) => Value::Byte(*v),
+            EncodedValue::ValueShort(v) => Value::Short(*v),
+            EncodedValue::ValueChar(v) => Value::Char(*v),
+            EncodedValue::ValueInt(v) => Value::Int(*v),
+            EncodedValue::ValueLong(v) => Value::Long(*v),
+            EncodedValue::ValueFloat(v) => Value::Float(*v),
+            EncodedValue::ValueDouble(v) => Value::Double(*v),
+            EncodedValue::ValueMethodType(proto_idx) => {
+                let proto = &resolver.dex.proto_ids[*proto_idx as usize];
+                Value::MethodType(format!(
+                    "({}){}",
+                    resolver
+                        .type_list_descriptors(proto.parameters_off)
+                        .join(""),
+                    resolver.type_descriptor(proto.return_type_idx)
+                ))
+            }
+            EncodedValue::ValueMethodHandle(index) => {
+                let handle = &resolver.dex.method_handles[*index as usize];
+                let kind = handle.method_handle_type as usize;
+                let target = match kind {
+                    0..=3 => resolver
+                        .field_ref(handle.field_or_method_id as u32)
+                        .signature(),
+                    _ => resolver
+                        .method_ref(handle.field_or_method_id as u32)
+                        .signature(),
+                };
+                let kind = METHOD_HANDLE_KINDS.get(kind).copied().unwrap_or("unknown");
+                Value::MethodHandle(format!("{}@{}", kind, target))
+            }
+            EncodedValue::ValueString(string_idx) => {
+                Value::String(resolver.string(*string_idx).to_string())
+            }
+            EncodedValue::ValueType(type_idx) => {
+                Value::Type(resolver.type_descriptor(*type_idx).to_string())
+            }
+            EncodedValue::ValueField(field_idx) => {
+                Value::Field(resolver.field_ref(*field_idx).signature())
+            }
+            EncodedValue::ValueMethod(method_idx) => {
+                Value::Method(resolver.method_ref(*method_idx).signature())
+            }
+            EncodedValue::ValueEnum(field_idx) => {
+                Value::Enum(resolver.field_ref(*field_idx).signature())
+            }
+            EncodedValue::ValueArray(array) => Value::Array(
+                array
+                    .values
+                    .iter()
+                    .map(|v| Value::new(resolver, v))
+                    .collect(),
+            ),
+            EncodedValue::ValueAnnotation(annotation) => {
+                Value::Annotation(Annotation::new(resolver, annotation, None))
+            }
+            EncodedValue::ValueNull => Value::Null,
+            EncodedValue::ValueBoolean(v) => Value::Boolean(*v),
+        };
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        return match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        };
+    }
+
+    /// The value of an integral value other than `long`.
+    pub fn as_int(&self) -> Option<i32> {
+        return match self {
+            Value::Byte(v) => Some(*v as i32),
+            Value::Short(v) => Some(*v as i32),
+            Value::Char(v) => Some(*v as i32),
+            Value::Int(v) => Some(*v),
+            _ => None,
+        };
+    }
+
+    pub fn as_type(&self) -> Option<&str> {
+        return match self {
+            Value::Type(descriptor) => Some(descriptor),
+            _ => None,
+        };
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        return match self {
+            Value::Array(values) => Some(values),
+            _ => None,
+        };
+    }
+
+    /// The strings of a `String[]` value; `None` entries are nulls.
+    pub fn as_strings(&self) -> Option<Vec<Option<&str>>> {
+        return self
+            .as_array()?
+            .iter()
+            .map(|v| match v {
+                Value::Null => Some(None),
+                v => v.as_str().map(Some),
+            })
+            .collect();
+    }
+}
+
+/// Java name of the member in a smali field or method signature, e.g.
+/// `com.Foo.BAR` for `Lcom/Foo;->BAR:Lcom/Foo;`.
+fn member_to_java(signature: &str) -> String {
+    let (class, member) = signature.split_once("->").unwrap_or(("", signature));
+    let name = member.split([':', '(']).next().unwrap_or(member);
+    return format!("{}.{}", descriptor_to_java(class), name);
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        return match self {
+            Value::Byte(v) => write!(f, "{}", v),
+            Value::Short(v) => write!(f, "{}", v),
+            Value::Char(v) => match char::from_u32(*v as u32) {
+                Some(c) => write!(f, "{:?}", c),
+                None => write!(f, "'\\u{:04x}'", v),
+            },
+            Value::Int(v) => write!(f, "{}", v),
+            Value::Long(v) => write!(f, "{}L", v),
+            Value::Float(v) => write!(f, "{:?}f", v),
+            Value::Double(v) => write!(f, "{:?}", v),
+            Value::MethodType(s) | Value::MethodHandle(s) => write!(f, "{}", s),
+            Value::String(s) => write!(f, "{:?}", s),
+            Value::Type(descriptor) => write!(f, "{}.class", descriptor_to_java(descriptor)),
+            Value::Field(s) | Value::Method(s) => write!(f, "{}", s),
+            Value::Enum(s) => write!(f, "{}", member_to_java(s)),
+            Value::Array(values) => {
+                let values = values.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+                write!(f, "{{{}}}", values.join(", "))
+            }
+            Value::Annotation(annotation) => write!(f, "{}", annotation),
+            Value::Null => write!(f, "null"),
+            Value::Boolean(v) => write!(f, "{}", v),
+        };
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Visibility {
+    Build,
+    Runtime,
+    System,
+}
+
+impl Visibility {
+    fn from_u8(visibility: u8) -> Option<Visibility> {
+        return match visibility {
+            0 => Some(Visibility::Build),
+            1 => Some(Visibility::Runtime),
+            2 => Some(Visibility::System),
+            _ => None,
+        };
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Annotation {
+    /// `None` for annotations nested in values.
+    pub visibility: Option<Visibility>,
+    /// Type descriptor, e.g. `Ldalvik/annotation/Signature;`.
+    pub type_descriptor: String,
+    pub elements: Vec<(String, Value)>,
+}
+
+impl Annotation {
+    fn new(
+        resolver: &DexResolver,
+        annotation: &EncodedAnnotation,
+        visibility: Option<Visibility>,
+    ) -> Annotation {
+        return Annotation {
+            visibility,
+            type_descriptor: resolver.type_descriptor(annotation.type_idx).to_string(),
+            elements: annotation
+                .elements
+                .iter()
+                .map(|e| {
+                    (
+                        resolver.string(e.name_idx).to_string(),
+                        Value::new(resolver, &e.value),
+                    )
+                })
+                .collect(),
+        };
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        return self
+            .elements
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value);
+    }
+}
+
+impl Display for Annotation {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "@{}", descriptor_to_java(&self.type_descriptor))?;
+        if self.elements.is_empty() {
+            return Ok(());
+        }
+        let elements = self
+            .elements
+            .iter()
+            .map(|(name, value)| format!("{} = {}", name, value))
+            .collect::<Vec<_>>();
+        return write!(f, "({})", elements.join(", "));
+    }
+}
+
+/// The annotations of the set at `off`, or none when `off` is 0.
+pub fn annotation_set(resolver: &DexResolver, off: u32) -> Vec<Annotation> {
+    let set = match resolver.annotation_set(off) {
+        Some(set) if off != 0 => set,
+        _ => return vec![],
+    };
+    return set
+        .entries
+        .iter()
+        .filter_map(|entry| resolver.annotation(entry.annotation_off))
+        .map(|item| {
+            Annotation::new(
+                resolver,
+                &item.annotation,
+                Visibility::from_u8(item.visibility),
+            )
+        })
+        .collect();
+}
+
+/// Every annotation of a class and its members.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ClassAnnotations {
+    pub class: Vec<Annotation>,
+    /// Annotations of fields, by `field_ids` index.
+    pub fields: Vec<(u32, Vec<Annotation>)>,
+    /// Annotations of methods, by `method_ids` index.
+    pub methods: Vec<(u32, Vec<Annotation>)>,
+    /// Annotations of each parameter of methods, by `method_ids` index.
+    pub parameters: Vec<(u32, Vec<Vec<Annotation>>)>,
+}
+
+impl ClassAnnotations {
+    pub fn new(resolver: &DexResolver, class_def: &ClassDefItem) -> ClassAnnotations {
+        let directory = match resolver.annotations_directory(class_def.annotations_off) {
+            Some(directory) if class_def.annotations_off != 0 => directory,
+            _ => return ClassAnnotations::default(),
+        };
+        let parameters = directory
+            .parameter_annotations
+            .iter()
+            .map(|p| {
+                let sets = match resolver.annotation_set_ref_list(p.annotations_off) {
+                    Some(list) => list
+                        .list
+                        .iter()
+                        .map(|item| annotation_set(resolver, item.annotations_off))
+                        .collect(),
+                    None => vec![],
+                };
+                (p.method_idx, sets)
+            })
+            .collect();
+        return ClassAnnotations {
+            class: annotation_set(resolver, directory.class_annotations_off),
+            fields: directory
+                .field_annotations
+                .iter()
+                .map(|a| (a.field_idx, annotation_set(resolver, a.annotations_off)))
+                .collect(),
+            methods: directory
+                .method_annotations
+                .iter()
+                .map(|a| (a.method_idx, annotation_set(resolver, a.annotations_off)))
+                .collect(),
+            parameters,
+        };
+    }
+
+    pub fn field(&self, field_idx: u32) -> &[Annotation] {
+        return self
+            .fields
+            .iter()
+            .find(|(idx, _)| *idx == field_idx)
+            .map_or(&[], |(_, annotations)| annotations.as_slice());
+    }
+
+    pub fn method(&self, method_idx: u32) -> &[Annotation] {
+        return self
+            .methods
+            .iter()
+            .find(|(idx, _)| *idx == method_idx)
+            .map_or(&[], |(_, annotations)| annotations.as_slice());
+    }
+
+    pub fn method_parameters(&self, method_idx: u32) -> &[Vec<Annotation>] {
+        return self
+            .parameters
+            .iter()
+            .find(|(idx, _)| *idx == method_idx)
+            .map_or(&[], |(_, sets)| sets.as_slice());
+    }
+}
+
+/// From `dalvik.annotation.InnerClass`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct InnerClass {
+    /// Simple name, or `None` for anonymous classes.
+    pub name: Option<String>,
+    /// Access flags as declared in source.
+    pub access_flags: u32,
+}
+
+/// One entry of `dalvik.annotation.MethodParameters`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MethodParameter {
+    pub name: Option<String>,
+    pub access_flags: u32,
+}
+
+/// The system annotations of a class, field or method.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct SystemAnnotations {
+    /// Generic signature, from `dalvik.annotation.Signature`.
+    pub signature: Option<String>,
+    pub inner_class: Option<InnerClass>,
+    /// Descriptor of the class lexically enclosing a member class.
+    pub enclosing_class: Option<String>,
+    /// Smali signature of the method enclosing a local or anonymous class.
+    pub enclosing_method: Option<String>,
+    /// Descriptors of the member classes.
+    pub member_classes: Vec<String>,
+    /// Descriptors of the declared exceptions.
+    pub throws: Vec<String>,
+    pub method_parameters: Vec<MethodParameter>,
+    /// SMAP of inlined code, as emitted by the Kotlin compiler.
+    pub source_debug_extension: Option<String>,
+    /// Default element values of an annotation type.
+    pub annotation_default: Option<Annotation>,
+}
+
+fn types(value: Option<&Value>) -> Vec<String> {
+    return value
+        .and_then(|v| v.as_array())
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|v| v.as_type().map(|t| t.to_string()))
+        .collect();
+}
+
+impl SystemAnnotations {
+    pub fn new(annotations: &[Annotation]) -> SystemAnnotations {
+        let mut system = SystemAnnotations::default();
+        for annotation in annotations {
+            let value = annotation.get("value");
+            match annotation.type_descriptor.as_str() {
+                SIGNATURE => {
+                    system.signature = value
+                        .and_then(|v| v.as_strings())
+                        .map(|parts| parts.into_iter().map(|p| p.unwrap_or_default()).collect());
+                }
+                INNER_CLASS => {
+                    system.inner_class = Some(InnerClass {
+                        name: annotation
+                            .get("name")
+                            .and_then(|v| v.as_str())
+                            .map(|s| s.to_string()),
+                        access_flags: annotation
+                            .get("accessFlags")
+                            .and_then(|v| v.as_int())
+                            .unwrap_or(0) as u32,
+                    });
+                }
+                ENCLOSING_CLASS => {
+                    system.enclosing_class = value.and_then(|v| v.as_type()).map(|t| t.to_string());
+                }
+                ENCLOSING_METHOD => {
+                    system.enclosing_method = match value {
+                        Some(Value::Method(signature)) => Some(signature.clone()),
+                        _ => None,
+                    };
+                }
+                MEMBER_CLASSES => system.member_classes = types(value),
+                THROWS => system.throws = types(value),
+                METHOD_PARAMETERS => {
+                    let names = annotation
+                        .get("names")
+                        .and_then(|v| v.as_strings())
+                        .unwrap_or_default();
+                    let flags = annotation
+                        .get("accessFlags")
+                        .and_then(|v| v.as_array())
+                        .unwrap_or_default();
+                    system.method_parameters = names
+                        .iter()
+                        .enumerate()
+                        .map(|(i, name)| MethodParameter {
+                            name: name.map(|n| n.to_string()),
+                            access_flags: flags.get(i).and_then(|f| f.as_int()).unwrap_or(0) as u32,
+                        })
+                        .collect();
+                }
+                SOURCE_DEBUG_EXTENSION => {
+                    system.source_debug_extension =
+                        value.and_then(|v| v.as_str()).map(|s| s.to_string());
+                }
+                ANNOTATION_DEFAULT => {
+                    system.annotation_default = match value {
+                        Some(Value::Annotation(annotation)) => Some(annotation.clone()),
+                        _ => None,
+                    };
+                }
+                _ => {}
+            }
+        }
+        return system;
+    }
+}
+
+/// A formal type parameter such as `T extends Comparable<T>`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TypeParameter {
+    pub name: String,
+    /// Bounds as Java types; empty for an implicit `Object` bound.
+    pub bounds: Vec<String>,
+}
+
+impl Display for TypeParameter {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        if !self.bounds.is_empty() {
+            write!(f, " extends {}", self.bounds.join(" & "))?;
+        }
+        return Ok(());
+    }
+}
+
+/// A generic signature from a `Signature` annotation, with every type
+/// written as in Java source, e.g. `java.util.List<? extends T>`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum GenericSignature {
+    Class {
+        type_parameters: Vec<TypeParameter>,
+        superclass: String,
+        interfaces: Vec<String>,
+    },
+    Method {
+        type_parameters: Vec<TypeParameter>,
+        parameters: Vec<String>,
+        return_type: String,
+        throws: Vec<String>,
+    },
+    Field {
+        field_type: String,
+    },
+}
+
+/// Recursive descent over the grammar of JVMS §4.7.9.1.
+struct SignatureParser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> SignatureParser<'a> {
+    fn peek(&self) -> Option<char> {
+        return self.text[self.pos..].chars().next();
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += c.len_utf8();
+            return true;
+        }
+        return false;
+    }
+
+    fn expect(&mut self, c: char) -> Option<()> {
+        return self.eat(c).then_some(());
+    }
+
+    fn identifier(&mut self) -> Option<&'a str> {
+        let start = self.pos;
+        let len = self.text[start..]
+            .find(['.', ';', '[', '/', '<', '>', ':'])
+            .unwrap_or(self.text.len() - start);
+        self.pos += len;
+        return (len > 0).then(|| &self.text[start..start + len]);
+    }
+
+    fn type_parameters(&mut self) -> Option<Vec<TypeParameter>> {
+        let mut parameters = vec![];
+        if !self.eat('<') {
+            return Some(parameters);
+        }
+        while !self.eat('>') {
+            let name = self.identifier()?.to_string();
+            let mut bounds = vec![];
+            self.expect(':')?;
+            // The class bound may be empty when only interfaces bound it.
+            if !matches!(self.peek(), Some(':') | Some('>')) {
+                bounds.push(self.reference_type()?);
+            }
+            while self.eat(':') {
+                bounds.push(self.reference_type()?);
+            }
+            bounds.retain(|b| b != "java.lang.Object");
+            parameters.push(TypeParameter { name, bounds });
+        }
+        return Some(parameters);
+    }
+
+    fn type_arguments(&mut self) -> Option<String> {
+        let mut arguments = vec![];
+        while !self.eat('>') {
+            let argument = match self.peek()? {
+                '*' => {
+                    self.pos += 1;
+                    "?".to_string()
+                }
+                '+' => {
+                    self.pos += 1;
+                    format!("? extends {}", self.reference_type()?)
+                }
+                '-' => {
+                    self.pos += 1;
+                    format!("? super {}", self.reference_type()?)
+                }
+                _ => self.reference_type()?,
+            };
+            arguments.push(argument);
+        }
+        return Some(format!("<{}>", arguments.join(", ")));
+    }
+
+    fn class_type(&mut self) -> Option<String> {
+        self.expect('L')?;
+        let mut name = String::new();
+        loop {
+            name.push_str(self.identifier()?);
+            if self.eat('<') {
+                name.push_str(&self.type_arguments()?);
+            }
+            match self.peek()? {
+                // `.` separates an inner class from a parameterized outer one.
+                '/' | '.' => name.push('.'),
+                ';' => {
+                    self.pos += 1;
+                    return Some(name);
+                }
+                _ => return None,
+            }
+            self.pos += 1;
+        }
+    }
+
+    fn reference_type(&mut self) -> Option<String> {
+        return match self.peek()? {
+            'L' => self.class_type(),
+            'T' => {
+                self.pos += 1;
+                let name = self.identifier()?.to_string();
+                self.expect(';')?;
+                Some(name)
+            }
+            '[' => {
+                self.pos += 1;
+                Some(format!("{}[]", self.java_type()?))
+            }
+            _ => None,
+        };
+    }
+
+    fn java_type(&mut self) -> Option<String> {
+        let c = self.peek()?;
+        if "ZBSCIJFDV".contains(c) {
+            self.pos += 1;
+            return Some(descriptor_to_java(&c.to_string()));
+        }
+        return self.reference_type();
+    }
+
+    fn done<T>(&self, value: T) -> Option<T> {
+        return (self.pos == self.text.len()).then_some(value);
+    }
+}
+
+impl GenericSignature {
+    pub fn parse_class(signature: &str) -> Option<GenericSignature> {
+        let mut parser = SignatureParser {
+            text: signature,
+            pos: 0,
+        };
+        let type_parameters = parser.type_parameters()?;
+        let superclass = parser.class_type()?;
+        let mut interfaces = vec![];
+        while parser.peek().is_some() {
+            interfaces.push(parser.class_type()?);
+        }
+        return parser.done(GenericSignature::Class {
+            type_parameters,
+            superclass,
+            interfaces,
+        });
+    }
+
+    pub fn parse_method(signature: &str) -> Option<GenericSignature> {
+        let mut parser = SignatureParser {
+            text: signature,
+            pos: 0,
+        };
+        let type_parameters = parser.type_parameters()?;
+        parser.expect('(')?;
+        let mut parameters = vec![];
+        while !parser.eat(')') {
+            parameters.push(parser.java_type()?);
+        }
+        let return_type = parser.java_type()?;
+        let mut throws = vec![];
+        while parser.eat('^') {
+            throws.push(parser.reference_type()?);
+        }
+        return parser.done(GenericSignature::Method {
+            type_parameters,
+            parameters,
+            return_type,
+            throws,
+        });
+    }
+
+    pub fn parse_field(signature: &str) -> Option<GenericSignature> {
+        let mut parser = SignatureParser {
+            text: signature,
+            pos: 0,
+        };
+        let field_type = parser.reference_type()?;
+        return parser.done(GenericSignature::Field { field_type });
+    }
+}
+
+fn write_type_parameters(f: &mut Formatter, parameters: &[TypeParameter]) -> fmt::Result {
+    if parameters.is_empty() {
+        return Ok(());
+    }
+    let parameters = parameters.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+    return write!(f, "<{}> ", parameters.join(", "));
+}
+
+impl Display for GenericSignature {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        return match self {
+            GenericSignature::Class {
+                type_parameters,
+                superclass,
+                interfaces,
+            } => {
+                write_type_parameters(f, type_parameters)?;
+                write!(f, "extends {}", superclass)?;
+                if !interfaces.is_empty() {
+                    write!(f, " implements {}", interfaces.join(", "))?;
+                }
+                Ok(())
+            }
+            GenericSignature::Method {
+                type_parameters,
+                parameters,
+                return_type,
+                throws,
+            } => {
+                write_type_parameters(f, type_parameters)?;
+                write!(f, "{} ({})", return_type, parameters.join(", "))?;
+                if !throws.is_empty() {
+                    write!(f, " throws {}", throws.join(", "))?;
+                }
+                Ok(())
+            }
+            GenericSignature::Field { field_type } => write!(f, "{}", field_type),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        dex_structs::EncodedArray,
+        resolver::DexResolver,
+        test_utils::{add_annotations, annotation_item, build_dex, string_array, TestClass},
+    };
+
+    #[test]
+    fn test_class_annotations() {
+        let mut dex = build_dex(
+            vec![
+                TestClass::new("Lcom/example/Box;")
+                    .field("items", "Ljava/util/List;")
+                    .method("get", &["I"], "Ljava/lang/Object;", &[0x0011]),
+                TestClass::new("Ldalvik/annotation/Signature;"),
+                TestClass::new("Ldalvik/annotation/Throws;"),
+                TestClass::new("Ldalvik/annotation/MethodParameters;"),
+                TestClass::new("Ljava/io/IOException;"),
+                TestClass::new("Lcom/example/NonNull;"),
+            ],
+            &[
+                "value",
+                "names",
+                "accessFlags",
+                "index",
+                "<T:",
+                "Ljava/lang/Object;>",
+                "Ljava/lang/Object;",
+                "Ljava/util/List<TT;>;",
+                "(I)TT;",
+            ],
+        );
+        let resolver = DexResolver::new(&dex);
+        let class_signature = annotation_item(
+            &resolver,
+            SIGNATURE,
+            vec![(
+                "value",
+                string_array(
+                    &resolver,
+                    &["<T:", "Ljava/lang/Object;>", "Ljava/lang/Object;"],
+                ),
+            )],
+        );
+        let field_signature = annotation_item(
+            &resolver,
+            SIGNATURE,
+            vec![("value", string_array(&resolver, &["Ljava/util/List<TT;>;"]))],
+        );
+        let method_signature = annotation_item(
+            &resolver,
+            SIGNATURE,
+            vec![("value", string_array(&resolver, &["(I)TT;"]))],
+        );
+        let io_exception = (0..dex.type_ids.len() as u32)
+            .find(|t| resolver.type_descriptor(*t) == "Ljava/io/IOException;")
+            .unwrap();
+        let throws = annotation_item(
+            &resolver,
+            THROWS,
+            vec![(
+                "value",
+                EncodedValue::ValueArray(EncodedArray {
+                    values: vec![EncodedValue::ValueType(io_exception)],
+                }),
+            )],
+        );
+        let parameters = annotation_item(
+            &resolver,
+            METHOD_PARAMETERS,
+            vec![
+                ("names", string_array(&resolver, &["index"])),
+                (
+                    "accessFlags",
+                    EncodedValue::ValueArray(EncodedArray {
+                        values: vec![EncodedValue::ValueInt(0x10)],
+                    }),
+                ),
+            ],
+        );
+        let mut non_null = annotation_item(&resolver, "Lcom/example/NonNull;", vec![]);
+        non_null.visibility = 1;
+        add_annotations(
+            &mut dex,
+            0,
+            vec![class_signature],
+            vec![(0, vec![field_signature])],
+            vec![(0, vec![method_signature, throws, parameters])],
+            vec![(0, vec![vec![non_null]])],
+        );
+
+        let resolver = DexResolver::new(&dex);
+        let class_def = &dex.class_defs[0];
+        assert_eq!(
+            resolver.type_descriptor(class_def.class_idx),
+            "Lcom/example/Box;"
+        );
+        let annotations = ClassAnnotations::new(&resolver, class_def);
+
+        let class = SystemAnnotations::new(&annotations.class);
+        let signature = class.signature.unwrap();
+        assert_eq!(signature, "<T:Ljava/lang/Object;>Ljava/lang/Object;");
+        assert_eq!(
+            GenericSignature::parse_class(&signature)
+                .unwrap()
+                .to_string(),
+            "<T> extends java.lang.Object"
+        );
+
+        let field = SystemAnnotations::new(annotations.field(0));
+        assert_eq!(
+            GenericSignature::parse_field(&field.signature.unwrap())
+                .unwrap()
+                .to_string(),
+            "java.util.List<T>"
+        );
+
+        let method = annotations.method(0);
+        assert_eq!(
+            method[1].to_string(),
+            "@dalvik.annotation.Throws(value = {java.io.IOException.class})"
+        );
+        let method = SystemAnnotations::new(method);
+        assert_eq!(
+            GenericSignature::parse_method(&method.signature.unwrap())
+                .unwrap()
+                .to_string(),
+            "T (int)"
+        );
+        assert_eq!(method.throws, vec!["Ljava/io/IOException;"]);
+        assert_eq!(
+            method.method_parameters,
+            vec![MethodParameter {
+                name: Some("index".to_string()),
+                access_flags: 0x10
+            }]
+        );
+
+        let parameters = annotations.method_parameters(0);
+        assert_eq!(parameters.len(), 1);
+        assert_eq!(parameters[0][0].visibility, Some(Visibility::Runtime));
+        assert_eq!(parameters[0][0].to_string(), "@com.example.NonNull");
+        assert!(annotations.method(1).is_empty());
+    }
+
+    #[test]
+    fn test_generic_signature() {
+        let class = GenericSignature::parse_class(
+            "<K:Ljava/lang/Object;V::Ljava/lang/Comparable<-TV;>;>Ljava/util/AbstractMap<TK;TV;>;Ljava/io/Serializable;",
+        )
+        .unwrap();
+        assert_eq!(
+            class.to_string(),
+            "<K, V extends java.lang.Comparable<? super V>> extends java.util.AbstractMap<K, V> implements java.io.Serializable"
+        );
+        let method = GenericSignature::parse_method(
+            "<E:Ljava/lang/Exception;>([Ljava/util/List<+Ljava/lang/Number;>;Ljava/util/Map$Entry<**>;)V^TE;^Ljava/io/IOException;",
+        )
+        .unwrap();
+        assert_eq!(
+            method.to_string(),
+            "<E extends java.lang.Exception> void (java.util.List<? extends java.lang.Number>[], java.util.Map$Entry<?, ?>) throws E, java.io.IOException"
+        );
+        assert_eq!(
+            GenericSignature::parse_field("Lcom/Outer<TT;>.Inner<Ljava/lang/String;>;")
+                .unwrap()
+                .to_string(),
+            "com.Outer<T>.Inner<java.lang.String>"
+        );
+        assert_eq!(GenericSignature::parse_field("I"), None);
+        assert_eq!(GenericSignature::parse_method("(I"), None);
+        assert_eq!(GenericSignature::parse_class("Ljava/lang/Object;X"), None);
+    }
+
+    #[test]
+    fn test_value_display() {
+        assert_eq!(Value::Long(3).to_string(), "3L");
+        assert_eq!(Value::Float(1.0).to_string(), "1.0f");
+        assert_eq!(Value::Char(0x41).to_string(), "'A'");
+        assert_eq!(
+            Value::Enum("Lcom/Color;->RED:Lcom/Color;".to_string()).to_string(),
+            "com.Color.RED"
+        );
+        assert_eq!(
+            Value::Array(vec![Value::String("a".to_string()), Value::Null]).to_string(),
+            "{\"a\", null}"
+        );
+        assert_eq!(
+            Value::Array(vec![Value::String("a".to_string()), Value::Null]).as_strings(),
+            Some(vec![Some("a"), None])
+        );
+    }
+}
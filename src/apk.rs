@@ -0,0 +1,224 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    fs,
+    io::{self, Cursor, Read},
+    path::Path,
+};
+
+use zip::{result::ZipError, ZipArchive};
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use crate::{
+    aab::{Aab, BUNDLE_CONFIG},
+    axml::AxmlError,
+    deserialize_vec,
+    dex_model::DexModel,
+    DeserializeError,
+};
+
+#[derive(Debug)]
+pub enum ApkError {
+    FileOpenError(io::Error),
+    ZipError(ZipError),
+    DexError(String, DeserializeError),
+    /// A binary XML or resource table entry failed to decode.
+    ResourceError(String, AxmlError),
+    /// A module was asked for, but the archive is not an `.aab`.
+    NotABundle,
+    ModuleNotFound(String),
+}
+
+impl From<io::Error> for ApkError {
+    fn from(err: io::Error) -> Self {
+        ApkError::FileOpenError(err)
+    }
+}
+
+impl From<ZipError> for ApkError {
+    fn from(err: ZipError) -> Self {
+        ApkError::ZipError(err)
+    }
+}
+
+impl Display for ApkError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            ApkError::FileOpenError(err) => write!(f, "{}", err),
+            ApkError::ZipError(err) => write!(f, "{}", err),
+            ApkError::DexError(name, err) => write!(f, "{}: {:?}", name, err),
+            ApkError::ResourceError(name, err) => write!(f, "{}: {}", name, err),
+            ApkError::NotABundle => write!(f, "not an app bundle"),
+            ApkError::ModuleNotFound(module) => write!(f, "no module named {}", module),
+        }
+    }
+}
+
+/// An APK (or any other zip archive containing dex files, such as a jar).
+pub struct Apk {
+    archive: ZipArchive<Cursor<Vec<u8>>>,
+}
+
+/// Sort key placing `classes.dex` before `classes2.dex` ... `classes10.dex`.
+fn dex_sort_key(name: &str) -> (String, u32) {
+    let (dir, file) = match name.rsplit_once('/') {
+        Some((dir, file)) => (dir.to_string(), file),
+        None => (String::new(), name),
+    };
+    let number = file
+        .strip_prefix("classes")
+        .and_then(|rest| rest.strip_suffix(".dex"))
+        .map(|n| {
+            if n.is_empty() {
+                1
+            } else {
+                n.parse().unwrap_or(u32::MAX)
+            }
+        })
+        .unwrap_or(u32::MAX);
+    return (dir, number);
+}
+
+/// Whether an entry name is a `classesN.dex` file at the root of the
+/// archive. Dex files deeper down, e.g. in `assets/`, are data the app
+/// loads itself, if at all.
+pub(crate) fn is_dex_entry(name: &str) -> bool {
+    return !name.contains('/') && name.starts_with("classes") && name.ends_with(".dex");
+}
+
+/// Sorts dex entry names into multidex order.
+pub(crate) fn sort_dex_entries(names: &mut [String]) {
+    names.sort_by_key(|name| dex_sort_key(name));
+}
+
+impl Apk {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Apk, ApkError> {
+        return Apk::from_bytes(fs::read(path)?);
+    }
+
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Apk, ApkError> {
+        let archive = ZipArchive::new(Cursor::new(bytes))?;
+        return Ok(Apk { archive });
+    }
+
+    pub fn entry_names(&self) -> Vec<String> {
+        return self.archive.file_names().map(String::from).collect();
+    }
+
+    pub fn has_entry(&self, name: &str) -> bool {
+        return self.archive.file_names().any(|n| n == name);
+    }
+
+    pub fn read_entry(&mut self, name: &str) -> Result<Vec<u8>, ApkError> {
+        let mut file = self.archive.by_name(name)?;
+        let mut bytes = Vec::with_capacity(file.size() as usize);
+        file.read_to_end(&mut bytes)?;
+        return Ok(bytes);
+    }
+
+    /// Uncompressed and compressed size of an entry.
+    pub fn entry_size(&mut self, name: &str) -> Result<(u64, u64), ApkError> {
+        let file = self.archive.by_name(name)?;
+        return Ok((file.size(), file.compressed_size()));
+    }
+
+    /// Names of the `classesN.dex` entries at the root of the archive, in
+    /// multidex order.
+    pub fn dex_entry_names(&self) -> Vec<String> {
+        let mut names = self
+            .archive
+            .file_names()
+            .filter(|name| is_dex_entry(name))
+            .map(String::from)
+            .collect::<Vec<_>>();
+        sort_dex_entries(&mut names);
+        return names;
+    }
+
+    /// Parses every root `classesN.dex` entry, in multidex order. With the
+    /// `parallel` feature the entries are parsed concurrently.
+    pub fn dex_files(&mut self) -> Result<Vec<(String, DexModel)>, ApkError> {
+        return self.parse_dex_entries(self.dex_entry_names());
+    }
+
+    /// Parses the named dex entries, concurrently with the `parallel`
+    /// feature.
+    pub(crate) fn parse_dex_entries(
+        &mut self,
+        names: Vec<String>,
+    ) -> Result<Vec<(String, DexModel)>, ApkError> {
+        let mut entries = vec![];
+        for name in names {
+            let bytes = self.read_entry(&name)?;
+            entries.push((name, bytes));
+        }
+        #[cfg(feature = "parallel")]
+        let entries = entries.into_par_iter();
+        #[cfg(not(feature = "parallel"))]
+        let entries = entries.into_iter();
+        return entries
+            .map(|(name, bytes)| match deserialize_vec(bytes) {
+                Ok(dex) => Ok((name, dex)),
+                Err(err) => Err(ApkError::DexError(name, err)),
+            })
+            .collect();
+    }
+}
+
+/// The last component of `path`, used to name a dex file loaded on its own.
+pub(crate) fn file_name(path: &Path) -> String {
+    return path
+        .file_name()
+        .unwrap_or(path.as_os_str())
+        .to_string_lossy()
+        .to_string();
+}
+
+/// Loads the dex files in `path`, which is either a single `.dex` file or a
+/// zip archive (`.apk`, `.jar`, ...) containing `classesN.dex` entries. The
+/// dex files of an `.aab` are returned module by module, `base` first.
+pub fn load_dex_files<P: AsRef<Path>>(path: P) -> Result<Vec<(String, DexModel)>, ApkError> {
+    let bytes = fs::read(&path)?;
+    if bytes.starts_with(b"dex\n") {
+        let name = file_name(path.as_ref());
+        let dex = deserialize_vec(bytes).map_err(|err| ApkError::DexError(name.clone(), err))?;
+        return Ok(vec![(name, dex)]);
+    }
+    let mut apk = Apk::from_bytes(bytes)?;
+    if apk.has_entry(BUNDLE_CONFIG) {
+        return Aab::from_apk(apk).all_dex_files();
+    }
+    return apk.dex_files();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        serialize,
+        test_utils::{build_dex, build_zip, TestClass},
+    };
+
+    #[test]
+    fn test_dex_sort_key() {
+        let mut names = vec!["classes10.dex", "classes2.dex", "classes.dex"];
+        names.sort_by_key(|name| dex_sort_key(name));
+        assert_eq!(names, vec!["classes.dex", "classes2.dex", "classes10.dex"]);
+        assert!(is_dex_entry("classes3.dex"));
+        assert!(!is_dex_entry("base/dex/classes3.dex"));
+        assert!(!is_dex_entry("assets/data.bin"));
+    }
+
+    #[test]
+    fn test_nested_dex_is_not_code() {
+        let dex = serialize(build_dex(vec![TestClass::new("La/A;")], &[]));
+        let zip = build_zip(&[
+            ("classes.dex", &dex),
+            ("assets/payload/classes.dex", b"dex\n035\0 truncated"),
+        ]);
+        let mut apk = Apk::from_bytes(zip).unwrap();
+        assert_eq!(apk.dex_entry_names(), vec!["classes.dex"]);
+        assert_eq!(apk.dex_files().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,777 @@
+//! Decoder for the compiled resource table, `resources.arsc`.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::{self, Display, Formatter},
+};
+
+use serde::Serialize;
+
+use crate::{
+    apk::{Apk, ApkError},
+    axml::{self, AxmlError, ByteReader, ChunkHeader, StringPool, Value, XmlDocument, XmlElement},
+    dex_model::DexModel,
+    dex_structs::EncodedValue,
+    instructions::IndexKind,
+    resolver::DexResolver,
+};
+
+const RES_STRING_POOL_TYPE: u16 = 0x0001;
+const RES_TABLE_TYPE: u16 = 0x0002;
+const RES_TABLE_PACKAGE_TYPE: u16 = 0x0200;
+const RES_TABLE_TYPE_TYPE: u16 = 0x0201;
+const RES_TABLE_TYPE_SPEC_TYPE: u16 = 0x0202;
+
+const TYPE_FLAG_SPARSE: u8 = 0x01;
+const TYPE_FLAG_OFFSET16: u8 = 0x02;
+const ENTRY_FLAG_COMPLEX: u16 = 0x0001;
+const ENTRY_FLAG_COMPACT: u16 = 0x0008;
+const NO_ENTRY: u32 = 0xffffffff;
+
+/// The device configuration a set of resource values applies to
+/// (`ResTable_config`), reduced to the commonly used qualifiers.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+pub struct ResourceConfig {
+    pub mcc: u16,
+    pub mnc: u16,
+    pub language: String,
+    pub country: String,
+    pub orientation: u8,
+    pub density: u16,
+    pub sdk_version: u16,
+    pub ui_mode: u8,
+    pub smallest_screen_width_dp: u16,
+    pub screen_width_dp: u16,
+    pub screen_height_dp: u16,
+}
+
+impl ResourceConfig {
+    fn read(r: &ByteReader, offset: usize) -> Result<ResourceConfig, AxmlError> {
+        let size = r.u32(offset)? as usize;
+        // Fields past the recorded size are absent and read as zero.
+        let u8_at = |o: usize| if o < size { r.u8(offset + o) } else { Ok(0) };
+        let u16_at = |o: usize| {
+            if o + 1 < size {
+                r.u16(offset + o)
+            } else {
+                Ok(0)
+            }
+        };
+        let chars = |o: usize| -> Result<String, AxmlError> {
+            let bytes = [u8_at(o)?, u8_at(o + 1)?];
+            return Ok(bytes
+                .iter()
+                .filter(|b| **b != 0)
+                .map(|b| *b as char)
+                .collect());
+        };
+        return Ok(ResourceConfig {
+            mcc: u16_at(4)?,
+            mnc: u16_at(6)?,
+            language: chars(8)?,
+            country: chars(10)?,
+            orientation: u8_at(12)?,
+            density: u16_at(14)?,
+            sdk_version: u16_at(24)?,
+            ui_mode: u8_at(29)?,
+            smallest_screen_width_dp: u16_at(30)?,
+            screen_width_dp: u16_at(32)?,
+            screen_height_dp: u16_at(34)?,
+        });
+    }
+
+    /// Resource directory qualifiers, e.g. `fr-rCA-night-xxhdpi-v26`, or
+    /// `default` for the default configuration.
+    pub fn qualifiers(&self) -> String {
+        let mut parts = vec![];
+        if self.mcc != 0 {
+            parts.push(format!("mcc{}", self.mcc));
+        }
+        if self.mnc != 0 {
+            parts.push(format!("mnc{}", self.mnc));
+        }
+        if !self.language.is_empty() {
+            parts.push(self.language.clone());
+        }
+        if !self.country.is_empty() {
+            parts.push(format!("r{}", self.country));
+        }
+        if self.smallest_screen_width_dp != 0 {
+            parts.push(format!("sw{}dp", self.smallest_screen_width_dp));
+        }
+        if self.screen_width_dp != 0 {
+            parts.push(format!("w{}dp", self.screen_width_dp));
+        }
+        if self.screen_height_dp != 0 {
+            parts.push(format!("h{}dp", self.screen_height_dp));
+        }
+        match self.orientation {
+            1 => parts.push("port".to_string()),
+            2 => parts.push("land".to_string()),
+            _ => {}
+        }
+        match self.ui_mode & 0x30 {
+            0x10 => parts.push("notnight".to_string()),
+            0x20 => parts.push("night".to_string()),
+            _ => {}
+        }
+        match self.density {
+            0 => {}
+            120 => parts.push("ldpi".to_string()),
+            160 => parts.push("mdpi".to_string()),
+            213 => parts.push("tvdpi".to_string()),
+            240 => parts.push("hdpi".to_string()),
+            320 => parts.push("xhdpi".to_string()),
+            480 => parts.push("xxhdpi".to_string()),
+            640 => parts.push("xxxhdpi".to_string()),
+            0xfffe => parts.push("anydpi".to_string()),
+            0xffff => parts.push("nodpi".to_string()),
+            density => parts.push(format!("{}dpi", density)),
+        }
+        if self.sdk_version != 0 {
+            parts.push(format!("v{}", self.sdk_version));
+        }
+        if parts.is_empty() {
+            return "default".to_string();
+        }
+        return parts.join("-");
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResourceValue {
+    Simple(Value),
+    /// A bag such as a style or plural: parent resource id and
+    /// `(attribute or key id, value)` pairs.
+    Complex {
+        parent: u32,
+        values: Vec<(u32, Value)>,
+    },
+}
+
+impl ResourceValue {
+    /// Resource ids this value refers to.
+    fn references(&self) -> Vec<u32> {
+        let reference = |value: &Value| match value {
+            Value::Reference(id) | Value::Attribute(id) => Some(*id),
+            _ => None,
+        };
+        return match self {
+            ResourceValue::Simple(value) => reference(value).into_iter().collect(),
+            ResourceValue::Complex { parent, values } => values
+                .iter()
+                .flat_map(|(key, value)| [Some(*key), reference(value)])
+                .flatten()
+                .chain((*parent != 0).then_some(*parent))
+                .collect(),
+        };
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResourceEntry {
+    /// Index of the entry within its type; the low 16 bits of the id.
+    pub index: u16,
+    pub key: String,
+    pub value: ResourceValue,
+}
+
+/// Values of one type for one configuration (a `ResTable_type` chunk).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResourceTypeChunk {
+    pub config: ResourceConfig,
+    pub entries: Vec<ResourceEntry>,
+    /// Size of the chunk in bytes.
+    pub size: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResourceType {
+    pub id: u8,
+    pub name: String,
+    /// Configuration change flags from the type spec, one per entry.
+    pub spec_flags: Vec<u32>,
+    pub chunks: Vec<ResourceTypeChunk>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResourcePackage {
+    pub id: u8,
+    pub name: String,
+    pub types: Vec<ResourceType>,
+}
+
+/// Count and size of the entries of one type in one configuration.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ResourceStats {
+    pub package: String,
+    pub type_name: String,
+    pub config: String,
+    pub entries: usize,
+    pub size: u32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ResourceTable {
+    /// Global pool holding string values.
+    pub strings: StringPool,
+    pub packages: Vec<ResourcePackage>,
+}
+
+fn read_entry(
+    r: &ByteReader,
+    offset: usize,
+    index: u16,
+    strings: &StringPool,
+    keys: &StringPool,
+) -> Result<ResourceEntry, AxmlError> {
+    let size = r.u16(offset)?;
+    let flags = r.u16(offset + 2)?;
+    let value = |data_type: u8, data: u32| {
+        let raw = if data_type == 0x03 {
+            strings.get(data)
+        } else {
+            None
+        };
+        Value::from_res_value(data_type, data, raw)
+    };
+    if flags & ENTRY_FLAG_COMPACT != 0 {
+        return Ok(ResourceEntry {
+            index,
+            key: keys.get(size as u32).unwrap_or("").to_string(),
+            value: ResourceValue::Simple(value((flags >> 8) as u8, r.u32(offset + 4)?)),
+        });
+    }
+    let key = keys.get(r.u32(offset + 4)?).unwrap_or("").to_string();
+    let body = offset + size as usize;
+    if flags & ENTRY_FLAG_COMPLEX != 0 {
+        let parent = r.u32(offset + 8)?;
+        let count = r.u32(offset + 12)? as usize;
+        let mut values = vec![];
+        for i in 0..count {
+            let map = body + i * 12;
+            values.push((r.u32(map)?, value(r.u8(map + 7)?, r.u32(map + 8)?)));
+        }
+        return Ok(ResourceEntry {
+            index,
+            key,
+            value: ResourceValue::Complex { parent, values },
+        });
+    }
+    return Ok(ResourceEntry {
+        index,
+        key,
+        value: ResourceValue::Simple(value(r.u8(body + 3)?, r.u32(body + 4)?)),
+    });
+}
+
+fn read_type_chunk(
+    r: &ByteReader,
+    chunk: &ChunkHeader,
+    strings: &StringPool,
+    keys: &StringPool,
+) -> Result<ResourceTypeChunk, AxmlError> {
+    let flags = r.u8(chunk.offset + 9)?;
+    let entry_count = r.u32(chunk.offset + 12)? as usize;
+    let entries_start = chunk.offset + r.u32(chunk.offset + 16)? as usize;
+    let config = ResourceConfig::read(r, chunk.offset + 20)?;
+
+    let offsets = chunk.body();
+    let mut entry_offsets = vec![];
+    for i in 0..entry_count {
+        if flags & TYPE_FLAG_SPARSE != 0 {
+            let index = r.u16(offsets + i * 4)?;
+            let offset = r.u16(offsets + i * 4 + 2)? as usize * 4;
+            entry_offsets.push((index, offset));
+        } else if flags & TYPE_FLAG_OFFSET16 != 0 {
+            let offset = r.u16(offsets + i * 2)?;
+            if offset != 0xffff {
+                entry_offsets.push((i as u16, offset as usize * 4));
+            }
+        } else {
+            let offset = r.u32(offsets + i * 4)?;
+            if offset != NO_ENTRY {
+                entry_offsets.push((i as u16, offset as usize));
+            }
+        }
+    }
+    let entries = entry_offsets
+        .into_iter()
+        .map(|(index, offset)| read_entry(r, entries_start + offset, index, strings, keys))
+        .collect::<Result<_, _>>()?;
+    return Ok(ResourceTypeChunk {
+        config,
+        entries,
+        size: chunk.size as u32,
+    });
+}
+
+fn read_package(
+    r: &ByteReader,
+    chunk: &ChunkHeader,
+    strings: &StringPool,
+) -> Result<ResourcePackage, AxmlError> {
+    let id = r.u32(chunk.offset + 8)? as u8;
+    let name_units = r
+        .slice(chunk.offset + 12, 256)?
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .take_while(|c| *c != 0)
+        .collect::<Vec<_>>();
+    let type_strings = r.u32(chunk.offset + 268)? as usize;
+    let key_strings = r.u32(chunk.offset + 276)? as usize;
+    let type_names = StringPool::read(r, &ChunkHeader::read(r, chunk.offset + type_strings)?)?;
+    let keys = StringPool::read(r, &ChunkHeader::read(r, chunk.offset + key_strings)?)?;
+
+    let mut types: BTreeMap<u8, ResourceType> = BTreeMap::new();
+    let mut offset = chunk.body();
+    while offset < chunk.end() {
+        let child = ChunkHeader::read(r, offset)?;
+        if child.chunk_type == RES_TABLE_TYPE_SPEC_TYPE || child.chunk_type == RES_TABLE_TYPE_TYPE {
+            // Type ids start at 1; 0 is reserved.
+            let type_id = r.u8(child.offset + 8)?;
+            let type_index = (type_id as u32)
+                .checked_sub(1)
+                .ok_or_else(|| AxmlError::InvalidChunk(child.offset, "type id 0".to_string()))?;
+            let resource_type = types.entry(type_id).or_insert_with(|| ResourceType {
+                id: type_id,
+                name: type_names.get(type_index).unwrap_or("").to_string(),
+                spec_flags: vec![],
+                chunks: vec![],
+            });
+            if child.chunk_type == RES_TABLE_TYPE_SPEC_TYPE {
+                let entry_count = r.u32(child.offset + 12)? as usize;
+                resource_type.spec_flags = (0..entry_count)
+                    .map(|i| r.u32(child.body() + i * 4))
+                    .collect::<Result<_, _>>()?;
+            } else {
+                resource_type
+                    .chunks
+                    .push(read_type_chunk(r, &child, strings, &keys)?);
+            }
+        }
+        offset = child.end();
+    }
+
+    return Ok(ResourcePackage {
+        id,
+        name: String::from_utf16_lossy(&name_units),
+        types: types.into_values().collect(),
+    });
+}
+
+/// Formats a resource id as it appears in an attribute, e.g. `@0x7f0e001b`.
+pub fn format_reference(id: u32) -> String {
+    return Value::Reference(id).to_string();
+}
+
+impl ResourceTable {
+    pub fn parse(bytes: &[u8]) -> Result<ResourceTable, AxmlError> {
+        let r = ByteReader::new(bytes);
+        let table = ChunkHeader::read(&r, 0)?;
+        if table.chunk_type != RES_TABLE_TYPE {
+            return Err(AxmlError::InvalidChunk(
+                0,
+                "not a resource table".to_string(),
+            ));
+        }
+        let mut resources = ResourceTable::default();
+        let mut offset = table.body();
+        while offset < table.end() {
+            let chunk = ChunkHeader::read(&r, offset)?;
+            match chunk.chunk_type {
+                RES_STRING_POOL_TYPE => resources.strings = StringPool::read(&r, &chunk)?,
+                RES_TABLE_PACKAGE_TYPE => {
+                    resources
+                        .packages
+                        .push(read_package(&r, &chunk, &resources.strings)?)
+                }
+                _ => {}
+            }
+            offset = chunk.end();
+        }
+        return Ok(resources);
+    }
+
+    pub fn from_apk(apk: &mut Apk) -> Result<ResourceTable, ApkError> {
+        let bytes = apk.read_entry("resources.arsc")?;
+        return ResourceTable::parse(&bytes)
+            .map_err(|err| ApkError::ResourceError("resources.arsc".to_string(), err));
+    }
+
+    fn resource_type(&self, id: u32) -> Option<(&ResourcePackage, &ResourceType)> {
+        let package = self.packages.iter().find(|p| p.id as u32 == id >> 24)?;
+        let type_id = ((id >> 16) & 0xff) as u8;
+        let resource_type = package.types.iter().find(|t| t.id == type_id)?;
+        return Some((package, resource_type));
+    }
+
+    /// Every configuration's value of the resource `id`.
+    pub fn values(&self, id: u32) -> Vec<(&ResourceConfig, &ResourceValue)> {
+        let index = (id & 0xffff) as u16;
+        return match self.resource_type(id) {
+            Some((_, resource_type)) => resource_type
+                .chunks
+                .iter()
+                .flat_map(|chunk| {
+                    chunk
+                        .entries
+                        .iter()
+                        .filter(move |e| e.index == index)
+                        .map(move |e| (&chunk.config, &e.value))
+                })
+                .collect(),
+            None => vec![],
+        };
+    }
+
+    /// Name of a resource as `type/name`, e.g. `string/app_name`.
+    pub fn name(&self, id: u32) -> Option<String> {
+        let (_, resource_type) = self.resource_type(id)?;
+        let index = (id & 0xffff) as u16;
+        let entry = resource_type
+            .chunks
+            .iter()
+            .flat_map(|chunk| chunk.entries.iter())
+            .find(|e| e.index == index)?;
+        return Some(format!("{}/{}", resource_type.name, entry.key));
+    }
+
+    /// Resolves a reference to a displayable string: the default string
+    /// value when there is one, else the `@type/name` of the resource.
+    pub fn resolve(&self, value: &Value) -> String {
+        let mut value = value.clone();
+        // Follow aliases such as `@string/a` -> `@string/b`, bounded to
+        // tolerate reference cycles.
+        for _ in 0..8 {
+            let id = match value {
+                Value::Reference(id) => id,
+                _ => return value.to_string(),
+            };
+            let values = self.values(id);
+            let default = values
+                .iter()
+                .find(|(config, _)| config.qualifiers() == "default")
+                .or(values.first());
+            match default {
+                Some((_, ResourceValue::Simple(next))) => value = next.clone(),
+                _ => {
+                    return match self.name(id) {
+                        Some(name) => format!("@{}", name),
+                        None => format_reference(id),
+                    }
+                }
+            }
+        }
+        return value.to_string();
+    }
+
+    /// Resolves a manifest attribute string such as `@0x7f0e001b`; other
+    /// strings are returned as is.
+    pub fn resolve_str(&self, s: &str) -> String {
+        return match s
+            .strip_prefix("@0x")
+            .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+        {
+            Some(id) => self.resolve(&Value::Reference(id)),
+            None => s.to_string(),
+        };
+    }
+
+    /// Ids of every resource defined by the table.
+    pub fn resource_ids(&self) -> Vec<u32> {
+        let mut ids = HashSet::new();
+        for package in self.packages.iter() {
+            for resource_type in package.types.iter() {
+                for chunk in resource_type.chunks.iter() {
+                    ids.extend(chunk.entries.iter().map(|e| {
+                        (package.id as u32) << 24 | (resource_type.id as u32) << 16 | e.index as u32
+                    }));
+                }
+            }
+        }
+        let mut ids = ids.into_iter().collect::<Vec<_>>();
+        ids.sort();
+        return ids;
+    }
+
+    /// Looks up a resource id by type and name, e.g. `("string", "app_name")`.
+    /// The name may also be that of the R field, which has `_` in place of
+    /// each `.`, e.g. `Theme_App` for `style/Theme.App`.
+    pub fn id_by_name(&self, type_name: &str, name: &str) -> Option<u32> {
+        let matches = |key: &str| key == name || key.replace('.', "_") == name;
+        for package in self.packages.iter() {
+            for resource_type in package.types.iter().filter(|t| t.name == type_name) {
+                for chunk in resource_type.chunks.iter() {
+                    if let Some(entry) = chunk.entries.iter().find(|e| matches(&e.key)) {
+                        return Some(
+                            (package.id as u32) << 24
+                                | (resource_type.id as u32) << 16
+                                | entry.index as u32,
+                        );
+                    }
+                }
+            }
+        }
+        return None;
+    }
+
+    /// Entry counts and sizes per type and per configuration.
+    pub fn stats(&self) -> Vec<ResourceStats> {
+        let mut stats = vec![];
+        for package in self.packages.iter() {
+            for resource_type in package.types.iter() {
+                for chunk in resource_type.chunks.iter() {
+                    stats.push(ResourceStats {
+                        package: package.name.clone(),
+                        type_name: resource_type.name.clone(),
+                        config: chunk.config.qualifiers(),
+                        entries: chunk.entries.len(),
+                        size: chunk.size,
+                    });
+                }
+            }
+        }
+        return stats;
+    }
+
+    /// Resources of the app package that nothing reaches. Roots are the
+    /// resource ids loaded by `const` instructions and static values outside
+    /// the `R` classes, `R` fields read by code, and references in the
+    /// manifest and in `xml_files` (binary XML resources keyed by path, such
+    /// as `res/layout/main.xml`). Resources referenced by reachable
+    /// resources or XML files are reachable too.
+    pub fn unused_resources(
+        &self,
+        dexes: &[(String, DexModel)],
+        manifest: Option<&XmlDocument>,
+        xml_files: &HashMap<String, XmlDocument>,
+    ) -> Vec<String> {
+        let defined = self.resource_ids();
+        let defined_set = defined.iter().copied().collect::<HashSet<_>>();
+        let mut roots = dex_resource_references(self, dexes);
+        if let Some(manifest) = manifest {
+            xml_references(&manifest.root, &mut roots);
+        }
+
+        let mut used = HashSet::new();
+        while let Some(id) = roots.pop() {
+            if !defined_set.contains(&id) || !used.insert(id) {
+                continue;
+            }
+            for (_, value) in self.values(id) {
+                roots.extend(value.references());
+                if let ResourceValue::Simple(Value::String(path)) = value {
+                    if let Some(document) = xml_files.get(path) {
+                        xml_references(&document.root, &mut roots);
+                    }
+                }
+            }
+        }
+
+        return defined
+            .into_iter()
+            // Only the app's own resources; 0x01 is the framework package.
+            .filter(|id| id >> 24 == 0x7f && !used.contains(id))
+            .filter_map(|id| self.name(id))
+            .collect();
+    }
+}
+
+/// Resource ids in a resource-typed range (package byte set).
+fn is_resource_id(value: i64) -> bool {
+    return (0x01000000..=0x7fffffff).contains(&value) && value & 0x00ff0000 != 0;
+}
+
+/// Whether a class descriptor names a generated `R` inner class.
+fn r_class_type(descriptor: &str) -> Option<&str> {
+    let java = descriptor.strip_suffix(';')?;
+    let simple = java.rsplit('/').next()?;
+    return simple.strip_prefix("R$");
+}
+
+fn encoded_value_ints(value: &EncodedValue, out: &mut Vec<u32>) {
+    match value {
+        EncodedValue::ValueInt(i) if is_resource_id(*i as i64) => out.push(*i as u32),
+        EncodedValue::ValueArray(array) => {
+            for value in array.values.iter() {
+                encoded_value_ints(value, out);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn dex_resource_references(table: &ResourceTable, dexes: &[(String, DexModel)]) -> Vec<u32> {
+    let mut ids = vec![];
+    for (_, dex) in dexes {
+        let resolver = DexResolver::new(dex);
+        for class_def in dex.class_defs.iter() {
+            // Values in the R classes define resources rather than use them.
+            if r_class_type(resolver.type_descriptor(class_def.class_idx)).is_some() {
+                continue;
+            }
+            if let Some(array) = resolver.encoded_array(class_def.static_values_off) {
+                for value in array.value.values.iter() {
+                    encoded_value_ints(value, &mut ids);
+                }
+            }
+            for (_, method) in resolver.class_methods(class_def) {
+                let code = match resolver.code_item(method.code_off) {
+                    Some(code) => code,
+                    None => continue,
+                };
+                for insn in code.insns.iter() {
+                    if let Some(literal) = insn.literal() {
+                        if is_resource_id(literal) {
+                            ids.push(literal as u32);
+                        }
+                    }
+                    for index_ref in insn.index_refs() {
+                        if index_ref.kind != IndexKind::Field {
+                            continue;
+                        }
+                        let field = resolver.field_ref(index_ref.index);
+                        if let Some(type_name) = r_class_type(&field.class) {
+                            ids.extend(table.id_by_name(type_name, &field.name));
+                        }
+                    }
+                }
+            }
+        }
+    }
+    return ids;
+}
+
+fn xml_references(element: &XmlElement, out: &mut Vec<u32>) {
+    for attribute in element.attributes.iter() {
+        if let Value::Reference(id) | Value::Attribute(id) = attribute.value {
+            out.push(id);
+        }
+    }
+    for child in element.children.iter() {
+        xml_references(child, out);
+    }
+}
+
+/// Finds unused resources in an APK, reading the manifest and every binary
+/// XML file under `res/`. Files under `res/raw/` are kept as they are, so
+/// XML there is plain text and skipped.
+pub fn find_unused_resources(apk: &mut Apk) -> Result<Vec<String>, ApkError> {
+    let table = ResourceTable::from_apk(apk)?;
+    let dexes = apk.dex_files()?;
+    let parse_xml = |name: &str, bytes: Vec<u8>| {
+        axml::parse(&bytes).map_err(|err| ApkError::ResourceError(name.to_string(), err))
+    };
+    let manifest = parse_xml(
+        "AndroidManifest.xml",
+        apk.read_entry("AndroidManifest.xml")?,
+    )?;
+    let mut xml_files = HashMap::new();
+    for name in apk.entry_names() {
+        let raw = name.starts_with("res/raw/") || name.starts_with("res/raw-");
+        if name.starts_with("res/") && name.ends_with(".xml") && !raw {
+            let document = parse_xml(&name, apk.read_entry(&name)?)?;
+            xml_files.insert(name, document);
+        }
+    }
+    return Ok(table.unused_resources(&dexes, Some(&manifest), &xml_files));
+}
+
+impl Display for ResourceTable {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for stats in self.stats() {
+            writeln!(
+                f,
+                "{}:{} [{}]: {} entries, {} bytes",
+                stats.package, stats.type_name, stats.config, stats.entries, stats.size
+            )?;
+        }
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        serialize,
+        test_utils::{arsc_table, build_dex, build_zip, AxmlBuilder, TestClass},
+    };
+
+    #[test]
+    fn test_parse_and_resolve() {
+        let table = ResourceTable::parse(&arsc_table()).unwrap();
+        assert_eq!(table.packages[0].name, "com.example");
+        assert_eq!(table.name(0x7f010000).as_deref(), Some("string/app_name"));
+        assert_eq!(table.resolve_str("@0x7f010000"), "Example");
+        assert_eq!(table.resolve_str("plain"), "plain");
+        assert_eq!(table.values(0x7f010000).len(), 2);
+        assert_eq!(table.values(0x7f010000)[1].0.qualifiers(), "fr");
+
+        let stats = table.stats();
+        assert_eq!(stats.len(), 3);
+        assert_eq!(stats[0].type_name, "string");
+        assert_eq!(stats[0].entries, 2);
+
+        // Type id 0 is reserved.
+        let mut bytes = arsc_table();
+        let spec = (0..bytes.len() - 8)
+            .find(|&i| bytes[i..i + 2] == [0x02, 0x02] && bytes[i + 8] == 1)
+            .unwrap();
+        bytes[spec + 8] = 0;
+        assert!(matches!(
+            ResourceTable::parse(&bytes),
+            Err(AxmlError::InvalidChunk(offset, _)) if offset == spec
+        ));
+    }
+
+    #[test]
+    fn test_unused_resources() {
+        let table = ResourceTable::parse(&arsc_table()).unwrap();
+        // const v0, 0x7f010001 (string/second); return-void
+        let dex = build_dex(
+            vec![TestClass::new("Lcom/example/Main;").method(
+                "run",
+                &[],
+                "V",
+                &[0x0014, 0x0001, 0x7f01, 0x000e],
+            )],
+            &[],
+        );
+        let mut manifest = AxmlBuilder::new();
+        manifest.start("manifest").start("application");
+        manifest
+            .android("label", Value::Reference(0x7f010000))
+            .end()
+            .end();
+        let manifest_bytes = manifest.build();
+        let manifest = axml::parse(&manifest_bytes).unwrap();
+
+        let unused = table.unused_resources(
+            &[("classes.dex".to_string(), dex.clone())],
+            Some(&manifest),
+            &HashMap::new(),
+        );
+        assert_eq!(unused, vec!["layout/main"]);
+
+        let apk = build_zip(&[
+            ("AndroidManifest.xml", &manifest_bytes),
+            ("resources.arsc", &arsc_table()),
+            ("classes.dex", &serialize(dex.clone())),
+            ("res/raw/config.xml", b"<config/>"),
+            ("res/raw-v21/config.xml", b"<config/>"),
+        ]);
+        let unused = find_unused_resources(&mut Apk::from_bytes(apk).unwrap()).unwrap();
+        assert_eq!(unused, vec!["layout/main"]);
+    }
+
+    #[test]
+    fn test_id_by_r_field_name() {
+        let mut table = ResourceTable::parse(&arsc_table()).unwrap();
+        table.packages[0].types[1].chunks[0].entries[0].key = "Theme.App".to_string();
+        assert_eq!(table.id_by_name("layout", "Theme_App"), Some(0x7f020000));
+        assert_eq!(table.id_by_name("layout", "Theme.App"), Some(0x7f020000));
+        assert_eq!(table.id_by_name("layout", "ThemeApp"), None);
+    }
+}
@@ -0,0 +1,546 @@
+//! Decoder for Android binary XML (AXML), the compiled form of
+//! `AndroidManifest.xml` and of the XML resources inside an APK.
+
+use std::fmt::{self, Display, Formatter};
+
+use crate::protobuf::ProtobufError;
+
+const RES_STRING_POOL_TYPE: u16 = 0x0001;
+const RES_XML_TYPE: u16 = 0x0003;
+const RES_XML_START_NAMESPACE_TYPE: u16 = 0x0100;
+const RES_XML_END_NAMESPACE_TYPE: u16 = 0x0101;
+const RES_XML_START_ELEMENT_TYPE: u16 = 0x0102;
+const RES_XML_END_ELEMENT_TYPE: u16 = 0x0103;
+const RES_XML_CDATA_TYPE: u16 = 0x0104;
+const RES_XML_RESOURCE_MAP_TYPE: u16 = 0x0180;
+
+const UTF8_FLAG: u32 = 0x100;
+const NO_ENTRY: u32 = 0xffffffff;
+
+pub const ANDROID_NAMESPACE: &str = "http://schemas.android.com/apk/res/android";
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum AxmlError {
+    /// A read went past the end of the data.
+    Truncated(usize),
+    /// A chunk is malformed, e.g. has an unexpected type or size.
+    InvalidChunk(usize, String),
+    InvalidString(u32),
+    /// A bundle resource, which is protobuf rather than binary XML, failed
+    /// to decode.
+    InvalidProtobuf(ProtobufError),
+}
+
+impl From<ProtobufError> for AxmlError {
+    fn from(err: ProtobufError) -> Self {
+        AxmlError::InvalidProtobuf(err)
+    }
+}
+
+impl Display for AxmlError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            AxmlError::Truncated(offset) => write!(f, "truncated data at offset {:#x}", offset),
+            AxmlError::InvalidChunk(offset, reason) => {
+                write!(f, "invalid chunk at offset {:#x}: {}", offset, reason)
+            }
+            AxmlError::InvalidString(idx) => write!(f, "invalid string index {}", idx),
+            AxmlError::InvalidProtobuf(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+/// Bounds-checked little-endian reads over a byte slice.
+pub(crate) struct ByteReader<'a> {
+    pub(crate) bytes: &'a [u8],
+}
+
+impl<'a> ByteReader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        return Self { bytes };
+    }
+
+    pub(crate) fn slice(&self, offset: usize, len: usize) -> Result<&'a [u8], AxmlError> {
+        return offset
+            .checked_add(len)
+            .and_then(|end| self.bytes.get(offset..end))
+            .ok_or(AxmlError::Truncated(offset));
+    }
+
+    pub(crate) fn u8(&self, offset: usize) -> Result<u8, AxmlError> {
+        return Ok(self.slice(offset, 1)?[0]);
+    }
+
+    pub(crate) fn u16(&self, offset: usize) -> Result<u16, AxmlError> {
+        return Ok(u16::from_le_bytes(
+            self.slice(offset, 2)?.try_into().unwrap(),
+        ));
+    }
+
+    pub(crate) fn u32(&self, offset: usize) -> Result<u32, AxmlError> {
+        return Ok(u32::from_le_bytes(
+            self.slice(offset, 4)?.try_into().unwrap(),
+        ));
+    }
+}
+
+/// Header shared by every resource chunk.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ChunkHeader {
+    pub(crate) offset: usize,
+    pub(crate) chunk_type: u16,
+    pub(crate) header_size: usize,
+    pub(crate) size: usize,
+}
+
+impl ChunkHeader {
+    pub(crate) fn read(r: &ByteReader, offset: usize) -> Result<ChunkHeader, AxmlError> {
+        let header = ChunkHeader {
+            offset,
+            chunk_type: r.u16(offset)?,
+            header_size: r.u16(offset + 2)? as usize,
+            size: r.u32(offset + 4)? as usize,
+        };
+        if header.header_size < 8 || header.size < header.header_size {
+            return Err(AxmlError::InvalidChunk(
+                offset,
+                "bad chunk size".to_string(),
+            ));
+        }
+        r.slice(offset, header.size)?;
+        return Ok(header);
+    }
+
+    pub(crate) fn end(&self) -> usize {
+        return self.offset + self.size;
+    }
+
+    pub(crate) fn body(&self) -> usize {
+        return self.offset + self.header_size;
+    }
+}
+
+/// A decoded `ResStringPool`, shared with the resource table decoder.
+#[derive(Debug, Clone, Default)]
+pub struct StringPool {
+    pub strings: Vec<String>,
+}
+
+impl StringPool {
+    pub(crate) fn read(r: &ByteReader, chunk: &ChunkHeader) -> Result<StringPool, AxmlError> {
+        let base = chunk.offset;
+        let string_count = r.u32(base + 8)? as usize;
+        let flags = r.u32(base + 16)?;
+        let strings_start = r.u32(base + 20)? as usize;
+        let utf8 = flags & UTF8_FLAG != 0;
+
+        let mut strings = Vec::with_capacity(string_count.min(chunk.size / 4));
+        for i in 0..string_count {
+            let offset = base + strings_start + r.u32(chunk.body() + i * 4)? as usize;
+            strings.push(if utf8 {
+                read_utf8_string(r, offset)?
+            } else {
+                read_utf16_string(r, offset)?
+            });
+        }
+        return Ok(StringPool { strings });
+    }
+
+    pub fn get(&self, idx: u32) -> Option<&str> {
+        return self.strings.get(idx as usize).map(|s| s.as_str());
+    }
+}
+
+/// Reads a UTF-8 pool string: UTF-16 length, UTF-8 length, then the bytes.
+fn read_utf8_string(r: &ByteReader, mut offset: usize) -> Result<String, AxmlError> {
+    let length = |offset: &mut usize| -> Result<usize, AxmlError> {
+        let first = r.u8(*offset)? as usize;
+        *offset += 1;
+        if first & 0x80 == 0 {
+            return Ok(first);
+        }
+        let second = r.u8(*offset)? as usize;
+        *offset += 1;
+        return Ok(((first & 0x7f) << 8) | second);
+    };
+    length(&mut offset)?;
+    let len = length(&mut offset)?;
+    return Ok(String::from_utf8_lossy(r.slice(offset, len)?).into_owned());
+}
+
+/// Reads a UTF-16 pool string: length in code units, then the code units.
+fn read_utf16_string(r: &ByteReader, mut offset: usize) -> Result<String, AxmlError> {
+    let mut len = r.u16(offset)? as usize;
+    offset += 2;
+    if len & 0x8000 != 0 {
+        len = ((len & 0x7fff) << 16) | r.u16(offset)? as usize;
+        offset += 2;
+    }
+    let units = r
+        .slice(offset, len * 2)?
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .collect::<Vec<_>>();
+    return Ok(String::from_utf16_lossy(&units));
+}
+
+/// A typed attribute value (`Res_value`).
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    /// Resource id, e.g. `@string/app_name` as `0x7f0e001b`.
+    Reference(u32),
+    /// Theme attribute id, e.g. `?attr/colorPrimary`.
+    Attribute(u32),
+    String(String),
+    Float(f32),
+    Dimension(u32),
+    Fraction(u32),
+    Int(i32),
+    Hex(u32),
+    Boolean(bool),
+    Color(u32),
+    Other {
+        data_type: u8,
+        data: u32,
+    },
+}
+
+impl Value {
+    /// Decodes a `Res_value`; `raw` is the string of `TYPE_STRING` values.
+    pub(crate) fn from_res_value(data_type: u8, data: u32, raw: Option<&str>) -> Value {
+        return match data_type {
+            0x00 => Value::Null,
+            0x01 | 0x07 => Value::Reference(data),
+            0x02 | 0x08 => Value::Attribute(data),
+            0x03 => Value::String(raw.unwrap_or("").to_string()),
+            0x04 => Value::Float(f32::from_bits(data)),
+            0x05 => Value::Dimension(data),
+            0x06 => Value::Fraction(data),
+            0x10 => Value::Int(data as i32),
+            0x11 => Value::Hex(data),
+            0x12 => Value::Boolean(data != 0),
+            0x1c..=0x1f => Value::Color(data),
+            _ => Value::Other { data_type, data },
+        };
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        return match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        };
+    }
+
+    /// Integer value, also accepting decimal strings such as `"21"`.
+    pub fn as_int(&self) -> Option<i64> {
+        return match self {
+            Value::Int(i) => Some(*i as i64),
+            Value::Hex(i) => Some(*i as i64),
+            Value::String(s) => s.trim().parse().ok(),
+            _ => None,
+        };
+    }
+
+    /// Boolean value, also accepting `"true"` and `"false"` strings.
+    pub fn as_bool(&self) -> Option<bool> {
+        return match self {
+            Value::Boolean(b) => Some(*b),
+            Value::String(s) => s.parse().ok(),
+            _ => None,
+        };
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Value::Null => write!(f, "@null"),
+            Value::Reference(id) => write!(f, "@0x{:08x}", id),
+            Value::Attribute(id) => write!(f, "?0x{:08x}", id),
+            Value::String(s) => write!(f, "{}", s),
+            Value::Float(v) => write!(f, "{}", v),
+            Value::Dimension(v) | Value::Fraction(v) => write!(f, "0x{:08x}", v),
+            Value::Int(v) => write!(f, "{}", v),
+            Value::Hex(v) => write!(f, "0x{:x}", v),
+            Value::Boolean(v) => write!(f, "{}", v),
+            Value::Color(v) => write!(f, "#{:08x}", v),
+            Value::Other { data_type, data } => write!(f, "(0x{:02x})0x{:08x}", data_type, data),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct XmlAttribute {
+    pub namespace: Option<String>,
+    pub name: String,
+    /// Id of the framework attribute, from the resource map.
+    pub resource_id: Option<u32>,
+    pub value: Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct XmlElement {
+    pub namespace: Option<String>,
+    pub name: String,
+    pub line: u32,
+    pub attributes: Vec<XmlAttribute>,
+    pub children: Vec<XmlElement>,
+    pub text: Option<String>,
+}
+
+impl XmlElement {
+    /// Value of the attribute `name`, in any namespace.
+    pub fn attribute(&self, name: &str) -> Option<&Value> {
+        return self
+            .attributes
+            .iter()
+            .find(|a| a.name == name)
+            .map(|a| &a.value);
+    }
+
+    /// Value of the `android:` attribute `name`.
+    pub fn android_attribute(&self, name: &str) -> Option<&Value> {
+        return self
+            .attributes
+            .iter()
+            .find(|a| a.name == name && a.namespace.as_deref() == Some(ANDROID_NAMESPACE))
+            .map(|a| &a.value);
+    }
+
+    /// String value of the `android:` attribute `name`.
+    pub fn android_string(&self, name: &str) -> Option<String> {
+        return self.android_attribute(name).map(|v| v.to_string());
+    }
+
+    /// Direct children named `name`.
+    pub fn children_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a XmlElement> {
+        return self.children.iter().filter(move |c| c.name == name);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct XmlDocument {
+    /// Declared namespaces as `(prefix, uri)`.
+    pub namespaces: Vec<(String, String)>,
+    pub root: XmlElement,
+}
+
+/// Framework attribute ids and names. Obfuscated manifests may strip the
+/// attribute name strings and leave only the resource map.
+const FRAMEWORK_ATTRIBUTES: &[(u32, &str)] = &[
+    (0x01010000, "theme"),
+    (0x01010001, "label"),
+    (0x01010002, "icon"),
+    (0x01010003, "name"),
+    (0x01010006, "permission"),
+    (0x0101000e, "enabled"),
+    (0x0101000f, "debuggable"),
+    (0x01010010, "exported"),
+    (0x01010018, "authorities"),
+    (0x01010024, "value"),
+    (0x01010026, "mimeType"),
+    (0x01010027, "scheme"),
+    (0x01010028, "host"),
+    (0x01010029, "port"),
+    (0x0101002a, "path"),
+    (0x0101002b, "pathPrefix"),
+    (0x0101002c, "pathPattern"),
+    (0x01010202, "targetActivity"),
+    (0x0101020c, "minSdkVersion"),
+    (0x0101021b, "versionCode"),
+    (0x0101021c, "versionName"),
+    (0x01010270, "targetSdkVersion"),
+    (0x01010271, "maxSdkVersion"),
+    (0x01010280, "allowBackup"),
+    (0x010104ec, "usesCleartextTraffic"),
+    (0x01010527, "networkSecurityConfig"),
+    (0x01010572, "compileSdkVersion"),
+    (0x01010573, "compileSdkVersionCodename"),
+];
+
+pub(crate) fn framework_attribute_name(resource_id: u32) -> Option<&'static str> {
+    return FRAMEWORK_ATTRIBUTES
+        .iter()
+        .find(|(id, _)| *id == resource_id)
+        .map(|(_, name)| *name);
+}
+
+#[cfg(test)]
+pub(crate) fn framework_attribute_id(name: &str) -> Option<u32> {
+    return FRAMEWORK_ATTRIBUTES
+        .iter()
+        .find(|(_, n)| *n == name)
+        .map(|(id, _)| *id);
+}
+
+/// Decodes a binary XML document.
+pub fn parse(bytes: &[u8]) -> Result<XmlDocument, AxmlError> {
+    let r = ByteReader::new(bytes);
+    let document = ChunkHeader::read(&r, 0)?;
+    if document.chunk_type != RES_XML_TYPE {
+        return Err(AxmlError::InvalidChunk(
+            0,
+            "not a binary XML file".to_string(),
+        ));
+    }
+
+    let mut strings = StringPool::default();
+    let mut resource_ids: Vec<u32> = vec![];
+    let mut namespaces = vec![];
+    // Elements being built; the bottom one collects the root element.
+    let mut stack = vec![XmlElement::default()];
+
+    let string = |strings: &StringPool, idx: u32| -> Result<Option<String>, AxmlError> {
+        if idx == NO_ENTRY {
+            return Ok(None);
+        }
+        return match strings.get(idx) {
+            Some(s) => Ok(Some(s.to_string())),
+            None => Err(AxmlError::InvalidString(idx)),
+        };
+    };
+
+    let mut offset = document.body();
+    while offset < document.end() {
+        let chunk = ChunkHeader::read(&r, offset)?;
+        let body = chunk.body();
+        match chunk.chunk_type {
+            RES_STRING_POOL_TYPE => strings = StringPool::read(&r, &chunk)?,
+            RES_XML_RESOURCE_MAP_TYPE => {
+                resource_ids = (body..chunk.end())
+                    .step_by(4)
+                    .map(|o| r.u32(o))
+                    .collect::<Result<_, _>>()?;
+            }
+            RES_XML_START_NAMESPACE_TYPE => {
+                let prefix = string(&strings, r.u32(body)?)?.unwrap_or_default();
+                let uri = string(&strings, r.u32(body + 4)?)?.unwrap_or_default();
+                namespaces.push((prefix, uri));
+            }
+            RES_XML_END_NAMESPACE_TYPE => {}
+            RES_XML_START_ELEMENT_TYPE => {
+                let attribute_start = r.u16(body + 8)? as usize;
+                let attribute_size = r.u16(body + 10)? as usize;
+                let attribute_count = r.u16(body + 12)? as usize;
+                let mut element = XmlElement {
+                    namespace: string(&strings, r.u32(body)?)?,
+                    name: string(&strings, r.u32(body + 4)?)?.unwrap_or_default(),
+                    line: r.u32(chunk.offset + 8)?,
+                    ..Default::default()
+                };
+                for i in 0..attribute_count {
+                    let a = body + attribute_start + i * attribute_size;
+                    let name_idx = r.u32(a + 4)?;
+                    let raw_value = string(&strings, r.u32(a + 8)?)?;
+                    let resource_id = resource_ids.get(name_idx as usize).copied();
+                    let mut name = string(&strings, name_idx)?.unwrap_or_default();
+                    if name.is_empty() {
+                        if let Some(known) = resource_id.and_then(framework_attribute_name) {
+                            name = known.to_string();
+                        }
+                    }
+                    element.attributes.push(XmlAttribute {
+                        namespace: string(&strings, r.u32(a)?)?,
+                        name,
+                        resource_id,
+                        value: Value::from_res_value(
+                            r.u8(a + 15)?,
+                            r.u32(a + 16)?,
+                            raw_value.as_deref(),
+                        ),
+                    });
+                }
+                stack.push(element);
+            }
+            RES_XML_END_ELEMENT_TYPE => {
+                if stack.len() < 2 {
+                    return Err(AxmlError::InvalidChunk(
+                        offset,
+                        "unbalanced end element".to_string(),
+                    ));
+                }
+                let element = stack.pop().unwrap();
+                stack.last_mut().unwrap().children.push(element);
+            }
+            RES_XML_CDATA_TYPE => {
+                let text = string(&strings, r.u32(body)?)?.unwrap_or_default();
+                if let Some(element) = stack.last_mut() {
+                    element.text.get_or_insert_with(String::new).push_str(&text);
+                }
+            }
+            // Unknown chunks are skipped, as the platform does.
+            _ => {}
+        }
+        offset = chunk.end();
+    }
+
+    if stack.len() != 1 {
+        return Err(AxmlError::InvalidChunk(
+            offset,
+            "unclosed element".to_string(),
+        ));
+    }
+    let root = stack
+        .pop()
+        .unwrap()
+        .children
+        .into_iter()
+        .next()
+        .unwrap_or_default();
+    return Ok(XmlDocument { namespaces, root });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::AxmlBuilder;
+
+    #[test]
+    fn test_parse() {
+        let mut builder = AxmlBuilder::new();
+        builder
+            .start("manifest")
+            .attr("package", Value::String("com.example".to_string()))
+            .android("versionCode", Value::Int(7))
+            .start("application")
+            .android("debuggable", Value::Boolean(true))
+            .end()
+            .end();
+        let document = parse(&builder.build()).unwrap();
+
+        assert_eq!(
+            document.namespaces,
+            vec![("android".to_string(), ANDROID_NAMESPACE.to_string())]
+        );
+        let manifest = &document.root;
+        assert_eq!(manifest.name, "manifest");
+        assert_eq!(
+            manifest.attribute("package").unwrap().as_str(),
+            Some("com.example")
+        );
+        assert_eq!(
+            manifest.android_attribute("versionCode"),
+            Some(&Value::Int(7))
+        );
+        let application = manifest.children_named("application").next().unwrap();
+        assert_eq!(
+            application
+                .android_attribute("debuggable")
+                .unwrap()
+                .as_bool(),
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn test_truncated() {
+        let mut builder = AxmlBuilder::new();
+        builder.start("manifest").end();
+        let bytes = builder.build();
+        assert!(matches!(
+            parse(&bytes[..bytes.len() - 4]),
+            Err(AxmlError::Truncated(_))
+        ));
+    }
+}
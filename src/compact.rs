@@ -0,0 +1,613 @@
+//! Drops the ids and data items a `DexModel` no longer references, e.g.
+//! after classes were taken out of it, and renumbers what remains.
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use crate::{
+    dex_model::DexModel,
+    dex_structs::{ClassDefItem, TypeCode},
+    instructions::IndexKind,
+    layout::{relayout_from, ItemOffsets},
+    remap::{remap_indices, remap_pool_indices, METHOD_HANDLE_TYPE_INVOKE_STATIC},
+    resolver::NO_INDEX,
+};
+
+/// Keeps the items of `type_code` whose offset is in `live`, together with
+/// their entries in `offsets`.
+fn retain_live<T>(
+    items: &mut Vec<T>,
+    offsets: &mut ItemOffsets,
+    type_code: TypeCode,
+    live: &HashSet<(TypeCode, u32)>,
+) {
+    let section = offsets.sections.entry(type_code).or_default();
+    let mut kept = vec![];
+    let mut kept_offsets = vec![];
+    for (item, offset) in std::mem::take(items).into_iter().zip(section.iter()) {
+        if live.contains(&(type_code, *offset)) {
+            kept.push(item);
+            kept_offsets.push(*offset);
+        }
+    }
+    *items = kept;
+    *section = kept_offsets;
+}
+
+/// Copies of the items of `type_code` whose offset is in `live`, narrowing
+/// the section in `offsets` to them.
+fn clone_live<T: Clone>(
+    items: &[T],
+    offsets: &mut ItemOffsets,
+    type_code: TypeCode,
+    live: &HashSet<(TypeCode, u32)>,
+) -> Vec<T> {
+    let section = offsets.sections.entry(type_code).or_default();
+    let mut kept = vec![];
+    let mut kept_offsets = vec![];
+    for (item, offset) in items.iter().zip(section.iter()) {
+        if live.contains(&(type_code, *offset)) {
+            kept.push(item.clone());
+            kept_offsets.push(*offset);
+        }
+    }
+    *section = kept_offsets;
+    return kept;
+}
+
+/// Whether each item of a section is live, in section order.
+fn live_offsets(
+    offsets: &ItemOffsets,
+    type_code: TypeCode,
+    live: &HashSet<(TypeCode, u32)>,
+) -> Vec<bool> {
+    return offsets
+        .get(type_code)
+        .iter()
+        .map(|offset| live.contains(&(type_code, *offset)))
+        .collect();
+}
+
+fn mark(live: &mut HashSet<(TypeCode, u32)>, type_code: TypeCode, offset: u32) {
+    if offset != 0 {
+        live.insert((type_code, offset));
+    }
+}
+
+/// Ids referenced from a model, per pool.
+#[derive(Default)]
+struct UsedIds {
+    ids: BTreeMap<IndexKind, BTreeSet<u32>>,
+}
+
+impl UsedIds {
+    fn insert(&mut self, kind: IndexKind, index: u32) {
+        self.ids.entry(kind).or_default().insert(index);
+    }
+
+    fn contains(&self, kind: IndexKind, index: usize) -> bool {
+        return self
+            .ids
+            .get(&kind)
+            .is_some_and(|ids| ids.contains(&(index as u32)));
+    }
+
+    /// New index of each entry of a pool of `len` ids, or `NO_INDEX` when
+    /// the entry is unused.
+    fn ranks(&self, kind: IndexKind, len: usize) -> Vec<u32> {
+        let mut next = 0;
+        return (0..len)
+            .map(|index| {
+                if self.contains(kind, index) {
+                    next += 1;
+                    next - 1
+                } else {
+                    NO_INDEX
+                }
+            })
+            .collect();
+    }
+}
+
+fn clone_used<T: Clone>(items: &[T], ranks: &[u32]) -> Vec<T> {
+    return items
+        .iter()
+        .zip(ranks)
+        .filter(|(_, rank)| **rank != NO_INDEX)
+        .map(|(item, _)| item.clone())
+        .collect();
+}
+
+fn retain_used<T>(items: &mut Vec<T>, ranks: &[u32]) {
+    let mut i = 0;
+    items.retain(|_| {
+        i += 1;
+        ranks[i - 1] != NO_INDEX
+    });
+}
+
+/// Removes every data item that no class definition reaches and every call
+/// site that no remaining code invokes, then every id that no remaining
+/// item references, renumbering the pools and laying the file out again.
+/// Hidden API flags are dropped since they are indexed by class.
+pub fn compact(dex: &mut DexModel) {
+    let offsets = ItemOffsets::of(dex);
+    compact_from(dex, offsets);
+}
+
+/// Like `compact`, for a model whose stored offsets refer to the items at
+/// `offsets` rather than to its map list, e.g. one with items added since
+/// it was read.
+pub(crate) fn compact_from(dex: &mut DexModel, mut offsets: ItemOffsets) {
+    let (live, call_sites) = reachable(dex, &dex.class_defs, &offsets);
+    retain_live(
+        &mut dex.class_data_items,
+        &mut offsets,
+        TypeCode::TypeClassDataItem,
+        &live,
+    );
+    retain_live(
+        &mut dex.code_items,
+        &mut offsets,
+        TypeCode::TypeCodeItem,
+        &live,
+    );
+    retain_live(
+        &mut dex.debug_info_items,
+        &mut offsets,
+        TypeCode::TypeDebugInfoItem,
+        &live,
+    );
+    retain_live(
+        &mut dex.encoded_array_items,
+        &mut offsets,
+        TypeCode::TypeEncodedArrayItem,
+        &live,
+    );
+    retain_live(
+        &mut dex.annotations_directory_items,
+        &mut offsets,
+        TypeCode::TypeAnnotationsDirectoryItem,
+        &live,
+    );
+    retain_live(
+        &mut dex.annotation_set_ref_lists,
+        &mut offsets,
+        TypeCode::TypeAnnotationSetRefList,
+        &live,
+    );
+    retain_live(
+        &mut dex.annotation_set_items,
+        &mut offsets,
+        TypeCode::TypeAnnotationSetItem,
+        &live,
+    );
+    retain_live(
+        &mut dex.annotation_items,
+        &mut offsets,
+        TypeCode::TypeAnnotationItem,
+        &live,
+    );
+    let call_sites = call_sites.ranks(IndexKind::CallSite, dex.call_site_ids.len());
+    retain_used(&mut dex.call_site_ids, &call_sites);
+    compact_ids(dex, offsets, &call_sites);
+}
+
+/// A compacted copy of `dex` defining only `class_defs`, which clones only
+/// the data items and call sites those classes reach.
+pub(crate) fn compact_copy(dex: &DexModel, class_defs: Vec<ClassDefItem>) -> DexModel {
+    let mut offsets = ItemOffsets::of(dex);
+    let (live, call_sites) = reachable(dex, &class_defs, &offsets);
+    let call_sites = call_sites.ranks(IndexKind::CallSite, dex.call_site_ids.len());
+    let mut out = DexModel {
+        header: dex.header,
+        string_ids: dex.string_ids.clone(),
+        type_ids: dex.type_ids.clone(),
+        proto_ids: dex.proto_ids.clone(),
+        field_ids: dex.field_ids.clone(),
+        method_ids: dex.method_ids.clone(),
+        class_defs,
+        call_site_ids: clone_used(&dex.call_site_ids, &call_sites),
+        method_handles: dex.method_handles.clone(),
+        type_lists: dex.type_lists.clone(),
+        string_data_items: dex.string_data_items.clone(),
+        annotation_set_ref_lists: clone_live(
+            &dex.annotation_set_ref_lists,
+            &mut offsets,
+            TypeCode::TypeAnnotationSetRefList,
+            &live,
+        ),
+        annotation_set_items: clone_live(
+            &dex.annotation_set_items,
+            &mut offsets,
+            TypeCode::TypeAnnotationSetItem,
+            &live,
+        ),
+        annotation_items: clone_live(
+            &dex.annotation_items,
+            &mut offsets,
+            TypeCode::TypeAnnotationItem,
+            &live,
+        ),
+        annotations_directory_items: clone_live(
+            &dex.annotations_directory_items,
+            &mut offsets,
+            TypeCode::TypeAnnotationsDirectoryItem,
+            &live,
+        ),
+        hiddenapi_class_data_items: vec![],
+        encoded_array_items: clone_live(
+            &dex.encoded_array_items,
+            &mut offsets,
+            TypeCode::TypeEncodedArrayItem,
+            &live,
+        ),
+        class_data_items: clone_live(
+            &dex.class_data_items,
+            &mut offsets,
+            TypeCode::TypeClassDataItem,
+            &live,
+        ),
+        debug_info_items: clone_live(
+            &dex.debug_info_items,
+            &mut offsets,
+            TypeCode::TypeDebugInfoItem,
+            &live,
+        ),
+        code_items: clone_live(&dex.code_items, &mut offsets, TypeCode::TypeCodeItem, &live),
+        link_data: dex.link_data.clone(),
+        map_list: dex.map_list.clone(),
+    };
+    compact_ids(&mut out, offsets, &call_sites);
+    return out;
+}
+
+/// The data items reachable from `class_defs`, and the call sites invoked
+/// by their code.
+fn reachable(
+    dex: &DexModel,
+    class_defs: &[ClassDefItem],
+    offsets: &ItemOffsets,
+) -> (HashSet<(TypeCode, u32)>, UsedIds) {
+    let mut live = HashSet::new();
+    let mut call_sites = UsedIds::default();
+
+    for class_def in class_defs.iter() {
+        mark(
+            &mut live,
+            TypeCode::TypeAnnotationsDirectoryItem,
+            class_def.annotations_off,
+        );
+        mark(
+            &mut live,
+            TypeCode::TypeClassDataItem,
+            class_def.class_data_off,
+        );
+        mark(
+            &mut live,
+            TypeCode::TypeEncodedArrayItem,
+            class_def.static_values_off,
+        );
+    }
+    let is_live = live_offsets(offsets, TypeCode::TypeClassDataItem, &live);
+    for (class_data, _) in dex.class_data_items.iter().zip(is_live).filter(|x| x.1) {
+        let methods = class_data
+            .direct_methods
+            .iter()
+            .chain(class_data.virtual_methods.iter());
+        for method in methods {
+            mark(&mut live, TypeCode::TypeCodeItem, method.code_off);
+        }
+    }
+    let is_live = live_offsets(offsets, TypeCode::TypeCodeItem, &live);
+    for (code_item, _) in dex.code_items.iter().zip(is_live).filter(|x| x.1) {
+        mark(
+            &mut live,
+            TypeCode::TypeDebugInfoItem,
+            code_item.debug_info_off,
+        );
+        for insn in code_item.insns.iter() {
+            for index_ref in insn.index_refs() {
+                if index_ref.kind == IndexKind::CallSite {
+                    call_sites.insert(IndexKind::CallSite, index_ref.index);
+                }
+            }
+        }
+    }
+    for (i, call_site) in dex.call_site_ids.iter().enumerate() {
+        if call_sites.contains(IndexKind::CallSite, i) {
+            mark(
+                &mut live,
+                TypeCode::TypeEncodedArrayItem,
+                call_site.call_site_off,
+            );
+        }
+    }
+    let is_live = live_offsets(offsets, TypeCode::TypeAnnotationsDirectoryItem, &live);
+    for (directory, _) in dex
+        .annotations_directory_items
+        .iter()
+        .zip(is_live)
+        .filter(|x| x.1)
+    {
+        let sets = directory
+            .field_annotations
+            .iter()
+            .map(|a| a.annotations_off)
+            .chain(
+                directory
+                    .method_annotations
+                    .iter()
+                    .map(|a| a.annotations_off),
+            )
+            .chain([directory.class_annotations_off]);
+        for set in sets {
+            mark(&mut live, TypeCode::TypeAnnotationSetItem, set);
+        }
+        for parameter in directory.parameter_annotations.iter() {
+            mark(
+                &mut live,
+                TypeCode::TypeAnnotationSetRefList,
+                parameter.annotations_off,
+            );
+        }
+    }
+    let is_live = live_offsets(offsets, TypeCode::TypeAnnotationSetRefList, &live);
+    for (ref_list, _) in dex
+        .annotation_set_ref_lists
+        .iter()
+        .zip(is_live)
+        .filter(|x| x.1)
+    {
+        for item in ref_list.list.iter() {
+            mark(
+                &mut live,
+                TypeCode::TypeAnnotationSetItem,
+                item.annotations_off,
+            );
+        }
+    }
+    let is_live = live_offsets(offsets, TypeCode::TypeAnnotationSetItem, &live);
+    for (set, _) in dex.annotation_set_items.iter().zip(is_live).filter(|x| x.1) {
+        for entry in set.entries.iter() {
+            mark(
+                &mut live,
+                TypeCode::TypeAnnotationItem,
+                entry.annotation_off,
+            );
+        }
+    }
+    return (live, call_sites);
+}
+
+/// Renumbers the pools of a model whose data items have been pruned,
+/// keeping the ids the remaining items reference and the call sites that
+/// `call_sites` ranks, and lays it out again.
+fn compact_ids(dex: &mut DexModel, mut offsets: ItemOffsets, call_sites: &[u32]) {
+    dex.hiddenapi_class_data_items.clear();
+    offsets
+        .sections
+        .remove(&TypeCode::TypeHiddenapiClassDataItem);
+
+    // Ids referenced by the remaining items, then by the ids themselves.
+    // Type lists are left out, since dead protos still point at theirs.
+    let mut used = UsedIds::default();
+    let type_lists = std::mem::take(&mut dex.type_lists);
+    remap_indices(dex, &mut |kind, index| {
+        used.insert(kind, index);
+        return index;
+    })
+    .unwrap();
+    dex.type_lists = type_lists;
+    let type_list_index = offsets.index();
+    let use_type_list = |used: &mut UsedIds, offset| {
+        if let Some(i) = type_list_index.get(&(TypeCode::TypeTypeList, offset)) {
+            for item in dex.type_lists[*i].list.iter() {
+                used.insert(IndexKind::Type, item.type_idx as u32);
+            }
+        }
+    };
+    for class_def in dex.class_defs.iter() {
+        use_type_list(&mut used, class_def.interfaces_off);
+    }
+    for (i, handle) in dex.method_handles.iter().enumerate() {
+        if used.contains(IndexKind::MethodHandle, i) {
+            let kind = if handle.method_handle_type < METHOD_HANDLE_TYPE_INVOKE_STATIC {
+                IndexKind::Field
+            } else {
+                IndexKind::Method
+            };
+            used.insert(kind, handle.field_or_method_id as u32);
+        }
+    }
+    for (i, method) in dex.method_ids.iter().enumerate() {
+        if used.contains(IndexKind::Method, i) {
+            used.insert(IndexKind::Type, method.class_idx as u32);
+            used.insert(IndexKind::Proto, method.proto_idx as u32);
+            used.insert(IndexKind::String, method.name_idx);
+        }
+    }
+    for (i, field) in dex.field_ids.iter().enumerate() {
+        if used.contains(IndexKind::Field, i) {
+            used.insert(IndexKind::Type, field.class_idx as u32);
+            used.insert(IndexKind::Type, field.type_idx as u32);
+            used.insert(IndexKind::String, field.name_idx);
+        }
+    }
+    for (i, proto) in dex.proto_ids.iter().enumerate() {
+        if used.contains(IndexKind::Proto, i) {
+            used.insert(IndexKind::String, proto.shorty_idx);
+            used.insert(IndexKind::Type, proto.return_type_idx);
+            use_type_list(&mut used, proto.parameters_off);
+        }
+    }
+    for (i, type_id) in dex.type_ids.iter().enumerate() {
+        if used.contains(IndexKind::Type, i) {
+            used.insert(IndexKind::String, type_id.descriptor_idx);
+        }
+    }
+
+    // Renumber the pools.
+    let strings = used.ranks(IndexKind::String, dex.string_ids.len());
+    let types = used.ranks(IndexKind::Type, dex.type_ids.len());
+    let protos = used.ranks(IndexKind::Proto, dex.proto_ids.len());
+    let fields = used.ranks(IndexKind::Field, dex.field_ids.len());
+    let methods = used.ranks(IndexKind::Method, dex.method_ids.len());
+    let method_handles = used.ranks(IndexKind::MethodHandle, dex.method_handles.len());
+    retain_used(&mut dex.string_ids, &strings);
+    retain_used(&mut dex.type_ids, &types);
+    retain_used(&mut dex.proto_ids, &protos);
+    retain_used(&mut dex.field_ids, &fields);
+    retain_used(&mut dex.method_ids, &methods);
+    retain_used(&mut dex.method_handles, &method_handles);
+    let mut map = |kind, index: u32| {
+        let ranks = match kind {
+            IndexKind::String => &strings,
+            IndexKind::Type => &types,
+            IndexKind::Proto => &protos,
+            IndexKind::Field => &fields,
+            IndexKind::Method => &methods,
+            IndexKind::MethodHandle => &method_handles,
+            IndexKind::CallSite => call_sites,
+        };
+        return ranks[index as usize];
+    };
+    remap_pool_indices(dex, &mut map);
+    // Indices only shrink, so every operand still fits.
+    remap_indices(dex, &mut map).unwrap();
+
+    // Type lists and string data, which the ids reach.
+    let mut live = HashSet::new();
+    for class_def in dex.class_defs.iter() {
+        mark(&mut live, TypeCode::TypeTypeList, class_def.interfaces_off);
+    }
+    for proto in dex.proto_ids.iter() {
+        mark(&mut live, TypeCode::TypeTypeList, proto.parameters_off);
+    }
+    for string_id in dex.string_ids.iter() {
+        mark(
+            &mut live,
+            TypeCode::TypeStringDataItem,
+            string_id.string_data_off,
+        );
+    }
+    retain_live(
+        &mut dex.type_lists,
+        &mut offsets,
+        TypeCode::TypeTypeList,
+        &live,
+    );
+    retain_live(
+        &mut dex.string_data_items,
+        &mut offsets,
+        TypeCode::TypeStringDataItem,
+        &live,
+    );
+
+    relayout_from(dex, offsets);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        deserialize_vec,
+        dex_structs::{CallSiteIdItem, EncodedArray, EncodedArrayItem, EncodedValue},
+        resolver::DexResolver,
+        serialize,
+        test_utils::{build_dex, TestClass},
+    };
+
+    #[test]
+    fn test_compact() {
+        let mut dex = build_dex(
+            vec![
+                TestClass::new("La/A;")
+                    .field("count", "I")
+                    // const-string v0, "unused"; return-void
+                    .method("run", &[], "V", &[0x001a, 0x000d, 0x000e]),
+                TestClass::new("Lb/B;")
+                    .field("secret", "Ljava/lang/String;")
+                    .method("hidden", &["J"], "Z", &[0x000e]),
+            ],
+            &["unused"],
+        );
+        assert_eq!(DexResolver::new(&dex).string(0x0d), "unused");
+        let b = DexResolver::new(&dex)
+            .class_def_by_descriptor("Lb/B;")
+            .unwrap()
+            .class_idx;
+        dex.class_defs.retain(|c| c.class_idx != b);
+        compact(&mut dex);
+
+        let dex = deserialize_vec(serialize(dex)).unwrap();
+        let resolver = DexResolver::new(&dex);
+        assert_eq!(
+            resolver.strings(),
+            &[
+                "I",
+                "La/A;",
+                "Ljava/lang/Object;",
+                "V",
+                "count",
+                "run",
+                "unused"
+            ]
+        );
+        assert_eq!(dex.proto_ids.len(), 1);
+        assert_eq!(dex.code_items.len(), 1);
+        let class = resolver.class_def_by_descriptor("La/A;").unwrap();
+        let (method_idx, method) = resolver.class_methods(class)[0];
+        assert_eq!(resolver.method_ref(method_idx).signature(), "La/A;->run()V");
+        let code = resolver.code_item(method.code_off).unwrap();
+        assert_eq!(
+            resolver.string(code.insns[0].index_refs()[0].index),
+            "unused"
+        );
+        let (field_idx, _) = resolver.class_fields(class)[0];
+        assert_eq!(resolver.field_ref(field_idx).signature(), "La/A;->count:I");
+    }
+
+    #[test]
+    fn test_compact_call_sites() {
+        // invoke-custom {}, call_site@1; return-void
+        let mut dex = build_dex(
+            vec![TestClass::new("La/A;").method(
+                "run",
+                &[],
+                "V",
+                &[0x00fc, 0x0001, 0x0000, 0x000e],
+            )],
+            &["dead", "live"],
+        );
+        let resolver = DexResolver::new(&dex);
+        let strings = ["dead", "live"]
+            .map(|s| resolver.strings().iter().position(|t| t == s).unwrap() as u32);
+        let mut offsets = ItemOffsets::of(&dex);
+        for string_idx in strings {
+            dex.encoded_array_items.push(EncodedArrayItem {
+                value: EncodedArray {
+                    values: vec![EncodedValue::ValueString(string_idx)],
+                },
+            });
+            let call_site_off = offsets.append(TypeCode::TypeEncodedArrayItem);
+            dex.call_site_ids.push(CallSiteIdItem { call_site_off });
+        }
+        compact_from(&mut dex, offsets);
+
+        let dex = deserialize_vec(serialize(dex)).unwrap();
+        let resolver = DexResolver::new(&dex);
+        assert_eq!(dex.call_site_ids.len(), 1);
+        assert!(!resolver.strings().iter().any(|s| s == "dead"));
+        let class = resolver.class_def_by_descriptor("La/A;").unwrap();
+        let (_, method) = resolver.class_methods(class)[0];
+        let code = resolver.code_item(method.code_off).unwrap();
+        assert_eq!(code.insns[0].index_refs()[0].index, 0);
+        let array = resolver
+            .encoded_array(dex.call_site_ids[0].call_site_off)
+            .unwrap();
+        match array.value.values[0] {
+            EncodedValue::ValueString(i) => assert_eq!(resolver.string(i), "live"),
+            ref value => panic!("unexpected {:?}", value),
+        }
+    }
+}
@@ -0,0 +1,161 @@
+//! Tracks which registers hold constants while walking a method's
+//! instructions, so that analyses can recover e.g. the string passed to a
+//! call.
+
+use std::collections::HashMap;
+
+use crate::{
+    instructions::{IndexKind, Instruction},
+    opcodes::{opcode_info, CONST},
+    resolver::DexResolver,
+};
+
+/// A constant loaded into a register.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum ConstValue {
+    String(String),
+    Int(i64),
+    /// A `const-class`, as a type descriptor.
+    Class(String),
+}
+
+impl ConstValue {
+    pub(crate) fn as_str(&self) -> Option<&str> {
+        return match self {
+            ConstValue::String(s) => Some(s),
+            _ => None,
+        };
+    }
+
+    pub(crate) fn as_int(&self) -> Option<i64> {
+        return match self {
+            ConstValue::Int(i) => Some(*i),
+            _ => None,
+        };
+    }
+}
+
+/// A straight-line approximation of constant propagation: constants flow
+/// through `move` instructions and are forgotten when their register is
+/// overwritten. Control flow is ignored, so a value reaching an instruction
+/// along one path only is still reported.
+#[derive(Debug, Default)]
+pub(crate) struct ConstTracker {
+    registers: HashMap<u16, ConstValue>,
+}
+
+impl ConstTracker {
+    pub(crate) fn new() -> Self {
+        return Self::default();
+    }
+
+    /// Updates the register state with the effect of `insn`.
+    pub(crate) fn step(&mut self, insn: &Instruction, resolver: &DexResolver) {
+        let info = opcode_info(insn.opcode());
+        // A wide write also clobbers the high half, vA+1.
+        if let (2, Some(dest)) = (info.register_width(0), insn.written_register()) {
+            if let Some(high) = dest.checked_add(1) {
+                self.registers.remove(&high);
+            }
+        }
+        if let Some((dest, src)) = insn.move_registers() {
+            match self.registers.get(&src).cloned() {
+                Some(value) => self.registers.insert(dest, value),
+                None => self.registers.remove(&dest),
+            };
+            return;
+        }
+        let dest = match insn.written_register() {
+            Some(dest) => dest,
+            None => return,
+        };
+        let index = insn.index_refs().first().map(|r| r.index);
+        let value = match (info.has(CONST), info.index_kind, index) {
+            (true, Some(IndexKind::String), Some(index)) => {
+                Some(ConstValue::String(resolver.string(index).to_string()))
+            }
+            (true, Some(IndexKind::Type), Some(index)) => Some(ConstValue::Class(
+                resolver.type_descriptor(index).to_string(),
+            )),
+            _ => insn.literal().map(ConstValue::Int),
+        };
+        match value {
+            Some(value) => self.registers.insert(dest, value),
+            None => self.registers.remove(&dest),
+        };
+    }
+
+    /// Records a value the caller derived itself, e.g. the class returned by
+    /// a `Class.forName` call with a constant name.
+    pub(crate) fn set(&mut self, register: u16, value: ConstValue) {
+        self.registers.insert(register, value);
+    }
+
+    pub(crate) fn get(&self, register: u16) -> Option<&ConstValue> {
+        return self.registers.get(&register);
+    }
+
+    /// Constant held by each argument register of an invoke, in order.
+    pub(crate) fn arguments(&self, insn: &Instruction) -> Vec<Option<&ConstValue>> {
+        return insn
+            .invoke_registers()
+            .iter()
+            .map(|r| self.get(*r))
+            .collect();
+    }
+}
+
+/// Register offset of each declared parameter within the argument registers
+/// of an invoke: `long` and `double` parameters take two registers, and
+/// instance methods pass the receiver first.
+pub(crate) fn parameter_registers(parameters: &[String], is_static: bool) -> Vec<usize> {
+    let mut offset = if is_static { 0 } else { 1 };
+    let mut offsets = vec![];
+    for parameter in parameters {
+        offsets.push(offset);
+        offset += if parameter == "J" || parameter == "D" {
+            2
+        } else {
+            1
+        };
+    }
+    return offsets;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{build_dex, insns};
+
+    #[test]
+    fn test_const_tracking() {
+        let dex = build_dex(vec![], &["DES"]);
+        let resolver = DexResolver::new(&dex);
+        // const-string v1, "DES"; move-object v2, v1; const/4 v1, 3;
+        // invoke-static {v2, v1}, method@0
+        let code = insns(&[0x011a, 0x0000, 0x1207, 0x3112, 0x2071, 0x0000, 0x0012]);
+        let mut tracker = ConstTracker::new();
+        for insn in code.iter().take(3) {
+            tracker.step(insn, &resolver);
+        }
+        assert_eq!(
+            tracker.arguments(&code[3]),
+            vec![
+                Some(&ConstValue::String("DES".to_string())),
+                Some(&ConstValue::Int(3))
+            ]
+        );
+        // const-string v1, "DES"; const-wide/16 v0, 5; invoke-static {v1}, method@0
+        let code = insns(&[0x011a, 0x0000, 0x0016, 0x0005, 0x1071, 0x0000, 0x0001]);
+        let mut tracker = ConstTracker::new();
+        for insn in code.iter().take(2) {
+            tracker.step(insn, &resolver);
+        }
+        assert_eq!(tracker.get(0), Some(&ConstValue::Int(5)));
+        assert_eq!(tracker.arguments(&code[2]), vec![None]);
+        assert_eq!(
+            parameter_registers(&["J".to_string(), "I".to_string()], false),
+            vec![1, 3]
+        );
+    }
+}
@@ -0,0 +1,701 @@
+//! Rule-based detection of calls to sensitive APIs, such as running shell
+//! commands, loading code at runtime or using weak cryptography.
+//!
+//! Rules are data: the built-in set lives in `rules/dangerous_apis.toml`,
+//! and further rules can be loaded from TOML or JSON files with the same
+//! layout.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::{self, Display, Formatter},
+    fs, io,
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    const_tracker::{parameter_registers, ConstTracker, ConstValue},
+    dex_model::DexModel,
+    dex_structs::CodeItem,
+    findings::{Baseline, Issue},
+    instructions::IndexKind,
+    opcodes::{opcode_info, CONTINUE, INVOKE, STATIC, THROW},
+    resolver::{descriptor_to_java, java_to_descriptor, DexResolver, NO_INDEX},
+};
+
+const BUILTIN_RULES: &str = include_str!("../rules/dangerous_apis.toml");
+
+#[derive(Debug)]
+pub enum RuleError {
+    FileOpenError(io::Error),
+    TomlError(toml::de::Error),
+    JsonError(serde_json::Error),
+    InvalidRule(String),
+}
+
+impl From<io::Error> for RuleError {
+    fn from(err: io::Error) -> Self {
+        RuleError::FileOpenError(err)
+    }
+}
+
+impl From<toml::de::Error> for RuleError {
+    fn from(err: toml::de::Error) -> Self {
+        RuleError::TomlError(err)
+    }
+}
+
+impl From<serde_json::Error> for RuleError {
+    fn from(err: serde_json::Error) -> Self {
+        RuleError::JsonError(err)
+    }
+}
+
+impl Display for RuleError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            RuleError::FileOpenError(err) => write!(f, "{}", err),
+            RuleError::TomlError(err) => write!(f, "{}", err),
+            RuleError::JsonError(err) => write!(f, "{}", err),
+            RuleError::InvalidRule(reason) => write!(f, "invalid rule: {}", reason),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Low,
+    Medium,
+    High,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let name = match self {
+            Severity::Info => "info",
+            Severity::Low => "low",
+            Severity::Medium => "medium",
+            Severity::High => "high",
+        };
+        return write!(f, "{}", name);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleKind {
+    /// Calls to a method.
+    Invoke,
+    /// Reads and writes of a field.
+    Field,
+    /// Overrides of a method whose body neither throws nor calls anything.
+    Override,
+}
+
+/// One entry of a rule file; see `rules/dangerous_apis.toml` for the
+/// meaning of each key.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rule {
+    pub id: String,
+    pub severity: Severity,
+    pub message: String,
+    pub kind: RuleKind,
+    /// Java name of the declaring class, also matching subclasses defined in
+    /// the scanned dex files.
+    #[serde(default)]
+    pub class: Option<String>,
+    #[serde(default)]
+    pub member: Option<String>,
+    /// Smali prototype of a method or type descriptor of a field.
+    #[serde(default)]
+    pub descriptor: Option<String>,
+    /// Declared parameter that must hold a matching constant.
+    #[serde(default)]
+    pub argument: Option<usize>,
+    /// Glob patterns, one of which a string argument must match.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub strings: Vec<String>,
+    /// Bits of which an integer argument must have at least one set.
+    #[serde(default)]
+    pub flags: Option<i64>,
+}
+
+impl Rule {
+    fn validate(&self) -> Result<(), RuleError> {
+        let invalid = |reason: &str| {
+            return Err(RuleError::InvalidRule(format!("{}: {}", self.id, reason)));
+        };
+        if self.class.is_none() && self.member.is_none() {
+            return invalid("needs a class or a member");
+        }
+        if self.kind == RuleKind::Override && (self.class.is_none() || self.member.is_none()) {
+            return invalid("override rules need a class and a member");
+        }
+        let has_condition = !self.strings.is_empty() || self.flags.is_some();
+        if has_condition && (self.argument.is_none() || self.kind != RuleKind::Invoke) {
+            return invalid("strings and flags need an invoke rule with an argument");
+        }
+        if self.argument.is_some() && !has_condition {
+            return invalid("argument needs strings or flags");
+        }
+        return Ok(());
+    }
+
+    fn matches_argument(&self, value: Option<&ConstValue>) -> bool {
+        if self.argument.is_none() {
+            return true;
+        }
+        let value = match value {
+            Some(value) => value,
+            None => return false,
+        };
+        if let Some(s) = value.as_str() {
+            return self.strings.iter().any(|pattern| glob_match(pattern, s));
+        }
+        if let (Some(i), Some(flags)) = (value.as_int(), self.flags) {
+            return i & flags != 0;
+        }
+        return false;
+    }
+}
+
+/// Case-insensitive glob match where `*` matches any run of characters.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.to_lowercase().chars().collect::<Vec<_>>();
+    let text = text.to_lowercase().chars().collect::<Vec<_>>();
+    // matches[j]: whether the pattern so far matches text[..j].
+    let mut matches = vec![false; text.len() + 1];
+    matches[0] = true;
+    for p in pattern.iter() {
+        if *p == '*' {
+            for j in 1..=text.len() {
+                matches[j] = matches[j] || matches[j - 1];
+            }
+        } else {
+            for j in (1..=text.len()).rev() {
+                matches[j] = matches[j - 1] && text[j - 1] == *p;
+            }
+            matches[0] = false;
+        }
+    }
+    return matches[text.len()];
+}
+
+/// A set of rules, as stored in a rule file under `[[rule]]` entries.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RuleSet {
+    #[serde(default, rename = "rule")]
+    pub rules: Vec<Rule>,
+}
+
+impl RuleSet {
+    /// The rules shipped in `rules/dangerous_apis.toml`.
+    pub fn builtin() -> RuleSet {
+        return RuleSet::from_toml(BUILTIN_RULES).unwrap();
+    }
+
+    pub fn from_toml(text: &str) -> Result<RuleSet, RuleError> {
+        let rules: RuleSet = toml::from_str(text)?;
+        rules.validate()?;
+        return Ok(rules);
+    }
+
+    pub fn from_json(text: &str) -> Result<RuleSet, RuleError> {
+        let rules: RuleSet = serde_json::from_str(text)?;
+        rules.validate()?;
+        return Ok(rules);
+    }
+
+    /// Loads a `.json` rule file, or a TOML one for any other extension.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<RuleSet, RuleError> {
+        let text = fs::read_to_string(&path)?;
+        if path.as_ref().extension().is_some_and(|e| e == "json") {
+            return RuleSet::from_json(&text);
+        }
+        return RuleSet::from_toml(&text);
+    }
+
+    /// Adds the rules of `other`, replacing rules with the same id.
+    pub fn extend(&mut self, other: RuleSet) {
+        for rule in other.rules {
+            self.rules.retain(|r| r.id != rule.id);
+            self.rules.push(rule);
+        }
+    }
+
+    fn validate(&self) -> Result<(), RuleError> {
+        for rule in self.rules.iter() {
+            rule.validate()?;
+        }
+        return Ok(());
+    }
+}
+
+/// A use of a sensitive API.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Finding {
+    pub rule: String,
+    pub severity: Severity,
+    pub message: String,
+    pub dex: String,
+    /// Java name of the class containing the use.
+    pub class: String,
+    pub method: String,
+    /// Smali signature of the method containing the use, which mappings do
+    /// not change.
+    pub method_signature: String,
+    /// Code unit offset of the instruction within the method.
+    pub address: u32,
+    pub source_file: Option<String>,
+    pub line: Option<u32>,
+    /// Smali signature of the matched method or field.
+    pub target: String,
+    /// The constant argument that matched the rule, if it has a condition.
+    pub argument: Option<String>,
+}
+
+/// Superclasses and interfaces of the classes defined in the scanned dex
+/// files, by descriptor.
+struct Hierarchy {
+    supertypes: HashMap<String, Vec<String>>,
+}
+
+impl Hierarchy {
+    fn new(dexes: &[(String, DexResolver)]) -> Self {
+        let mut supertypes = HashMap::new();
+        for (_, resolver) in dexes {
+            for class_def in resolver.dex.class_defs.iter() {
+                let mut direct = resolver.type_list_descriptors(class_def.interfaces_off);
+                if class_def.superclass_idx != NO_INDEX {
+                    direct.push(
+                        resolver
+                            .type_descriptor(class_def.superclass_idx)
+                            .to_string(),
+                    );
+                }
+                supertypes.insert(
+                    resolver.type_descriptor(class_def.class_idx).to_string(),
+                    direct,
+                );
+            }
+        }
+        return Self { supertypes };
+    }
+
+    /// Whether `descriptor` is `ancestor` or inherits from it.
+    fn is_subtype(&self, descriptor: &str, ancestor: &str) -> bool {
+        let mut pending = vec![descriptor];
+        let mut seen = HashSet::new();
+        while let Some(current) = pending.pop() {
+            if current == ancestor {
+                return true;
+            }
+            if !seen.insert(current) {
+                continue;
+            }
+            if let Some(direct) = self.supertypes.get(current) {
+                pending.extend(direct.iter().map(|s| s.as_str()));
+            }
+        }
+        return false;
+    }
+}
+
+/// A rule with its class name converted to a descriptor.
+struct CompiledRule<'a> {
+    rule: &'a Rule,
+    class: Option<String>,
+}
+
+impl CompiledRule<'_> {
+    fn matches_member(
+        &self,
+        hierarchy: &Hierarchy,
+        class: &str,
+        name: &str,
+        descriptor: &str,
+    ) -> bool {
+        return self
+            .class
+            .as_ref()
+            .is_none_or(|c| hierarchy.is_subtype(class, c))
+            && self.rule.member.as_ref().is_none_or(|m| m == name)
+            && self
+                .rule
+                .descriptor
+                .as_ref()
+                .is_none_or(|d| d == descriptor);
+    }
+}
+
+/// Whether a method body neither throws nor calls anything, as with trust
+/// managers and hostname verifiers that accept everything.
+fn is_trivial(code: &CodeItem) -> bool {
+    return code.insns.iter().all(|insn| {
+        let info = opcode_info(insn.opcode());
+        // Only `throw` always throws.
+        let throws = info.has(THROW) && !info.has(CONTINUE);
+        !throws && !info.has(INVOKE)
+    });
+}
+
+struct Scanner<'a> {
+    rules: Vec<CompiledRule<'a>>,
+    hierarchy: Hierarchy,
+    findings: Vec<Finding>,
+}
+
+impl<'a> Scanner<'a> {
+    fn scan_dex(&mut self, name: &str, resolver: &DexResolver) {
+        for class_def in resolver.dex.class_defs.iter() {
+            let class_descriptor = resolver.type_descriptor(class_def.class_idx);
+            let class = resolver.type_name(class_def.class_idx);
+            for (method_idx, method) in resolver.class_methods(class_def) {
+                let code = match resolver.code_item(method.code_off) {
+                    Some(code) if method.code_off != 0 => code,
+                    _ => continue,
+                };
+                let method_ref = resolver.method_ref(method_idx);
+                let mut finding = |rule: &Rule, address: u32, target: String, argument| {
+                    self.findings.push(Finding {
+                        rule: rule.id.clone(),
+                        severity: rule.severity,
+                        message: rule.message.clone(),
+                        dex: name.to_string(),
+                        class: class.clone(),
+                        method: resolver.method_display(method_idx),
+                        method_signature: method_ref.signature(),
+                        address,
+                        source_file: resolver.source_file_for_address(class_def, code, address),
+                        line: resolver.line_for_address(code, address),
+                        target,
+                        argument,
+                    });
+                };
+
+                for compiled in self.rules.iter() {
+                    if compiled.rule.kind == RuleKind::Override
+                        && compiled.matches_member(
+                            &self.hierarchy,
+                            class_descriptor,
+                            &method_ref.name,
+                            &prototype(&method_ref.parameters, &method_ref.return_type),
+                        )
+                        && class_descriptor != compiled.class.as_deref().unwrap()
+                        && is_trivial(code)
+                    {
+                        finding(compiled.rule, 0, method_ref.signature(), None);
+                    }
+                }
+
+                let mut tracker = ConstTracker::new();
+                let mut address = 0;
+                for insn in code.insns.iter() {
+                    for index_ref in insn.index_refs() {
+                        match index_ref.kind {
+                            IndexKind::Method => {
+                                let target = resolver.method_ref(index_ref.index);
+                                let descriptor = prototype(&target.parameters, &target.return_type);
+                                let is_static = opcode_info(insn.opcode()).has(STATIC);
+                                let registers = parameter_registers(&target.parameters, is_static);
+                                let arguments = tracker.arguments(insn);
+                                for compiled in self.rules.iter() {
+                                    let rule = compiled.rule;
+                                    if rule.kind != RuleKind::Invoke
+                                        || !compiled.matches_member(
+                                            &self.hierarchy,
+                                            &target.class,
+                                            &target.name,
+                                            &descriptor,
+                                        )
+                                    {
+                                        continue;
+                                    }
+                                    let value = rule
+                                        .argument
+                                        .and_then(|a| registers.get(a))
+                                        .and_then(|r| arguments.get(*r).copied().flatten());
+                                    if rule.matches_argument(value) {
+                                        let argument = rule.argument.and(value).map(describe);
+                                        finding(rule, address, target.signature(), argument);
+                                    }
+                                }
+                            }
+                            IndexKind::Field => {
+                                let target = resolver.field_ref(index_ref.index);
+                                for compiled in self.rules.iter() {
+                                    if compiled.rule.kind == RuleKind::Field
+                                        && compiled.matches_member(
+                                            &self.hierarchy,
+                                            &target.class,
+                                            &target.name,
+                                            &target.field_type,
+                                        )
+                                    {
+                                        finding(compiled.rule, address, target.signature(), None);
+                                    }
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+                    tracker.step(insn, resolver);
+                    address += insn.size() as u32 / 2;
+                }
+            }
+        }
+    }
+}
+
+fn prototype(parameters: &[String], return_type: &str) -> String {
+    return format!("({}){}", parameters.join(""), return_type);
+}
+
+fn describe(value: &ConstValue) -> String {
+    return match value {
+        ConstValue::String(s) => format!("{:?}", s),
+        ConstValue::Int(i) => format!("0x{:x}", i),
+        ConstValue::Class(descriptor) => descriptor_to_java(descriptor),
+    };
+}
+
+/// Findings of a rule set over every dex of a build, ordered by severity.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DangerousApiReport {
+    pub findings: Vec<Finding>,
+    /// Findings ignored because a baseline lists them.
+    pub suppressed: Vec<Finding>,
+}
+
+impl DangerousApiReport {
+    pub fn new(dexes: &[(String, DexModel)], rules: &RuleSet) -> DangerousApiReport {
+        let resolvers = dexes
+            .iter()
+            .map(|(name, dex)| (name.clone(), DexResolver::new(dex)))
+            .collect::<Vec<_>>();
+        return DangerousApiReport::from_resolvers(&resolvers, rules);
+    }
+
+    /// Runs every rule over the invoke instructions of each
+    /// `(entry name, resolver)` pair. Rules match the obfuscated names in
+    /// the dex; findings name their calling method as the resolver does.
+    pub fn from_resolvers(dexes: &[(String, DexResolver)], rules: &RuleSet) -> DangerousApiReport {
+        let mut scanner = Scanner {
+            rules: rules
+                .rules
+                .iter()
+                .map(|rule| CompiledRule {
+                    rule,
+                    class: rule.class.as_deref().map(java_to_descriptor),
+                })
+                .collect(),
+            hierarchy: Hierarchy::new(dexes),
+            findings: vec![],
+        };
+        for (name, resolver) in dexes {
+            scanner.scan_dex(name, resolver);
+        }
+        let mut findings = scanner.findings;
+        findings.sort_by_key(|f| std::cmp::Reverse(f.severity));
+        return DangerousApiReport {
+            findings,
+            suppressed: vec![],
+        };
+    }
+
+    /// Moves the findings listed in `baseline` to `suppressed`.
+    pub fn apply_baseline(&mut self, baseline: &Baseline) {
+        let issues = self.findings.iter().map(Issue::from).collect::<Vec<_>>();
+        let (suppressed, findings): (Vec<_>, Vec<_>) = self
+            .findings
+            .drain(..)
+            .zip(baseline.suppressed(&issues))
+            .partition(|(_, suppressed)| *suppressed);
+        self.findings = findings.into_iter().map(|(f, _)| f).collect();
+        self.suppressed
+            .extend(suppressed.into_iter().map(|(f, _)| f));
+    }
+
+    pub fn issues(&self) -> Vec<Issue> {
+        return self.findings.iter().map(Issue::from).collect();
+    }
+
+    pub fn suppressed_issues(&self) -> Vec<Issue> {
+        return self.suppressed.iter().map(Issue::from).collect();
+    }
+
+    pub fn to_json(&self) -> String {
+        return serde_json::to_string_pretty(self).unwrap();
+    }
+}
+
+impl Display for DangerousApiReport {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for finding in self.findings.iter() {
+            let line = finding.line.map(|l| format!(":{}", l)).unwrap_or_default();
+            writeln!(
+                f,
+                "[{}] {}: {}",
+                finding.severity, finding.rule, finding.message
+            )?;
+            write!(
+                f,
+                "    {} ({}{}) -> {}",
+                finding.method, finding.dex, line, finding.target
+            )?;
+            if let Some(argument) = &finding.argument {
+                write!(f, " with {}", argument)?;
+            }
+            writeln!(f)?;
+        }
+        if !self.suppressed.is_empty() {
+            writeln!(
+                f,
+                "{} finding(s) suppressed by baseline",
+                self.suppressed.len()
+            )?;
+        }
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{build_dex, TestClass};
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*/ECB/*", "AES/ECB/PKCS5Padding"));
+        assert!(glob_match("des", "DES"));
+        assert!(!glob_match("DES", "DESede"));
+        assert!(glob_match("DESede*", "DESede/CBC/NoPadding"));
+        assert!(!glob_match("*/ECB/*", "AES/GCM/NoPadding"));
+    }
+
+    #[test]
+    fn test_builtin_rules() {
+        let rules = RuleSet::builtin();
+        assert!(rules.rules.iter().any(|r| r.id == "weak-cipher"));
+        let mut json = RuleSet::from_json(
+            r#"{"rule": [{"id": "log", "severity": "info", "message": "Logs",
+                "kind": "invoke", "class": "android.util.Log"}]}"#,
+        )
+        .unwrap();
+        json.extend(rules);
+        assert_eq!(json.rules[0].id, "log");
+        assert!(RuleSet::from_toml(
+            "[[rule]]\nid = \"x\"\nseverity = \"low\"\nmessage = \"\"\nkind = \"field\"\n"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_scan() {
+        // Strings: 0 "AES/ECB/PKCS5Padding", 1 "AES/GCM/NoPadding", ...
+        // Types: 0 Lcom/app/Main;, 1 Lcom/app/Trust;, 2 Ljava/lang/Object;,
+        // 3 Ljava/lang/String;, 4 Ljavax/crypto/Cipher;,
+        // 5 Ljavax/net/ssl/X509TrustManager;
+        // Methods: 0 Main.run, 1 Trust.checkServerTrusted,
+        // 2 Cipher.getInstance
+        let dex = build_dex(
+            vec![
+                // const-string v0, "AES/ECB/..."; invoke-static {v0}, method@2;
+                // const-string v0, "AES/GCM/..."; invoke-static {v0}, method@2;
+                // return-void
+                TestClass::new("Lcom/app/Main;").method(
+                    "run",
+                    &[],
+                    "V",
+                    &[
+                        0x001a, 0x0000, 0x1071, 0x0002, 0x0000, 0x001a, 0x0001, 0x1071, 0x0002,
+                        0x0000, 0x000e,
+                    ],
+                ),
+                TestClass::new("Lcom/app/Trust;")
+                    .implements("Ljavax/net/ssl/X509TrustManager;")
+                    .method(
+                        "checkServerTrusted",
+                        &["Ljava/lang/String;"],
+                        "V",
+                        &[0x000e],
+                    ),
+                TestClass::new("Ljavax/crypto/Cipher;").method(
+                    "getInstance",
+                    &["Ljava/lang/String;"],
+                    "Ljavax/crypto/Cipher;",
+                    &[0x0011],
+                ),
+            ],
+            &["AES/ECB/PKCS5Padding", "AES/GCM/NoPadding"],
+        );
+        let resolver = DexResolver::new(&dex);
+        assert_eq!(resolver.string(0), "AES/ECB/PKCS5Padding");
+        assert_eq!(resolver.method_ref(2).name, "getInstance");
+
+        let report =
+            DangerousApiReport::new(&[("classes.dex".to_string(), dex)], &RuleSet::builtin());
+        let rules = report
+            .findings
+            .iter()
+            .map(|f| (f.rule.as_str(), f.class.as_str(), f.address))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rules,
+            vec![
+                ("weak-cipher", "com.app.Main", 2),
+                ("trust-all-certificates", "com.app.Trust", 0)
+            ]
+        );
+        assert_eq!(
+            report.findings[0].argument.as_deref(),
+            Some("\"AES/ECB/PKCS5Padding\"")
+        );
+        assert_eq!(
+            report.findings[0].method_signature,
+            "Lcom/app/Main;->run()V"
+        );
+        assert_eq!(report.findings[0].source_file, None);
+    }
+
+    #[test]
+    fn test_flags_argument() {
+        // Methods: 0 WebSettings.setJavaScriptEnabled, 1 Main.run
+        // const/4 v1, 0; invoke-virtual {v0, v1}, method@0;
+        // const/4 v1, 1; invoke-virtual {v0, v1}, method@0; return-void
+        let dex = build_dex(
+            vec![
+                TestClass::new("Lcom/app/Main;").method(
+                    "run",
+                    &[],
+                    "V",
+                    &[
+                        0x0112, 0x206e, 0x0000, 0x0010, 0x1112, 0x206e, 0x0000, 0x0010, 0x000e,
+                    ],
+                ),
+                TestClass::new("Landroid/webkit/WebSettings;").method(
+                    "setJavaScriptEnabled",
+                    &["Z"],
+                    "V",
+                    &[0x000e],
+                ),
+            ],
+            &[],
+        );
+        assert_eq!(
+            DexResolver::new(&dex).method_ref(0).name,
+            "setJavaScriptEnabled"
+        );
+        let report =
+            DangerousApiReport::new(&[("classes.dex".to_string(), dex)], &RuleSet::builtin());
+        let rules = report
+            .findings
+            .iter()
+            .map(|f| (f.rule.as_str(), f.address, f.argument.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(rules, vec![("webview-javascript-enabled", 5, Some("0x1"))]);
+    }
+}
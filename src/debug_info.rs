@@ -0,0 +1,647 @@
+use std::{
+    collections::HashMap,
+    io::{self, Cursor},
+};
+
+use crate::{
+    decode::{decode_u8, decode_uleb128p1},
+    dex_structs::DebugInfoItem,
+    encode::{encode_sleb128, encode_u8, encode_uleb128, encode_uleb128p1},
+    instructions::IndexKind,
+    uleb128p1,
+};
+
+pub const DBG_END_SEQUENCE: u8 = 0x00;
+pub const DBG_ADVANCE_PC: u8 = 0x01;
+pub const DBG_ADVANCE_LINE: u8 = 0x02;
+pub const DBG_START_LOCAL: u8 = 0x03;
+pub const DBG_START_LOCAL_EXTENDED: u8 = 0x04;
+pub const DBG_END_LOCAL: u8 = 0x05;
+pub const DBG_RESTART_LOCAL: u8 = 0x06;
+pub const DBG_SET_PROLOGUE_END: u8 = 0x07;
+pub const DBG_SET_EPILOGUE_BEGIN: u8 = 0x08;
+pub const DBG_SET_FILE: u8 = 0x09;
+
+const DBG_FIRST_SPECIAL: u8 = 0x0a;
+const DBG_LINE_BASE: i32 = -4;
+const DBG_LINE_RANGE: i32 = 15;
+
+/// A row of the line table: the bytecode at `address` (in 16-bit code units)
+/// was generated from `line`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PositionEntry {
+    pub address: u32,
+    pub line: u32,
+    /// String index set by `DBG_SET_FILE`.  `None` means the source file of
+    /// the enclosing class applies.
+    pub source_file_idx: Option<u32>,
+    pub prologue_end: bool,
+    pub epilogue_begin: bool,
+}
+
+/// A local variable live in `register` from `start_address` until
+/// `end_address`.  An `end_address` of `None` means the local stays live until
+/// the end of the method.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocalEntry {
+    pub register: u32,
+    pub name_idx: Option<u32>,
+    pub type_idx: Option<u32>,
+    pub sig_idx: Option<u32>,
+    pub start_address: u32,
+    pub end_address: Option<u32>,
+}
+
+/// Decoded form of the `debug_info_item` state machine program.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DebugInfo {
+    pub positions: Vec<PositionEntry>,
+    pub locals: Vec<LocalEntry>,
+}
+
+/// Records `local` as live in its register.  Starting a local implicitly ends
+/// whatever was live in the register before.
+fn open_local(info: &mut DebugInfo, last_local: &mut HashMap<u32, usize>, local: LocalEntry) {
+    if let Some(prev) = last_local.insert(local.register, info.locals.len()) {
+        if info.locals[prev].end_address.is_none() {
+            info.locals[prev].end_address = Some(local.start_address);
+        }
+    }
+    info.locals.push(local);
+}
+
+fn to_option(idx: uleb128p1) -> Option<u32> {
+    if idx < 0 {
+        None
+    } else {
+        Some(idx as u32)
+    }
+}
+
+fn from_option(idx: Option<u32>) -> uleb128p1 {
+    match idx {
+        Some(idx) => idx as uleb128p1,
+        None => -1,
+    }
+}
+
+/// Copies one leb128 value from `r` into `buf` without interpreting it, so
+/// that non-minimal encodings survive a round trip.
+fn copy_leb128<R>(r: &mut R, buf: &mut Vec<u8>)
+where
+    R: io::Read,
+{
+    loop {
+        let byte = decode_u8(r);
+        buf.push(byte);
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+}
+
+/// Reads the raw state machine program up to and including
+/// `DBG_END_SEQUENCE`.  Opcode arguments are walked rather than scanning for a
+/// zero byte, since leb128 arguments may themselves contain zeros.
+pub(crate) fn read_debug_bytecode<R>(r: &mut R) -> Vec<u8>
+where
+    R: io::Read,
+{
+    let mut bytecode = vec![];
+    loop {
+        let opcode = decode_u8(r);
+        bytecode.push(opcode);
+        match opcode {
+            DBG_END_SEQUENCE => break,
+            DBG_ADVANCE_PC | DBG_ADVANCE_LINE | DBG_END_LOCAL | DBG_RESTART_LOCAL
+            | DBG_SET_FILE => {
+                copy_leb128(r, &mut bytecode);
+            }
+            DBG_START_LOCAL => {
+                for _ in 0..3 {
+                    copy_leb128(r, &mut bytecode);
+                }
+            }
+            DBG_START_LOCAL_EXTENDED => {
+                for _ in 0..4 {
+                    copy_leb128(r, &mut bytecode);
+                }
+            }
+            _ => {
+                // DBG_SET_PROLOGUE_END, DBG_SET_EPILOGUE_BEGIN and special
+                // opcodes take no arguments.
+            }
+        }
+    }
+    return bytecode;
+}
+
+/// Bounds-checked reads of a state machine program, so that decoding stops
+/// at a truncated one instead of panicking.
+struct Program<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Program<'_> {
+    fn u8(&mut self) -> Option<u8> {
+        let byte = *self.bytes.get(self.pos)?;
+        self.pos += 1;
+        return Some(byte);
+    }
+
+    /// At most five bytes, as a `u32` holds no more.
+    fn uleb128(&mut self) -> Option<u32> {
+        let mut result = 0u32;
+        for k in 0..5 {
+            let byte = self.u8()?;
+            result |= ((byte & 0x7f) as u32) << (7 * k);
+            if byte & 0x80 == 0 {
+                return Some(result);
+            }
+        }
+        return None;
+    }
+
+    fn uleb128p1(&mut self) -> Option<uleb128p1> {
+        return Some((self.uleb128()? as i32).wrapping_sub(1));
+    }
+
+    /// As `decode_sleb128`, the fifth byte ends the value.
+    fn sleb128(&mut self) -> Option<i32> {
+        let mut result = 0i64;
+        for k in 0..5 {
+            let byte = self.u8()?;
+            result |= ((byte & 0x7f) as i64) << (7 * k);
+            if byte & 0x80 == 0 || k == 4 {
+                if byte & 0x40 != 0 && k < 4 {
+                    result |= -1 << (7 * (k + 1));
+                }
+                return Some(result as i32);
+            }
+        }
+        return None;
+    }
+
+    fn run(&mut self, line_start: u32, info: &mut DebugInfo) -> Option<()> {
+        let mut address = 0u32;
+        let mut line = line_start as i64;
+        let mut source_file_idx = None;
+        let mut prologue_end = false;
+        let mut epilogue_begin = false;
+        // Index into `info.locals` of the last local seen for each register.
+        let mut last_local: HashMap<u32, usize> = HashMap::new();
+
+        while self.pos < self.bytes.len() {
+            let opcode = self.u8()?;
+            match opcode {
+                DBG_END_SEQUENCE => break,
+                DBG_ADVANCE_PC => {
+                    address = address.saturating_add(self.uleb128()?);
+                }
+                DBG_ADVANCE_LINE => {
+                    line = line.saturating_add(self.sleb128()? as i64);
+                }
+                DBG_START_LOCAL | DBG_START_LOCAL_EXTENDED => {
+                    let register = self.uleb128()?;
+                    let name_idx = to_option(self.uleb128p1()?);
+                    let type_idx = to_option(self.uleb128p1()?);
+                    let sig_idx = if opcode == DBG_START_LOCAL_EXTENDED {
+                        to_option(self.uleb128p1()?)
+                    } else {
+                        None
+                    };
+                    let local = LocalEntry {
+                        register,
+                        name_idx,
+                        type_idx,
+                        sig_idx,
+                        start_address: address,
+                        end_address: None,
+                    };
+                    open_local(info, &mut last_local, local);
+                }
+                DBG_END_LOCAL => {
+                    let register = self.uleb128()?;
+                    if let Some(prev) = last_local.get(&register) {
+                        if info.locals[*prev].end_address.is_none() {
+                            info.locals[*prev].end_address = Some(address);
+                        }
+                    }
+                }
+                DBG_RESTART_LOCAL => {
+                    let register = self.uleb128()?;
+                    let prev = last_local
+                        .get(&register)
+                        .map(|prev| info.locals[*prev].clone());
+                    let local = match prev {
+                        Some(prev) => LocalEntry {
+                            start_address: address,
+                            end_address: None,
+                            ..prev
+                        },
+                        // Restarting a register that never held a local is
+                        // malformed; keep the register so the range survives.
+                        None => LocalEntry {
+                            register,
+                            name_idx: None,
+                            type_idx: None,
+                            sig_idx: None,
+                            start_address: address,
+                            end_address: None,
+                        },
+                    };
+                    open_local(info, &mut last_local, local);
+                }
+                DBG_SET_PROLOGUE_END => prologue_end = true,
+                DBG_SET_EPILOGUE_BEGIN => epilogue_begin = true,
+                DBG_SET_FILE => {
+                    source_file_idx = to_option(self.uleb128p1()?);
+                }
+                special => {
+                    let adjusted = (special - DBG_FIRST_SPECIAL) as i32;
+                    line = line.saturating_add((DBG_LINE_BASE + adjusted % DBG_LINE_RANGE) as i64);
+                    address = address.saturating_add((adjusted / DBG_LINE_RANGE) as u32);
+                    info.positions.push(PositionEntry {
+                        address,
+                        line: line.clamp(0, u32::MAX as i64) as u32,
+                        source_file_idx,
+                        prologue_end,
+                        epilogue_begin,
+                    });
+                    prologue_end = false;
+                    epilogue_begin = false;
+                }
+            }
+        }
+        return Some(());
+    }
+}
+
+impl DebugInfoItem {
+    /// Runs the state machine program and returns the line table and local
+    /// variable table it describes. A truncated program yields the entries
+    /// before the end, and lines and addresses saturate rather than wrap.
+    pub fn decode(&self) -> DebugInfo {
+        let mut info = DebugInfo::default();
+        let mut program = Program {
+            bytes: &self.bytecode,
+            pos: 0,
+        };
+        program.run(self.line_start, &mut info);
+        return info;
+    }
+
+    /// Builds a debug info item whose bytecode is generated from `info`.
+    pub fn from_debug_info(
+        line_start: u32,
+        parameter_names: Vec<uleb128p1>,
+        info: &DebugInfo,
+    ) -> DebugInfoItem {
+        return DebugInfoItem {
+            line_start,
+            parameter_names,
+            bytecode: info.encode(line_start),
+        };
+    }
+}
+
+enum Event<'a> {
+    EndLocal(&'a LocalEntry),
+    StartLocal(&'a LocalEntry),
+    Position(&'a PositionEntry),
+}
+
+impl Event<'_> {
+    fn address(&self) -> u32 {
+        match self {
+            Event::EndLocal(local) => local.end_address.unwrap(),
+            Event::StartLocal(local) => local.start_address,
+            Event::Position(position) => position.address,
+        }
+    }
+
+    /// Ordering of events that share an address: locals are closed before new
+    /// ones are opened, and positions come last.
+    fn rank(&self) -> u8 {
+        match self {
+            Event::EndLocal(_) => 0,
+            Event::StartLocal(_) => 1,
+            Event::Position(_) => 2,
+        }
+    }
+}
+
+impl DebugInfo {
+    /// Generates a state machine program describing this line table and local
+    /// variable table, terminated by `DBG_END_SEQUENCE`.
+    pub fn encode(&self, line_start: u32) -> Vec<u8> {
+        let mut events = vec![];
+        for local in self.locals.iter() {
+            events.push(Event::StartLocal(local));
+            if local.end_address.is_some() {
+                events.push(Event::EndLocal(local));
+            }
+        }
+        for position in self.positions.iter() {
+            events.push(Event::Position(position));
+        }
+        // Stable sort keeps table order for events at the same address.
+        events.sort_by_key(|event| (event.address(), event.rank()));
+
+        let mut w = Cursor::new(vec![]);
+        let mut address = 0u32;
+        let mut line = line_start as i64;
+        let mut source_file_idx = None;
+        // The most recently ended local of each register, for DBG_RESTART_LOCAL.
+        let mut ended: Vec<Option<&LocalEntry>> = vec![];
+
+        for event in events {
+            let event_address = event.address();
+            match event {
+                Event::EndLocal(local) => {
+                    advance_pc(&mut w, &mut address, event_address);
+                    encode_u8(&mut w, DBG_END_LOCAL);
+                    encode_uleb128(&mut w, local.register);
+                    let register = local.register as usize;
+                    if register >= ended.len() {
+                        ended.resize(register + 1, None);
+                    }
+                    ended[register] = Some(local);
+                }
+                Event::StartLocal(local) => {
+                    advance_pc(&mut w, &mut address, event_address);
+                    let restartable = ended
+                        .get(local.register as usize)
+                        .copied()
+                        .flatten()
+                        .is_some_and(|prev| {
+                            prev.name_idx == local.name_idx
+                                && prev.type_idx == local.type_idx
+                                && prev.sig_idx == local.sig_idx
+                        });
+                    if restartable {
+                        encode_u8(&mut w, DBG_RESTART_LOCAL);
+                        encode_uleb128(&mut w, local.register);
+                    } else if local.sig_idx.is_some() {
+                        encode_u8(&mut w, DBG_START_LOCAL_EXTENDED);
+                        encode_uleb128(&mut w, local.register);
+                        encode_uleb128p1(&mut w, from_option(local.name_idx));
+                        encode_uleb128p1(&mut w, from_option(local.type_idx));
+                        encode_uleb128p1(&mut w, from_option(local.sig_idx));
+                    } else {
+                        encode_u8(&mut w, DBG_START_LOCAL);
+                        encode_uleb128(&mut w, local.register);
+                        encode_uleb128p1(&mut w, from_option(local.name_idx));
+                        encode_uleb128p1(&mut w, from_option(local.type_idx));
+                    }
+                    if let Some(prev) = ended.get_mut(local.register as usize) {
+                        *prev = None;
+                    }
+                }
+                Event::Position(position) => {
+                    if position.source_file_idx != source_file_idx {
+                        encode_u8(&mut w, DBG_SET_FILE);
+                        encode_uleb128p1(&mut w, from_option(position.source_file_idx));
+                        source_file_idx = position.source_file_idx;
+                    }
+                    if position.prologue_end {
+                        encode_u8(&mut w, DBG_SET_PROLOGUE_END);
+                    }
+                    if position.epilogue_begin {
+                        encode_u8(&mut w, DBG_SET_EPILOGUE_BEGIN);
+                    }
+
+                    let mut line_delta = position.line as i64 - line;
+                    if line_delta < DBG_LINE_BASE as i64
+                        || line_delta >= (DBG_LINE_BASE + DBG_LINE_RANGE) as i64
+                    {
+                        encode_u8(&mut w, DBG_ADVANCE_LINE);
+                        encode_sleb128(&mut w, line_delta as i32);
+                        line_delta = 0;
+                    }
+                    let line_part = (line_delta as i32 - DBG_LINE_BASE) as u32;
+                    let mut address_delta = event_address - address;
+                    let max_special = 0xff - DBG_FIRST_SPECIAL as u32;
+                    if line_part + address_delta * DBG_LINE_RANGE as u32 > max_special {
+                        encode_u8(&mut w, DBG_ADVANCE_PC);
+                        encode_uleb128(&mut w, address_delta);
+                        address_delta = 0;
+                    }
+                    let special = DBG_FIRST_SPECIAL as u32
+                        + line_part
+                        + address_delta * DBG_LINE_RANGE as u32;
+                    encode_u8(&mut w, special as u8);
+                    address = event_address;
+                    line = position.line as i64;
+                }
+            }
+        }
+        encode_u8(&mut w, DBG_END_SEQUENCE);
+        return w.into_inner();
+    }
+}
+
+fn advance_pc<W>(w: &mut W, address: &mut u32, target: u32)
+where
+    W: io::Write,
+{
+    if target > *address {
+        encode_u8(w, DBG_ADVANCE_PC);
+        encode_uleb128(w, target - *address);
+        *address = target;
+    }
+}
+
+/// Copies a `uleb128p1` index of `kind` from `r` to `w` through `f`,
+/// keeping the "no index" value.
+fn remap_uleb128p1<R, W>(
+    r: &mut R,
+    w: &mut W,
+    kind: IndexKind,
+    f: &mut dyn FnMut(IndexKind, u32) -> u32,
+) where
+    R: io::Read,
+    W: io::Write,
+{
+    let index = to_option(decode_uleb128p1(r)).map(|index| f(kind, index));
+    encode_uleb128p1(w, from_option(index));
+}
+
+impl DebugInfoItem {
+    /// Rewrites the string and type indices of the parameter names and the
+    /// state machine program, e.g. after the pools of several files are
+    /// merged. Other opcodes are copied unchanged.
+    pub(crate) fn remap_indices(&mut self, f: &mut dyn FnMut(IndexKind, u32) -> u32) {
+        for name in self.parameter_names.iter_mut() {
+            *name = from_option(to_option(*name).map(|index| f(IndexKind::String, index)));
+        }
+        let mut r = Cursor::new(std::mem::take(&mut self.bytecode));
+        let mut w = vec![];
+        loop {
+            let opcode = decode_u8(&mut r);
+            w.push(opcode);
+            match opcode {
+                DBG_END_SEQUENCE => break,
+                DBG_ADVANCE_PC | DBG_ADVANCE_LINE | DBG_END_LOCAL | DBG_RESTART_LOCAL => {
+                    copy_leb128(&mut r, &mut w);
+                }
+                DBG_SET_FILE => remap_uleb128p1(&mut r, &mut w, IndexKind::String, f),
+                DBG_START_LOCAL | DBG_START_LOCAL_EXTENDED => {
+                    copy_leb128(&mut r, &mut w);
+                    remap_uleb128p1(&mut r, &mut w, IndexKind::String, f);
+                    remap_uleb128p1(&mut r, &mut w, IndexKind::Type, f);
+                    if opcode == DBG_START_LOCAL_EXTENDED {
+                        remap_uleb128p1(&mut r, &mut w, IndexKind::String, f);
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.bytecode = w;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dex_structs::DexStruct;
+
+    use super::*;
+
+    #[test]
+    fn test_zero_argument_does_not_end_sequence() {
+        // line_start=10, no parameters, ADVANCE_LINE 0, special opcode, END.
+        let bytes = vec![10, 0, DBG_ADVANCE_LINE, 0x00, 0x0e, DBG_END_SEQUENCE, 0xaa];
+        let mut cursor = Cursor::new(bytes);
+        let item = DebugInfoItem::deserialize(&mut cursor);
+        assert_eq!(item.bytecode, vec![DBG_ADVANCE_LINE, 0x00, 0x0e, 0x00]);
+        assert_eq!(cursor.position(), 6);
+
+        let info = item.decode();
+        assert_eq!(info.positions.len(), 1);
+        assert_eq!(info.positions[0].line, 10);
+        assert_eq!(info.positions[0].address, 0);
+    }
+
+    #[test]
+    fn test_decode_malformed() {
+        let item = |bytecode: Vec<u8>| DebugInfoItem {
+            line_start: 1,
+            parameter_names: vec![],
+            bytecode,
+        };
+        // ADVANCE_LINE -10, ADVANCE_PC 0xffffffff twice, special opcode.
+        let info = item(vec![
+            DBG_ADVANCE_LINE,
+            0x76,
+            DBG_ADVANCE_PC,
+            0xff,
+            0xff,
+            0xff,
+            0xff,
+            0x0f,
+            DBG_ADVANCE_PC,
+            0xff,
+            0xff,
+            0xff,
+            0xff,
+            0x0f,
+            0x1e,
+        ])
+        .decode();
+        assert_eq!(info.positions[0].line, 0);
+        assert_eq!(info.positions[0].address, u32::MAX);
+
+        // START_LOCAL in the last register with a name index of 2^31 - 1,
+        // then a truncated ADVANCE_PC.
+        let info = item(vec![
+            DBG_START_LOCAL,
+            0xff,
+            0xff,
+            0xff,
+            0xff,
+            0x0f,
+            0x80,
+            0x80,
+            0x80,
+            0x80,
+            0x08,
+            0x00,
+            0x0e,
+            DBG_ADVANCE_PC,
+            0x80,
+        ])
+        .decode();
+        assert_eq!(info.locals.len(), 1);
+        assert_eq!(info.locals[0].register, u32::MAX);
+        assert_eq!(info.locals[0].name_idx, Some(0x7fffffff));
+        assert_eq!(info.positions.len(), 1);
+    }
+
+    #[test]
+    fn test_encode_decode_round_trip() {
+        let info = DebugInfo {
+            positions: vec![
+                PositionEntry {
+                    address: 0,
+                    line: 12,
+                    source_file_idx: None,
+                    prologue_end: true,
+                    epilogue_begin: false,
+                },
+                PositionEntry {
+                    address: 3,
+                    line: 40,
+                    source_file_idx: Some(7),
+                    prologue_end: false,
+                    epilogue_begin: false,
+                },
+                PositionEntry {
+                    address: 500,
+                    line: 8,
+                    source_file_idx: Some(7),
+                    prologue_end: false,
+                    epilogue_begin: true,
+                },
+            ],
+            locals: vec![
+                LocalEntry {
+                    register: 2,
+                    name_idx: Some(4),
+                    type_idx: Some(5),
+                    sig_idx: None,
+                    start_address: 1,
+                    end_address: Some(3),
+                },
+                LocalEntry {
+                    register: 2,
+                    name_idx: Some(4),
+                    type_idx: Some(5),
+                    sig_idx: None,
+                    start_address: 6,
+                    end_address: None,
+                },
+                LocalEntry {
+                    register: 0,
+                    name_idx: Some(1),
+                    type_idx: Some(2),
+                    sig_idx: Some(3),
+                    start_address: 3,
+                    end_address: Some(500),
+                },
+            ],
+        };
+        let item = DebugInfoItem::from_debug_info(12, vec![-1, 6], &info);
+        assert!(item.bytecode.contains(&DBG_RESTART_LOCAL));
+
+        let mut cursor = Cursor::new(vec![0u8; item.size()]);
+        item.serialize(&mut cursor);
+        cursor.set_position(0);
+        let decoded = DebugInfoItem::deserialize(&mut cursor).decode();
+
+        let mut expected_locals = info.locals.clone();
+        expected_locals.sort_by_key(|local| local.start_address);
+        assert_eq!(decoded.positions, info.positions);
+        assert_eq!(decoded.locals, expected_locals);
+    }
+}
@@ -0,0 +1,330 @@
+//! Minimal DER reader for the X.509 certificates and PKCS #7 blobs found in
+//! APK signatures.
+
+use std::fmt::{self, Display, Formatter};
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+pub(crate) const TAG_INTEGER: u8 = 0x02;
+pub(crate) const TAG_OID: u8 = 0x06;
+pub(crate) const TAG_UTC_TIME: u8 = 0x17;
+pub(crate) const TAG_GENERALIZED_TIME: u8 = 0x18;
+pub(crate) const TAG_SEQUENCE: u8 = 0x30;
+pub(crate) const TAG_SET: u8 = 0x31;
+pub(crate) const TAG_CONTEXT_0: u8 = 0xa0;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DerError {
+    Truncated,
+    UnexpectedTag { expected: u8, found: u8 },
+    Unsupported(String),
+}
+
+impl Display for DerError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            DerError::Truncated => write!(f, "truncated DER data"),
+            DerError::UnexpectedTag { expected, found } => write!(
+                f,
+                "unexpected DER tag 0x{:02x}, expected 0x{:02x}",
+                found, expected
+            ),
+            DerError::Unsupported(reason) => write!(f, "unsupported DER data: {}", reason),
+        }
+    }
+}
+
+/// A tag-length-value element; `raw` covers the whole encoding.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Tlv<'a> {
+    pub(crate) tag: u8,
+    pub(crate) value: &'a [u8],
+    pub(crate) raw: &'a [u8],
+}
+
+/// Reads consecutive DER elements.
+pub(crate) struct DerReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> DerReader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Self {
+        return Self { data };
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        return self.data.is_empty();
+    }
+
+    pub(crate) fn peek_tag(&self) -> Option<u8> {
+        return self.data.first().copied();
+    }
+
+    pub(crate) fn read(&mut self) -> Result<Tlv<'a>, DerError> {
+        let data = self.data;
+        let tag = *data.first().ok_or(DerError::Truncated)?;
+        if tag & 0x1f == 0x1f {
+            return Err(DerError::Unsupported("multi-byte tag".to_string()));
+        }
+        let first = *data.get(1).ok_or(DerError::Truncated)? as usize;
+        let (len, header) = if first < 0x80 {
+            (first, 2)
+        } else {
+            let count = first & 0x7f;
+            if count == 0 || count > 4 {
+                return Err(DerError::Unsupported("length encoding".to_string()));
+            }
+            let bytes = data.get(2..2 + count).ok_or(DerError::Truncated)?;
+            (
+                bytes.iter().fold(0usize, |len, b| len << 8 | *b as usize),
+                2 + count,
+            )
+        };
+        let end = header.checked_add(len).ok_or(DerError::Truncated)?;
+        if end > data.len() {
+            return Err(DerError::Truncated);
+        }
+        self.data = &data[end..];
+        return Ok(Tlv {
+            tag,
+            value: &data[header..end],
+            raw: &data[..end],
+        });
+    }
+
+    pub(crate) fn expect(&mut self, tag: u8) -> Result<Tlv<'a>, DerError> {
+        let tlv = self.read()?;
+        if tlv.tag != tag {
+            return Err(DerError::UnexpectedTag {
+                expected: tag,
+                found: tlv.tag,
+            });
+        }
+        return Ok(tlv);
+    }
+
+    /// Reads a constructed element and returns a reader over its contents.
+    pub(crate) fn nested(&mut self, tag: u8) -> Result<DerReader<'a>, DerError> {
+        return Ok(DerReader::new(self.expect(tag)?.value));
+    }
+}
+
+/// Dotted form of an object identifier, e.g. `2.5.4.3`.
+pub(crate) fn oid_to_string(value: &[u8]) -> String {
+    let mut parts = vec![];
+    let mut acc = 0u64;
+    for byte in value.iter() {
+        acc = acc << 7 | (*byte & 0x7f) as u64;
+        if byte & 0x80 != 0 {
+            continue;
+        }
+        if parts.is_empty() {
+            let first = (acc / 40).min(2);
+            parts.push(first);
+            parts.push(acc - first * 40);
+        } else {
+            parts.push(acc);
+        }
+        acc = 0;
+    }
+    return parts
+        .iter()
+        .map(|p| p.to_string())
+        .collect::<Vec<_>>()
+        .join(".");
+}
+
+fn attribute_name(oid: &str) -> &str {
+    return match oid {
+        "2.5.4.3" => "CN",
+        "2.5.4.5" => "SERIALNUMBER",
+        "2.5.4.6" => "C",
+        "2.5.4.7" => "L",
+        "2.5.4.8" => "ST",
+        "2.5.4.9" => "STREET",
+        "2.5.4.10" => "O",
+        "2.5.4.11" => "OU",
+        "1.2.840.113549.1.9.1" => "EMAILADDRESS",
+        oid => oid,
+    };
+}
+
+/// Formats an X.501 name like `CN=Android Debug, O=Android, C=US`.
+fn read_name(name: &[u8]) -> Result<String, DerError> {
+    let mut parts = vec![];
+    let mut rdns = DerReader::new(name);
+    while !rdns.is_empty() {
+        let mut set = rdns.nested(TAG_SET)?;
+        while !set.is_empty() {
+            let mut attribute = set.nested(TAG_SEQUENCE)?;
+            let oid = oid_to_string(attribute.expect(TAG_OID)?.value);
+            let value = attribute.read()?;
+            let text = match value.tag {
+                // BMPString is UTF-16BE; the other string types are ASCII
+                // compatible.
+                0x1e => String::from_utf16_lossy(
+                    &value
+                        .value
+                        .chunks_exact(2)
+                        .map(|c| u16::from_be_bytes([c[0], c[1]]))
+                        .collect::<Vec<_>>(),
+                ),
+                _ => String::from_utf8_lossy(value.value).into_owned(),
+            };
+            parts.push(format!("{}={}", attribute_name(&oid), text));
+        }
+    }
+    // Names are stored most significant first; display them like apksigner.
+    parts.reverse();
+    return Ok(parts.join(", "));
+}
+
+/// Formats a UTCTime or GeneralizedTime as `YYYY-MM-DD HH:MM:SS UTC`.
+fn read_time(tlv: &Tlv) -> Result<String, DerError> {
+    let text = String::from_utf8_lossy(tlv.value);
+    let digits = text.trim_end_matches('Z');
+    let invalid = || DerError::Unsupported(format!("time {:?}", text));
+    let year_digits = match tlv.tag {
+        TAG_UTC_TIME => 2,
+        TAG_GENERALIZED_TIME => 4,
+        _ => return Err(invalid()),
+    };
+    let (year, rest) = match (digits.get(..year_digits), digits.get(year_digits..)) {
+        (Some(year), Some(rest)) if rest.len() >= 8 => {
+            (year.parse::<u32>().map_err(|_| invalid())?, rest)
+        }
+        _ => return Err(invalid()),
+    };
+    let year = match tlv.tag {
+        TAG_UTC_TIME if year >= 50 => 1900 + year,
+        TAG_UTC_TIME => 2000 + year,
+        _ => year,
+    };
+    let field = |i: usize| rest.get(i..i + 2).unwrap_or("00");
+    return Ok(format!(
+        "{:04}-{}-{} {}:{}:{} UTC",
+        year,
+        field(0),
+        field(2),
+        field(4),
+        field(6),
+        field(8)
+    ));
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    return bytes.iter().map(|b| format!("{:02x}", b)).collect();
+}
+
+/// The parts of an X.509 certificate reported for APK signers.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Certificate {
+    pub subject: String,
+    pub issuer: String,
+    pub serial_number: String,
+    pub not_before: String,
+    pub not_after: String,
+    /// OID of the public key algorithm, e.g. `1.2.840.113549.1.1.1` (RSA).
+    pub public_key_algorithm: String,
+    /// Lowercase hex SHA-256 of the DER encoding.
+    pub sha256: String,
+    /// Lowercase hex SHA-256 of the DER `SubjectPublicKeyInfo`.
+    pub public_key_sha256: String,
+    #[serde(skip)]
+    pub der: Vec<u8>,
+}
+
+impl Certificate {
+    pub fn parse(der: &[u8]) -> Result<Certificate, DerError> {
+        let mut outer = DerReader::new(der);
+        let certificate = outer.expect(TAG_SEQUENCE)?;
+        let mut certificate_fields = DerReader::new(certificate.value);
+        let mut tbs = certificate_fields.nested(TAG_SEQUENCE)?;
+        if tbs.peek_tag() == Some(TAG_CONTEXT_0) {
+            tbs.read()?;
+        }
+        let serial_number = hex(tbs.expect(TAG_INTEGER)?.value);
+        tbs.expect(TAG_SEQUENCE)?;
+        let issuer = read_name(tbs.expect(TAG_SEQUENCE)?.value)?;
+        let mut validity = tbs.nested(TAG_SEQUENCE)?;
+        let not_before = read_time(&validity.read()?)?;
+        let not_after = read_time(&validity.read()?)?;
+        let subject = read_name(tbs.expect(TAG_SEQUENCE)?.value)?;
+        let public_key_info = tbs.expect(TAG_SEQUENCE)?;
+        let mut algorithm = DerReader::new(public_key_info.value).nested(TAG_SEQUENCE)?;
+        let public_key_algorithm = oid_to_string(algorithm.expect(TAG_OID)?.value);
+
+        return Ok(Certificate {
+            subject,
+            issuer,
+            serial_number,
+            not_before,
+            not_after,
+            public_key_algorithm,
+            sha256: hex(&Sha256::digest(certificate.raw)),
+            public_key_sha256: hex(&Sha256::digest(public_key_info.raw)),
+            der: certificate.raw.to_vec(),
+        });
+    }
+}
+
+/// Extracts the certificates of a PKCS #7 `SignedData` blob, as stored in
+/// the `META-INF/*.RSA`, `*.DSA` and `*.EC` files of v1 signatures.
+pub fn pkcs7_certificates(der: &[u8]) -> Result<Vec<Certificate>, DerError> {
+    let mut content_info = DerReader::new(der).nested(TAG_SEQUENCE)?;
+    let content_type = oid_to_string(content_info.expect(TAG_OID)?.value);
+    if content_type != "1.2.840.113549.1.7.2" {
+        return Err(DerError::Unsupported(format!(
+            "content type {}",
+            content_type
+        )));
+    }
+    let mut signed_data = content_info.nested(TAG_CONTEXT_0)?.nested(TAG_SEQUENCE)?;
+    signed_data.expect(TAG_INTEGER)?;
+    signed_data.expect(TAG_SET)?;
+    signed_data.expect(TAG_SEQUENCE)?;
+    let mut certificates = vec![];
+    if signed_data.peek_tag() == Some(TAG_CONTEXT_0) {
+        let mut set = signed_data.nested(TAG_CONTEXT_0)?;
+        while !set.is_empty() {
+            certificates.push(Certificate::parse(set.read()?.raw)?);
+        }
+    }
+    return Ok(certificates);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_oid_and_time() {
+        assert_eq!(oid_to_string(&[0x55, 0x04, 0x03]), "2.5.4.3");
+        assert_eq!(
+            oid_to_string(&[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01]),
+            "1.2.840.113549.1.1.1"
+        );
+        let time = Tlv {
+            tag: TAG_UTC_TIME,
+            value: b"230102030405Z",
+            raw: &[],
+        };
+        assert_eq!(read_time(&time).unwrap(), "2023-01-02 03:04:05 UTC");
+
+        let values: [(u8, &[u8]); 4] = [
+            (TAG_UTC_TIME, "é30102030405Z".as_bytes()),
+            (TAG_GENERALIZED_TIME, "2é30102030405Z".as_bytes()),
+            (TAG_GENERALIZED_TIME, b"2023"),
+            (TAG_UTC_TIME, b"\xff\xff0102030405Z"),
+        ];
+        for (tag, value) in values {
+            let time = Tlv {
+                tag,
+                value,
+                raw: &[],
+            };
+            assert!(matches!(read_time(&time), Err(DerError::Unsupported(_))));
+        }
+    }
+}
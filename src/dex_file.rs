@@ -0,0 +1,567 @@
+//! A read-only view of a dex file in memory that decodes items only when they
+//! are asked for, as an alternative to building a `DexModel`. Opening a file
+//! reads nothing but the header; ids are read straight from the buffer, and
+//! class data and code are decoded one class or method at a time.
+
+use std::{
+    borrow::Cow,
+    fmt::{self, Display, Formatter},
+    io::Cursor,
+};
+
+use crate::{
+    dex_structs::{
+        ClassDataItem, ClassDefItem, CodeItem, DexStruct, FieldIdItem, Header, MethodIdItem,
+        ProtoIdItem, StringIdItem, TypeIdItem, TypeList,
+    },
+    instructions::{decode_insn, instruction_units, Instruction},
+};
+
+const HEADER_SIZE: usize = 0x70;
+const CODE_HEADER_SIZE: usize = 16;
+
+#[derive(Debug)]
+pub enum DexFileError {
+    TooShort,
+    BadMagic,
+    /// An id section of the header lies outside the file.
+    SectionOutOfBounds(&'static str),
+}
+
+impl Display for DexFileError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            DexFileError::TooShort => write!(f, "file is shorter than a dex header"),
+            DexFileError::BadMagic => write!(f, "not a dex file"),
+            DexFileError::SectionOutOfBounds(section) => {
+                write!(f, "{} section lies outside the file", section)
+            }
+        }
+    }
+}
+
+/// A fixed-size id section: offset, count and size of each item.
+#[derive(Debug, Clone, Copy)]
+struct Section {
+    off: usize,
+    size: usize,
+    item_size: usize,
+}
+
+/// Bounds-checked reads over an item, to check that it lies within the
+/// file before `DexStruct::deserialize`, which panics on truncated data,
+/// decodes it.
+struct ItemReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl ItemReader<'_> {
+    fn skip(&mut self, len: usize) -> Option<()> {
+        let end = self.pos.checked_add(len)?;
+        if end > self.data.len() {
+            return None;
+        }
+        self.pos = end;
+        return Some(());
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        let bytes = self.data.get(self.pos..self.pos + 2)?;
+        self.pos += 2;
+        return Some(u16::from_le_bytes([bytes[0], bytes[1]]));
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        let bytes = self.data.get(self.pos..self.pos + 4)?;
+        self.pos += 4;
+        return Some(u32::from_le_bytes(bytes.try_into().unwrap()));
+    }
+
+    /// At most five bytes, as more overflow `decode_uleb128`.
+    fn uleb128(&mut self) -> Option<u32> {
+        let mut result = 0u32;
+        for k in 0..5 {
+            let byte = *self.data.get(self.pos)?;
+            self.pos += 1;
+            result |= ((byte & 0x7f) as u32) << (7 * k);
+            if byte & 0x80 == 0 {
+                return Some(result);
+            }
+        }
+        return None;
+    }
+
+    fn sleb128(&mut self) -> Option<i32> {
+        let mut result = 0i64;
+        for k in 0..5 {
+            let byte = *self.data.get(self.pos)?;
+            self.pos += 1;
+            result |= ((byte & 0x7f) as i64) << (7 * k);
+            if byte & 0x80 == 0 {
+                if byte & 0x40 != 0 {
+                    result |= -1 << (7 * (k + 1));
+                }
+                return Some(result as i32);
+            }
+        }
+        return None;
+    }
+
+    fn type_list(&mut self) -> Option<()> {
+        let size = self.u32()? as usize;
+        return self.skip(size.checked_mul(2)?);
+    }
+
+    fn class_data(&mut self) -> Option<()> {
+        let fields = self.uleb128()? as u64 + self.uleb128()? as u64;
+        let methods = self.uleb128()? as u64 + self.uleb128()? as u64;
+        // Each member takes at least one byte, so these loops end at the
+        // end of the data at the latest.
+        for _ in 0..fields * 2 + methods * 3 {
+            self.uleb128()?;
+        }
+        return Some(());
+    }
+
+    fn code_item(&mut self) -> Option<()> {
+        self.skip(6)?;
+        let tries_size = self.u16()?;
+        self.skip(4)?;
+        let insns_size = self.u32()? as usize;
+        let insns = self
+            .data
+            .get(self.pos..self.pos.checked_add(insns_size.checked_mul(2)?)?)?;
+        let units = insns
+            .chunks_exact(2)
+            .map(|u| u16::from_le_bytes([u[0], u[1]]))
+            .collect::<Vec<_>>();
+        let mut unit = 0;
+        while unit < units.len() {
+            unit += instruction_units(&units, unit).filter(|n| *n <= units.len() - unit)?;
+        }
+        self.skip(insns.len())?;
+        if tries_size == 0 {
+            return Some(());
+        }
+        if insns_size % 2 == 1 {
+            self.skip(2)?;
+        }
+        self.skip(tries_size as usize * 8)?;
+        for _ in 0..self.uleb128()? {
+            let size = self.sleb128()?;
+            for _ in 0..size.checked_abs()? {
+                self.uleb128()?;
+                self.uleb128()?;
+            }
+            if size <= 0 {
+                self.uleb128()?;
+            }
+        }
+        return Some(());
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct DexFile<'a> {
+    data: &'a [u8],
+    header: Header,
+}
+
+impl<'a> DexFile<'a> {
+    /// Checks the header and that the id sections lie within `data`.
+    pub fn new(data: &'a [u8]) -> Result<Self, DexFileError> {
+        if data.len() < HEADER_SIZE {
+            return Err(DexFileError::TooShort);
+        }
+        if &data[..4] != b"dex\n" {
+            return Err(DexFileError::BadMagic);
+        }
+        let header = Header::deserialize(&mut Cursor::new(&data[..HEADER_SIZE]));
+        let dex = DexFile { data, header };
+        for (name, section) in [
+            ("string_ids", dex.string_ids_section()),
+            ("type_ids", dex.type_ids_section()),
+            ("proto_ids", dex.proto_ids_section()),
+            ("field_ids", dex.field_ids_section()),
+            ("method_ids", dex.method_ids_section()),
+            ("class_defs", dex.class_defs_section()),
+        ] {
+            let end = section.off as u64 + (section.size * section.item_size) as u64;
+            if end > data.len() as u64 {
+                return Err(DexFileError::SectionOutOfBounds(name));
+            }
+        }
+        return Ok(dex);
+    }
+
+    pub fn header(&self) -> &Header {
+        return &self.header;
+    }
+
+    pub fn data(&self) -> &'a [u8] {
+        return self.data;
+    }
+
+    fn string_ids_section(&self) -> Section {
+        return Section {
+            off: self.header.string_ids_off as usize,
+            size: self.header.string_ids_size as usize,
+            item_size: 4,
+        };
+    }
+
+    fn type_ids_section(&self) -> Section {
+        return Section {
+            off: self.header.type_ids_off as usize,
+            size: self.header.type_ids_size as usize,
+            item_size: 4,
+        };
+    }
+
+    fn proto_ids_section(&self) -> Section {
+        return Section {
+            off: self.header.proto_ids_off as usize,
+            size: self.header.proto_ids_size as usize,
+            item_size: 12,
+        };
+    }
+
+    fn field_ids_section(&self) -> Section {
+        return Section {
+            off: self.header.field_ids_off as usize,
+            size: self.header.field_ids_size as usize,
+            item_size: 8,
+        };
+    }
+
+    fn method_ids_section(&self) -> Section {
+        return Section {
+            off: self.header.method_ids_off as usize,
+            size: self.header.method_ids_size as usize,
+            item_size: 8,
+        };
+    }
+
+    fn class_defs_section(&self) -> Section {
+        return Section {
+            off: self.header.class_defs_off as usize,
+            size: self.header.class_defs_size as usize,
+            item_size: 32,
+        };
+    }
+
+    /// Decodes item `index` of an id section, which `new` checked is in
+    /// bounds.
+    fn id<T: DexStruct>(&self, section: Section, index: u32) -> Option<T> {
+        if index as usize >= section.size {
+            return None;
+        }
+        let start = section.off + index as usize * section.item_size;
+        let bytes = &self.data[start..start + section.item_size];
+        return Some(T::deserialize(&mut Cursor::new(bytes)));
+    }
+
+    fn ids<T: DexStruct>(&self, section: Section) -> impl ExactSizeIterator<Item = T> + 'a {
+        let dex = *self;
+        return (0..section.size as u32).map(move |i| dex.id(section, i).unwrap());
+    }
+
+    /// Decodes an item of the data section at `offset`, once `fits` has
+    /// checked that all of it lies within the file.
+    fn item<T: DexStruct>(
+        &self,
+        offset: u32,
+        fits: fn(&mut ItemReader) -> Option<()>,
+    ) -> Option<T> {
+        if offset == 0 {
+            return None;
+        }
+        let data = self.data.get(offset as usize..)?;
+        fits(&mut ItemReader { data, pos: 0 })?;
+        return Some(T::deserialize(&mut Cursor::new(data)));
+    }
+
+    pub fn string_id(&self, index: u32) -> Option<StringIdItem> {
+        return self.id(self.string_ids_section(), index);
+    }
+
+    pub fn type_id(&self, index: u32) -> Option<TypeIdItem> {
+        return self.id(self.type_ids_section(), index);
+    }
+
+    pub fn proto_id(&self, index: u32) -> Option<ProtoIdItem> {
+        return self.id(self.proto_ids_section(), index);
+    }
+
+    pub fn field_id(&self, index: u32) -> Option<FieldIdItem> {
+        return self.id(self.field_ids_section(), index);
+    }
+
+    pub fn method_id(&self, index: u32) -> Option<MethodIdItem> {
+        return self.id(self.method_ids_section(), index);
+    }
+
+    pub fn class_def(&self, index: u32) -> Option<ClassDefItem> {
+        return self.id(self.class_defs_section(), index);
+    }
+
+    pub fn type_ids(&self) -> impl ExactSizeIterator<Item = TypeIdItem> + 'a {
+        return self.ids(self.type_ids_section());
+    }
+
+    pub fn proto_ids(&self) -> impl ExactSizeIterator<Item = ProtoIdItem> + 'a {
+        return self.ids(self.proto_ids_section());
+    }
+
+    pub fn field_ids(&self) -> impl ExactSizeIterator<Item = FieldIdItem> + 'a {
+        return self.ids(self.field_ids_section());
+    }
+
+    pub fn method_ids(&self) -> impl ExactSizeIterator<Item = MethodIdItem> + 'a {
+        return self.ids(self.method_ids_section());
+    }
+
+    pub fn class_defs(&self) -> impl ExactSizeIterator<Item = ClassDefItem> + 'a {
+        return self.ids(self.class_defs_section());
+    }
+
+    /// The string at `index`, borrowed from the file unless it has to be
+    /// converted from MUTF-8.
+    pub fn string(&self, index: u32) -> Option<Cow<'a, str>> {
+        let offset = self.string_id(index)?.string_data_off as usize;
+        let data = self.data.get(offset..)?;
+        // Skip the utf16_size prefix.
+        let start = data.iter().position(|b| b & 0x80 == 0)? + 1;
+        let len = data[start..].iter().position(|b| *b == 0)?;
+        let bytes = &data[start..start + len];
+        return Some(match mutf8::decode(bytes) {
+            Ok(s) => s,
+            Err(_) => String::from_utf8_lossy(bytes),
+        });
+    }
+
+    pub fn type_descriptor(&self, index: u32) -> Option<Cow<'a, str>> {
+        return self.string(self.type_id(index)?.descriptor_idx);
+    }
+
+    pub fn type_list(&self, offset: u32) -> Option<TypeList> {
+        return self.item(offset, |r| r.type_list());
+    }
+
+    /// Decodes the class data of one class, or `None` when it runs past the
+    /// end of the file.
+    pub fn class_data(&self, class_def: &ClassDefItem) -> Option<ClassDataItem> {
+        return self.item(class_def.class_data_off, |r| r.class_data());
+    }
+
+    /// Fully decodes the code item at `offset`, or `None` when it runs past
+    /// the end of the file or holds an invalid instruction.
+    pub fn code_item(&self, offset: u32) -> Option<CodeItem> {
+        return self.item(offset, |r| r.code_item());
+    }
+
+    /// A view of the code item at `offset` that decodes its instructions
+    /// while iterating.
+    pub fn code(&self, offset: u32) -> Option<Code<'a>> {
+        let start = offset as usize;
+        let header = self.data.get(start..start + CODE_HEADER_SIZE)?;
+        let u16_at = |i: usize| u16::from_le_bytes([header[i], header[i + 1]]);
+        let u32_at = |i: usize| u32::from_le_bytes(header[i..i + 4].try_into().unwrap());
+        let insns_size = u32_at(12) as usize;
+        let insns_start = start + CODE_HEADER_SIZE;
+        return Some(Code {
+            registers_size: u16_at(0),
+            ins_size: u16_at(2),
+            outs_size: u16_at(4),
+            tries_size: u16_at(6),
+            debug_info_off: u32_at(8),
+            insns: self.data.get(insns_start..insns_start + insns_size * 2)?,
+        });
+    }
+}
+
+/// The header of a code item and its undecoded instructions.
+#[derive(Debug, Clone, Copy)]
+pub struct Code<'a> {
+    pub registers_size: u16,
+    pub ins_size: u16,
+    pub outs_size: u16,
+    pub tries_size: u16,
+    pub debug_info_off: u32,
+    pub insns: &'a [u8],
+}
+
+impl<'a> Code<'a> {
+    /// Size of the instructions in 16-bit code units.
+    pub fn insns_size(&self) -> u32 {
+        return self.insns.len() as u32 / 2;
+    }
+
+    pub fn instructions(&self) -> Instructions<'a> {
+        return Instructions {
+            cursor: Cursor::new(self.insns),
+        };
+    }
+}
+
+/// Decodes instructions one at a time, with their address in code units.
+/// Stops early at an instruction that is truncated or invalid.
+#[derive(Debug, Clone)]
+pub struct Instructions<'a> {
+    cursor: Cursor<&'a [u8]>,
+}
+
+impl Iterator for Instructions<'_> {
+    type Item = (u32, Instruction);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let insns = *self.cursor.get_ref();
+        let position = self.cursor.position();
+        let rest = insns.get(position as usize..).unwrap_or_default();
+        // A payload needs at most its first four code units for its size.
+        let head = rest
+            .chunks_exact(2)
+            .take(4)
+            .map(|u| u16::from_le_bytes([u[0], u[1]]))
+            .collect::<Vec<_>>();
+        match head.first().and_then(|_| instruction_units(&head, 0)) {
+            Some(units) if units * 2 <= rest.len() => {}
+            _ => {
+                self.cursor.set_position(insns.len() as u64);
+                return None;
+            }
+        }
+        let insn = decode_insn(&mut self.cursor);
+        return Some((position as u32 / 2, insn));
+    }
+}
+
+/// A dex file mapped into memory, for scanning files without reading them
+/// in full.
+#[cfg(feature = "mmap")]
+pub struct MappedDexFile {
+    mmap: memmap2::Mmap,
+}
+
+#[cfg(feature = "mmap")]
+impl MappedDexFile {
+    pub fn open<P: AsRef<std::path::Path>>(path: P) -> std::io::Result<Self> {
+        let file = std::fs::File::open(path)?;
+        // The mapping is only read; it is undefined behaviour if another
+        // process truncates the file meanwhile, as with any mmap.
+        let mmap = unsafe { memmap2::Mmap::map(&file)? };
+        return Ok(MappedDexFile { mmap });
+    }
+
+    pub fn dex_file(&self) -> Result<DexFile<'_>, DexFileError> {
+        return DexFile::new(&self.mmap);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        resolver::DexResolver,
+        serialize,
+        test_utils::{build_dex, TestClass},
+    };
+
+    #[test]
+    fn test_matches_eager_model() {
+        let dex = build_dex(
+            vec![
+                TestClass::new("La/Main;")
+                    .field("count", "I")
+                    // const-string v0, "hello"; return-void
+                    .method("run", &["J"], "V", &[0x001a, 0x0000, 0x000e]),
+                TestClass::new("Lb/Helper;").method("help", &[], "V", &[0x000e]),
+            ],
+            &["hello"],
+        );
+        let bytes = serialize(dex.clone());
+        let file = DexFile::new(&bytes).unwrap();
+        let resolver = DexResolver::new(&dex);
+
+        assert_eq!(file.method_ids().len(), dex.method_ids.len());
+        assert!(file.method_ids().eq(dex.method_ids.iter().cloned()));
+        for i in 0..dex.string_ids.len() as u32 {
+            assert_eq!(file.string(i).unwrap(), resolver.string(i));
+        }
+        assert_eq!(
+            file.type_descriptor(0).unwrap(),
+            resolver.type_descriptor(0)
+        );
+        assert!(file.string(dex.string_ids.len() as u32).is_none());
+
+        for class_def in file.class_defs() {
+            let class_data = file.class_data(&class_def).unwrap();
+            let methods = class_data
+                .direct_methods
+                .iter()
+                .chain(class_data.virtual_methods.iter());
+            for method in methods {
+                let code = file.code(method.code_off).unwrap();
+                let eager = resolver.code_item(method.code_off).unwrap();
+                assert_eq!(code.insns_size(), eager.insns_size);
+                let (addresses, insns): (Vec<_>, Vec<_>) = code.instructions().unzip();
+                assert_eq!(insns, eager.insns);
+                assert_eq!(addresses[addresses.len() - 1], eager.insns_size - 1);
+                assert_eq!(file.code_item(method.code_off).as_ref(), Some(eager));
+            }
+        }
+
+        assert!(matches!(
+            DexFile::new(&bytes[..0x20]),
+            Err(DexFileError::TooShort)
+        ));
+    }
+
+    #[test]
+    fn test_truncated_items() {
+        // const-string v0, "hello"; return-void
+        let dex = build_dex(
+            vec![TestClass::new("La/Main;").method("run", &[], "V", &[0x001a, 0x0000, 0x000e])],
+            &["hello"],
+        );
+        let bytes = serialize(dex);
+        let file = DexFile::new(&bytes).unwrap();
+        let class_def = file.class_defs().next().unwrap();
+        let class_data = file.class_data(&class_def).unwrap();
+        let code_off = class_data
+            .direct_methods
+            .iter()
+            .chain(class_data.virtual_methods.iter())
+            .next()
+            .unwrap()
+            .code_off;
+        assert!(file.code_item(code_off).is_some());
+        assert!(file.code_item(bytes.len() as u32 + 1).is_none());
+
+        // Cut the file in the middle of const-string, and of the class data.
+        let truncated = DexFile::new(&bytes[..code_off as usize + 18]).unwrap();
+        assert!(truncated.code_item(code_off).is_none());
+        assert!(truncated.code(code_off).is_none());
+        let truncated = DexFile::new(&bytes[..class_def.class_data_off as usize + 2]).unwrap();
+        assert!(truncated.class_data(&class_def).is_none());
+
+        // A nop with bad high bits.
+        let code = Code {
+            registers_size: 1,
+            ins_size: 0,
+            outs_size: 0,
+            tries_size: 0,
+            debug_info_off: 0,
+            insns: &[0x0e, 0x00, 0x00, 0x07, 0x0e, 0x00],
+        };
+        assert_eq!(code.instructions().count(), 1);
+        // A const-string missing its index.
+        let code = Code {
+            insns: &[0x0e, 0x00, 0x1a, 0x00],
+            ..code
+        };
+        let addresses = code.instructions().map(|(a, _)| a).collect::<Vec<_>>();
+        assert_eq!(addresses, vec![0]);
+    }
+}
@@ -1,6 +1,7 @@
 use std::{fmt::Debug, io, vec};
 
 use crate::{
+    debug_info::read_debug_bytecode,
     decode::{
         decode_i8, decode_nbytes_as_f32, decode_nbytes_as_f64, decode_nbytes_signed,
         decode_nbytes_unsigned, decode_sleb128, decode_u16, decode_u32, decode_u8, decode_uleb128,
@@ -161,7 +162,7 @@ impl DexStruct for Header {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct StringIdItem {
     pub string_data_off: u32,
 }
@@ -188,7 +189,7 @@ impl DexStruct for StringIdItem {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct StringDataItem {
     pub utf16_size: uleb128,
     pub data: Vec<u8>,
@@ -228,7 +229,7 @@ impl DexStruct for StringDataItem {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct TypeIdItem {
     pub descriptor_idx: u32,
 }
@@ -256,7 +257,7 @@ impl DexStruct for TypeIdItem {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ProtoIdItem {
     pub shorty_idx: u32,
     pub return_type_idx: u32,
@@ -294,7 +295,7 @@ impl DexStruct for ProtoIdItem {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct FieldIdItem {
     pub class_idx: u16,
     pub type_idx: u16,
@@ -332,7 +333,7 @@ impl DexStruct for FieldIdItem {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct MethodIdItem {
     pub class_idx: u16,
     pub proto_idx: u16,
@@ -370,7 +371,7 @@ impl DexStruct for MethodIdItem {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ClassDefItem {
     pub class_idx: u32,
     pub access_flags: u32,
@@ -428,7 +429,7 @@ impl DexStruct for ClassDefItem {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct CallSiteIdItem {
     pub call_site_off: u32,
 }
@@ -458,7 +459,7 @@ impl DexStruct for CallSiteIdItem {
 
 pub type CallSiteItem = EncodedArrayItem;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct EncodedArrayItem {
     pub value: EncodedArray,
 }
@@ -486,7 +487,7 @@ impl DexStruct for EncodedArrayItem {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct EncodedArray {
     pub values: Vec<EncodedValue>,
 }
@@ -521,7 +522,7 @@ impl DexStruct for EncodedArray {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum EncodedValue {
     ValueByte(i8),
     ValueShort(i16),
@@ -736,7 +737,7 @@ impl DexStruct for EncodedValue {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct MethodHandleItem {
     pub method_handle_type: u16,
     pub unused1: u16,
@@ -778,7 +779,7 @@ impl DexStruct for MethodHandleItem {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ClassDataItem {
     pub static_fields: Vec<EncodedField>,
     pub instance_fields: Vec<EncodedField>,
@@ -852,7 +853,7 @@ impl DexStruct for ClassDataItem {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct EncodedField {
     pub field_idx_off: uleb128,
     pub access_flags: uleb128,
@@ -886,7 +887,7 @@ impl DexStruct for EncodedField {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct EncodedMethod {
     pub method_idx_off: uleb128,
     pub access_flags: uleb128,
@@ -926,7 +927,7 @@ impl DexStruct for EncodedMethod {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct TypeList {
     pub list: Vec<TypeItem>,
 }
@@ -958,7 +959,7 @@ impl DexStruct for TypeList {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct TypeItem {
     pub type_idx: u16,
 }
@@ -987,7 +988,7 @@ impl DexStruct for TypeItem {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct CodeItem {
     pub registers_size: u16,
     pub ins_size: u16,
@@ -1073,7 +1074,7 @@ impl DexStruct for CodeItem {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct TryItem {
     pub start_addr: u32,
     pub insn_count: u16,
@@ -1111,7 +1112,7 @@ impl DexStruct for TryItem {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct EncodedCatchHandlerList {
     pub list: Vec<EncodedCatchHandler>,
 }
@@ -1145,7 +1146,7 @@ impl DexStruct for EncodedCatchHandlerList {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct EncodedCatchHandler {
     pub handlers: Vec<EncodedTypeAddressPair>,
     pub catch_all_addr: Option<uleb128>,
@@ -1208,7 +1209,7 @@ impl DexStruct for EncodedCatchHandler {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct EncodedTypeAddressPair {
     pub type_idx: uleb128,
     pub addr: uleb128,
@@ -1239,7 +1240,7 @@ impl DexStruct for EncodedTypeAddressPair {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct DebugInfoItem {
     pub line_start: uleb128,
     pub parameter_names: Vec<uleb128p1>,
@@ -1256,10 +1257,7 @@ impl DexStruct for DebugInfoItem {
         let line_start = decode_uleb128(r);
         let parameters_size = decode_uleb128(r);
         let parameter_names = (0..parameters_size).map(|_| decode_uleb128p1(r)).collect();
-        let mut bytecode = vec![];
-        let end_opcode = 0x00; // DBG_END_SEQUENCE
-        r.read_until(end_opcode, &mut bytecode)
-            .expect("debug_info_item deserializer did not find DBG_END_SEQUENCE");
+        let bytecode = read_debug_bytecode(r);
         return Self {
             line_start,
             parameter_names,
@@ -1291,7 +1289,7 @@ impl DexStruct for DebugInfoItem {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct AnnotationsDirectoryItem {
     pub class_annotations_off: u32,
     pub field_annotations: Vec<FieldAnnotation>,
@@ -1366,7 +1364,7 @@ impl DexStruct for AnnotationsDirectoryItem {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct FieldAnnotation {
     pub field_idx: u32,
     pub annotations_off: u32,
@@ -1400,7 +1398,7 @@ impl DexStruct for FieldAnnotation {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct MethodAnnotation {
     pub method_idx: u32,
     pub annotations_off: u32,
@@ -1434,7 +1432,7 @@ impl DexStruct for MethodAnnotation {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ParameterAnnotation {
     pub method_idx: u32,
     pub annotations_off: u32,
@@ -1468,7 +1466,7 @@ impl DexStruct for ParameterAnnotation {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct AnnotationSetRefList {
     pub list: Vec<AnnotationSetRefItem>,
 }
@@ -1502,7 +1500,7 @@ impl DexStruct for AnnotationSetRefList {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct AnnotationSetRefItem {
     pub annotations_off: u32,
 }
@@ -1530,7 +1528,7 @@ impl DexStruct for AnnotationSetRefItem {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct AnnotationSetItem {
     pub entries: Vec<AnnotationOffItem>,
 }
@@ -1564,7 +1562,7 @@ impl DexStruct for AnnotationSetItem {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct AnnotationOffItem {
     pub annotation_off: u32,
 }
@@ -1592,7 +1590,7 @@ impl DexStruct for AnnotationOffItem {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct AnnotationItem {
     pub visibility: u8,
     pub annotation: EncodedAnnotation,
@@ -1626,7 +1624,7 @@ impl DexStruct for AnnotationItem {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct EncodedAnnotation {
     pub type_idx: uleb128,
     pub elements: Vec<AnnotationElement>,
@@ -1666,7 +1664,7 @@ impl DexStruct for EncodedAnnotation {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct AnnotationElement {
     pub name_idx: uleb128,
     pub value: EncodedValue,
@@ -1697,7 +1695,7 @@ impl DexStruct for AnnotationElement {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct HiddenapiClassDataItem {
     pub size: u32,
     pub offsets: Vec<u32>,
@@ -1726,7 +1724,7 @@ impl DexStruct for HiddenapiClassDataItem {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct MapList {
     pub list: Vec<MapItem>,
 }
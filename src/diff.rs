@@ -0,0 +1,491 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::{self, Display, Formatter},
+};
+
+use serde::Serialize;
+
+use crate::{dex_model::DexModel, resolver::DexResolver};
+
+/// Maximum number of method references a single dex file can hold.
+pub const METHOD_REFERENCE_LIMIT: usize = 65536;
+
+/// Structural differences between two builds, each made of one or more dex
+/// files. Classes, methods and fields are matched by resolved name, so a
+/// mapping attached to the resolvers makes obfuscated builds comparable.
+#[derive(Debug, Default, Serialize)]
+pub struct DexDiff {
+    pub added_classes: Vec<String>,
+    pub removed_classes: Vec<String>,
+    pub changed_classes: Vec<ClassDiff>,
+    pub added_strings: Vec<String>,
+    pub removed_strings: Vec<String>,
+    pub method_counts: Vec<MethodCountChange>,
+    /// Change in total bytecode size, in bytes.
+    pub bytecode_delta: i64,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ClassDiff {
+    pub name: String,
+    pub access_flags: Option<AccessFlagsChange>,
+    pub added_methods: Vec<MethodSize>,
+    pub removed_methods: Vec<MethodSize>,
+    pub changed_methods: Vec<MethodChange>,
+    pub added_fields: Vec<String>,
+    pub removed_fields: Vec<String>,
+    pub changed_fields: Vec<FieldChange>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct AccessFlagsChange {
+    pub old: u32,
+    pub new: u32,
+}
+
+/// A method and its bytecode size in bytes (`insns_size` code units of two
+/// bytes each; zero for abstract and native methods).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MethodSize {
+    pub signature: String,
+    pub size: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MethodChange {
+    pub signature: String,
+    pub old_size: u32,
+    pub new_size: u32,
+    pub delta: i64,
+    pub access_flags: Option<AccessFlagsChange>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FieldChange {
+    pub signature: String,
+    pub access_flags: AccessFlagsChange,
+}
+
+/// Method reference count of one dex file, matched by multidex position so
+/// that a lone dex compares with a lone dex whatever the file names.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MethodCountChange {
+    /// Multidex entry name of the position, `classes.dex`, `classes2.dex`...
+    pub dex: String,
+    pub old: usize,
+    pub new: usize,
+    pub delta: i64,
+}
+
+#[derive(Default)]
+struct ClassSummary {
+    access_flags: u32,
+    methods: BTreeMap<String, (u32, u32)>,
+    fields: BTreeMap<String, u32>,
+}
+
+fn flags_change(old: u32, new: u32) -> Option<AccessFlagsChange> {
+    return if old == new {
+        None
+    } else {
+        Some(AccessFlagsChange { old, new })
+    };
+}
+
+/// Summarizes every class by name. A class defined in more than one dex is
+/// summarized from the first, which is the one the runtime loads.
+fn summarize(dexes: &[(String, DexResolver)]) -> BTreeMap<String, ClassSummary> {
+    let mut classes = BTreeMap::new();
+    for (_, resolver) in dexes {
+        for class_def in resolver.dex.class_defs.iter() {
+            let name = resolver.type_name(class_def.class_idx);
+            if classes.contains_key(&name) {
+                continue;
+            }
+            let mut summary = ClassSummary {
+                access_flags: class_def.access_flags,
+                ..Default::default()
+            };
+            for (method_idx, method) in resolver.class_methods(class_def) {
+                let method_ref = resolver.method_ref(method_idx);
+                let signature = format!(
+                    "{} {}({})",
+                    resolver.java_type_name(&method_ref.return_type),
+                    resolver.method_name(method_idx),
+                    method_ref
+                        .parameters
+                        .iter()
+                        .map(|p| resolver.java_type_name(p))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+                let size = resolver
+                    .code_item(method.code_off)
+                    .map_or(0, |code| code.insns_size * 2);
+                summary
+                    .methods
+                    .insert(signature, (size, method.access_flags));
+            }
+            for (field_idx, field) in resolver.class_fields(class_def) {
+                let field_ref = resolver.field_ref(field_idx);
+                let signature = format!(
+                    "{}: {}",
+                    resolver.field_name(field_idx),
+                    resolver.java_type_name(&field_ref.field_type)
+                );
+                summary.fields.insert(signature, field.access_flags);
+            }
+            classes.insert(name, summary);
+        }
+    }
+    return classes;
+}
+
+fn resolve(dexes: &[(String, DexModel)]) -> Vec<(String, DexResolver<'_>)> {
+    return dexes
+        .iter()
+        .map(|(name, dex)| (name.clone(), DexResolver::new(dex)))
+        .collect();
+}
+
+fn bytecode_size(class: &ClassSummary) -> i64 {
+    return class.methods.values().map(|(size, _)| *size as i64).sum();
+}
+
+fn diff_class(name: &str, old: &ClassSummary, new: &ClassSummary) -> ClassDiff {
+    let mut diff = ClassDiff {
+        name: name.to_string(),
+        access_flags: flags_change(old.access_flags, new.access_flags),
+        ..Default::default()
+    };
+    for (signature, (old_size, old_flags)) in old.methods.iter() {
+        match new.methods.get(signature) {
+            Some((new_size, new_flags)) => {
+                let access_flags = flags_change(*old_flags, *new_flags);
+                if old_size != new_size || access_flags.is_some() {
+                    diff.changed_methods.push(MethodChange {
+                        signature: signature.clone(),
+                        old_size: *old_size,
+                        new_size: *new_size,
+                        delta: *new_size as i64 - *old_size as i64,
+                        access_flags,
+                    });
+                }
+            }
+            None => diff.removed_methods.push(MethodSize {
+                signature: signature.clone(),
+                size: *old_size,
+            }),
+        }
+    }
+    for (signature, (size, _)) in new.methods.iter() {
+        if !old.methods.contains_key(signature) {
+            diff.added_methods.push(MethodSize {
+                signature: signature.clone(),
+                size: *size,
+            });
+        }
+    }
+    for (signature, old_flags) in old.fields.iter() {
+        match new.fields.get(signature) {
+            Some(new_flags) => {
+                if let Some(access_flags) = flags_change(*old_flags, *new_flags) {
+                    diff.changed_fields.push(FieldChange {
+                        signature: signature.clone(),
+                        access_flags,
+                    });
+                }
+            }
+            None => diff.removed_fields.push(signature.clone()),
+        }
+    }
+    for signature in new.fields.keys() {
+        if !old.fields.contains_key(signature) {
+            diff.added_fields.push(signature.clone());
+        }
+    }
+    return diff;
+}
+
+impl ClassDiff {
+    pub fn is_empty(&self) -> bool {
+        return self.access_flags.is_none()
+            && self.added_methods.is_empty()
+            && self.removed_methods.is_empty()
+            && self.changed_methods.is_empty()
+            && self.added_fields.is_empty()
+            && self.removed_fields.is_empty()
+            && self.changed_fields.is_empty();
+    }
+}
+
+impl DexDiff {
+    /// Compares two builds given as `(entry name, dex)` pairs, e.g. the
+    /// output of `apk::load_dex_files`.
+    pub fn new(old: &[(String, DexModel)], new: &[(String, DexModel)]) -> DexDiff {
+        return DexDiff::from_resolvers(&resolve(old), &resolve(new));
+    }
+
+    /// Compares two builds given as `(entry name, resolver)` pairs in
+    /// multidex order. Classes and members are matched by the names the
+    /// resolvers report.
+    pub fn from_resolvers(old: &[(String, DexResolver)], new: &[(String, DexResolver)]) -> DexDiff {
+        let mut diff = DexDiff::default();
+
+        let old_classes = summarize(old);
+        let new_classes = summarize(new);
+        for (name, old_class) in old_classes.iter() {
+            match new_classes.get(name) {
+                Some(new_class) => {
+                    let class_diff = diff_class(name, old_class, new_class);
+                    if !class_diff.is_empty() {
+                        diff.changed_classes.push(class_diff);
+                    }
+                }
+                None => diff.removed_classes.push(name.clone()),
+            }
+            diff.bytecode_delta -= bytecode_size(old_class);
+        }
+        for (name, new_class) in new_classes.iter() {
+            if !old_classes.contains_key(name) {
+                diff.added_classes.push(name.clone());
+            }
+            diff.bytecode_delta += bytecode_size(new_class);
+        }
+
+        let strings = |dexes: &[(String, DexResolver)]| {
+            dexes
+                .iter()
+                .flat_map(|(_, resolver)| resolver.strings().iter().cloned())
+                .collect::<BTreeSet<_>>()
+        };
+        let old_strings = strings(old);
+        let new_strings = strings(new);
+        diff.added_strings = new_strings.difference(&old_strings).cloned().collect();
+        diff.removed_strings = old_strings.difference(&new_strings).cloned().collect();
+
+        let count = |dexes: &[(String, DexResolver)], position: usize| {
+            dexes
+                .get(position)
+                .map_or(0, |(_, resolver)| resolver.dex.method_ids.len())
+        };
+        for position in 0..old.len().max(new.len()) {
+            let (old_count, new_count) = (count(old, position), count(new, position));
+            let dex = match position {
+                0 => "classes.dex".to_string(),
+                _ => format!("classes{}.dex", position + 1),
+            };
+            diff.method_counts.push(MethodCountChange {
+                dex,
+                old: old_count,
+                new: new_count,
+                delta: new_count as i64 - old_count as i64,
+            });
+        }
+
+        return diff;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.added_classes.is_empty()
+            && self.removed_classes.is_empty()
+            && self.changed_classes.is_empty()
+            && self.added_strings.is_empty()
+            && self.removed_strings.is_empty()
+            && self.method_counts.iter().all(|count| count.delta == 0);
+    }
+
+    pub fn to_json(&self) -> String {
+        return serde_json::to_string_pretty(self).unwrap();
+    }
+}
+
+fn format_flags(change: &AccessFlagsChange) -> String {
+    return format!("access 0x{:x} -> 0x{:x}", change.old, change.new);
+}
+
+impl Display for DexDiff {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, "Method references (limit {}):", METHOD_REFERENCE_LIMIT)?;
+        for count in self.method_counts.iter() {
+            writeln!(
+                f,
+                "  {}: {} -> {} ({:+}, {:.1}% of limit){}",
+                count.dex,
+                count.old,
+                count.new,
+                count.delta,
+                count.new as f64 * 100.0 / METHOD_REFERENCE_LIMIT as f64,
+                if count.new > METHOD_REFERENCE_LIMIT {
+                    " OVER LIMIT"
+                } else {
+                    ""
+                }
+            )?;
+        }
+        writeln!(f, "Bytecode: {:+} bytes", self.bytecode_delta)?;
+
+        writeln!(
+            f,
+            "Classes: {} added, {} removed, {} changed",
+            self.added_classes.len(),
+            self.removed_classes.len(),
+            self.changed_classes.len()
+        )?;
+        for class in self.added_classes.iter() {
+            writeln!(f, "  + {}", class)?;
+        }
+        for class in self.removed_classes.iter() {
+            writeln!(f, "  - {}", class)?;
+        }
+        for class in self.changed_classes.iter() {
+            write!(f, "  ~ {}", class.name)?;
+            if let Some(change) = &class.access_flags {
+                write!(f, " ({})", format_flags(change))?;
+            }
+            writeln!(f)?;
+            for method in class.added_methods.iter() {
+                writeln!(f, "      + {} [{} bytes]", method.signature, method.size)?;
+            }
+            for method in class.removed_methods.iter() {
+                writeln!(f, "      - {} [{} bytes]", method.signature, method.size)?;
+            }
+            for method in class.changed_methods.iter() {
+                write!(
+                    f,
+                    "      ~ {} [{} -> {} bytes, {:+}]",
+                    method.signature, method.old_size, method.new_size, method.delta
+                )?;
+                if let Some(change) = &method.access_flags {
+                    write!(f, " ({})", format_flags(change))?;
+                }
+                writeln!(f)?;
+            }
+            for field in class.added_fields.iter() {
+                writeln!(f, "      + {}", field)?;
+            }
+            for field in class.removed_fields.iter() {
+                writeln!(f, "      - {}", field)?;
+            }
+            for field in class.changed_fields.iter() {
+                writeln!(
+                    f,
+                    "      ~ {} ({})",
+                    field.signature,
+                    format_flags(&field.access_flags)
+                )?;
+            }
+        }
+
+        writeln!(
+            f,
+            "Strings: {} added, {} removed",
+            self.added_strings.len(),
+            self.removed_strings.len()
+        )?;
+        for string in self.added_strings.iter() {
+            writeln!(f, "  + {:?}", string)?;
+        }
+        for string in self.removed_strings.iter() {
+            writeln!(f, "  - {:?}", string)?;
+        }
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{build_dex, TestClass};
+
+    #[test]
+    fn test_diff() {
+        // return-void, and a longer body of nop; nop; return-void.
+        let old = build_dex(
+            vec![
+                TestClass::new("Lcom/example/Kept;")
+                    .method("run", &[], "V", &[0x000e])
+                    .field("count", "I"),
+                TestClass::new("Lcom/example/Gone;").method("run", &[], "V", &[0x000e]),
+            ],
+            &["old string"],
+        );
+        let new = build_dex(
+            vec![
+                TestClass::new("Lcom/example/Kept;")
+                    .method("run", &[], "V", &[0x0000, 0x0000, 0x000e])
+                    .method("extra", &["I"], "V", &[0x000e])
+                    .field("count", "J"),
+                TestClass::new("Lcom/example/Fresh;"),
+            ],
+            &["new string"],
+        );
+
+        let diff = DexDiff::new(
+            &[("classes.dex".to_string(), old)],
+            &[("classes.dex".to_string(), new)],
+        );
+        assert_eq!(diff.added_classes, vec!["com.example.Fresh"]);
+        assert_eq!(diff.removed_classes, vec!["com.example.Gone"]);
+        assert_eq!(diff.changed_classes.len(), 1);
+
+        let kept = &diff.changed_classes[0];
+        assert_eq!(kept.name, "com.example.Kept");
+        assert_eq!(
+            kept.added_methods,
+            vec![MethodSize {
+                signature: "void extra(int)".to_string(),
+                size: 2,
+            }]
+        );
+        assert_eq!(kept.changed_methods.len(), 1);
+        assert_eq!(kept.changed_methods[0].signature, "void run()");
+        assert_eq!(kept.changed_methods[0].delta, 4);
+        assert_eq!(kept.added_fields, vec!["count: long"]);
+        assert_eq!(kept.removed_fields, vec!["count: int"]);
+
+        assert!(diff.added_strings.contains(&"new string".to_string()));
+        assert!(diff.removed_strings.contains(&"old string".to_string()));
+        assert_eq!(diff.method_counts[0].old, 2);
+        assert_eq!(diff.method_counts[0].new, 2);
+        assert_eq!(diff.bytecode_delta, 6 + 2 - 2 - 2);
+
+        let report = diff.to_string();
+        assert!(report.contains("  ~ com.example.Kept"));
+        assert!(report.contains("      ~ void run() [2 -> 6 bytes, +4]"));
+        assert!(diff.to_json().contains("\"added_classes\""));
+    }
+
+    #[test]
+    fn test_diff_pairs_dexes_by_position() {
+        let dex = || {
+            build_dex(
+                vec![TestClass::new("Lcom/example/A;").method("run", &[], "V", &[0x000e])],
+                &[],
+            )
+        };
+        let diff = DexDiff::new(
+            &[("old.dex".to_string(), dex())],
+            &[("new.dex".to_string(), dex())],
+        );
+        assert!(diff.is_empty());
+        assert_eq!(diff.method_counts.len(), 1);
+        assert_eq!(diff.method_counts[0].dex, "classes.dex");
+
+        // A duplicate in a later dex does not hide the first definition.
+        let longer = build_dex(
+            vec![TestClass::new("Lcom/example/A;").method("run", &[], "V", &[0, 0x000e])],
+            &[],
+        );
+        let diff = DexDiff::new(
+            &[("classes.dex".to_string(), dex())],
+            &[
+                ("classes.dex".to_string(), dex()),
+                ("classes2.dex".to_string(), longer),
+            ],
+        );
+        assert!(diff.changed_classes.is_empty());
+        assert_eq!(diff.method_counts[1].dex, "classes2.dex");
+        assert_eq!(diff.method_counts[1].old, 0);
+    }
+}
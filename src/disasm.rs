@@ -0,0 +1,219 @@
+//! Smali-like listings of method bytecode with every index operand
+//! resolved.
+
+use crate::{
+    dex_structs::{CodeItem, EncodedMethod},
+    instructions::IndexKind,
+    resolver::{java_to_descriptor, DexResolver},
+};
+
+const ACCESS_FLAGS: [(u32, &str); 14] = [
+    (0x1, "public"),
+    (0x2, "private"),
+    (0x4, "protected"),
+    (0x8, "static"),
+    (0x10, "final"),
+    (0x20, "synchronized"),
+    (0x40, "bridge"),
+    (0x80, "varargs"),
+    (0x100, "native"),
+    (0x400, "abstract"),
+    (0x800, "strictfp"),
+    (0x1000, "synthetic"),
+    (0x10000, "constructor"),
+    (0x20000, "declared-synchronized"),
+];
+
+/// Method access flags as smali keywords, e.g. `public static`.
+pub fn method_access_flags(flags: u32) -> String {
+    return ACCESS_FLAGS
+        .iter()
+        .filter(|(flag, _)| flags & flag != 0)
+        .map(|(_, name)| *name)
+        .collect::<Vec<_>>()
+        .join(" ");
+}
+
+/// The item an index operand refers to: its smali form, or its original
+/// Java name when a mapping is attached.
+fn resolve_index(resolver: &DexResolver, kind: IndexKind, index: u32) -> String {
+    let mapped = resolver.mapping().is_some();
+    return match kind {
+        IndexKind::String => format!("{:?}", resolver.string(index)),
+        IndexKind::Type if mapped => resolver.type_name(index),
+        IndexKind::Type => resolver.type_descriptor(index).to_string(),
+        IndexKind::Field if mapped => resolver.field_display(index),
+        IndexKind::Field => resolver.field_ref(index).signature(),
+        IndexKind::Method if mapped => resolver.method_display(index),
+        IndexKind::Method => resolver.method_ref(index).signature(),
+        IndexKind::Proto => {
+            let proto = &resolver.dex.proto_ids[index as usize];
+            format!(
+                "({}){}",
+                resolver
+                    .type_list_descriptors(proto.parameters_off)
+                    .join(""),
+                resolver.type_descriptor(proto.return_type_idx)
+            )
+        }
+        IndexKind::CallSite => format!("call_site@{}", index),
+        IndexKind::MethodHandle => format!("method_handle@{}", index),
+    };
+}
+
+/// One line per instruction: the address in code units, the instruction and
+/// the items its index operands refer to.
+pub fn disassemble_code(resolver: &DexResolver, code: &CodeItem) -> String {
+    let mut text = String::new();
+    let mut address = 0;
+    for insn in code.insns.iter() {
+        let refs = insn
+            .index_refs()
+            .iter()
+            .map(|r| resolve_index(resolver, r.kind, r.index))
+            .collect::<Vec<_>>();
+        text.push_str(&format!("    {:04x}: {}", address, insn));
+        if !refs.is_empty() {
+            text.push_str(&format!("  # {}", refs.join(", ")));
+        }
+        text.push('\n');
+        address += insn.size() / 2;
+    }
+    return text;
+}
+
+/// A method definition with its bytecode, if it has any. With a mapping
+/// attached the definition line ends with the original name.
+pub fn disassemble_method(
+    resolver: &DexResolver,
+    method_idx: u32,
+    method: &EncodedMethod,
+) -> String {
+    let signature = resolver.method_ref(method_idx).signature();
+    let flags = method_access_flags(method.access_flags);
+    let mut text = format!(
+        ".method {}{}",
+        if flags.is_empty() { flags } else { flags + " " },
+        signature.split_once("->").map_or(&*signature, |(_, m)| m)
+    );
+    if resolver.mapping().is_some() {
+        text.push_str(&format!("  # {}", resolver.method_display(method_idx)));
+    }
+    text.push('\n');
+    if let Some(code) = resolver.code_item(method.code_off) {
+        text.push_str(&format!(
+            "    .registers {}, ins {}, outs {}\n",
+            code.registers_size, code.ins_size, code.outs_size
+        ));
+        text.push_str(&disassemble_code(resolver, code));
+    }
+    text.push_str(".end method\n");
+    return text;
+}
+
+/// Disassembles `target`, a class (`com.Foo` or `Lcom/Foo;`) or a method
+/// of one (`com.Foo.bar`, `Lcom/Foo;->bar` or `Lcom/Foo;->bar(I)V`).
+/// With a mapping attached, Java class and method names may also be the
+/// original ones. `None` when no class or method in this dex matches.
+pub fn disassemble(resolver: &DexResolver, target: &str) -> Option<String> {
+    let to_descriptor = |class: &str| {
+        if class.ends_with(';') || class.starts_with('[') {
+            return class.to_string();
+        }
+        return match resolver.mapping().and_then(|m| m.class_by_original(class)) {
+            Some(class) => java_to_descriptor(&class.obfuscated),
+            None => java_to_descriptor(class),
+        };
+    };
+    let (class, method) = match target.split_once("->") {
+        Some((class, method)) => (to_descriptor(class), Some(method)),
+        None if resolver
+            .class_def_by_descriptor(&to_descriptor(target))
+            .is_some() =>
+        {
+            (to_descriptor(target), None)
+        }
+        None => {
+            let (class, method) = target.rsplit_once('.')?;
+            (to_descriptor(class), Some(method))
+        }
+    };
+    let class_def = resolver.class_def_by_descriptor(&class)?;
+    let mut text = String::new();
+    for (method_idx, encoded) in resolver.class_methods(class_def) {
+        let method_ref = resolver.method_ref(method_idx);
+        let selected = method.is_none_or(|method| {
+            method == method_ref.name
+                || method_ref.signature().split_once("->").unwrap().1 == method
+                || (resolver.mapping().is_some() && resolver.method_name(method_idx) == method)
+        });
+        if selected {
+            if !text.is_empty() {
+                text.push('\n');
+            }
+            text.push_str(&disassemble_method(resolver, method_idx, encoded));
+        }
+    }
+    if text.is_empty() {
+        return None;
+    }
+    if resolver.mapping().is_some() {
+        let original = resolver.type_name(class_def.class_idx);
+        return Some(format!(".class {}  # {}\n\n{}", class, original, text));
+    }
+    return Some(format!(".class {}\n\n{}", class, text));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        mapping::Mapping,
+        test_utils::{build_dex, TestClass},
+    };
+
+    #[test]
+    fn test_disassemble() {
+        let dex = build_dex(
+            vec![TestClass::new("La/A;")
+                .method("run", &[], "V", &[0x0070, 0x0000, 0x0000, 0x000e])
+                .method("other", &["I"], "I", &[0x000f])],
+            &[],
+        );
+        let resolver = DexResolver::new(&dex);
+
+        let text = disassemble(&resolver, "a.A.run").unwrap();
+        assert!(text.starts_with(".class La/A;\n\n.method public run()V\n"));
+        assert!(text.contains("    0000: invoke-direct"));
+        assert!(text.contains("  # La/A;->other(I)I\n"));
+        assert!(text.contains("    0003: return-void\n"));
+        assert!(!text.contains("other(I)I\n    .registers"));
+
+        let class = disassemble(&resolver, "La/A;").unwrap();
+        assert_eq!(class.matches(".method").count(), 2);
+        assert!(disassemble(&resolver, "La/A;->other(I)I").is_some());
+        assert!(disassemble(&resolver, "a.A.missing").is_none());
+        assert!(disassemble(&resolver, "a.B").is_none());
+    }
+
+    #[test]
+    fn test_disassemble_with_mapping() {
+        let dex = build_dex(
+            vec![TestClass::new("La/A;")
+                .method("a", &[], "V", &[0x0070, 0x0001, 0x0000, 0x000e])
+                .method("b", &["I"], "I", &[0x000f])],
+            &[],
+        );
+        let mapping = Mapping::parse(
+            "com.example.Main -> a.A:\n    void run() -> a\n    int other(int) -> b\n",
+        )
+        .unwrap();
+        let resolver = DexResolver::new(&dex).with_mapping(&mapping);
+        let text = disassemble(&resolver, "com.example.Main.run").unwrap();
+        assert!(text.starts_with(".class La/A;  # com.example.Main\n\n"));
+        assert!(text.contains(".method public a()V  # com.example.Main.run()\n"));
+        assert!(text.contains("  # com.example.Main.other(int)\n"));
+        assert!(disassemble(&resolver, "com.example.Main").is_some());
+        assert!(disassemble(&resolver, "a.A.b").is_some());
+    }
+}
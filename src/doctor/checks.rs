@@ -0,0 +1,612 @@
+//! The built-in checks of the doctor.
+
+use super::{AppContext, Category, Check, Finding, Severity};
+use crate::{
+    dangerous_apis::{DangerousApiReport, RuleSet},
+    findings::Location,
+    manifest::ComponentKind,
+    ref_counts::INDEX_LIMIT,
+};
+
+/// Entries whose compression ratio would not improve by deflating them.
+const COMPRESSED_EXTENSIONS: [&str; 13] = [
+    ".png", ".jpg", ".jpeg", ".webp", ".gif", ".mp3", ".mp4", ".ogg", ".webm", ".zip", ".gz",
+    ".jar", ".apk",
+];
+const LARGE_ASSET_SIZE: u64 = 100 * 1024;
+const BASELINE_PROFILE: &str = "assets/dexopt/baseline.prof";
+
+/// Declares a unit struct implementing `Check`. The body of `run` sees the
+/// check as `$check` and the app as `$context`.
+macro_rules! check {
+    (
+        $name:ident, $id:literal, $severity:ident, $category:ident, $remediation:literal,
+        |$check:ident, $context:ident| $body:block
+    ) => {
+        pub(crate) struct $name;
+
+        impl Check for $name {
+            fn id(&self) -> &'static str {
+                return $id;
+            }
+
+            fn severity(&self) -> Severity {
+                return Severity::$severity;
+            }
+
+            fn category(&self) -> Category {
+                return Category::$category;
+            }
+
+            fn remediation(&self) -> &'static str {
+                return $remediation;
+            }
+
+            fn run(&self, $context: &AppContext) -> Vec<Finding> {
+                let $check: &dyn Check = self;
+                $body
+            }
+        }
+    };
+}
+
+check!(
+    Debuggable,
+    "debuggable",
+    High,
+    Security,
+    "Remove android:debuggable from the manifest and let the build type set it.",
+    |check, context| {
+        return match context.manifest.as_ref().and_then(|m| m.debuggable) {
+            Some(true) => vec![Finding::new(
+                check,
+                "the application is debuggable".to_string(),
+            )],
+            _ => vec![],
+        };
+    }
+);
+
+check!(
+    AllowBackup,
+    "allow-backup",
+    Low,
+    Security,
+    "Set android:allowBackup=\"false\", or restrict backups with android:fullBackupContent.",
+    |check, context| {
+        return match context.manifest.as_ref() {
+            Some(manifest) if manifest.allow_backup.unwrap_or(true) => vec![Finding::new(
+                check,
+                "application data can be backed up and restored over adb".to_string(),
+            )],
+            _ => vec![],
+        };
+    }
+);
+
+check!(
+    CleartextTraffic,
+    "cleartext-traffic",
+    Medium,
+    Security,
+    "Set android:usesCleartextTraffic=\"false\" or allow cleartext per domain in a network security config.",
+    |check, context| {
+        let manifest = match context.manifest.as_ref() {
+            Some(manifest) => manifest,
+            None => return vec![],
+        };
+        // Cleartext is allowed by default below API 28.
+        let allowed = manifest
+            .uses_cleartext_traffic
+            .unwrap_or(manifest.target_sdk.is_some_and(|sdk| sdk < 28));
+        if !allowed {
+            return vec![];
+        }
+        return vec![Finding::new(
+            check,
+            "the application allows cleartext network traffic".to_string(),
+        )];
+    }
+);
+
+check!(
+    ExportedComponents,
+    "exported-components",
+    Medium,
+    Security,
+    "Set android:exported=\"false\" on components other apps do not need, or protect them with android:permission.",
+    |check, context| {
+        let manifest = match context.manifest.as_ref() {
+            Some(manifest) => manifest,
+            None => return vec![],
+        };
+        let mut findings = vec![];
+        for component in manifest.components.iter() {
+            if !component.is_exported() || component.permission.is_some() {
+                continue;
+            }
+            let launcher = component.intent_filters.iter().any(|filter| {
+                filter.actions.iter().any(|a| a == "android.intent.action.MAIN")
+                    && filter
+                        .categories
+                        .iter()
+                        .any(|c| c == "android.intent.category.LAUNCHER")
+            });
+            let activity = matches!(
+                component.kind,
+                ComponentKind::Activity | ComponentKind::ActivityAlias
+            );
+            if activity && launcher {
+                continue;
+            }
+            findings.push(
+                Finding::new(
+                    check,
+                    format!("exported {} without a permission", component.kind.tag()),
+                )
+                .at(Location::class(&component.name)),
+            );
+        }
+        return findings;
+    }
+);
+
+check!(
+    MissingClasses,
+    "missing-classes",
+    High,
+    Correctness,
+    "Add the classes to the app or remove them from the manifest; check shrinker keep rules.",
+    |check, context| {
+        let manifest = match context.manifest.as_ref() {
+            Some(manifest) if !context.dexes.is_empty() => manifest,
+            _ => return vec![],
+        };
+        return manifest
+            .missing_classes(&context.dexes)
+            .into_iter()
+            .map(|class| {
+                Finding::new(
+                    check,
+                    "declared in the manifest but not defined".to_string(),
+                )
+                .at(Location::class(&class))
+            })
+            .collect();
+    }
+);
+
+check!(
+    DangerousApis,
+    "dangerous-apis",
+    Medium,
+    Security,
+    "Review each call; run `apkdoctor lint` for the rule behind every finding.",
+    |check, context| {
+        let report = DangerousApiReport::from_resolvers(&context.resolvers(), &RuleSet::builtin());
+        return report
+            .findings
+            .into_iter()
+            .map(|finding| {
+                let location = Location::from(&finding);
+                let mut result = Finding::new(check, finding.message).at(location);
+                result.severity = finding.severity;
+                result
+            })
+            .collect();
+    }
+);
+
+check!(
+    MethodCount,
+    "method-count",
+    Medium,
+    Size,
+    "Enable code shrinking, or split code into more dex files before the 64K reference limit is hit.",
+    |check, context| {
+        let mut findings = vec![];
+        for (name, dex) in context.dexes.iter() {
+            let count = dex.method_ids.len();
+            if count * 10 > INDEX_LIMIT * 9 {
+                findings.push(
+                    Finding::new(
+                        check,
+                        format!("{} of {} method references used", count, INDEX_LIMIT),
+                    )
+                    .at(Location::dex(name)),
+                );
+            }
+        }
+        return findings;
+    }
+);
+
+check!(
+    UnstrippedNativeLibs,
+    "unstripped-native-libs",
+    Low,
+    Size,
+    "Strip symbol tables and debug sections from release libraries and upload them to crash reporting instead.",
+    |check, context| {
+        return context
+            .native_libs
+            .iter()
+            .filter(|lib| lib.has_symbols == Some(true))
+            .map(|lib| {
+                Finding::new(check, format!("{} bytes with symbols", lib.size)).at(Location::entry(&lib.path))
+            })
+            .collect();
+    }
+);
+
+check!(
+    BaselineProfile,
+    "baseline-profile",
+    Info,
+    Performance,
+    "Generate a baseline profile with the Jetpack Macrobenchmark library to speed up startup.",
+    |check, context| {
+        // Bundles keep the profile under BUNDLE-METADATA instead.
+        let found = context
+            .entries
+            .iter()
+            .any(|e| e.name == BASELINE_PROFILE || e.name.ends_with("/baseline.prof"));
+        if context.entries.is_empty() || found {
+            return vec![];
+        }
+        return vec![Finding::new(
+            check,
+            format!("{} is missing", BASELINE_PROFILE),
+        )];
+    }
+);
+
+check!(
+    UncompressedAssets,
+    "uncompressed-assets",
+    Info,
+    Size,
+    "Let the build compress the asset, or keep it stored only if it is memory-mapped at runtime.",
+    |check, context| {
+        return context
+            .entries
+            .iter()
+            .filter(|e| e.name.starts_with("assets/") || e.name.contains("/assets/"))
+            .filter(|e| e.size >= LARGE_ASSET_SIZE && e.compressed_size == e.size)
+            .filter(|e| {
+                let name = e.name.to_lowercase();
+                !COMPRESSED_EXTENSIONS.iter().any(|ext| name.ends_with(ext))
+            })
+            .map(|e| {
+                Finding::new(check, format!("{} bytes stored uncompressed", e.size))
+                    .at(Location::entry(&e.name))
+            })
+            .collect();
+    }
+);
+
+/// Every built-in check, in the order they run.
+pub fn builtin_checks() -> Vec<Box<dyn Check>> {
+    return vec![
+        Box::new(Debuggable),
+        Box::new(AllowBackup),
+        Box::new(CleartextTraffic),
+        Box::new(ExportedComponents),
+        Box::new(MissingClasses),
+        Box::new(DangerousApis),
+        Box::new(MethodCount),
+        Box::new(UnstrippedNativeLibs),
+        Box::new(BaselineProfile),
+        Box::new(UncompressedAssets),
+    ];
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        dex_model::DexModel,
+        doctor::{Doctor, EntryInfo, NativeLib},
+        manifest::{Component, IntentFilter, Manifest},
+        resolver::DexResolver,
+        test_utils::{build_dex, TestClass},
+    };
+
+    fn entry(name: &str, size: u64, compressed_size: u64) -> EntryInfo {
+        return EntryInfo {
+            name: name.to_string(),
+            size,
+            compressed_size,
+        };
+    }
+
+    fn component(kind: ComponentKind, name: &str) -> Component {
+        return Component {
+            kind,
+            name: name.to_string(),
+            exported: None,
+            enabled: None,
+            permission: None,
+            target_activity: None,
+            authorities: None,
+            intent_filters: vec![],
+        };
+    }
+
+    fn with_manifest(manifest: Manifest) -> AppContext {
+        let mut context = AppContext::from_dex_files(vec![]);
+        context.manifest = Some(manifest);
+        return context;
+    }
+
+    fn locations(findings: &[Finding]) -> Vec<Location> {
+        return findings.iter().map(|f| f.location.clone()).collect();
+    }
+
+    #[test]
+    fn test_debuggable() {
+        assert!(Debuggable
+            .run(&AppContext::from_dex_files(vec![]))
+            .is_empty());
+        assert!(Debuggable
+            .run(&with_manifest(Manifest::default()))
+            .is_empty());
+        let manifest = Manifest {
+            debuggable: Some(true),
+            ..Manifest::default()
+        };
+        let findings = Debuggable.run(&with_manifest(manifest));
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].severity, Severity::High);
+    }
+
+    #[test]
+    fn test_allow_backup() {
+        assert!(AllowBackup
+            .run(&AppContext::from_dex_files(vec![]))
+            .is_empty());
+        // Backups are allowed unless the manifest says otherwise.
+        assert_eq!(
+            AllowBackup.run(&with_manifest(Manifest::default())).len(),
+            1
+        );
+        let manifest = Manifest {
+            allow_backup: Some(false),
+            ..Manifest::default()
+        };
+        assert!(AllowBackup.run(&with_manifest(manifest)).is_empty());
+    }
+
+    #[test]
+    fn test_cleartext_traffic() {
+        let run = |target_sdk: Option<i64>, uses_cleartext_traffic: Option<bool>| {
+            let manifest = Manifest {
+                target_sdk,
+                uses_cleartext_traffic,
+                ..Manifest::default()
+            };
+            return CleartextTraffic.run(&with_manifest(manifest)).len();
+        };
+        assert_eq!(run(Some(27), None), 1);
+        assert_eq!(run(Some(28), None), 0);
+        assert_eq!(run(None, None), 0);
+        assert_eq!(run(Some(34), Some(true)), 1);
+        assert_eq!(run(Some(27), Some(false)), 0);
+    }
+
+    #[test]
+    fn test_exported_components() {
+        let launcher = IntentFilter {
+            actions: vec!["android.intent.action.MAIN".to_string()],
+            categories: vec!["android.intent.category.LAUNCHER".to_string()],
+            ..IntentFilter::default()
+        };
+        let view = IntentFilter {
+            actions: vec!["android.intent.action.VIEW".to_string()],
+            ..IntentFilter::default()
+        };
+        let mut main = component(ComponentKind::Activity, "com.app.Main");
+        main.intent_filters = vec![launcher.clone()];
+        let mut link = component(ComponentKind::Activity, "com.app.Link");
+        link.intent_filters = vec![view];
+        let mut sync = component(ComponentKind::Service, "com.app.Sync");
+        sync.exported = Some(true);
+        // A launcher filter only exempts activities.
+        let mut boot = component(ComponentKind::Receiver, "com.app.Boot");
+        boot.intent_filters = vec![launcher];
+        let mut guarded = component(ComponentKind::Service, "com.app.Guarded");
+        guarded.exported = Some(true);
+        guarded.permission = Some("com.app.permission.BIND".to_string());
+        let mut private = component(ComponentKind::Provider, "com.app.Data");
+        private.exported = Some(false);
+        let internal = component(ComponentKind::Service, "com.app.Internal");
+        let manifest = Manifest {
+            components: vec![main, link, sync, boot, guarded, private, internal],
+            ..Manifest::default()
+        };
+        let findings = ExportedComponents.run(&with_manifest(manifest));
+        assert_eq!(
+            locations(&findings),
+            vec![
+                Location::class("com.app.Link"),
+                Location::class("com.app.Sync"),
+                Location::class("com.app.Boot"),
+            ]
+        );
+        assert_eq!(findings[1].message, "exported service without a permission");
+    }
+
+    #[test]
+    fn test_missing_classes() {
+        let manifest = Manifest {
+            package: "com.app".to_string(),
+            application_class: Some("com.app.App".to_string()),
+            components: vec![
+                component(ComponentKind::Activity, "com.app.Main"),
+                component(ComponentKind::Service, "com.app.Gone"),
+            ],
+            ..Manifest::default()
+        };
+        // Without dex files there is nothing to look classes up in.
+        assert!(MissingClasses
+            .run(&with_manifest(manifest.clone()))
+            .is_empty());
+
+        let dex = build_dex(
+            vec![
+                TestClass::new("Lcom/app/App;"),
+                TestClass::new("Lcom/app/Main;"),
+            ],
+            &[],
+        );
+        let mut context = AppContext::from_dex_files(vec![("classes.dex".to_string(), dex)]);
+        assert!(MissingClasses.run(&context).is_empty());
+        context.manifest = Some(manifest);
+        let findings = MissingClasses.run(&context);
+        assert_eq!(locations(&findings), vec![Location::class("com.app.Gone")]);
+    }
+
+    /// Types: 0 Landroid/os/Environment;, 1 Lcom/app/Main;, ...
+    /// Methods: 0 Environment.getExternalStorageDirectory, 1 Main.run,
+    /// 2 Cipher.getInstance
+    fn dangerous_dex(code: &[u16]) -> DexModel {
+        return build_dex(
+            vec![
+                TestClass::new("Landroid/os/Environment;").method(
+                    "getExternalStorageDirectory",
+                    &[],
+                    "Ljava/io/File;",
+                    &[0x0011],
+                ),
+                TestClass::new("Lcom/app/Main;").method("run", &[], "V", code),
+                TestClass::new("Ljavax/crypto/Cipher;").method(
+                    "getInstance",
+                    &["Ljava/lang/String;"],
+                    "Ljavax/crypto/Cipher;",
+                    &[0x0011],
+                ),
+            ],
+            &["AES/ECB/PKCS5Padding"],
+        );
+    }
+
+    #[test]
+    fn test_dangerous_apis() {
+        // invoke-static {}, method@0; return-void
+        let dex = dangerous_dex(&[0x0071, 0x0000, 0x0000, 0x000e]);
+        let resolver = DexResolver::new(&dex);
+        assert_eq!(resolver.method_ref(0).name, "getExternalStorageDirectory");
+        assert_eq!(resolver.method_ref(2).name, "getInstance");
+        assert_eq!(resolver.string(0), "AES/ECB/PKCS5Padding");
+        let context = AppContext::from_dex_files(vec![("classes.dex".to_string(), dex)]);
+        let doctor = Doctor::with_checks(vec![Box::new(DangerousApis)]);
+        let report = doctor.run(&context);
+        assert_eq!(report.findings.len(), 1);
+        assert_eq!(report.findings[0].severity, Severity::Low);
+        assert_eq!(report.checks[0].severity, Severity::Low);
+
+        // invoke-static {}, method@0; const-string v0, "AES/ECB/...";
+        // invoke-static {v0}, method@2; return-void
+        let dex = dangerous_dex(&[
+            0x0071, 0x0000, 0x0000, 0x001a, 0x0000, 0x1071, 0x0002, 0x0000, 0x000e,
+        ]);
+        let context = AppContext::from_dex_files(vec![("classes.dex".to_string(), dex)]);
+        let report = doctor.run(&context);
+        let severities = report
+            .findings
+            .iter()
+            .map(|f| f.severity)
+            .collect::<Vec<_>>();
+        assert_eq!(severities.len(), 2);
+        assert!(severities.contains(&Severity::Low) && severities.contains(&Severity::High));
+        // The check is as severe as its worst finding, not its default.
+        assert_eq!(report.checks[0].severity, Severity::High);
+
+        let dex = dangerous_dex(&[0x000e]);
+        let context = AppContext::from_dex_files(vec![("classes.dex".to_string(), dex)]);
+        let report = doctor.run(&context);
+        assert!(report.checks[0].passed());
+        assert_eq!(report.checks[0].severity, Severity::Medium);
+    }
+
+    #[test]
+    fn test_method_count() {
+        let mut dex = build_dex(
+            vec![TestClass::new("La/a;").method("b", &[], "V", &[0x000e])],
+            &[],
+        );
+        let mut context =
+            AppContext::from_dex_files(vec![("classes.dex".to_string(), dex.clone())]);
+        assert!(MethodCount.run(&context).is_empty());
+
+        dex.method_ids = vec![dex.method_ids[0].clone(); INDEX_LIMIT * 9 / 10 + 1];
+        context.dexes.push(("classes2.dex".to_string(), dex));
+        let findings = MethodCount.run(&context);
+        assert_eq!(locations(&findings), vec![Location::dex("classes2.dex")]);
+        assert_eq!(
+            findings[0].message,
+            format!(
+                "{} of {} method references used",
+                INDEX_LIMIT * 9 / 10 + 1,
+                INDEX_LIMIT
+            )
+        );
+    }
+
+    #[test]
+    fn test_unstripped_native_libs() {
+        let lib = |path: &str, has_symbols: Option<bool>| NativeLib {
+            path: path.to_string(),
+            abi: "arm64-v8a".to_string(),
+            size: 4096,
+            has_symbols,
+        };
+        let mut context = AppContext::from_dex_files(vec![]);
+        context.native_libs = vec![
+            lib("lib/arm64-v8a/libdebug.so", Some(true)),
+            lib("lib/arm64-v8a/librelease.so", Some(false)),
+            lib("lib/arm64-v8a/libscript.so", None),
+        ];
+        let findings = UnstrippedNativeLibs.run(&context);
+        assert_eq!(
+            locations(&findings),
+            vec![Location::entry("lib/arm64-v8a/libdebug.so")]
+        );
+        assert_eq!(findings[0].message, "4096 bytes with symbols");
+    }
+
+    #[test]
+    fn test_baseline_profile() {
+        // A bare dex file has no archive to look in.
+        let mut context = AppContext::from_dex_files(vec![]);
+        assert!(BaselineProfile.run(&context).is_empty());
+        context.entries = vec![entry("classes.dex", 1000, 500)];
+        assert_eq!(BaselineProfile.run(&context).len(), 1);
+        context.entries.push(entry(BASELINE_PROFILE, 100, 100));
+        assert!(BaselineProfile.run(&context).is_empty());
+        context.entries = vec![
+            entry("base/dex/classes.dex", 1000, 500),
+            entry(
+                "BUNDLE-METADATA/com.android.tools.build.profiles/baseline.prof",
+                100,
+                100,
+            ),
+        ];
+        assert!(BaselineProfile.run(&context).is_empty());
+    }
+
+    #[test]
+    fn test_uncompressed_assets() {
+        let mut context = AppContext::from_dex_files(vec![]);
+        context.entries = vec![
+            entry("assets/model.bin", 200_000, 200_000),
+            entry("assets/small.bin", 1_000, 1_000),
+            entry("assets/packed.bin", 200_000, 50_000),
+            entry("assets/photo.JPG", 200_000, 200_000),
+            entry("res/raw/data.bin", 200_000, 200_000),
+        ];
+        let findings = UncompressedAssets.run(&context);
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].location, Location::entry("assets/model.bin"));
+    }
+}
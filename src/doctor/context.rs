@@ -0,0 +1,279 @@
+use std::{cell::RefCell, fs, path::Path};
+
+use serde::Serialize;
+use zip::result::ZipError;
+
+use crate::{
+    aab::{Aab, BASE_MODULE, BUNDLE_CONFIG},
+    apk::{file_name, Apk, ApkError},
+    deserialize_vec,
+    dex_model::DexModel,
+    manifest::{Manifest, ManifestError},
+    mapping::Mapping,
+    resolver::DexResolver,
+};
+
+const ELF_MAGIC: &[u8] = b"\x7fELF";
+
+/// Size of one entry of the archive.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct EntryInfo {
+    pub name: String,
+    pub size: u64,
+    pub compressed_size: u64,
+}
+
+/// A shared library under `lib/<abi>/`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NativeLib {
+    pub path: String,
+    pub abi: String,
+    pub size: u64,
+    /// Whether the library still has a symbol table or debug sections;
+    /// `None` when it is not an ELF file.
+    pub has_symbols: Option<bool>,
+}
+
+/// Whether an ELF file has a `.symtab` or `.debug_*` section, which release
+/// builds strip. `None` when the bytes are not a well-formed ELF file.
+pub(crate) fn elf_has_symbols(bytes: &[u8]) -> Option<bool> {
+    if !bytes.starts_with(ELF_MAGIC) {
+        return None;
+    }
+    let is_64 = *bytes.get(4)? == 2;
+    let little_endian = *bytes.get(5)? == 1;
+    let uint = |offset: usize, len: usize| -> Option<u64> {
+        let field = bytes.get(offset..offset.checked_add(len)?)?;
+        let fold = |value: u64, byte: &u8| value << 8 | *byte as u64;
+        return Some(match little_endian {
+            true => field.iter().rev().fold(0, fold),
+            false => field.iter().fold(0, fold),
+        });
+    };
+    let word = if is_64 { 8 } else { 4 };
+    let (section_headers, header_size, count, names_index) = match is_64 {
+        true => (
+            uint(0x28, 8)?,
+            uint(0x3a, 2)?,
+            uint(0x3c, 2)?,
+            uint(0x3e, 2)?,
+        ),
+        false => (
+            uint(0x20, 4)?,
+            uint(0x2e, 2)?,
+            uint(0x30, 2)?,
+            uint(0x32, 2)?,
+        ),
+    };
+    // sh_name, then sh_offset at 0x18 / 0x10.
+    let section = |index: u64| -> Option<(u64, u64)> {
+        let header = section_headers.checked_add(index.checked_mul(header_size)?)? as usize;
+        let offset_field = header + if is_64 { 0x18 } else { 0x10 };
+        return Some((uint(header, 4)?, uint(offset_field, word)?));
+    };
+    let names = section(names_index)?.1 as usize;
+    for index in 0..count {
+        let name_offset = names.checked_add(section(index)?.0 as usize)?;
+        let name = bytes
+            .get(name_offset..)?
+            .split(|b| *b == 0)
+            .next()
+            .unwrap_or_default();
+        if name == b".symtab" || name.starts_with(b".debug_") {
+            return Some(true);
+        }
+    }
+    return Some(false);
+}
+
+/// ABI of a shared library entry such as `lib/arm64-v8a/libfoo.so`, or
+/// `base/lib/arm64-v8a/libfoo.so` in a bundle.
+fn native_lib_abi(name: &str, in_bundle: bool) -> Option<&str> {
+    let parts = name.split('/').collect::<Vec<_>>();
+    let lib = in_bundle as usize;
+    if parts.len() != lib + 3 || parts[lib] != "lib" || !name.ends_with(".so") {
+        return None;
+    }
+    return Some(parts[lib + 1]);
+}
+
+/// Everything checks can inspect about an app: its archive, dex files,
+/// manifest and native libraries. A bare dex file has no archive.
+pub struct AppContext {
+    archive: Option<RefCell<Apk>>,
+    pub entries: Vec<EntryInfo>,
+    pub dexes: Vec<(String, DexModel)>,
+    pub manifest: Option<Manifest>,
+    pub native_libs: Vec<NativeLib>,
+    /// Mapping that findings in code report original names with.
+    pub mapping: Option<Mapping>,
+}
+
+impl AppContext {
+    /// Opens a `.dex`, `.apk`, `.jar` or `.aab` file.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<AppContext, ManifestError> {
+        let bytes = fs::read(&path).map_err(ApkError::from)?;
+        if bytes.starts_with(b"dex\n") {
+            let name = file_name(path.as_ref());
+            let dex =
+                deserialize_vec(bytes).map_err(|err| ApkError::DexError(name.clone(), err))?;
+            return Ok(AppContext::from_dex_files(vec![(name, dex)]));
+        }
+        let apk = Apk::from_bytes(bytes)?;
+        if apk.has_entry(BUNDLE_CONFIG) {
+            return AppContext::from_aab(Aab::from_apk(apk));
+        }
+        return AppContext::from_apk(apk);
+    }
+
+    pub fn from_dex_files(dexes: Vec<(String, DexModel)>) -> AppContext {
+        return AppContext {
+            archive: None,
+            entries: vec![],
+            dexes,
+            manifest: None,
+            native_libs: vec![],
+            mapping: None,
+        };
+    }
+
+    pub fn from_apk(mut apk: Apk) -> Result<AppContext, ManifestError> {
+        let dexes = apk.dex_files()?;
+        let manifest = match apk.has_entry("AndroidManifest.xml") {
+            true => Some(Manifest::from_apk(&mut apk)?),
+            false => None,
+        };
+        return AppContext::from_archive(apk, false, dexes, manifest);
+    }
+
+    /// A bundle is diagnosed as a whole: the dex files of every module and
+    /// the manifest of the base module.
+    pub fn from_aab(mut aab: Aab) -> Result<AppContext, ManifestError> {
+        let dexes = aab.all_dex_files()?;
+        let manifest = match aab.modules().iter().any(|m| m == BASE_MODULE) {
+            true => Some(aab.manifest(BASE_MODULE)?),
+            false => None,
+        };
+        return AppContext::from_archive(aab.into_archive(), true, dexes, manifest);
+    }
+
+    /// Diagnoses one module of a bundle: its dex files, manifest and native
+    /// libraries.
+    pub fn from_aab_module(mut aab: Aab, module: &str) -> Result<AppContext, ManifestError> {
+        let dexes = aab.dex_files(module)?;
+        let manifest = aab.manifest(module)?;
+        let mut context =
+            AppContext::from_archive(aab.into_archive(), true, dexes, Some(manifest))?;
+        let prefix = format!("{}/", module);
+        context
+            .native_libs
+            .retain(|lib| lib.path.starts_with(&prefix));
+        return Ok(context);
+    }
+
+    fn from_archive(
+        mut apk: Apk,
+        in_bundle: bool,
+        dexes: Vec<(String, DexModel)>,
+        manifest: Option<Manifest>,
+    ) -> Result<AppContext, ManifestError> {
+        let mut entries = vec![];
+        let mut native_libs = vec![];
+        for name in apk.entry_names() {
+            let (size, compressed_size) = apk.entry_size(&name)?;
+            if let Some(abi) = native_lib_abi(&name, in_bundle) {
+                native_libs.push(NativeLib {
+                    path: name.clone(),
+                    abi: abi.to_string(),
+                    size,
+                    has_symbols: elf_has_symbols(&apk.read_entry(&name)?),
+                });
+            }
+            entries.push(EntryInfo {
+                name,
+                size,
+                compressed_size,
+            });
+        }
+        return Ok(AppContext {
+            archive: Some(RefCell::new(apk)),
+            entries,
+            dexes,
+            manifest,
+            native_libs,
+            mapping: None,
+        });
+    }
+
+    /// Reports findings in code with the original names of `mapping`.
+    pub fn with_mapping(mut self, mapping: Mapping) -> AppContext {
+        self.mapping = Some(mapping);
+        return self;
+    }
+
+    /// A resolver for each dex file, with the mapping attached if any.
+    pub fn resolvers(&self) -> Vec<(String, DexResolver<'_>)> {
+        return self
+            .dexes
+            .iter()
+            .map(|(name, dex)| {
+                let resolver = DexResolver::new(dex);
+                let resolver = match self.mapping.as_ref() {
+                    Some(mapping) => resolver.with_mapping(mapping),
+                    None => resolver,
+                };
+                (name.clone(), resolver)
+            })
+            .collect();
+    }
+
+    pub fn has_entry(&self, name: &str) -> bool {
+        return self.entries.iter().any(|entry| entry.name == name);
+    }
+
+    /// Reads an entry of the archive.
+    pub fn read_entry(&self, name: &str) -> Result<Vec<u8>, ApkError> {
+        return match self.archive.as_ref() {
+            Some(archive) => archive.borrow_mut().read_entry(name),
+            None => Err(ApkError::ZipError(ZipError::FileNotFound)),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{build_dex, elf64, TestClass};
+
+    #[test]
+    fn test_elf_has_symbols() {
+        assert_eq!(elf_has_symbols(&elf64(".symtab")), Some(true));
+        assert_eq!(elf_has_symbols(&elf64(".debug_info")), Some(true));
+        assert_eq!(elf_has_symbols(&elf64(".dynsym")), Some(false));
+        assert_eq!(elf_has_symbols(&elf64(".symtab")[..0x50]), None);
+        assert_eq!(elf_has_symbols(b"not an elf"), None);
+    }
+
+    #[test]
+    fn test_resolvers_with_mapping() {
+        let dex = build_dex(vec![TestClass::new("La/a;")], &[]);
+        let context = AppContext::from_dex_files(vec![("classes.dex".to_string(), dex)]);
+        assert_eq!(context.resolvers()[0].1.type_name(0), "a.a");
+        let context = context.with_mapping(Mapping::parse("com.example.Main -> a.a:\n").unwrap());
+        let resolvers = context.resolvers();
+        assert_eq!(resolvers[0].0, "classes.dex");
+        assert_eq!(resolvers[0].1.type_name(0), "com.example.Main");
+    }
+
+    #[test]
+    fn test_native_lib_abi() {
+        assert_eq!(
+            native_lib_abi("lib/arm64-v8a/libfoo.so", false),
+            Some("arm64-v8a")
+        );
+        assert_eq!(native_lib_abi("base/lib/x86/libfoo.so", true), Some("x86"));
+        assert_eq!(native_lib_abi("assets/lib/x86/libfoo.so", false), None);
+        assert_eq!(native_lib_abi("lib/arm64-v8a/readme.txt", false), None);
+        assert_eq!(native_lib_abi("libfoo.so", false), None);
+    }
+}
@@ -0,0 +1,556 @@
+//! Diagnosis engine: runs a registry of checks over an app and scores the
+//! result.
+//!
+//! Checks implement `Check` and see the app through an `AppContext`. Which
+//! checks run, and at what severity, can be changed with a TOML or JSON
+//! config file:
+//!
+//! ```toml
+//! [checks.allow-backup]
+//! enabled = false
+//!
+//! [checks.cleartext-traffic]
+//! severity = "high"
+//! ```
+
+mod checks;
+mod context;
+
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display, Formatter},
+    fs, io,
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+pub use crate::dangerous_apis::Severity;
+use crate::findings::{Baseline, Issue, Location, RuleInfo};
+pub use checks::builtin_checks;
+pub use context::{AppContext, EntryInfo, NativeLib};
+
+/// Score deducted for a failed check, by its severity.
+const PENALTIES: [(Severity, u32); 4] = [
+    (Severity::Info, 1),
+    (Severity::Low, 5),
+    (Severity::Medium, 10),
+    (Severity::High, 20),
+];
+
+#[derive(Debug)]
+pub enum ConfigError {
+    FileOpenError(io::Error),
+    TomlError(toml::de::Error),
+    JsonError(serde_json::Error),
+    /// The config names a check that is not registered.
+    UnknownCheck(String),
+}
+
+impl From<io::Error> for ConfigError {
+    fn from(err: io::Error) -> Self {
+        ConfigError::FileOpenError(err)
+    }
+}
+
+impl From<toml::de::Error> for ConfigError {
+    fn from(err: toml::de::Error) -> Self {
+        ConfigError::TomlError(err)
+    }
+}
+
+impl From<serde_json::Error> for ConfigError {
+    fn from(err: serde_json::Error) -> Self {
+        ConfigError::JsonError(err)
+    }
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            ConfigError::FileOpenError(err) => write!(f, "{}", err),
+            ConfigError::TomlError(err) => write!(f, "{}", err),
+            ConfigError::JsonError(err) => write!(f, "{}", err),
+            ConfigError::UnknownCheck(id) => write!(f, "unknown check: {}", id),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Category {
+    Security,
+    Correctness,
+    Size,
+    Performance,
+}
+
+impl Display for Category {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let name = match self {
+            Category::Security => "security",
+            Category::Correctness => "correctness",
+            Category::Size => "size",
+            Category::Performance => "performance",
+        };
+        return write!(f, "{}", name);
+    }
+}
+
+/// One problem reported by a check.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Finding {
+    pub check: String,
+    pub severity: Severity,
+    pub category: Category,
+    pub message: String,
+    /// Where the problem is, e.g. an archive entry or a method.
+    pub location: Location,
+}
+
+impl Finding {
+    /// A finding with the id, severity and category of `check`.
+    pub fn new(check: &dyn Check, message: String) -> Finding {
+        return Finding {
+            check: check.id().to_string(),
+            severity: check.severity(),
+            category: check.category(),
+            message,
+            location: Location::default(),
+        };
+    }
+
+    pub fn at(mut self, location: Location) -> Finding {
+        self.location = location;
+        return self;
+    }
+}
+
+pub trait Check {
+    /// Stable identifier used in config files, e.g. `debuggable`.
+    fn id(&self) -> &'static str;
+    /// Default severity of the findings.
+    fn severity(&self) -> Severity;
+    fn category(&self) -> Category;
+    /// How to fix what the check reports.
+    fn remediation(&self) -> &'static str;
+    fn run(&self, context: &AppContext) -> Vec<Finding>;
+}
+
+/// Settings of one check; unset fields keep the check's defaults.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CheckConfig {
+    pub enabled: Option<bool>,
+    pub severity: Option<Severity>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DoctorConfig {
+    /// Settings by check id.
+    #[serde(default)]
+    pub checks: BTreeMap<String, CheckConfig>,
+}
+
+impl DoctorConfig {
+    pub fn from_toml(text: &str) -> Result<DoctorConfig, ConfigError> {
+        return Ok(toml::from_str(text)?);
+    }
+
+    pub fn from_json(text: &str) -> Result<DoctorConfig, ConfigError> {
+        return Ok(serde_json::from_str(text)?);
+    }
+
+    /// Loads a `.json` config file, or a TOML one for any other extension.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<DoctorConfig, ConfigError> {
+        let text = fs::read_to_string(&path)?;
+        if path.as_ref().extension().is_some_and(|e| e == "json") {
+            return DoctorConfig::from_json(&text);
+        }
+        return DoctorConfig::from_toml(&text);
+    }
+}
+
+/// Outcome of one enabled check.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CheckResult {
+    pub id: String,
+    pub category: Category,
+    /// The configured severity, else that of the most severe finding, else
+    /// the default of the check.
+    pub severity: Severity,
+    pub findings: usize,
+    pub remediation: String,
+}
+
+impl CheckResult {
+    pub fn passed(&self) -> bool {
+        return self.findings == 0;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DoctorReport {
+    /// Overall health from 0 to 100: every failed check deducts a penalty
+    /// that grows with its severity.
+    pub score: u32,
+    pub checks: Vec<CheckResult>,
+    pub findings: Vec<Finding>,
+    /// Findings ignored because a baseline lists them.
+    pub suppressed: Vec<Finding>,
+}
+
+/// 100 minus, for every check with findings, the penalty of its most
+/// severe finding.
+fn score(findings: &[Finding]) -> u32 {
+    let mut worst = BTreeMap::new();
+    for finding in findings {
+        let severity = worst.entry(&finding.check).or_insert(finding.severity);
+        *severity = finding.severity.max(*severity);
+    }
+    return worst.values().fold(100u32, |score, worst| {
+        let penalty = PENALTIES.iter().find(|(s, _)| s == worst).unwrap().1;
+        score.saturating_sub(penalty)
+    });
+}
+
+impl DoctorReport {
+    /// Moves the findings listed in `baseline` to `suppressed`, so that they
+    /// no longer count against the score.
+    pub fn apply_baseline(&mut self, baseline: &Baseline) {
+        let issues = self.findings.iter().map(Issue::from).collect::<Vec<_>>();
+        let (suppressed, findings): (Vec<_>, Vec<_>) = self
+            .findings
+            .drain(..)
+            .zip(baseline.suppressed(&issues))
+            .partition(|(_, suppressed)| *suppressed);
+        self.findings = findings.into_iter().map(|(f, _)| f).collect();
+        self.suppressed
+            .extend(suppressed.into_iter().map(|(f, _)| f));
+        for check in self.checks.iter_mut() {
+            check.findings = self.findings.iter().filter(|f| f.check == check.id).count();
+        }
+        self.score = score(&self.findings);
+    }
+
+    /// The checks that ran, as rules for SARIF and JUnit output.
+    pub fn rules(&self) -> Vec<RuleInfo> {
+        return self.checks.iter().map(RuleInfo::from).collect();
+    }
+
+    pub fn issues(&self) -> Vec<Issue> {
+        return self.findings.iter().map(Issue::from).collect();
+    }
+
+    pub fn suppressed_issues(&self) -> Vec<Issue> {
+        return self.suppressed.iter().map(Issue::from).collect();
+    }
+
+    pub fn to_json(&self) -> String {
+        return serde_json::to_string_pretty(self).unwrap();
+    }
+}
+
+impl Display for DoctorReport {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, "Health score: {}/100", self.score)?;
+        let mut failed = self
+            .checks
+            .iter()
+            .filter(|c| !c.passed())
+            .collect::<Vec<_>>();
+        failed.sort_by_key(|c| std::cmp::Reverse(c.severity));
+        for check in failed.iter() {
+            writeln!(
+                f,
+                "\n[{}] {} ({}): {} finding(s)",
+                check.severity, check.id, check.category, check.findings
+            )?;
+            for finding in self.findings.iter().filter(|x| x.check == check.id) {
+                match finding.location.is_empty() {
+                    true => writeln!(f, "  {}", finding.message)?,
+                    false => writeln!(f, "  {}: {}", finding.location, finding.message)?,
+                }
+            }
+            writeln!(f, "  Remediation: {}", check.remediation)?;
+        }
+        writeln!(
+            f,
+            "\n{} of {} checks passed",
+            self.checks.len() - failed.len(),
+            self.checks.len()
+        )?;
+        if !self.suppressed.is_empty() {
+            writeln!(
+                f,
+                "{} finding(s) suppressed by baseline",
+                self.suppressed.len()
+            )?;
+        }
+        return Ok(());
+    }
+}
+
+/// A registry of checks and the config applied to them.
+pub struct Doctor {
+    checks: Vec<Box<dyn Check>>,
+    config: DoctorConfig,
+}
+
+impl Default for Doctor {
+    fn default() -> Self {
+        return Doctor::new();
+    }
+}
+
+impl Doctor {
+    /// The built-in checks with their default settings.
+    pub fn new() -> Doctor {
+        return Doctor::with_checks(builtin_checks());
+    }
+
+    pub fn with_checks(checks: Vec<Box<dyn Check>>) -> Doctor {
+        return Doctor {
+            checks,
+            config: DoctorConfig::default(),
+        };
+    }
+
+    /// Adds a check, replacing a registered check with the same id.
+    pub fn register(&mut self, check: Box<dyn Check>) {
+        self.checks.retain(|c| c.id() != check.id());
+        self.checks.push(check);
+    }
+
+    /// Ids of the registered checks, in run order.
+    pub fn check_ids(&self) -> Vec<&'static str> {
+        return self.checks.iter().map(|c| c.id()).collect();
+    }
+
+    /// Applies `config`, failing if it names a check that is not registered.
+    pub fn configure(&mut self, config: DoctorConfig) -> Result<(), ConfigError> {
+        if let Some(id) = config
+            .checks
+            .keys()
+            .find(|id| !self.check_ids().contains(&id.as_str()))
+        {
+            return Err(ConfigError::UnknownCheck(id.clone()));
+        }
+        self.config = config;
+        return Ok(());
+    }
+
+    pub fn run(&self, context: &AppContext) -> DoctorReport {
+        let mut report = DoctorReport {
+            score: 100,
+            checks: vec![],
+            findings: vec![],
+            suppressed: vec![],
+        };
+        for check in self.checks.iter() {
+            let config = self.config.checks.get(check.id());
+            if config.and_then(|c| c.enabled) == Some(false) {
+                continue;
+            }
+            let severity = config.and_then(|c| c.severity);
+            let mut findings = check.run(context);
+            if let Some(severity) = severity {
+                findings.iter_mut().for_each(|f| f.severity = severity);
+            }
+            report.checks.push(CheckResult {
+                id: check.id().to_string(),
+                category: check.category(),
+                severity: severity.unwrap_or_else(|| {
+                    let worst = findings.iter().map(|f| f.severity).max();
+                    worst.unwrap_or(check.severity())
+                }),
+                findings: findings.len(),
+                remediation: check.remediation().to_string(),
+            });
+            report.findings.extend(findings);
+        }
+        report.score = score(&report.findings);
+        return report;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        apk::Apk,
+        axml::Value,
+        serialize,
+        test_utils::{build_dex, build_zip, elf64, AxmlBuilder, TestClass},
+    };
+
+    fn string(s: &str) -> Value {
+        return Value::String(s.to_string());
+    }
+
+    fn context() -> AppContext {
+        let mut manifest = AxmlBuilder::new();
+        manifest
+            .start("manifest")
+            .attr("package", string("com.example"))
+            .start("application")
+            .android("debuggable", Value::Boolean(true))
+            .android("allowBackup", Value::Boolean(false))
+            .start("activity")
+            .android("name", string(".Main"))
+            .android("exported", Value::Boolean(true))
+            .end()
+            .start("service")
+            .android("name", string(".Sync"))
+            .android("exported", Value::Boolean(true))
+            .android("permission", string("com.example.SYNC"))
+            .end()
+            .end()
+            .end();
+        let dex = serialize(build_dex(vec![TestClass::new("Lcom/example/Sync;")], &[]));
+        let (manifest, unstripped, stripped) =
+            (manifest.build(), elf64(".symtab"), elf64(".dynsym"));
+        let apk = build_zip(&[
+            ("AndroidManifest.xml", &manifest),
+            ("classes.dex", &dex),
+            ("lib/arm64-v8a/libdebug.so", &unstripped),
+            ("lib/arm64-v8a/librelease.so", &stripped),
+        ]);
+        return AppContext::from_apk(Apk::from_bytes(apk).unwrap()).unwrap();
+    }
+
+    fn ids(findings: &[Finding]) -> Vec<&str> {
+        return findings.iter().map(|f| f.check.as_str()).collect();
+    }
+
+    #[test]
+    fn test_builtin_checks() {
+        let context = context();
+        assert_eq!(context.native_libs.len(), 2);
+        let report = Doctor::new().run(&context);
+        assert_eq!(
+            ids(&report.findings),
+            vec![
+                "debuggable",
+                "exported-components",
+                "missing-classes",
+                "unstripped-native-libs",
+                "baseline-profile",
+            ]
+        );
+        assert_eq!(
+            report.findings[1].location,
+            Location::class("com.example.Main")
+        );
+        assert_eq!(
+            report.findings[3].location,
+            Location::entry("lib/arm64-v8a/libdebug.so")
+        );
+        // high, medium, high, low, info
+        assert_eq!(report.score, 100 - 20 - 10 - 20 - 5 - 1);
+        assert_eq!(report.checks.len(), Doctor::new().check_ids().len());
+        assert!(report.to_string().contains("Remediation: "));
+    }
+
+    #[test]
+    fn test_config() {
+        let config = DoctorConfig::from_toml(
+            r#"
+            [checks.debuggable]
+            enabled = false
+
+            [checks.baseline-profile]
+            severity = "high"
+            "#,
+        )
+        .unwrap();
+        let mut doctor = Doctor::new();
+        doctor.configure(config).unwrap();
+        let report = doctor.run(&context());
+        assert!(!ids(&report.findings).contains(&"debuggable"));
+        assert!(!report.checks.iter().any(|c| c.id == "debuggable"));
+        let baseline = report.findings.last().unwrap();
+        assert_eq!(baseline.check, "baseline-profile");
+        assert_eq!(baseline.severity, Severity::High);
+        assert_eq!(report.score, 100 - 10 - 20 - 5 - 20);
+
+        let unknown = DoctorConfig::from_json(r#"{"checks": {"nope": {"enabled": false}}}"#);
+        assert!(matches!(
+            Doctor::new().configure(unknown.unwrap()),
+            Err(ConfigError::UnknownCheck(id)) if id == "nope"
+        ));
+        assert!(DoctorConfig::from_toml("[checks.debuggable]\nenable = false").is_err());
+    }
+
+    #[test]
+    fn test_baseline() {
+        let context = context();
+        let doctor = Doctor::new();
+        let mut report = doctor.run(&context);
+        let baseline = Baseline::from_issues(&report.issues()[..2]);
+        report.apply_baseline(&baseline);
+        assert_eq!(
+            ids(&report.findings),
+            vec![
+                "missing-classes",
+                "unstripped-native-libs",
+                "baseline-profile"
+            ]
+        );
+        assert_eq!(
+            ids(&report.suppressed),
+            vec!["debuggable", "exported-components"]
+        );
+        assert!(report
+            .checks
+            .iter()
+            .find(|c| c.id == "debuggable")
+            .unwrap()
+            .passed());
+        assert_eq!(report.score, 100 - 20 - 5 - 1);
+        assert_eq!(report.rules().len(), report.checks.len());
+        assert_eq!(
+            report.suppressed_issues()[1].location.class.as_deref(),
+            Some("com.example.Main")
+        );
+    }
+
+    struct AlwaysFails;
+
+    impl Check for AlwaysFails {
+        fn id(&self) -> &'static str {
+            return "debuggable";
+        }
+
+        fn severity(&self) -> Severity {
+            return Severity::Low;
+        }
+
+        fn category(&self) -> Category {
+            return Category::Correctness;
+        }
+
+        fn remediation(&self) -> &'static str {
+            return "Nothing to do.";
+        }
+
+        fn run(&self, _context: &AppContext) -> Vec<Finding> {
+            return vec![Finding::new(self, "always".to_string())];
+        }
+    }
+
+    #[test]
+    fn test_register() {
+        let mut doctor = Doctor::with_checks(vec![]);
+        doctor.register(Box::new(AlwaysFails));
+        let report = doctor.run(&AppContext::from_dex_files(vec![]));
+        assert_eq!(report.score, 95);
+        assert_eq!(report.findings[0].message, "always");
+
+        let mut doctor = Doctor::new();
+        let count = doctor.check_ids().len();
+        doctor.register(Box::new(AlwaysFails));
+        assert_eq!(doctor.check_ids().len(), count);
+        assert_eq!(doctor.check_ids().last(), Some(&"debuggable"));
+    }
+}
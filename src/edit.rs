@@ -0,0 +1,782 @@
+//! Removes and renames classes and members of a `DexModel` in place, and
+//! sets the initial values of static fields. Every index and offset that
+//! refers to what changed is kept consistent, the pools are re-sorted, and
+//! entries that become unused are dropped.
+//!
+//! Names embedded in strings, such as `Signature` annotations or Kotlin
+//! metadata, are not rewritten.
+
+use std::{
+    collections::HashMap,
+    fmt::{self, Display, Formatter},
+};
+
+use crate::{
+    annotations::Value,
+    compact::{compact, compact_from},
+    dex_model::DexModel,
+    dex_structs::{
+        AnnotationsDirectoryItem, EncodedArray, EncodedArrayItem, EncodedField, EncodedMethod,
+        EncodedValue, StringDataItem, StringIdItem, TypeCode, TypeIdItem,
+    },
+    instructions::{IndexKind, IndexRef},
+    layout::ItemOffsets,
+    remap::{pool_name, remap_indices, remap_pool_indices, string_key},
+    resolver::{field_indices, method_indices, DexResolver},
+    static_values::{accepts, default_value, is_default},
+};
+
+#[derive(Debug)]
+pub enum EditError {
+    ClassNotFound(String),
+    MemberNotFound(String),
+    /// A rename target is already taken.
+    AlreadyExists(String),
+    /// An instruction operand cannot hold an index after the pools were
+    /// re-sorted, e.g. a `const-string` that would need
+    /// `const-string/jumbo`.
+    IndexOverflow {
+        pool: &'static str,
+        index: u32,
+    },
+    /// A value does not fit the type of the field it is assigned to, or
+    /// cannot be encoded as a static value.
+    InvalidValue(String),
+}
+
+impl Display for EditError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            EditError::ClassNotFound(class) => write!(f, "class {} not found", class),
+            EditError::MemberNotFound(member) => write!(f, "member {} not found", member),
+            EditError::AlreadyExists(name) => write!(f, "{} already exists", name),
+            EditError::IndexOverflow { pool, index } => write!(
+                f,
+                "{} index {} does not fit its 16-bit instruction operand",
+                pool, index
+            ),
+            EditError::InvalidValue(reason) => write!(f, "invalid value: {}", reason),
+        }
+    }
+}
+
+fn overflow(index_ref: IndexRef) -> EditError {
+    return EditError::IndexOverflow {
+        pool: pool_name(index_ref.kind),
+        index: index_ref.index,
+    };
+}
+
+/// Index of the string `s`, adding it to the pool if needed. New strings
+/// are appended, so the pool must be sorted again afterwards.
+fn intern(dex: &mut DexModel, offsets: &mut ItemOffsets, s: &str) -> u32 {
+    let index = offsets.index();
+    let existing = dex.string_ids.iter().position(|id| {
+        index
+            .get(&(TypeCode::TypeStringDataItem, id.string_data_off))
+            .is_some_and(|i| dex.string_data_items[*i].to_string_lossy() == s)
+    });
+    if let Some(existing) = existing {
+        return existing as u32;
+    }
+    let mut data = mutf8::encode(s).into_owned();
+    data.push(0);
+    dex.string_data_items.push(StringDataItem {
+        utf16_size: s.encode_utf16().count() as u32,
+        data,
+    });
+    dex.string_ids.push(StringIdItem {
+        string_data_off: offsets.append(TypeCode::TypeStringDataItem),
+    });
+    return dex.string_ids.len() as u32 - 1;
+}
+
+/// Indices of the types, fields and methods a value may refer to, by
+/// descriptor or smali signature, taken before any string is interned.
+struct ValueIndices {
+    types: HashMap<String, u32>,
+    fields: HashMap<String, u32>,
+    methods: HashMap<String, u32>,
+}
+
+impl ValueIndices {
+    fn new(dex: &DexModel) -> ValueIndices {
+        let resolver = DexResolver::new(dex);
+        return ValueIndices {
+            types: (0..dex.type_ids.len() as u32)
+                .map(|t| (resolver.type_descriptor(t).to_string(), t))
+                .collect(),
+            fields: (0..dex.field_ids.len() as u32)
+                .map(|f| (resolver.field_ref(f).signature(), f))
+                .collect(),
+            methods: (0..dex.method_ids.len() as u32)
+                .map(|m| (resolver.method_ref(m).signature(), m))
+                .collect(),
+        };
+    }
+}
+
+/// Encodes `value`, adding strings and types to the pools as needed.
+/// Fields, methods and enum constants must already be referenced.
+fn encode_value(
+    dex: &mut DexModel,
+    offsets: &mut ItemOffsets,
+    indices: &mut ValueIndices,
+    value: &Value,
+) -> Result<EncodedValue, EditError> {
+    let member = |members: &HashMap<String, u32>, signature: &String| {
+        return members
+            .get(signature)
+            .copied()
+            .ok_or_else(|| EditError::MemberNotFound(signature.clone()));
+    };
+    return Ok(match value {
+        Value::Byte(v) => EncodedValue::ValueByte(*v),
+        Value::Short(v) => EncodedValue::ValueShort(*v),
+        Value::Char(v) => EncodedValue::ValueChar(*v),
+        Value::Int(v) => EncodedValue::ValueInt(*v),
+        Value::Long(v) => EncodedValue::ValueLong(*v),
+        Value::Float(v) => EncodedValue::ValueFloat(*v),
+        Value::Double(v) => EncodedValue::ValueDouble(*v),
+        Value::String(s) => EncodedValue::ValueString(intern(dex, offsets, s)),
+        Value::Type(descriptor) => match indices.types.get(descriptor) {
+            Some(type_idx) => EncodedValue::ValueType(*type_idx),
+            None => {
+                let descriptor_idx = intern(dex, offsets, descriptor);
+                dex.type_ids.push(TypeIdItem { descriptor_idx });
+                let type_idx = dex.type_ids.len() as u32 - 1;
+                indices.types.insert(descriptor.clone(), type_idx);
+                EncodedValue::ValueType(type_idx)
+            }
+        },
+        Value::Field(signature) => EncodedValue::ValueField(member(&indices.fields, signature)?),
+        Value::Method(signature) => EncodedValue::ValueMethod(member(&indices.methods, signature)?),
+        Value::Enum(signature) => EncodedValue::ValueEnum(member(&indices.fields, signature)?),
+        Value::Array(values) => {
+            let mut encoded = vec![];
+            for value in values {
+                encoded.push(encode_value(dex, offsets, indices, value)?);
+            }
+            EncodedValue::ValueArray(EncodedArray { values: encoded })
+        }
+        Value::Null => EncodedValue::ValueNull,
+        Value::Boolean(v) => EncodedValue::ValueBoolean(*v),
+        Value::MethodType(_) | Value::MethodHandle(_) | Value::Annotation(_) => {
+            return Err(EditError::InvalidValue(format!(
+                "{} is not supported as a static value",
+                value
+            )));
+        }
+    });
+}
+
+/// Puts the pool of `kind` in the order of `keys`, one per entry, and
+/// renumbers every reference to it.
+fn reorder<K: Ord>(dex: &mut DexModel, kind: IndexKind, keys: Vec<K>) -> Result<(), IndexRef> {
+    let mut order = (0..keys.len()).collect::<Vec<_>>();
+    order.sort_by(|a, b| keys[*a].cmp(&keys[*b]));
+    if order.iter().enumerate().all(|(new, old)| new == *old) {
+        return Ok(());
+    }
+    let mut ranks = vec![0; order.len()];
+    for (new, old) in order.iter().enumerate() {
+        ranks[*old] = new as u32;
+    }
+    fn permute<T: Clone>(items: &mut Vec<T>, order: &[usize]) {
+        *items = order.iter().map(|i| items[*i].clone()).collect();
+    }
+    match kind {
+        IndexKind::String => permute(&mut dex.string_ids, &order),
+        IndexKind::Type => permute(&mut dex.type_ids, &order),
+        IndexKind::Proto => permute(&mut dex.proto_ids, &order),
+        IndexKind::Field => permute(&mut dex.field_ids, &order),
+        IndexKind::Method => permute(&mut dex.method_ids, &order),
+        IndexKind::CallSite | IndexKind::MethodHandle => unreachable!(),
+    }
+    let mut f = |k, index: u32| {
+        if k == kind {
+            return ranks[index as usize];
+        }
+        return index;
+    };
+    remap_pool_indices(dex, &mut f);
+    return remap_indices(dex, &mut f);
+}
+
+/// Restores the order the format requires of each pool after entries were
+/// added or renamed, one pool at a time since each is sorted by the indices
+/// of the ones before it.
+fn sort_pools(dex: &mut DexModel, offsets: &ItemOffsets) -> Result<(), IndexRef> {
+    let index = offsets.index();
+    let keys = dex
+        .string_ids
+        .iter()
+        .map(|id| {
+            let i = index[&(TypeCode::TypeStringDataItem, id.string_data_off)];
+            string_key(&dex.string_data_items[i])
+        })
+        .collect();
+    reorder(dex, IndexKind::String, keys)?;
+    let keys = dex.type_ids.iter().map(|t| t.descriptor_idx).collect();
+    reorder(dex, IndexKind::Type, keys)?;
+    let keys = dex
+        .proto_ids
+        .iter()
+        .map(|proto| {
+            let parameters = match index.get(&(TypeCode::TypeTypeList, proto.parameters_off)) {
+                Some(i) => dex.type_lists[*i]
+                    .list
+                    .iter()
+                    .map(|t| t.type_idx)
+                    .collect::<Vec<_>>(),
+                None => vec![],
+            };
+            (proto.return_type_idx, parameters)
+        })
+        .collect();
+    reorder(dex, IndexKind::Proto, keys)?;
+    let keys = dex
+        .field_ids
+        .iter()
+        .map(|f| (f.class_idx, f.name_idx, f.type_idx))
+        .collect();
+    reorder(dex, IndexKind::Field, keys)?;
+    let keys = dex
+        .method_ids
+        .iter()
+        .map(|m| (m.class_idx, m.name_idx, m.proto_idx))
+        .collect();
+    return reorder(dex, IndexKind::Method, keys);
+}
+
+/// Re-encodes class data members without the one at `removed`.
+fn remove_encoded_method(methods: &mut Vec<EncodedMethod>, removed: u32) {
+    let kept = method_indices(methods)
+        .into_iter()
+        .filter(|(idx, _)| *idx != removed)
+        .map(|(idx, m)| (idx, m.access_flags, m.code_off))
+        .collect::<Vec<_>>();
+    let mut prev = 0;
+    *methods = kept
+        .into_iter()
+        .map(|(idx, access_flags, code_off)| {
+            let method = EncodedMethod {
+                method_idx_off: idx - prev,
+                access_flags,
+                code_off,
+            };
+            prev = idx;
+            method
+        })
+        .collect();
+}
+
+fn remove_encoded_field(fields: &mut Vec<EncodedField>, removed: u32) {
+    let kept = field_indices(fields)
+        .into_iter()
+        .filter(|(idx, _)| *idx != removed)
+        .map(|(idx, f)| (idx, f.access_flags))
+        .collect::<Vec<_>>();
+    let mut prev = 0;
+    *fields = kept
+        .into_iter()
+        .map(|(idx, access_flags)| {
+            let field = EncodedField {
+                field_idx_off: idx - prev,
+                access_flags,
+            };
+            prev = idx;
+            field
+        })
+        .collect();
+}
+
+impl DexModel {
+    fn class_position(&self, descriptor: &str) -> Result<usize, EditError> {
+        let resolver = DexResolver::new(self);
+        return self
+            .class_defs
+            .iter()
+            .position(|c| resolver.type_descriptor(c.class_idx) == descriptor)
+            .ok_or_else(|| EditError::ClassNotFound(descriptor.to_string()));
+    }
+
+    fn type_position(&self, descriptor: &str) -> Option<usize> {
+        let resolver = DexResolver::new(self);
+        return (0..self.type_ids.len())
+            .find(|t| resolver.type_descriptor(*t as u32) == descriptor);
+    }
+
+    /// Applies `edit` to a copy of the model and keeps the copy only when
+    /// the edit succeeds, since sorting the pools can fail with an index
+    /// overflow after they were partly renumbered.
+    fn edit_copy(
+        &mut self,
+        edit: impl FnOnce(&mut DexModel) -> Result<(), EditError>,
+    ) -> Result<(), EditError> {
+        let mut dex = self.clone();
+        edit(&mut dex)?;
+        *self = dex;
+        return Ok(());
+    }
+
+    /// Gives class `class` its own copy of its annotations directory, which
+    /// d8 may share between classes, and applies `edit` to it.
+    fn edit_annotations(
+        &mut self,
+        class: usize,
+        offsets: &mut ItemOffsets,
+        edit: impl FnOnce(&mut AnnotationsDirectoryItem),
+    ) {
+        let annotations_off = self.class_defs[class].annotations_off;
+        let index = offsets.index();
+        let i = match index.get(&(TypeCode::TypeAnnotationsDirectoryItem, annotations_off)) {
+            Some(i) => *i,
+            None => return,
+        };
+        let mut directory = self.annotations_directory_items[i].clone();
+        edit(&mut directory);
+        self.annotations_directory_items.push(directory);
+        self.class_defs[class].annotations_off =
+            offsets.append(TypeCode::TypeAnnotationsDirectoryItem);
+    }
+
+    /// Removes the definition of a class, given as a type descriptor.
+    /// References to it from other classes are kept.
+    pub fn remove_class(&mut self, descriptor: &str) -> Result<(), EditError> {
+        let class = self.class_position(descriptor)?;
+        self.class_defs.remove(class);
+        compact(self);
+        return Ok(());
+    }
+
+    /// Removes a method, given by name and descriptor such as `(I)V`, from
+    /// the class that defines it, along with its code and annotations.
+    pub fn remove_method(
+        &mut self,
+        class: &str,
+        name: &str,
+        descriptor: &str,
+    ) -> Result<(), EditError> {
+        let position = self.class_position(class)?;
+        let signature = format!("{}->{}{}", class, name, descriptor);
+        let resolver = DexResolver::new(self);
+        let method_idx = resolver
+            .class_methods(&self.class_defs[position])
+            .into_iter()
+            .map(|(idx, _)| idx)
+            .find(|idx| resolver.method_ref(*idx).signature() == signature)
+            .ok_or(EditError::MemberNotFound(signature))?;
+
+        let mut offsets = ItemOffsets::of(self);
+        let index = offsets.index();
+        let class_data_off = self.class_defs[position].class_data_off;
+        if let Some(i) = index.get(&(TypeCode::TypeClassDataItem, class_data_off)) {
+            let class_data = &mut self.class_data_items[*i];
+            remove_encoded_method(&mut class_data.direct_methods, method_idx);
+            remove_encoded_method(&mut class_data.virtual_methods, method_idx);
+        }
+        self.edit_annotations(position, &mut offsets, |directory| {
+            directory
+                .method_annotations
+                .retain(|a| a.method_idx != method_idx);
+            directory
+                .parameter_annotations
+                .retain(|a| a.method_idx != method_idx);
+        });
+        compact_from(self, offsets);
+        return Ok(());
+    }
+
+    /// Removes a field, given by name and type descriptor, from the class
+    /// that defines it, along with its static value and annotations.
+    pub fn remove_field(
+        &mut self,
+        class: &str,
+        name: &str,
+        field_type: &str,
+    ) -> Result<(), EditError> {
+        let position = self.class_position(class)?;
+        let signature = format!("{}->{}:{}", class, name, field_type);
+        let resolver = DexResolver::new(self);
+        let class_def = &self.class_defs[position];
+        let field_idx = resolver
+            .class_fields(class_def)
+            .into_iter()
+            .map(|(idx, _)| idx)
+            .find(|idx| resolver.field_ref(*idx).signature() == signature)
+            .ok_or(EditError::MemberNotFound(signature))?;
+        // Static values are positional, one per static field in order.
+        let static_position = resolver.class_data(class_def).and_then(|data| {
+            field_indices(&data.static_fields)
+                .into_iter()
+                .position(|(idx, _)| idx == field_idx)
+        });
+
+        let mut offsets = ItemOffsets::of(self);
+        let index = offsets.index();
+        let class_def = &self.class_defs[position];
+        if let Some(i) = index.get(&(TypeCode::TypeClassDataItem, class_def.class_data_off)) {
+            let class_data = &mut self.class_data_items[*i];
+            remove_encoded_field(&mut class_data.static_fields, field_idx);
+            remove_encoded_field(&mut class_data.instance_fields, field_idx);
+        }
+        let static_values =
+            index.get(&(TypeCode::TypeEncodedArrayItem, class_def.static_values_off));
+        if let (Some(i), Some(k)) = (static_values, static_position) {
+            // Arrays may be shared between classes, so edit a copy.
+            let mut array = self.encoded_array_items[*i].clone();
+            if k < array.value.values.len() {
+                array.value.values.remove(k);
+            }
+            self.encoded_array_items.push(array);
+            self.class_defs[position].static_values_off =
+                offsets.append(TypeCode::TypeEncodedArrayItem);
+        }
+        self.edit_annotations(position, &mut offsets, |directory| {
+            directory
+                .field_annotations
+                .retain(|a| a.field_idx != field_idx);
+        });
+        compact_from(self, offsets);
+        return Ok(());
+    }
+
+    /// Sets the initial value of the static field `name` of `class`, given
+    /// as a type descriptor. Fields before it that have no value yet get the
+    /// default of their type, and defaults at the end of the array are left
+    /// out, as d8 does.
+    pub fn set_static_value(
+        &mut self,
+        class: &str,
+        name: &str,
+        value: &Value,
+    ) -> Result<(), EditError> {
+        let position = self.class_position(class)?;
+        let resolver = DexResolver::new(self);
+        let class_def = &self.class_defs[position];
+        let field_types = resolver.class_data(class_def).map_or(vec![], |data| {
+            field_indices(&data.static_fields)
+                .into_iter()
+                .map(|(idx, _)| resolver.field_ref(idx))
+                .collect::<Vec<_>>()
+        });
+        let k = field_types
+            .iter()
+            .position(|f| f.name == name)
+            .ok_or_else(|| EditError::MemberNotFound(format!("{}->{}", class, name)))?;
+        let field_types = field_types
+            .into_iter()
+            .map(|f| f.field_type)
+            .collect::<Vec<_>>();
+        if !accepts(&field_types[k], value) {
+            return Err(EditError::InvalidValue(format!(
+                "{} cannot be assigned to {}->{}:{}",
+                value, class, name, field_types[k]
+            )));
+        }
+        let mut values = match resolver.encoded_array(class_def.static_values_off) {
+            Some(array) if class_def.static_values_off != 0 => array.value.values.clone(),
+            _ => vec![],
+        };
+
+        // Encoding may fail after interning strings, so edit a copy.
+        return self.edit_copy(|dex| {
+            let mut indices = ValueIndices::new(dex);
+            let mut offsets = ItemOffsets::of(dex);
+            let encoded = encode_value(dex, &mut offsets, &mut indices, value)?;
+            while values.len() <= k {
+                values.push(default_value(&field_types[values.len()]));
+            }
+            values[k] = encoded;
+            while values
+                .last()
+                .is_some_and(|v| is_default(&field_types[values.len() - 1], v))
+            {
+                values.pop();
+            }
+            // Arrays may be shared between classes, so add a new one.
+            dex.class_defs[position].static_values_off = if values.is_empty() {
+                0
+            } else {
+                dex.encoded_array_items.push(EncodedArrayItem {
+                    value: EncodedArray { values },
+                });
+                offsets.append(TypeCode::TypeEncodedArrayItem)
+            };
+            sort_pools(dex, &offsets).map_err(overflow)?;
+            compact_from(dex, offsets);
+            return Ok(());
+        });
+    }
+
+    /// Renames a class, given as type descriptors such as `Lcom/Foo;`,
+    /// together with the array types of it.
+    pub fn rename_class(&mut self, old: &str, new: &str) -> Result<(), EditError> {
+        self.class_position(old)?;
+        let resolver = DexResolver::new(self);
+        let mut renames = vec![];
+        for t in 0..self.type_ids.len() {
+            let descriptor = resolver.type_descriptor(t as u32);
+            let element = descriptor.trim_start_matches('[');
+            if element == old {
+                let dims = descriptor.len() - element.len();
+                renames.push((t, format!("{}{}", &descriptor[..dims], new)));
+            }
+        }
+        for (_, renamed) in renames.iter() {
+            if self.type_position(renamed).is_some() {
+                return Err(EditError::AlreadyExists(renamed.clone()));
+            }
+        }
+
+        return self.edit_copy(|dex| {
+            let mut offsets = ItemOffsets::of(dex);
+            for (t, renamed) in renames {
+                dex.type_ids[t].descriptor_idx = intern(dex, &mut offsets, &renamed);
+            }
+            sort_pools(dex, &offsets).map_err(overflow)?;
+            compact_from(dex, offsets);
+            return Ok(());
+        });
+    }
+
+    /// Renames the field `name` of type `field_type` referenced through
+    /// `class`, both given as type descriptors, to `new`.
+    pub fn rename_field(
+        &mut self,
+        class: &str,
+        name: &str,
+        field_type: &str,
+        new: &str,
+    ) -> Result<(), EditError> {
+        self.type_position(class)
+            .ok_or_else(|| EditError::ClassNotFound(class.to_string()))?;
+        let resolver = DexResolver::new(self);
+        let signature = |name: &str| format!("{}->{}:{}", class, name, field_type);
+        let position = |signature: &str| {
+            (0..self.field_ids.len())
+                .find(|i| resolver.field_ref(*i as u32).signature() == signature)
+        };
+        let field =
+            position(&signature(name)).ok_or_else(|| EditError::MemberNotFound(signature(name)))?;
+        if position(&signature(new)).is_some() {
+            return Err(EditError::AlreadyExists(signature(new)));
+        }
+
+        return self.edit_copy(|dex| {
+            let mut offsets = ItemOffsets::of(dex);
+            dex.field_ids[field].name_idx = intern(dex, &mut offsets, new);
+            sort_pools(dex, &offsets).map_err(overflow)?;
+            compact_from(dex, offsets);
+            return Ok(());
+        });
+    }
+
+    /// Renames the method `name` with descriptor `descriptor`, such as
+    /// `(I)V`, referenced through `class` to `new`. Overloads keep their
+    /// name, and so do overrides in other classes and references through
+    /// subclasses: rename those as well, or virtual calls stop reaching
+    /// the renamed method.
+    pub fn rename_method(
+        &mut self,
+        class: &str,
+        name: &str,
+        descriptor: &str,
+        new: &str,
+    ) -> Result<(), EditError> {
+        self.type_position(class)
+            .ok_or_else(|| EditError::ClassNotFound(class.to_string()))?;
+        let resolver = DexResolver::new(self);
+        let signature = |name: &str| format!("{}->{}{}", class, name, descriptor);
+        let position = |signature: &str| {
+            (0..self.method_ids.len())
+                .find(|i| resolver.method_ref(*i as u32).signature() == signature)
+        };
+        let method =
+            position(&signature(name)).ok_or_else(|| EditError::MemberNotFound(signature(name)))?;
+        if position(&signature(new)).is_some() {
+            return Err(EditError::AlreadyExists(signature(new)));
+        }
+
+        return self.edit_copy(|dex| {
+            let mut offsets = ItemOffsets::of(dex);
+            dex.method_ids[method].name_idx = intern(dex, &mut offsets, new);
+            sort_pools(dex, &offsets).map_err(overflow)?;
+            compact_from(dex, offsets);
+            return Ok(());
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        deserialize_vec, serialize,
+        test_utils::{build_dex, TestClass},
+    };
+
+    fn round_trip(dex: DexModel) -> DexModel {
+        return deserialize_vec(serialize(dex)).unwrap();
+    }
+
+    fn sample() -> DexModel {
+        return build_dex(
+            vec![
+                TestClass::new("Lcom/app/Main;")
+                    .field("count", "I")
+                    .field("tracker", "Lcom/sdk/Tracker;")
+                    // invoke-static {}, Lcom/sdk/Tracker;->track()V; return-void
+                    .method("run", &[], "V", &[0x0071, 0x0003, 0x0000, 0x000e])
+                    .method("unused", &["I"], "I", &[0x000e]),
+                TestClass::new("Lcom/sdk/Tracker;")
+                    .method("init", &[], "V", &[0x000e])
+                    .method("track", &[], "V", &[0x000e]),
+            ],
+            &[],
+        );
+    }
+
+    fn signatures(dex: &DexModel) -> Vec<String> {
+        let resolver = DexResolver::new(dex);
+        return (0..dex.method_ids.len() as u32)
+            .map(|i| resolver.method_ref(i).signature())
+            .chain((0..dex.field_ids.len() as u32).map(|i| resolver.field_ref(i).signature()))
+            .collect();
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut dex = sample();
+        assert_eq!(signatures(&dex)[3], "Lcom/sdk/Tracker;->track()V");
+        dex.remove_method("Lcom/app/Main;", "unused", "(I)I")
+            .unwrap();
+        dex.remove_field("Lcom/app/Main;", "count", "I").unwrap();
+        let dex = round_trip(dex);
+        assert_eq!(
+            signatures(&dex),
+            vec![
+                "Lcom/app/Main;->run()V",
+                "Lcom/sdk/Tracker;->init()V",
+                "Lcom/sdk/Tracker;->track()V",
+                "Lcom/app/Main;->tracker:Lcom/sdk/Tracker;",
+            ]
+        );
+        assert_eq!(dex.proto_ids.len(), 1);
+        assert!(DexResolver::new(&dex)
+            .strings()
+            .iter()
+            .all(|s| s != "unused"));
+
+        let mut dex = dex;
+        dex.remove_class("Lcom/sdk/Tracker;").unwrap();
+        let dex = round_trip(dex);
+        // The call from `run` still refers to the removed class.
+        assert_eq!(
+            signatures(&dex),
+            vec![
+                "Lcom/app/Main;->run()V",
+                "Lcom/sdk/Tracker;->track()V",
+                "Lcom/app/Main;->tracker:Lcom/sdk/Tracker;",
+            ]
+        );
+        assert!(matches!(
+            dex.clone().remove_class("Lcom/sdk/Tracker;"),
+            Err(EditError::ClassNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_rename() {
+        let mut dex = sample();
+        dex.rename_class("Lcom/sdk/Tracker;", "La/T;").unwrap();
+        dex.rename_method("La/T;", "track", "()V", "a").unwrap();
+        let dex = round_trip(dex);
+        assert_eq!(
+            signatures(&dex),
+            vec![
+                "La/T;->a()V",
+                "La/T;->init()V",
+                "Lcom/app/Main;->run()V",
+                "Lcom/app/Main;->unused(I)I",
+                "Lcom/app/Main;->count:I",
+                "Lcom/app/Main;->tracker:La/T;",
+            ]
+        );
+        let resolver = DexResolver::new(&dex);
+        let main = resolver.class_def_by_descriptor("Lcom/app/Main;").unwrap();
+        let (_, run) = resolver
+            .class_methods(main)
+            .into_iter()
+            .find(|(i, _)| resolver.method_ref(*i).name == "run")
+            .unwrap();
+        let invoke = resolver.code_item(run.code_off).unwrap().insns[0].index_refs()[0];
+        assert_eq!(resolver.method_ref(invoke.index).signature(), "La/T;->a()V");
+        let mut strings = resolver.strings().to_vec();
+        strings.sort();
+        assert_eq!(resolver.strings(), strings.as_slice());
+        assert!(!strings.contains(&"track".to_string()));
+
+        let mut dex = dex;
+        assert!(matches!(
+            dex.rename_method("La/T;", "a", "()V", "init"),
+            Err(EditError::AlreadyExists(_))
+        ));
+    }
+
+    #[test]
+    fn test_overloads() {
+        let mut dex = build_dex(
+            vec![TestClass::new("Lc/C;")
+                .field("f", "I")
+                .field("f", "J")
+                .field("h", "I")
+                .method("f", &[], "V", &[0x000e])
+                .method("f", &["I"], "V", &[0x000e])],
+            &[],
+        );
+        assert!(matches!(
+            dex.clone().remove_field("Lc/C;", "f", "Z"),
+            Err(EditError::MemberNotFound(_))
+        ));
+        assert!(matches!(
+            dex.clone().rename_field("Lc/C;", "f", "I", "h"),
+            Err(EditError::AlreadyExists(_))
+        ));
+        dex.remove_field("Lc/C;", "f", "J").unwrap();
+        dex.rename_field("Lc/C;", "f", "I", "g").unwrap();
+        dex.rename_method("Lc/C;", "f", "(I)V", "g").unwrap();
+        let dex = round_trip(dex);
+        assert_eq!(
+            signatures(&dex),
+            vec!["Lc/C;->f()V", "Lc/C;->g(I)V", "Lc/C;->g:I", "Lc/C;->h:I"]
+        );
+    }
+
+    #[test]
+    fn test_rename_overflow_leaves_model_unchanged() {
+        // const-string v0, string@0xffff; return-void. Renaming the class
+        // to a name sorting first pushes the string out of 16 bits.
+        let extra = (0..0x10000)
+            .map(|i| format!("s{:05}", i))
+            .collect::<Vec<_>>();
+        let extra = extra.iter().map(String::as_str).collect::<Vec<_>>();
+        let mut dex = build_dex(
+            vec![TestClass::new("Lb/B;").method("run", &[], "V", &[0x001a, 0xffff, 0x000e])],
+            &extra,
+        );
+        assert!(DexResolver::new(&dex).string(0xffff).starts_with('s'));
+        let before = serialize(dex.clone());
+        assert!(matches!(
+            dex.rename_class("Lb/B;", "La/A;"),
+            Err(EditError::IndexOverflow { .. })
+        ));
+        assert_eq!(serialize(dex.clone()), before);
+        assert!(matches!(
+            dex.rename_method("Lb/B;", "run", "()V", "a"),
+            Err(EditError::IndexOverflow { .. })
+        ));
+        assert_eq!(serialize(dex), before);
+    }
+}
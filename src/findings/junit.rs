@@ -0,0 +1,144 @@
+//! JUnit XML output: one test suite per rule, one failing test case per
+//! issue, and a passing test case for rules without issues.
+
+use std::fmt::Write;
+
+use super::{all_rules, Issue, RuleInfo};
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // Control characters other than whitespace are not valid XML.
+            c if c.is_control() && !matches!(c, '\t' | '\n' | '\r') => {}
+            c => escaped.push(c),
+        }
+    }
+    return escaped;
+}
+
+/// Name of an issue as a test case: its location, else its rule.
+fn display_name(issue: &Issue) -> String {
+    return match issue.location.is_empty() {
+        true => issue.rule.clone(),
+        false => issue.location.to_string(),
+    };
+}
+
+/// A JUnit report in which issues suppressed by a baseline are skipped
+/// test cases.
+pub fn to_junit(rules: &[RuleInfo], issues: &[Issue], suppressed: &[Issue]) -> String {
+    let rules = all_rules(rules, &issues.iter().chain(suppressed).collect::<Vec<_>>());
+    let mut suites = String::new();
+    for rule in rules.iter() {
+        let failures = issues
+            .iter()
+            .filter(|i| i.rule == rule.id)
+            .collect::<Vec<_>>();
+        let skipped = suppressed
+            .iter()
+            .filter(|i| i.rule == rule.id)
+            .collect::<Vec<_>>();
+        let id = escape(&rule.id);
+        let tests = (failures.len() + skipped.len()).max(1);
+        writeln!(
+            suites,
+            "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" skipped=\"{}\">",
+            id,
+            tests,
+            failures.len(),
+            skipped.len()
+        )
+        .unwrap();
+        if failures.is_empty() && skipped.is_empty() {
+            writeln!(suites, "    <testcase classname=\"{0}\" name=\"{0}\"/>", id).unwrap();
+        }
+        for issue in failures {
+            writeln!(
+                suites,
+                "    <testcase classname=\"{}\" name=\"{}\">",
+                id,
+                escape(&display_name(issue))
+            )
+            .unwrap();
+            writeln!(
+                suites,
+                "      <failure type=\"{}\" message=\"{}\">{}</failure>",
+                issue.severity,
+                escape(&issue.message),
+                escape(rule.help.as_deref().unwrap_or_default())
+            )
+            .unwrap();
+            writeln!(suites, "    </testcase>").unwrap();
+        }
+        for issue in skipped {
+            writeln!(
+                suites,
+                "    <testcase classname=\"{}\" name=\"{}\">",
+                id,
+                escape(&display_name(issue))
+            )
+            .unwrap();
+            writeln!(
+                suites,
+                "      <skipped message=\"suppressed by baseline\"/>"
+            )
+            .unwrap();
+            writeln!(suites, "    </testcase>").unwrap();
+        }
+        writeln!(suites, "  </testsuite>").unwrap();
+    }
+    let tests = rules
+        .iter()
+        .map(|rule| {
+            let count = issues
+                .iter()
+                .chain(suppressed)
+                .filter(|i| i.rule == rule.id);
+            count.count().max(1)
+        })
+        .sum::<usize>();
+    return format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <testsuites name=\"apkdoctor\" tests=\"{}\" failures=\"{}\" skipped=\"{}\">\n\
+         {}</testsuites>\n",
+        tests,
+        issues.len(),
+        suppressed.len(),
+        suites
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{dangerous_apis::Severity, findings::tests::issues};
+
+    #[test]
+    fn test_to_junit() {
+        let issues = issues();
+        let rules = vec![RuleInfo {
+            id: "clean".to_string(),
+            severity: Severity::Low,
+            description: None,
+            help: None,
+        }];
+        let xml = to_junit(&rules, &issues[1..], &issues[..1]);
+        assert!(xml
+            .contains("<testsuites name=\"apkdoctor\" tests=\"3\" failures=\"1\" skipped=\"1\">"));
+        assert!(xml.contains("<testcase classname=\"clean\" name=\"clean\"/>"));
+        assert!(xml.contains(
+            "<failure type=\"low\" message=\"the application is &lt;debuggable&gt; &amp; &quot;exposed&quot;\">"
+        ));
+        assert!(xml.contains(
+            "<testsuite name=\"exec\" tests=\"1\" failures=\"0\" skipped=\"1\">\n    \
+             <testcase classname=\"exec\" name=\"classes2.dex Lcom/example/Main;-&gt;run()V @0004 (Main.kt:12)\">\n      \
+             <skipped message=\"suppressed by baseline\"/>"
+        ));
+    }
+}
@@ -0,0 +1,464 @@
+//! Findings in a tool-neutral form, for export to CI systems.
+//!
+//! Lint and doctor findings both convert to `Issue`, which carries a stable
+//! rule id and a `Location`. Issues can be written as SARIF 2.1.0 for code
+//! scanning or as JUnit XML for test dashboards, and a `Baseline` of known
+//! issues suppresses them in later runs.
+
+mod junit;
+mod sarif;
+
+use std::{
+    fmt::{self, Display, Formatter},
+    fs, io,
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+
+use crate::{
+    dangerous_apis::{self, Rule, Severity},
+    der::hex,
+    doctor::{self, CheckResult},
+};
+
+pub use junit::to_junit;
+pub use sarif::to_sarif;
+
+const BASELINE_VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub enum BaselineError {
+    FileOpenError(io::Error),
+    JsonError(serde_json::Error),
+    UnsupportedVersion(u32),
+}
+
+impl From<io::Error> for BaselineError {
+    fn from(err: io::Error) -> Self {
+        BaselineError::FileOpenError(err)
+    }
+}
+
+impl From<serde_json::Error> for BaselineError {
+    fn from(err: serde_json::Error) -> Self {
+        BaselineError::JsonError(err)
+    }
+}
+
+impl Display for BaselineError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            BaselineError::FileOpenError(err) => write!(f, "{}", err),
+            BaselineError::JsonError(err) => write!(f, "{}", err),
+            BaselineError::UnsupportedVersion(version) => {
+                write!(f, "unsupported baseline version {}", version)
+            }
+        }
+    }
+}
+
+/// Where a finding is. Every part is optional: a manifest setting has no
+/// location at all, a native library only an archive entry.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Location {
+    /// Archive entry other than a dex file, e.g. `lib/x86/libfoo.so`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub entry: Option<String>,
+    /// Name of the dex file, e.g. `classes2.dex` or `base/dex/classes.dex`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dex: Option<String>,
+    /// Java name of the class.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub class: Option<String>,
+    /// Smali signature of the method, e.g. `Lcom/Foo;->bar(I)V`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+    /// Code unit offset of the instruction within the method.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_file: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<u32>,
+}
+
+impl Location {
+    pub fn entry(name: &str) -> Location {
+        return Location {
+            entry: Some(name.to_string()),
+            ..Location::default()
+        };
+    }
+
+    pub fn dex(name: &str) -> Location {
+        return Location {
+            dex: Some(name.to_string()),
+            ..Location::default()
+        };
+    }
+
+    pub fn class(name: &str) -> Location {
+        return Location {
+            class: Some(name.to_string()),
+            ..Location::default()
+        };
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return *self == Location::default();
+    }
+
+    /// Path of the source file relative to a source root, derived from the
+    /// package of the class, e.g. `com/example/Main.kt`.
+    pub fn source_path(&self) -> Option<String> {
+        let source_file = self.source_file.as_ref()?;
+        return match self.class.as_ref().and_then(|c| c.rsplit_once('.')) {
+            Some((package, _)) => Some(format!("{}/{}", package.replace('.', "/"), source_file)),
+            None => Some(source_file.clone()),
+        };
+    }
+}
+
+impl From<&dangerous_apis::Finding> for Location {
+    fn from(finding: &dangerous_apis::Finding) -> Self {
+        return Location {
+            entry: None,
+            dex: Some(finding.dex.clone()),
+            class: Some(finding.class.clone()),
+            method: Some(finding.method_signature.clone()),
+            address: Some(finding.address),
+            source_file: finding.source_file.clone(),
+            line: finding.line,
+        };
+    }
+}
+
+impl Display for Location {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let mut parts = vec![];
+        parts.extend(self.entry.clone());
+        parts.extend(self.dex.clone());
+        parts.extend(self.method.clone().or(self.class.clone()));
+        parts.extend(self.address.map(|address| format!("@{:04x}", address)));
+        match (self.source_file.as_ref(), self.line) {
+            (Some(file), Some(line)) => parts.push(format!("({}:{})", file, line)),
+            (Some(file), None) => parts.push(format!("({})", file)),
+            (None, Some(line)) => parts.push(format!("(line {})", line)),
+            (None, None) => {}
+        }
+        return write!(f, "{}", parts.join(" "));
+    }
+}
+
+/// A rule that issues are reported against.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RuleInfo {
+    pub id: String,
+    pub severity: Severity,
+    pub description: Option<String>,
+    /// How to fix what the rule reports.
+    pub help: Option<String>,
+}
+
+impl From<&Rule> for RuleInfo {
+    fn from(rule: &Rule) -> Self {
+        return RuleInfo {
+            id: rule.id.clone(),
+            severity: rule.severity,
+            description: Some(rule.message.clone()),
+            help: None,
+        };
+    }
+}
+
+impl From<&CheckResult> for RuleInfo {
+    fn from(check: &CheckResult) -> Self {
+        return RuleInfo {
+            id: check.id.clone(),
+            severity: check.severity,
+            description: Some(format!("{} check", check.category)),
+            help: Some(check.remediation.clone()),
+        };
+    }
+}
+
+/// One finding of any analysis.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Issue {
+    pub rule: String,
+    pub severity: Severity,
+    pub message: String,
+    pub location: Location,
+}
+
+impl Issue {
+    /// Identifies the issue across builds: the rule, message, archive entry
+    /// and method, but not the dex file, address or line, which move
+    /// whenever unrelated code changes. The class only counts for issues
+    /// without a method, since a mapping changes its Java name but not the
+    /// smali signature of the method. Issues that only differ in the rest
+    /// share it; `fingerprints` tells them apart.
+    pub fn fingerprint(&self) -> String {
+        return self.fingerprint_at(0);
+    }
+
+    /// The fingerprint of the `occurrence`th issue sharing this one's. The
+    /// first keeps the plain fingerprint.
+    fn fingerprint_at(&self, occurrence: usize) -> String {
+        let location = &self.location;
+        let mut hasher = Sha1::new();
+        for part in [
+            Some(&self.rule),
+            Some(&self.message),
+            location.entry.as_ref(),
+            location
+                .class
+                .as_ref()
+                .filter(|_| location.method.is_none()),
+            location.method.as_ref(),
+        ] {
+            hasher.update(part.map(|p| p.as_str()).unwrap_or_default());
+            hasher.update([0]);
+        }
+        if occurrence > 0 {
+            hasher.update(occurrence.to_string());
+            hasher.update([0]);
+        }
+        return hex(&hasher.finalize());
+    }
+}
+
+/// The fingerprint of each of `issues`, with an occurrence index for issues
+/// whose `Issue::fingerprint` is the same, e.g. two calls to one API in one
+/// method. Occurrences are counted in dex file, address and line order, so
+/// the result does not depend on the order of `issues`.
+pub fn fingerprints(issues: &[Issue]) -> Vec<String> {
+    let plain = issues.iter().map(Issue::fingerprint).collect::<Vec<_>>();
+    let mut order = (0..issues.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&i| {
+        let location = &issues[i].location;
+        (&plain[i], &location.dex, location.address, location.line)
+    });
+    let mut fingerprints = vec![String::new(); issues.len()];
+    let mut occurrence = 0;
+    for (k, &i) in order.iter().enumerate() {
+        occurrence = match k > 0 && plain[order[k - 1]] == plain[i] {
+            true => occurrence + 1,
+            false => 0,
+        };
+        fingerprints[i] = issues[i].fingerprint_at(occurrence);
+    }
+    return fingerprints;
+}
+
+impl From<&dangerous_apis::Finding> for Issue {
+    fn from(finding: &dangerous_apis::Finding) -> Self {
+        let mut message = format!("{}: {}", finding.message, finding.target);
+        if let Some(argument) = &finding.argument {
+            message += &format!(" with {}", argument);
+        }
+        return Issue {
+            rule: finding.rule.clone(),
+            severity: finding.severity,
+            message,
+            location: Location::from(finding),
+        };
+    }
+}
+
+impl From<&doctor::Finding> for Issue {
+    fn from(finding: &doctor::Finding) -> Self {
+        return Issue {
+            rule: finding.check.clone(),
+            severity: finding.severity,
+            message: finding.message.clone(),
+            location: finding.location.clone(),
+        };
+    }
+}
+
+/// A known issue to ignore. `location` only helps humans review the file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Suppression {
+    pub rule: String,
+    pub fingerprint: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<String>,
+}
+
+/// Issues accepted in an earlier run, stored as JSON:
+///
+/// ```json
+/// {"version": 1, "suppressions": [{"rule": "debuggable", "fingerprint": "…"}]}
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Baseline {
+    pub version: u32,
+    pub suppressions: Vec<Suppression>,
+}
+
+impl Baseline {
+    /// A baseline suppressing every one of `issues`.
+    pub fn from_issues(issues: &[Issue]) -> Baseline {
+        let suppressions = issues
+            .iter()
+            .zip(fingerprints(issues))
+            .map(|(issue, fingerprint)| Suppression {
+                rule: issue.rule.clone(),
+                fingerprint,
+                location: Some(issue.location.to_string()).filter(|l| !l.is_empty()),
+            })
+            .collect();
+        return Baseline {
+            version: BASELINE_VERSION,
+            suppressions,
+        };
+    }
+
+    pub fn from_json(text: &str) -> Result<Baseline, BaselineError> {
+        let baseline: Baseline = serde_json::from_str(text)?;
+        if baseline.version != BASELINE_VERSION {
+            return Err(BaselineError::UnsupportedVersion(baseline.version));
+        }
+        return Ok(baseline);
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Baseline, BaselineError> {
+        return Baseline::from_json(&fs::read_to_string(path)?);
+    }
+
+    pub fn to_json(&self) -> String {
+        return serde_json::to_string_pretty(self).unwrap();
+    }
+
+    /// Whether each of `issues`, which should be every issue of a run, is
+    /// listed in the baseline.
+    pub fn suppressed(&self, issues: &[Issue]) -> Vec<bool> {
+        return issues
+            .iter()
+            .zip(fingerprints(issues))
+            .map(|(issue, fingerprint)| {
+                self.suppressions
+                    .iter()
+                    .any(|s| s.rule == issue.rule && s.fingerprint == fingerprint)
+            })
+            .collect();
+    }
+}
+
+/// Rules in report order, followed by any rule only known from an issue.
+fn all_rules(rules: &[RuleInfo], issues: &[&Issue]) -> Vec<RuleInfo> {
+    let mut all = rules.to_vec();
+    for issue in issues {
+        if !all.iter().any(|r| r.id == issue.rule) {
+            all.push(RuleInfo {
+                id: issue.rule.clone(),
+                severity: issue.severity,
+                description: None,
+                help: None,
+            });
+        }
+    }
+    return all;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    pub(super) fn issues() -> Vec<Issue> {
+        return vec![
+            Issue {
+                rule: "exec".to_string(),
+                severity: Severity::High,
+                message: "Runs a shell command: Ljava/lang/Runtime;->exec(Ljava/lang/String;)Ljava/lang/Process;".to_string(),
+                location: Location {
+                    dex: Some("classes2.dex".to_string()),
+                    class: Some("com.example.Main".to_string()),
+                    method: Some("Lcom/example/Main;->run()V".to_string()),
+                    address: Some(4),
+                    source_file: Some("Main.kt".to_string()),
+                    line: Some(12),
+                    ..Location::default()
+                },
+            },
+            Issue {
+                rule: "debuggable".to_string(),
+                severity: Severity::Low,
+                message: "the application is <debuggable> & \"exposed\"".to_string(),
+                location: Location::default(),
+            },
+        ];
+    }
+
+    #[test]
+    fn test_location() {
+        let issues = issues();
+        assert_eq!(
+            issues[0].location.to_string(),
+            "classes2.dex Lcom/example/Main;->run()V @0004 (Main.kt:12)"
+        );
+        assert_eq!(
+            issues[0].location.source_path().as_deref(),
+            Some("com/example/Main.kt")
+        );
+        assert_eq!(
+            Location::entry("lib/x86/libfoo.so").to_string(),
+            "lib/x86/libfoo.so"
+        );
+        assert!(issues[1].location.is_empty());
+    }
+
+    #[test]
+    fn test_baseline() {
+        let mut issues = issues();
+        let baseline = Baseline::from_json(&Baseline::from_issues(&issues[..1]).to_json()).unwrap();
+        assert_eq!(
+            baseline.suppressions[0].location.as_deref(),
+            Some("classes2.dex Lcom/example/Main;->run()V @0004 (Main.kt:12)")
+        );
+        assert_eq!(baseline.suppressed(&issues), vec![true, false]);
+
+        // Moving to another dex file or line keeps the fingerprint.
+        let fingerprint = issues[0].fingerprint();
+        issues[0].location.dex = Some("classes3.dex".to_string());
+        issues[0].location.line = Some(40);
+        assert_eq!(issues[0].fingerprint(), fingerprint);
+        // So does a mapping, which renames the class but not the method
+        // signature.
+        issues[0].location.class = Some("a.a".to_string());
+        assert_eq!(issues[0].fingerprint(), fingerprint);
+        let mut class = Issue {
+            location: Location::class("com.example.Main"),
+            ..issues[1].clone()
+        };
+        let class_fingerprint = class.fingerprint();
+        class.location.class = Some("com.example.Other".to_string());
+        assert_ne!(class.fingerprint(), class_fingerprint);
+        issues[0].location.method = Some("Lcom/example/Main;->start()V".to_string());
+        assert_eq!(baseline.suppressed(&issues), vec![false, false]);
+
+        // A second call in the same method is a new issue, whichever order
+        // the issues come in.
+        let mut second = issues[0].clone();
+        second.location.address = Some(9);
+        let baseline = Baseline::from_issues(&issues[..1]);
+        let both = vec![second.clone(), issues[0].clone()];
+        assert_eq!(baseline.suppressed(&both), vec![false, true]);
+        let fingerprints = fingerprints(&both);
+        assert_ne!(fingerprints[0], fingerprints[1]);
+        let baseline = Baseline::from_issues(&both);
+        assert_eq!(baseline.suppressions.len(), 2);
+        assert_eq!(
+            baseline.suppressed(&[issues[0].clone(), second]),
+            vec![true, true]
+        );
+
+        assert!(matches!(
+            Baseline::from_json(r#"{"version": 2, "suppressions": []}"#),
+            Err(BaselineError::UnsupportedVersion(2))
+        ));
+    }
+}
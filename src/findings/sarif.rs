@@ -0,0 +1,166 @@
+//! SARIF 2.1.0 output, as consumed by code scanning services.
+
+use serde_json::{json, Value};
+
+use super::{all_rules, fingerprints, Issue, RuleInfo};
+use crate::dangerous_apis::Severity;
+
+const SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+const FINGERPRINT_KEY: &str = "apkdoctor/v1";
+
+fn level(severity: Severity) -> &'static str {
+    return match severity {
+        Severity::High => "error",
+        Severity::Medium => "warning",
+        Severity::Low | Severity::Info => "note",
+    };
+}
+
+fn rule(rule: &RuleInfo) -> Value {
+    let mut value = json!({
+        "id": rule.id,
+        "defaultConfiguration": { "level": level(rule.severity) },
+        "properties": { "severity": rule.severity },
+    });
+    if let Some(description) = &rule.description {
+        value["shortDescription"] = json!({ "text": description });
+    }
+    if let Some(help) = &rule.help {
+        value["help"] = json!({ "text": help });
+    }
+    return value;
+}
+
+/// The source file when the line table names one, else the archive entry
+/// or dex file holding the issue.
+fn physical_location(issue: &Issue) -> Option<Value> {
+    let location = &issue.location;
+    if let Some(path) = location.source_path() {
+        let mut physical = json!({ "artifactLocation": { "uri": path } });
+        // Lines are 1-based; 0 means the line table has no entry.
+        if let Some(line) = location.line.filter(|line| *line > 0) {
+            physical["region"] = json!({ "startLine": line });
+        }
+        return Some(physical);
+    }
+    let uri = location.entry.as_ref().or(location.dex.as_ref())?;
+    return Some(json!({ "artifactLocation": { "uri": uri } }));
+}
+
+fn result(issue: &Issue, rule_index: usize, fingerprint: String, suppressed: bool) -> Value {
+    let location = &issue.location;
+    let mut sarif_location = json!({});
+    if let Some(physical) = physical_location(issue) {
+        sarif_location["physicalLocation"] = physical;
+    }
+    if let Some(name) = location.method.as_ref().or(location.class.as_ref()) {
+        let kind = if location.method.is_some() {
+            "function"
+        } else {
+            "type"
+        };
+        sarif_location["logicalLocations"] = json!([{ "fullyQualifiedName": name, "kind": kind }]);
+    }
+    let mut value = json!({
+        "ruleId": issue.rule,
+        "ruleIndex": rule_index,
+        "level": level(issue.severity),
+        "message": { "text": issue.message },
+        "partialFingerprints": { FINGERPRINT_KEY: fingerprint },
+        "properties": { "bytecode": location },
+    });
+    if sarif_location != json!({}) {
+        value["locations"] = json!([sarif_location]);
+    }
+    if suppressed {
+        value["suppressions"] = json!([{ "kind": "external", "justification": "baseline" }]);
+    }
+    return value;
+}
+
+/// A SARIF log with one run. Suppressed issues are kept as results with an
+/// external suppression, so that code scanning closes their alerts.
+pub fn to_sarif(rules: &[RuleInfo], issues: &[Issue], suppressed: &[Issue]) -> Value {
+    let rules = all_rules(rules, &issues.iter().chain(suppressed).collect::<Vec<_>>());
+    let index = |issue: &Issue| rules.iter().position(|r| r.id == issue.rule).unwrap();
+    let all = issues.iter().chain(suppressed).cloned().collect::<Vec<_>>();
+    let results = all
+        .iter()
+        .zip(fingerprints(&all))
+        .enumerate()
+        .map(|(i, (issue, fingerprint))| {
+            result(issue, index(issue), fingerprint, i >= issues.len())
+        })
+        .collect::<Vec<_>>();
+    return json!({
+        "$schema": SCHEMA,
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "apkdoctor",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": rules.iter().map(rule).collect::<Vec<_>>(),
+                },
+            },
+            "results": results,
+        }],
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::findings::tests::issues;
+
+    #[test]
+    fn test_to_sarif() {
+        let issues = issues();
+        let rules = vec![RuleInfo {
+            id: "debuggable".to_string(),
+            severity: Severity::High,
+            description: Some("security check".to_string()),
+            help: Some("Remove android:debuggable.".to_string()),
+        }];
+        let sarif = to_sarif(&rules, &issues[..1], &issues[1..]);
+        let run = &sarif["runs"][0];
+        let driver_rules = run["tool"]["driver"]["rules"].as_array().unwrap();
+        assert_eq!(driver_rules.len(), 2);
+        assert_eq!(
+            driver_rules[0]["help"]["text"],
+            "Remove android:debuggable."
+        );
+        assert_eq!(driver_rules[1]["id"], "exec");
+
+        let result = &run["results"][0];
+        assert_eq!(result["ruleIndex"], 1);
+        assert_eq!(result["level"], "error");
+        let location = &result["locations"][0];
+        assert_eq!(
+            location["physicalLocation"]["artifactLocation"]["uri"],
+            "com/example/Main.kt"
+        );
+        assert_eq!(location["physicalLocation"]["region"]["startLine"], 12);
+        assert_eq!(
+            location["logicalLocations"][0]["fullyQualifiedName"],
+            "Lcom/example/Main;->run()V"
+        );
+        assert_eq!(result["properties"]["bytecode"]["address"], 4);
+        assert_eq!(
+            result["partialFingerprints"][FINGERPRINT_KEY],
+            issues[0].fingerprint()
+        );
+
+        let suppressed = &run["results"][1];
+        assert_eq!(suppressed["level"], "note");
+        assert_eq!(suppressed["suppressions"][0]["kind"], "external");
+        assert!(suppressed.get("locations").is_none());
+
+        let mut issues = issues;
+        issues[0].location.line = Some(0);
+        let sarif = to_sarif(&rules, &issues[..1], &[]);
+        let physical = &sarif["runs"][0]["results"][0]["locations"][0]["physicalLocation"];
+        assert_eq!(physical["artifactLocation"]["uri"], "com/example/Main.kt");
+        assert!(physical.get("region").is_none());
+    }
+}
@@ -0,0 +1,250 @@
+//! Header and map summaries of dex files, and integrity checks of the raw
+//! bytes behind them.
+
+use std::fmt::{self, Display, Formatter};
+
+use serde::Serialize;
+use sha1::{Digest, Sha1};
+
+use crate::{dex_model::DexModel, dex_structs::TypeCode, layout::adler32};
+
+const HEADER_SIZE: usize = 0x70;
+const ENDIAN_CONSTANT: u32 = 0x12345678;
+
+/// Name of a map section, as used by `dexdump`, e.g. `string_id_item`.
+pub fn section_name(type_code: TypeCode) -> &'static str {
+    return match type_code {
+        TypeCode::TypeHeaderItem => "header_item",
+        TypeCode::TypeStringIdItem => "string_id_item",
+        TypeCode::TypeTypeIdItem => "type_id_item",
+        TypeCode::TypeProtoIdItem => "proto_id_item",
+        TypeCode::TypeFieldIdItem => "field_id_item",
+        TypeCode::TypeMethodIdItem => "method_id_item",
+        TypeCode::TypeClassDefItem => "class_def_item",
+        TypeCode::TypeCallSiteIdItem => "call_site_id_item",
+        TypeCode::TypeMethodHandleItem => "method_handle_item",
+        TypeCode::TypeMapList => "map_list",
+        TypeCode::TypeTypeList => "type_list",
+        TypeCode::TypeAnnotationSetRefList => "annotation_set_ref_list",
+        TypeCode::TypeAnnotationSetItem => "annotation_set_item",
+        TypeCode::TypeClassDataItem => "class_data_item",
+        TypeCode::TypeCodeItem => "code_item",
+        TypeCode::TypeStringDataItem => "string_data_item",
+        TypeCode::TypeDebugInfoItem => "debug_info_item",
+        TypeCode::TypeAnnotationItem => "annotation_item",
+        TypeCode::TypeEncodedArrayItem => "encoded_array_item",
+        TypeCode::TypeAnnotationsDirectoryItem => "annotations_directory_item",
+        TypeCode::TypeHiddenapiClassDataItem => "hiddenapi_class_data_item",
+    };
+}
+
+fn hex(bytes: &[u8]) -> String {
+    return bytes.iter().map(|b| format!("{:02x}", b)).collect();
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SectionInfo {
+    pub section: &'static str,
+    pub size: u32,
+    pub offset: u32,
+}
+
+/// Summary of a dex header and map list.
+#[derive(Debug, Clone, Serialize)]
+pub struct DexInfo {
+    pub dex: String,
+    /// Format version from the magic, e.g. `035`.
+    pub version: String,
+    pub file_size: u32,
+    pub checksum: String,
+    pub signature: String,
+    pub strings: u32,
+    pub types: u32,
+    pub protos: u32,
+    pub fields: u32,
+    pub methods: u32,
+    pub classes: u32,
+    pub sections: Vec<SectionInfo>,
+}
+
+impl DexInfo {
+    pub fn new(name: &str, dex: &DexModel) -> DexInfo {
+        let header = &dex.header;
+        return DexInfo {
+            dex: name.to_string(),
+            version: String::from_utf8_lossy(&header.magic[4..7]).to_string(),
+            file_size: header.file_size,
+            checksum: format!("{:08x}", header.checksum),
+            signature: hex(&header.signature),
+            strings: header.string_ids_size,
+            types: header.type_ids_size,
+            protos: header.proto_ids_size,
+            fields: header.field_ids_size,
+            methods: header.method_ids_size,
+            classes: header.class_defs_size,
+            sections: dex
+                .map_list
+                .list
+                .iter()
+                .map(|item| SectionInfo {
+                    section: section_name(item.type_code),
+                    size: item.size,
+                    offset: item.offset,
+                })
+                .collect(),
+        };
+    }
+
+    pub fn to_json(&self) -> String {
+        return serde_json::to_string_pretty(self).unwrap();
+    }
+}
+
+impl Display for DexInfo {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{}: dex {}, {} bytes",
+            self.dex, self.version, self.file_size
+        )?;
+        writeln!(f, "  checksum:  {}", self.checksum)?;
+        writeln!(f, "  signature: {}", self.signature)?;
+        writeln!(
+            f,
+            "  strings {}, types {}, protos {}, fields {}, methods {}, classes {}",
+            self.strings, self.types, self.protos, self.fields, self.methods, self.classes
+        )?;
+        for section in self.sections.iter() {
+            writeln!(
+                f,
+                "  {:<28} {:>8} @ 0x{:08x}",
+                section.section, section.size, section.offset
+            )?;
+        }
+        return Ok(());
+    }
+}
+
+/// Problems found in the header of an encoded dex file: a bad magic, size,
+/// checksum or signature, or map sections pointing outside the file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DexVerification {
+    pub dex: String,
+    pub problems: Vec<String>,
+}
+
+impl DexVerification {
+    pub fn new(name: &str, bytes: &[u8]) -> DexVerification {
+        let mut verification = DexVerification {
+            dex: name.to_string(),
+            problems: vec![],
+        };
+        if bytes.len() < HEADER_SIZE || !bytes.starts_with(b"dex\n") {
+            verification.problems.push("not a dex file".to_string());
+            return verification;
+        }
+        let u32_at = |off: usize| u32::from_le_bytes(bytes[off..off + 4].try_into().unwrap());
+        let mut problem = |problem: String| verification.problems.push(problem);
+
+        if u32_at(0x20) as usize != bytes.len() {
+            problem(format!(
+                "file_size is {} but the file has {} bytes",
+                u32_at(0x20),
+                bytes.len()
+            ));
+        }
+        if u32_at(0x24) as usize != HEADER_SIZE {
+            problem(format!("header_size is 0x{:x}", u32_at(0x24)));
+        }
+        if u32_at(0x28) != ENDIAN_CONSTANT {
+            problem(format!("endian_tag is 0x{:08x}", u32_at(0x28)));
+        }
+        let checksum = adler32(&bytes[12..]);
+        if u32_at(8) != checksum {
+            problem(format!(
+                "checksum is {:08x}, expected {:08x}",
+                u32_at(8),
+                checksum
+            ));
+        }
+        let signature = Sha1::digest(&bytes[32..]);
+        if bytes[12..32] != signature[..] {
+            problem(format!(
+                "signature is {}, expected {}",
+                hex(&bytes[12..32]),
+                hex(&signature)
+            ));
+        }
+
+        let map_off = u32_at(0x34) as usize;
+        if map_off.checked_add(4).is_none_or(|end| end > bytes.len()) {
+            problem(format!("map_off 0x{:x} is outside the file", map_off));
+            return verification;
+        }
+        let map_size = u32_at(map_off) as usize;
+        for i in 0..map_size {
+            let item = map_off + 4 + i * 12;
+            if item + 12 > bytes.len() {
+                problem("map_list is truncated".to_string());
+                break;
+            }
+            let offset = u32_at(item + 8) as usize;
+            if offset >= bytes.len() {
+                problem(format!(
+                    "map item 0x{:04x} starts at 0x{:x}, outside the file",
+                    u16::from_le_bytes([bytes[item], bytes[item + 1]]),
+                    offset
+                ));
+            }
+        }
+        return verification;
+    }
+
+    pub fn is_ok(&self) -> bool {
+        return self.problems.is_empty();
+    }
+}
+
+impl Display for DexVerification {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if self.is_ok() {
+            return writeln!(f, "{}: ok", self.dex);
+        }
+        writeln!(f, "{}: {} problem(s)", self.dex, self.problems.len())?;
+        for problem in self.problems.iter() {
+            writeln!(f, "  {}", problem)?;
+        }
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        layout::update_checksums,
+        serialize,
+        test_utils::{build_dex, TestClass},
+    };
+
+    #[test]
+    fn test_info_and_verification() {
+        let dex = build_dex(
+            vec![TestClass::new("La/A;").method("run", &[], "V", &[0x000e])],
+            &[],
+        );
+        let info = DexInfo::new("classes.dex", &dex);
+        assert_eq!(info.version, "038");
+        assert_eq!(info.classes, 1);
+        assert_eq!(info.sections[0].section, "header_item");
+
+        let mut bytes = serialize(dex);
+        update_checksums(&mut bytes);
+        assert!(DexVerification::new("classes.dex", &bytes).is_ok());
+        bytes[HEADER_SIZE] ^= 0xff;
+        let verification = DexVerification::new("classes.dex", &bytes);
+        assert_eq!(verification.problems.len(), 2);
+        assert!(verification.problems[0].starts_with("checksum"));
+        assert!(verification.problems[1].starts_with("signature"));
+    }
+}
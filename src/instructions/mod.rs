@@ -3,11 +3,11 @@ use std::{
     io,
 };
 
-mod op_to_str;
-
+pub use crate::opcodes::IndexKind;
 use crate::{
     decode::{decode_i8, decode_u16, decode_u8},
     encode::{encode_u16, encode_u64},
+    opcodes::{opcode_info, Format, CONST, MOVE, SETS_REGISTER, WIDE},
 };
 use crate::{
     decode::{decode_u32, decode_u64},
@@ -103,7 +103,7 @@ macro_rules! build_instruction_enum {
     ($($i:ident),* $(,)*) => {
         build_instruction_enum! {
             @as_item
-            #[derive(Debug, PartialEq, Eq)]
+            #[derive(Debug, Clone, PartialEq, Eq)]
             pub enum Instruction {
                 $($i($i),)*
             }
@@ -165,9 +165,338 @@ impl Instruction {
         }
         call_macro_with_structs!(impl_serialize);
     }
+
+    /// Opcode of the instruction; payload pseudo-instructions report `0x00`.
+    pub(crate) fn opcode(&self) -> u8 {
+        return match self {
+            Instruction::Ins10x(i) => i.op,
+            Instruction::Ins12x(i) => i.op,
+            Instruction::Ins11n(i) => i.op,
+            Instruction::Ins11x(i) => i.op,
+            Instruction::Ins10t(i) => i.op,
+            Instruction::Ins20t(i) => i.op,
+            Instruction::Ins20bc(i) => i.op,
+            Instruction::Ins22x(i) => i.op,
+            Instruction::Ins21t(i) => i.op,
+            Instruction::Ins21s(i) => i.op,
+            Instruction::Ins21h(i) => i.op,
+            Instruction::Ins21c(i) => i.op,
+            Instruction::Ins23x(i) => i.op,
+            Instruction::Ins22b(i) => i.op,
+            Instruction::Ins22t(i) => i.op,
+            Instruction::Ins22s(i) => i.op,
+            Instruction::Ins22c(i) => i.op,
+            Instruction::Ins22cs(i) => i.op,
+            Instruction::Ins30t(i) => i.op,
+            Instruction::Ins32x(i) => i.op,
+            Instruction::Ins31i(i) => i.op,
+            Instruction::Ins31t(i) => i.op,
+            Instruction::Ins31c(i) => i.op,
+            Instruction::Ins35c(i) => i.op,
+            Instruction::Ins35ms(i) => i.op,
+            Instruction::Ins35mi(i) => i.op,
+            Instruction::Ins3rc(i) => i.op,
+            Instruction::Ins3rms(i) => i.op,
+            Instruction::Ins3rmi(i) => i.op,
+            Instruction::Ins45cc(i) => i.op,
+            Instruction::Ins4rcc(i) => i.op,
+            Instruction::Ins51l(i) => i.op,
+            Instruction::PackedSwitchPayload(_)
+            | Instruction::SparseSwitchPayload(_)
+            | Instruction::FillArrayDataPayload(_) => 0x00,
+        };
+    }
+
+    /// Value loaded by a `const*` instruction (other than `const-string`,
+    /// `const-class` and friends), with `/high16` shifts applied.
+    pub(crate) fn literal(&self) -> Option<i64> {
+        let info = opcode_info(self.opcode());
+        if !info.has(CONST) {
+            return None;
+        }
+        return match self {
+            Instruction::Ins11n(i) => Some(i.b as i64),
+            Instruction::Ins21s(i) => Some(i.b as i64),
+            Instruction::Ins31i(i) => Some(i.b as i64),
+            Instruction::Ins21h(i) if info.has(WIDE) => Some((i.b as i64) << 48),
+            Instruction::Ins21h(i) => Some(((i.b as i32) << 16) as i64),
+            Instruction::Ins51l(i) => Some(i.b),
+            _ => None,
+        };
+    }
+
+    /// Argument registers of an invoke instruction, receiver first.
+    pub(crate) fn invoke_registers(&self) -> Vec<u16> {
+        let (count, registers) = match self {
+            Instruction::Ins35c(i) => (i.a, [i.c, i.d, i.e, i.f, i.g]),
+            Instruction::Ins45cc(i) => (i.a, [i.c, i.d, i.e, i.f, i.g]),
+            Instruction::Ins3rc(i) => return (i.c..i.c + i.a as u16).collect(),
+            Instruction::Ins4rcc(i) => return (i.c..i.c + i.a as u16).collect(),
+            _ => return vec![],
+        };
+        return registers
+            .iter()
+            .take(count as usize)
+            .map(|r| *r as u16)
+            .collect();
+    }
+
+    /// Destination and source registers of a `move`, `move-wide` or
+    /// `move-object` instruction.
+    pub(crate) fn move_registers(&self) -> Option<(u16, u16)> {
+        if !opcode_info(self.opcode()).has(MOVE) {
+            return None;
+        }
+        return match self {
+            Instruction::Ins12x(i) => Some((i.a as u16, i.b as u16)),
+            Instruction::Ins22x(i) => Some((i.a as u16, i.b)),
+            Instruction::Ins32x(i) => Some((i.a, i.b)),
+            _ => None,
+        };
+    }
+
+    /// Register the instruction writes, if any. Wide results also write the
+    /// following register.
+    pub(crate) fn written_register(&self) -> Option<u16> {
+        if !opcode_info(self.opcode()).has(SETS_REGISTER) {
+            return None;
+        }
+        return match self {
+            Instruction::Ins12x(i) => Some(i.a as u16),
+            Instruction::Ins11n(i) => Some(i.a as u16),
+            Instruction::Ins11x(i) => Some(i.a as u16),
+            Instruction::Ins22x(i) => Some(i.a as u16),
+            Instruction::Ins21s(i) => Some(i.a as u16),
+            Instruction::Ins21h(i) => Some(i.a as u16),
+            Instruction::Ins21c(i) => Some(i.a as u16),
+            Instruction::Ins23x(i) => Some(i.a as u16),
+            Instruction::Ins22b(i) => Some(i.a as u16),
+            Instruction::Ins22s(i) => Some(i.a as u16),
+            Instruction::Ins22c(i) => Some(i.a as u16),
+            Instruction::Ins32x(i) => Some(i.a),
+            Instruction::Ins31i(i) => Some(i.a as u16),
+            Instruction::Ins31c(i) => Some(i.a as u16),
+            Instruction::Ins51l(i) => Some(i.a as u16),
+            _ => None,
+        };
+    }
+
+    /// Pool entries referenced by the index operands of the instruction.
+    pub(crate) fn index_refs(&self) -> Vec<IndexRef> {
+        let kind = match opcode_info(self.opcode()).index_kind {
+            Some(kind) => kind,
+            None => return vec![],
+        };
+        return match self {
+            Instruction::Ins21c(i) => vec![IndexRef::new(kind, i.b as u32)],
+            Instruction::Ins31c(i) => vec![IndexRef::new(kind, i.b)],
+            Instruction::Ins22c(i) => vec![IndexRef::new(kind, i.c as u32)],
+            Instruction::Ins35c(i) => vec![IndexRef::new(kind, i.b as u32)],
+            Instruction::Ins3rc(i) => vec![IndexRef::new(kind, i.b as u32)],
+            Instruction::Ins45cc(i) => vec![
+                IndexRef::new(kind, i.b as u32),
+                IndexRef::new(IndexKind::Proto, i.h as u32),
+            ],
+            Instruction::Ins4rcc(i) => vec![
+                IndexRef::new(kind, i.b as u32),
+                IndexRef::new(IndexKind::Proto, i.h as u32),
+            ],
+            _ => vec![],
+        };
+    }
+
+    /// Replaces every index operand with `f(kind, index)`, failing with the
+    /// new reference if it does not fit the operand, e.g. a `const-string`
+    /// that would need `const-string/jumbo`.
+    pub(crate) fn remap_indices(
+        &mut self,
+        f: &mut dyn FnMut(IndexKind, u32) -> u32,
+    ) -> Result<(), IndexRef> {
+        let refs = self.index_refs();
+        let (kind, index) = match refs.first() {
+            Some(r) => (r.kind, f(r.kind, r.index)),
+            None => return Ok(()),
+        };
+        let narrow = |index: u32| u16::try_from(index).map_err(|_| IndexRef::new(kind, index));
+        match self {
+            Instruction::Ins21c(i) => i.b = narrow(index)?,
+            Instruction::Ins31c(i) => i.b = index,
+            Instruction::Ins22c(i) => i.c = narrow(index)?,
+            Instruction::Ins35c(i) => i.b = narrow(index)?,
+            Instruction::Ins3rc(i) => i.b = narrow(index)?,
+            Instruction::Ins45cc(i) => i.b = narrow(index)?,
+            Instruction::Ins4rcc(i) => i.b = narrow(index)?,
+            _ => {}
+        }
+        if let Some(proto) = refs.get(1) {
+            let index = f(IndexKind::Proto, proto.index);
+            let index = u16::try_from(index).map_err(|_| IndexRef::new(IndexKind::Proto, index))?;
+            match self {
+                Instruction::Ins45cc(i) => i.h = index,
+                Instruction::Ins4rcc(i) => i.h = index,
+                _ => {}
+            }
+        }
+        return Ok(());
+    }
+
+    /// Registers named by the operands, in operand order. The second half of
+    /// a wide pair is not listed.
+    pub(crate) fn registers(&self) -> Vec<u16> {
+        return match self {
+            Instruction::Ins12x(i) => vec![i.a as u16, i.b as u16],
+            Instruction::Ins11n(i) => vec![i.a as u16],
+            Instruction::Ins11x(i) => vec![i.a as u16],
+            Instruction::Ins22x(i) => vec![i.a as u16, i.b],
+            Instruction::Ins21t(i) => vec![i.a as u16],
+            Instruction::Ins21s(i) => vec![i.a as u16],
+            Instruction::Ins21h(i) => vec![i.a as u16],
+            Instruction::Ins21c(i) => vec![i.a as u16],
+            Instruction::Ins23x(i) => vec![i.a as u16, i.b as u16, i.c as u16],
+            Instruction::Ins22b(i) => vec![i.a as u16, i.b as u16],
+            Instruction::Ins22t(i) => vec![i.a as u16, i.b as u16],
+            Instruction::Ins22s(i) => vec![i.a as u16, i.b as u16],
+            Instruction::Ins22c(i) => vec![i.a as u16, i.b as u16],
+            Instruction::Ins22cs(i) => vec![i.a as u16, i.b as u16],
+            Instruction::Ins32x(i) => vec![i.a, i.b],
+            Instruction::Ins31i(i) => vec![i.a as u16],
+            Instruction::Ins31t(i) => vec![i.a as u16],
+            Instruction::Ins31c(i) => vec![i.a as u16],
+            Instruction::Ins35ms(i) => [i.c, i.d, i.e, i.f, i.g]
+                .iter()
+                .take(i.a as usize)
+                .map(|r| *r as u16)
+                .collect(),
+            Instruction::Ins35mi(i) => [i.c, i.d, i.e, i.f, i.g]
+                .iter()
+                .take(i.a as usize)
+                .map(|r| *r as u16)
+                .collect(),
+            Instruction::Ins3rms(i) => (i.c..i.c + i.a as u16).collect(),
+            Instruction::Ins3rmi(i) => (i.c..i.c + i.a as u16).collect(),
+            Instruction::Ins51l(i) => vec![i.a as u16],
+            _ => self.invoke_registers(),
+        };
+    }
+
+    /// Relative target of a branch, in 16-bit code units, or of the payload
+    /// a `fill-array-data` or switch instruction refers to.
+    pub(crate) fn branch_offset(&self) -> Option<i32> {
+        return match self {
+            Instruction::Ins10t(i) => Some(i.a as i32),
+            Instruction::Ins20t(i) => Some(i.a as i32),
+            Instruction::Ins30t(i) => Some(i.a),
+            Instruction::Ins21t(i) => Some(i.b as i32),
+            Instruction::Ins22t(i) => Some(i.c as i32),
+            Instruction::Ins31t(i) => Some(i.b),
+            _ => None,
+        };
+    }
+
+    /// Sets the offset returned by `branch_offset`, returning false if the
+    /// operand cannot hold it. Only `goto/32` may branch to itself.
+    pub(crate) fn set_branch_offset(&mut self, offset: i32) -> bool {
+        match self {
+            Instruction::Ins10t(i) if offset != 0 => match i8::try_from(offset) {
+                Ok(offset) => i.a = offset,
+                Err(_) => return false,
+            },
+            Instruction::Ins20t(i) if offset != 0 => match i16::try_from(offset) {
+                Ok(offset) => i.a = offset,
+                Err(_) => return false,
+            },
+            Instruction::Ins21t(i) => match i16::try_from(offset) {
+                Ok(offset) => i.b = offset,
+                Err(_) => return false,
+            },
+            Instruction::Ins22t(i) => match i16::try_from(offset) {
+                Ok(offset) => i.c = offset,
+                Err(_) => return false,
+            },
+            Instruction::Ins30t(i) => i.a = offset,
+            Instruction::Ins31t(i) => i.b = offset,
+            _ => return false,
+        }
+        return true;
+    }
+
+    /// Replacement for a branch whose offset does not fit: the next larger
+    /// `goto`, or for a conditional branch the opposite condition skipping
+    /// over a `goto/32`, whose offset is left for the caller to set.
+    pub(crate) fn widened(&self) -> Option<Vec<Instruction>> {
+        let goto32 = Instruction::Ins30t(Ins30t { op: 0x2a, a: 0 });
+        let skip = (self.size() + goto32.size()) as i16 / 2;
+        return match self {
+            Instruction::Ins10t(_) => Some(vec![Instruction::Ins20t(Ins20t { op: 0x29, a: 0 })]),
+            Instruction::Ins20t(_) => Some(vec![goto32]),
+            // The opposite of each condition is its neighbour: if-eq and
+            // if-ne, if-lt and if-ge, if-gt and if-le.
+            Instruction::Ins21t(i) => Some(vec![
+                Instruction::Ins21t(Ins21t {
+                    op: i.op ^ 1,
+                    a: i.a,
+                    b: skip,
+                }),
+                goto32,
+            ]),
+            Instruction::Ins22t(i) => Some(vec![
+                Instruction::Ins22t(Ins22t {
+                    op: i.op ^ 1,
+                    a: i.a,
+                    b: i.b,
+                    c: skip,
+                }),
+                goto32,
+            ]),
+            _ => None,
+        };
+    }
+
+    pub(crate) fn is_payload(&self) -> bool {
+        return matches!(
+            self,
+            Instruction::PackedSwitchPayload(_)
+                | Instruction::SparseSwitchPayload(_)
+                | Instruction::FillArrayDataPayload(_)
+        );
+    }
+
+    /// Case targets of a switch payload, relative to the switch instruction.
+    pub(crate) fn switch_targets(&self) -> Option<&[i32]> {
+        return match self {
+            Instruction::PackedSwitchPayload(p) => Some(&p.targets),
+            Instruction::SparseSwitchPayload(p) => Some(&p.targets),
+            _ => None,
+        };
+    }
+
+    pub(crate) fn set_switch_targets(&mut self, targets: Vec<i32>) {
+        match self {
+            Instruction::PackedSwitchPayload(p) => p.targets = targets,
+            Instruction::SparseSwitchPayload(p) => p.targets = targets,
+            _ => {}
+        }
+    }
+
+    pub(crate) fn nop() -> Instruction {
+        return Instruction::Ins10x(Ins10x { op: 0x00 });
+    }
+}
+
+/// An index operand of an instruction, e.g. the `string@` of `const-string`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IndexRef {
+    pub kind: IndexKind,
+    pub index: u32,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+impl IndexRef {
+    fn new(kind: IndexKind, index: u32) -> Self {
+        return Self { kind, index };
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Ins10x {
     op: u8,
 }
@@ -191,7 +520,7 @@ impl TInstruction for Ins10x {
     }
 
     fn display(&self) -> String {
-        op_to_str::op_to_str(self.op).to_string()
+        opcode_info(self.op).mnemonic.to_string()
     }
 
     fn size(&self) -> usize {
@@ -199,7 +528,7 @@ impl TInstruction for Ins10x {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Ins12x {
     op: u8,
     a: u8,
@@ -226,7 +555,7 @@ impl TInstruction for Ins12x {
     }
 
     fn display(&self) -> String {
-        format!("{} v{}, v{}", op_to_str::op_to_str(self.op), self.a, self.b)
+        format!("{} v{}, v{}", opcode_info(self.op).mnemonic, self.a, self.b)
     }
 
     fn size(&self) -> usize {
@@ -234,7 +563,7 @@ impl TInstruction for Ins12x {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Ins11n {
     op: u8,
     a: u8,
@@ -261,7 +590,7 @@ impl TInstruction for Ins11n {
     }
 
     fn display(&self) -> String {
-        format!("{} v{}, #{}", op_to_str::op_to_str(self.op), self.a, self.b)
+        format!("{} v{}, #{}", opcode_info(self.op).mnemonic, self.a, self.b)
     }
 
     fn size(&self) -> usize {
@@ -269,7 +598,7 @@ impl TInstruction for Ins11n {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Ins11x {
     op: u8,
     a: u8,
@@ -293,14 +622,14 @@ impl TInstruction for Ins11x {
     }
 
     fn display(&self) -> String {
-        format!("{} v{}", op_to_str::op_to_str(self.op), self.a)
+        format!("{} v{}", opcode_info(self.op).mnemonic, self.a)
     }
 
     fn size(&self) -> usize {
         2
     }
 }
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Ins10t {
     op: u8,
     a: i8,
@@ -324,7 +653,7 @@ impl TInstruction for Ins10t {
     }
 
     fn display(&self) -> String {
-        format!("{} {}", op_to_str::op_to_str(self.op), self.a)
+        format!("{} {}", opcode_info(self.op).mnemonic, self.a)
     }
 
     fn size(&self) -> usize {
@@ -332,7 +661,7 @@ impl TInstruction for Ins10t {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Ins20t {
     op: u8,
     a: i16,
@@ -359,7 +688,7 @@ impl TInstruction for Ins20t {
     }
 
     fn display(&self) -> String {
-        format!("{} {}", op_to_str::op_to_str(self.op), self.a)
+        format!("{} {}", opcode_info(self.op).mnemonic, self.a)
     }
 
     fn size(&self) -> usize {
@@ -367,7 +696,7 @@ impl TInstruction for Ins20t {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Ins20bc {
     op: u8,
     a: i8,
@@ -396,7 +725,7 @@ impl TInstruction for Ins20bc {
     fn display(&self) -> String {
         format!(
             "{} {}, kind@{}",
-            op_to_str::op_to_str(self.op),
+            opcode_info(self.op).mnemonic,
             self.a,
             self.b
         )
@@ -407,7 +736,7 @@ impl TInstruction for Ins20bc {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Ins22x {
     op: u8,
     a: u8,
@@ -434,7 +763,7 @@ impl TInstruction for Ins22x {
     }
 
     fn display(&self) -> String {
-        format!("{} v{}, v{}", op_to_str::op_to_str(self.op), self.a, self.b)
+        format!("{} v{}, v{}", opcode_info(self.op).mnemonic, self.a, self.b)
     }
 
     fn size(&self) -> usize {
@@ -442,7 +771,7 @@ impl TInstruction for Ins22x {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Ins21t {
     op: u8,
     a: u8,
@@ -469,7 +798,7 @@ impl TInstruction for Ins21t {
     }
 
     fn display(&self) -> String {
-        format!("{} v{}, {}", op_to_str::op_to_str(self.op), self.a, self.b)
+        format!("{} v{}, {}", opcode_info(self.op).mnemonic, self.a, self.b)
     }
 
     fn size(&self) -> usize {
@@ -477,7 +806,7 @@ impl TInstruction for Ins21t {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Ins21s {
     op: u8,
     a: u8,
@@ -504,7 +833,7 @@ impl TInstruction for Ins21s {
     }
 
     fn display(&self) -> String {
-        format!("{} v{}, {}", op_to_str::op_to_str(self.op), self.a, self.b)
+        format!("{} v{}, {}", opcode_info(self.op).mnemonic, self.a, self.b)
     }
 
     fn size(&self) -> usize {
@@ -512,7 +841,7 @@ impl TInstruction for Ins21s {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Ins21h {
     op: u8,
     a: u8,
@@ -539,14 +868,14 @@ impl TInstruction for Ins21h {
     }
 
     fn display(&self) -> String {
-        format!("{} v{}, #{}", op_to_str::op_to_str(self.op), self.a, self.b)
+        format!("{} v{}, #{}", opcode_info(self.op).mnemonic, self.a, self.b)
     }
 
     fn size(&self) -> usize {
         4
     }
 }
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Ins21c {
     op: u8,
     a: u8,
@@ -575,7 +904,7 @@ impl TInstruction for Ins21c {
     fn display(&self) -> String {
         format!(
             "{} v{}, kind@{}",
-            op_to_str::op_to_str(self.op),
+            opcode_info(self.op).mnemonic,
             self.a,
             self.b
         )
@@ -585,7 +914,7 @@ impl TInstruction for Ins21c {
         4
     }
 }
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Ins23x {
     op: u8,
     a: u8,
@@ -617,7 +946,7 @@ impl TInstruction for Ins23x {
     fn display(&self) -> String {
         format!(
             "{} v{}, v{}, v{}",
-            op_to_str::op_to_str(self.op),
+            opcode_info(self.op).mnemonic,
             self.a,
             self.b,
             self.c
@@ -629,7 +958,7 @@ impl TInstruction for Ins23x {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Ins22b {
     op: u8,
     a: u8,
@@ -661,7 +990,7 @@ impl TInstruction for Ins22b {
     fn display(&self) -> String {
         format!(
             "{} v{}, v{}, #{}",
-            op_to_str::op_to_str(self.op),
+            opcode_info(self.op).mnemonic,
             self.a,
             self.b,
             self.c
@@ -673,7 +1002,7 @@ impl TInstruction for Ins22b {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Ins22t {
     op: u8,
     a: u8,
@@ -708,7 +1037,7 @@ impl TInstruction for Ins22t {
     fn display(&self) -> String {
         format!(
             "{} v{}, v{}, {}",
-            op_to_str::op_to_str(self.op),
+            opcode_info(self.op).mnemonic,
             self.a,
             self.b,
             self.c
@@ -720,7 +1049,7 @@ impl TInstruction for Ins22t {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Ins22s {
     op: u8,
     a: u8,
@@ -755,7 +1084,7 @@ impl TInstruction for Ins22s {
     fn display(&self) -> String {
         format!(
             "{} v{}, v{}, #{}",
-            op_to_str::op_to_str(self.op),
+            opcode_info(self.op).mnemonic,
             self.a,
             self.b,
             self.c
@@ -767,7 +1096,7 @@ impl TInstruction for Ins22s {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Ins22c {
     op: u8,
     a: u8,
@@ -802,7 +1131,7 @@ impl TInstruction for Ins22c {
     fn display(&self) -> String {
         format!(
             "{} v{}, v{}, kind@{}",
-            op_to_str::op_to_str(self.op),
+            opcode_info(self.op).mnemonic,
             self.a,
             self.b,
             self.c
@@ -814,7 +1143,7 @@ impl TInstruction for Ins22c {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Ins22cs {
     op: u8,
     a: u8,
@@ -849,7 +1178,7 @@ impl TInstruction for Ins22cs {
     fn display(&self) -> String {
         format!(
             "{} v{}, v{}, fieldoff@{}",
-            op_to_str::op_to_str(self.op),
+            opcode_info(self.op).mnemonic,
             self.a,
             self.b,
             self.c
@@ -861,7 +1190,7 @@ impl TInstruction for Ins22cs {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Ins30t {
     op: u8,
     a: i32,
@@ -889,7 +1218,7 @@ impl TInstruction for Ins30t {
     }
 
     fn display(&self) -> String {
-        format!("{} {}", op_to_str::op_to_str(self.op), self.a,)
+        format!("{} {}", opcode_info(self.op).mnemonic, self.a,)
     }
 
     fn size(&self) -> usize {
@@ -897,7 +1226,7 @@ impl TInstruction for Ins30t {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Ins32x {
     op: u8,
     a: u16,
@@ -928,7 +1257,7 @@ impl TInstruction for Ins32x {
     }
 
     fn display(&self) -> String {
-        format!("{} v{}, v{}", op_to_str::op_to_str(self.op), self.a, self.b)
+        format!("{} v{}, v{}", opcode_info(self.op).mnemonic, self.a, self.b)
     }
 
     fn size(&self) -> usize {
@@ -936,7 +1265,7 @@ impl TInstruction for Ins32x {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Ins31i {
     op: u8,
     a: u8,
@@ -963,7 +1292,7 @@ impl TInstruction for Ins31i {
     }
 
     fn display(&self) -> String {
-        format!("{} v{}, #{}", op_to_str::op_to_str(self.op), self.a, self.b)
+        format!("{} v{}, #{}", opcode_info(self.op).mnemonic, self.a, self.b)
     }
 
     fn size(&self) -> usize {
@@ -971,7 +1300,7 @@ impl TInstruction for Ins31i {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Ins31t {
     op: u8,
     a: u8,
@@ -998,7 +1327,7 @@ impl TInstruction for Ins31t {
     }
 
     fn display(&self) -> String {
-        format!("{} v{}, {}", op_to_str::op_to_str(self.op), self.a, self.b)
+        format!("{} v{}, {}", opcode_info(self.op).mnemonic, self.a, self.b)
     }
 
     fn size(&self) -> usize {
@@ -1006,7 +1335,7 @@ impl TInstruction for Ins31t {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Ins31c {
     op: u8,
     a: u8,
@@ -1035,7 +1364,7 @@ impl TInstruction for Ins31c {
     fn display(&self) -> String {
         format!(
             "{} v{}, string@{}",
-            op_to_str::op_to_str(self.op),
+            opcode_info(self.op).mnemonic,
             self.a,
             self.b
         )
@@ -1046,7 +1375,7 @@ impl TInstruction for Ins31c {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Ins35c {
     op: u8,
     a: u8,
@@ -1093,7 +1422,7 @@ impl TInstruction for Ins35c {
     fn display(&self) -> String {
         format!(
             "{} {} v{}, v{}, v{}, v{}, v{}, kind@{}",
-            op_to_str::op_to_str(self.op),
+            opcode_info(self.op).mnemonic,
             self.a,
             self.c,
             self.d,
@@ -1108,7 +1437,7 @@ impl TInstruction for Ins35c {
         6
     }
 }
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Ins35ms {
     op: u8,
     a: u8,
@@ -1155,7 +1484,7 @@ impl TInstruction for Ins35ms {
     fn display(&self) -> String {
         format!(
             "{} {} v{}, v{}, v{}, v{}, v{}, vtaboff@{}",
-            op_to_str::op_to_str(self.op),
+            opcode_info(self.op).mnemonic,
             self.a,
             self.c,
             self.d,
@@ -1170,7 +1499,7 @@ impl TInstruction for Ins35ms {
         6
     }
 }
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Ins35mi {
     op: u8,
     a: u8,
@@ -1217,7 +1546,7 @@ impl TInstruction for Ins35mi {
     fn display(&self) -> String {
         format!(
             "{} {} v{}, v{}, v{}, v{}, v{}, inline@{}",
-            op_to_str::op_to_str(self.op),
+            opcode_info(self.op).mnemonic,
             self.a,
             self.c,
             self.d,
@@ -1233,7 +1562,7 @@ impl TInstruction for Ins35mi {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Ins3rc {
     op: u8,
     a: u8,
@@ -1265,7 +1594,7 @@ impl TInstruction for Ins3rc {
     fn display(&self) -> String {
         format!(
             "{} {{v{} .. v{}}}, kind@{}",
-            op_to_str::op_to_str(self.op),
+            opcode_info(self.op).mnemonic,
             self.c,
             self.c + self.a as u16 - 1,
             self.b
@@ -1277,7 +1606,7 @@ impl TInstruction for Ins3rc {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Ins3rms {
     op: u8,
     a: u8,
@@ -1309,7 +1638,7 @@ impl TInstruction for Ins3rms {
     fn display(&self) -> String {
         format!(
             "{} {{v{} .. v{}}}, vtaboff@{}",
-            op_to_str::op_to_str(self.op),
+            opcode_info(self.op).mnemonic,
             self.c,
             self.c + self.a as u16 - 1,
             self.b
@@ -1321,7 +1650,7 @@ impl TInstruction for Ins3rms {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Ins3rmi {
     op: u8,
     a: u8,
@@ -1353,7 +1682,7 @@ impl TInstruction for Ins3rmi {
     fn display(&self) -> String {
         format!(
             "{} {{v{} .. v{}}}, inline@{}",
-            op_to_str::op_to_str(self.op),
+            opcode_info(self.op).mnemonic,
             self.c,
             self.c + self.a as u16 - 1,
             self.b
@@ -1365,7 +1694,7 @@ impl TInstruction for Ins3rmi {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Ins45cc {
     op: u8,
     a: u8,
@@ -1416,7 +1745,7 @@ impl TInstruction for Ins45cc {
     fn display(&self) -> String {
         format!(
             "{} {} v{}, v{}, v{}, v{}, v{}, meth@{}, proto@{}",
-            op_to_str::op_to_str(self.op),
+            opcode_info(self.op).mnemonic,
             self.a,
             self.c,
             self.d,
@@ -1433,7 +1762,7 @@ impl TInstruction for Ins45cc {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Ins4rcc {
     op: u8,
     a: u8,
@@ -1468,7 +1797,7 @@ impl TInstruction for Ins4rcc {
     fn display(&self) -> String {
         format!(
             "{} {{v{} .. v{}}}, meth@{}, proto@{}",
-            op_to_str::op_to_str(self.op),
+            opcode_info(self.op).mnemonic,
             self.c,
             self.c + self.a as u16 - 1,
             self.b,
@@ -1481,7 +1810,7 @@ impl TInstruction for Ins4rcc {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Ins51l {
     op: u8,
     a: u8,
@@ -1508,7 +1837,7 @@ impl TInstruction for Ins51l {
     }
 
     fn display(&self) -> String {
-        format!("{} v{}, #{}", op_to_str::op_to_str(self.op), self.a, self.b)
+        format!("{} v{}, #{}", opcode_info(self.op).mnemonic, self.a, self.b)
     }
 
     fn size(&self) -> usize {
@@ -1516,7 +1845,7 @@ impl TInstruction for Ins51l {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct PackedSwitchPayload {
     size: u16,
     first_key: i32,
@@ -1567,7 +1896,7 @@ impl TInstruction for PackedSwitchPayload {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct SparseSwitchPayload {
     size: u16,
     keys: Vec<i32>,
@@ -1621,7 +1950,7 @@ impl TInstruction for SparseSwitchPayload {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct FillArrayDataPayload {
     element_width: u16,
     size: u32,
@@ -1689,89 +2018,76 @@ impl TInstruction for FillArrayDataPayload {
     }
 }
 
-fn decode_insn<R>(r: &mut R) -> Instruction
+/// Size in code units of the instruction at `unit`, or `None` when the code
+/// ends before the size is known or the instruction is not valid.
+pub(crate) fn instruction_units(code: &[u16], unit: usize) -> Option<usize> {
+    let first = code[unit];
+    let operand = |k: usize| code.get(unit + k).map(|u| *u as usize);
+    return match (first & 0xff, first >> 8) {
+        (0x00, 0x00) => Some(1),
+        // packed-switch-payload: size, first key, targets.
+        (0x00, 0x01) => Some(4 + operand(1)? * 2),
+        // sparse-switch-payload: size, keys, targets.
+        (0x00, 0x02) => Some(2 + operand(1)? * 4),
+        // fill-array-data-payload: element width, 32-bit size, data.
+        (0x00, 0x03) => {
+            let width = operand(1)?;
+            let size = operand(2)? | operand(3)? << 16;
+            Some(4 + size.checked_mul(width)?.div_ceil(2))
+        }
+        (0x00, _) => None,
+        (op, _) => Some(opcode_info(op as u8).format.units()),
+    };
+}
+
+pub(crate) fn decode_insn<R>(r: &mut R) -> Instruction
 where
     R: io::BufRead,
 {
-    match decode_u8(r) {
-        op @ 0x00 => {
-            let b = decode_u8(r);
-            match b {
-                0x00 => Ins10x { op: 0x00 }.into(),
-                0x01 => PackedSwitchPayload::deserialize(r, op).into(),
-                0x02 => SparseSwitchPayload::deserialize(r, op).into(),
-                0x03 => FillArrayDataPayload::deserialize(r, op).into(),
-                _ => panic!("bad nop high bits"),
-            }
-        }
-        op @ 0x01 => Ins12x::deserialize(r, op).into(),
-        op @ 0x02 => Ins22x::deserialize(r, op).into(),
-        op @ 0x03 => Ins32x::deserialize(r, op).into(),
-        op @ 0x04 => Ins12x::deserialize(r, op).into(),
-        op @ 0x05 => Ins22x::deserialize(r, op).into(),
-        op @ 0x06 => Ins32x::deserialize(r, op).into(),
-        op @ 0x07 => Ins12x::deserialize(r, op).into(),
-        op @ 0x08 => Ins22x::deserialize(r, op).into(),
-        op @ 0x09 => Ins32x::deserialize(r, op).into(),
-        op @ 0x0a => Ins11x::deserialize(r, op).into(),
-        op @ 0x0b => Ins11x::deserialize(r, op).into(),
-        op @ 0x0c => Ins11x::deserialize(r, op).into(),
-        op @ 0x0d => Ins11x::deserialize(r, op).into(),
-        op @ 0x0e => Ins10x::deserialize(r, op).into(),
-        op @ 0x0f => Ins11x::deserialize(r, op).into(),
-        op @ 0x10 => Ins11x::deserialize(r, op).into(),
-        op @ 0x11 => Ins11x::deserialize(r, op).into(),
-        op @ 0x12 => Ins11n::deserialize(r, op).into(),
-        op @ 0x13 => Ins21s::deserialize(r, op).into(),
-        op @ 0x14 => Ins31i::deserialize(r, op).into(),
-        op @ 0x15 => Ins21h::deserialize(r, op).into(),
-        op @ 0x16 => Ins21s::deserialize(r, op).into(),
-        op @ 0x17 => Ins31i::deserialize(r, op).into(),
-        op @ 0x18 => Ins51l::deserialize(r, op).into(),
-        op @ 0x19 => Ins21h::deserialize(r, op).into(),
-        op @ 0x1a => Ins21c::deserialize(r, op).into(),
-        op @ 0x1b => Ins31c::deserialize(r, op).into(),
-        op @ 0x1c => Ins21c::deserialize(r, op).into(),
-        op @ 0x1d => Ins11x::deserialize(r, op).into(),
-        op @ 0x1e => Ins11x::deserialize(r, op).into(),
-        op @ 0x1f => Ins21c::deserialize(r, op).into(),
-        op @ 0x20 => Ins22c::deserialize(r, op).into(),
-        op @ 0x21 => Ins12x::deserialize(r, op).into(),
-        op @ 0x22 => Ins21c::deserialize(r, op).into(),
-        op @ 0x23 => Ins22c::deserialize(r, op).into(),
-        op @ 0x24 => Ins35c::deserialize(r, op).into(),
-        op @ 0x25 => Ins3rc::deserialize(r, op).into(),
-        op @ 0x26 => Ins31t::deserialize(r, op).into(),
-        op @ 0x27 => Ins11x::deserialize(r, op).into(),
-        op @ 0x28 => Ins10t::deserialize(r, op).into(),
-        op @ 0x29 => Ins20t::deserialize(r, op).into(),
-        op @ 0x2a => Ins30t::deserialize(r, op).into(),
-        op @ 0x2b => Ins31t::deserialize(r, op).into(),
-        op @ 0x2c => Ins31t::deserialize(r, op).into(),
-        op @ 0x2d..=0x31 => Ins23x::deserialize(r, op).into(),
-        op @ 0x32..=0x37 => Ins22t::deserialize(r, op).into(),
-        op @ 0x38..=0x3d => Ins21t::deserialize(r, op).into(),
-        op @ 0x3e..=0x43 => Ins10x::deserialize(r, op).into(),
-        op @ 0x44..=0x51 => Ins23x::deserialize(r, op).into(),
-        op @ 0x52..=0x5f => Ins22c::deserialize(r, op).into(),
-        op @ 0x60..=0x6d => Ins21c::deserialize(r, op).into(),
-        op @ 0x6e..=0x72 => Ins35c::deserialize(r, op).into(),
-        op @ 0x73 => Ins10x::deserialize(r, op).into(),
-        op @ 0x74..=0x78 => Ins3rc::deserialize(r, op).into(),
-        op @ 0x79..=0x7a => Ins10x::deserialize(r, op).into(),
-        op @ 0x7b..=0x8f => Ins12x::deserialize(r, op).into(),
-        op @ 0x90..=0xaf => Ins23x::deserialize(r, op).into(),
-        op @ 0xb0..=0xcf => Ins12x::deserialize(r, op).into(),
-        op @ 0xd0..=0xd7 => Ins22s::deserialize(r, op).into(),
-        op @ 0xd8..=0xe2 => Ins22b::deserialize(r, op).into(),
-        op @ 0xe3..=0xf9 => Ins10x::deserialize(r, op).into(),
-        op @ 0xfa => Ins45cc::deserialize(r, op).into(),
-        op @ 0xfb => Ins4rcc::deserialize(r, op).into(),
-        op @ 0xfc => Ins35c::deserialize(r, op).into(),
-        op @ 0xfd => Ins3rc::deserialize(r, op).into(),
-        op @ 0xfe => Ins21c::deserialize(r, op).into(),
-        op @ 0xff => Ins21c::deserialize(r, op).into(),
-    }
+    let op = decode_u8(r);
+    if op == 0x00 {
+        return match decode_u8(r) {
+            0x00 => Ins10x { op: 0x00 }.into(),
+            0x01 => PackedSwitchPayload::deserialize(r, op).into(),
+            0x02 => SparseSwitchPayload::deserialize(r, op).into(),
+            0x03 => FillArrayDataPayload::deserialize(r, op).into(),
+            _ => panic!("bad nop high bits"),
+        };
+    }
+    return match opcode_info(op).format {
+        Format::F10x => Ins10x::deserialize(r, op).into(),
+        Format::F12x => Ins12x::deserialize(r, op).into(),
+        Format::F11n => Ins11n::deserialize(r, op).into(),
+        Format::F11x => Ins11x::deserialize(r, op).into(),
+        Format::F10t => Ins10t::deserialize(r, op).into(),
+        Format::F20t => Ins20t::deserialize(r, op).into(),
+        Format::F20bc => Ins20bc::deserialize(r, op).into(),
+        Format::F22x => Ins22x::deserialize(r, op).into(),
+        Format::F21t => Ins21t::deserialize(r, op).into(),
+        Format::F21s => Ins21s::deserialize(r, op).into(),
+        Format::F21h => Ins21h::deserialize(r, op).into(),
+        Format::F21c => Ins21c::deserialize(r, op).into(),
+        Format::F23x => Ins23x::deserialize(r, op).into(),
+        Format::F22b => Ins22b::deserialize(r, op).into(),
+        Format::F22t => Ins22t::deserialize(r, op).into(),
+        Format::F22s => Ins22s::deserialize(r, op).into(),
+        Format::F22c => Ins22c::deserialize(r, op).into(),
+        Format::F22cs => Ins22cs::deserialize(r, op).into(),
+        Format::F30t => Ins30t::deserialize(r, op).into(),
+        Format::F32x => Ins32x::deserialize(r, op).into(),
+        Format::F31i => Ins31i::deserialize(r, op).into(),
+        Format::F31t => Ins31t::deserialize(r, op).into(),
+        Format::F31c => Ins31c::deserialize(r, op).into(),
+        Format::F35c => Ins35c::deserialize(r, op).into(),
+        Format::F35ms => Ins35ms::deserialize(r, op).into(),
+        Format::F35mi => Ins35mi::deserialize(r, op).into(),
+        Format::F3rc => Ins3rc::deserialize(r, op).into(),
+        Format::F3rms => Ins3rms::deserialize(r, op).into(),
+        Format::F3rmi => Ins3rmi::deserialize(r, op).into(),
+        Format::F45cc => Ins45cc::deserialize(r, op).into(),
+        Format::F4rcc => Ins4rcc::deserialize(r, op).into(),
+        Format::F51l => Ins51l::deserialize(r, op).into(),
+    };
 }
 
 pub fn decode_insns<R>(r: &mut R, mut insns_size: usize) -> Vec<Instruction>
@@ -0,0 +1,799 @@
+//! Inserts, removes and replaces instructions of a method while keeping the
+//! rest of its code item consistent: branch and payload offsets, try blocks,
+//! catch handlers, debug info addresses, and the size fields.
+//!
+//! Edits are addressed by the position of an instruction in the original
+//! code. Code inserted before an instruction becomes part of it: branches,
+//! handlers, try blocks and line numbers that referred to the instruction
+//! now start at the inserted code. Code inserted after an instruction is only
+//! reached by falling through it.
+
+use std::{
+    collections::HashMap,
+    fmt::{self, Display, Formatter},
+    io::Cursor,
+};
+
+use crate::{
+    dex_model::DexModel,
+    dex_structs::{CodeItem, DebugInfoItem, TypeCode},
+    instructions::{decode_insns, instruction_units, Instruction},
+    layout::{relayout, ItemOffsets},
+    opcodes::{opcode_info, INVOKE, SWITCH},
+    remap::handler_offsets,
+    resolver::DexResolver,
+};
+
+#[derive(Debug)]
+pub enum InstrumentError {
+    /// An edit names an instruction past the end of the code.
+    NoSuchInstruction(usize),
+    /// A branch, payload reference or catch address in the original code
+    /// does not point at the start of an instruction.
+    InvalidTarget { address: u32 },
+    /// A branch in inserted code leaves the inserted code. The address is
+    /// relative to the start of the inserted code.
+    TargetOutsideInsertion { address: i64 },
+    /// Inserted code does not decode into whole instructions. The position
+    /// is the code unit of the instruction that fails.
+    InvalidCode(usize),
+    /// A payload is removed while an instruction still refers to it.
+    PayloadRemoved(usize),
+    /// A switch is removed while its payload is kept, which would leave the
+    /// case targets without a base address.
+    SwitchRemoved(usize),
+    /// A try block no longer fits its 16-bit instruction count.
+    TryTooLarge { start_addr: u32 },
+    /// Inserted code uses more registers than the method has. Growing the
+    /// frame would move the parameter registers.
+    TooManyRegisters { needed: u16, available: u16 },
+}
+
+impl Display for InstrumentError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            InstrumentError::NoSuchInstruction(index) => {
+                write!(f, "no instruction at position {}", index)
+            }
+            InstrumentError::InvalidTarget { address } => {
+                write!(f, "address {:#x} is not an instruction", address)
+            }
+            InstrumentError::TargetOutsideInsertion { address } => {
+                write!(f, "branch to {} leaves the inserted code", address)
+            }
+            InstrumentError::InvalidCode(unit) => {
+                write!(f, "invalid instruction at code unit {}", unit)
+            }
+            InstrumentError::PayloadRemoved(index) => {
+                write!(f, "payload at position {} is still referenced", index)
+            }
+            InstrumentError::SwitchRemoved(index) => {
+                write!(
+                    f,
+                    "switch at position {} is removed but not its payload",
+                    index
+                )
+            }
+            InstrumentError::TryTooLarge { start_addr } => {
+                write!(f, "try block at {:#x} exceeds 65535 code units", start_addr)
+            }
+            InstrumentError::TooManyRegisters { needed, available } => write!(
+                f,
+                "inserted code needs {} registers but the method has {}",
+                needed, available
+            ),
+        }
+    }
+}
+
+/// Stands for the address just past the last instruction.
+const END: usize = usize::MAX;
+
+/// What the offsets of an instruction point at, by node id.
+#[derive(Debug, Clone)]
+enum Target {
+    None,
+    Branch(usize),
+    Payload(usize),
+    /// A switch payload, whose case targets are relative to the switch.
+    Switch {
+        owner: usize,
+        cases: Vec<usize>,
+    },
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    id: usize,
+    insn: Instruction,
+    target: Target,
+}
+
+#[derive(Debug, Default)]
+struct Edit {
+    before: Vec<Vec<u16>>,
+    replacement: Option<Vec<u16>>,
+    after: Vec<Vec<u16>>,
+}
+
+/// Collects edits to a code item and applies them all at once in `finish`.
+pub struct CodeEditor {
+    code: CodeItem,
+    debug_info: Option<DebugInfoItem>,
+    edits: Vec<Edit>,
+}
+
+/// Address of each instruction in 16-bit code units.
+fn addresses(insns: &[Instruction]) -> Vec<u32> {
+    let mut address = 0;
+    return insns
+        .iter()
+        .map(|insn| {
+            let start = address;
+            address += insn.size() as u32 / 2;
+            start
+        })
+        .collect();
+}
+
+/// Decodes code supplied by the caller, checking first that it is a whole
+/// number of instructions.
+fn decode(code: &[u16]) -> Result<Vec<Instruction>, InstrumentError> {
+    let mut unit = 0;
+    while unit < code.len() {
+        match instruction_units(code, unit) {
+            Some(units) if units <= code.len() - unit => unit += units,
+            _ => return Err(InstrumentError::InvalidCode(unit)),
+        }
+    }
+    let bytes = code
+        .iter()
+        .flat_map(|unit| unit.to_le_bytes())
+        .collect::<Vec<u8>>();
+    return Ok(decode_insns(&mut Cursor::new(bytes), code.len()));
+}
+
+/// Resolves the offsets of `insns`, whose node ids are `ids`, into targets.
+/// `branch` and `payload` give the node id at an address relative to the
+/// start of `insns`.
+fn resolve(
+    insns: &[Instruction],
+    ids: &[usize],
+    branch: &dyn Fn(i64) -> Option<usize>,
+    payload: &dyn Fn(i64) -> Option<usize>,
+) -> Result<Vec<Target>, i64> {
+    let addrs = addresses(insns);
+    let mut targets = vec![Target::None; insns.len()];
+    // Switch payloads by address, with the switch that uses them.
+    let mut owners = HashMap::new();
+    for (k, insn) in insns.iter().enumerate() {
+        let offset = match insn.branch_offset() {
+            Some(offset) => offset,
+            None => continue,
+        };
+        let address = addrs[k] as i64 + offset as i64;
+        if matches!(insn, Instruction::Ins31t(_)) {
+            targets[k] = Target::Payload(payload(address).ok_or(address)?);
+            if opcode_info(insn.opcode()).has(SWITCH) {
+                owners.insert(address, k);
+            }
+        } else {
+            targets[k] = Target::Branch(branch(address).ok_or(address)?);
+        }
+    }
+    for (k, insn) in insns.iter().enumerate() {
+        let (owner, cases) = match (owners.get(&(addrs[k] as i64)), insn.switch_targets()) {
+            (Some(owner), Some(cases)) => (*owner, cases),
+            _ => continue,
+        };
+        let cases = cases
+            .iter()
+            .map(|case| {
+                let address = addrs[owner] as i64 + *case as i64;
+                branch(address).ok_or(address)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        targets[k] = Target::Switch {
+            owner: ids[owner],
+            cases,
+        };
+    }
+    return Ok(targets);
+}
+
+/// Node ids for inserted code, starting at `next_id`.
+fn insertion(code: &[u16], next_id: &mut usize) -> Result<Vec<Node>, InstrumentError> {
+    let insns = decode(code)?;
+    let ids = (*next_id..*next_id + insns.len()).collect::<Vec<_>>();
+    *next_id += insns.len();
+    let addrs = addresses(&insns);
+    let lookup = |address: i64| {
+        return addrs
+            .iter()
+            .position(|a| *a as i64 == address)
+            .map(|k| ids[k]);
+    };
+    let targets = resolve(&insns, &ids, &lookup, &lookup)
+        .map_err(|address| InstrumentError::TargetOutsideInsertion { address })?;
+    return Ok(ids
+        .into_iter()
+        .zip(insns)
+        .zip(targets)
+        .map(|((id, insn), target)| Node { id, insn, target })
+        .collect());
+}
+
+/// Positions of the nodes in code units, with the padding that aligns each
+/// payload to 32 bits, and the total size.
+fn layout(nodes: &[Node], ids: usize) -> (Vec<u32>, Vec<bool>, u32) {
+    let mut positions = vec![0; ids];
+    let mut padded = vec![false; nodes.len()];
+    let mut address = 0;
+    for (k, node) in nodes.iter().enumerate() {
+        if node.insn.is_payload() && address % 2 == 1 {
+            padded[k] = true;
+            address += 1;
+        }
+        positions[node.id] = address;
+        address += node.insn.size() as u32 / 2;
+    }
+    return (positions, padded, address);
+}
+
+impl CodeEditor {
+    pub fn new(code: &CodeItem, debug_info: Option<&DebugInfoItem>) -> Self {
+        return CodeEditor {
+            code: code.clone(),
+            debug_info: debug_info.cloned(),
+            edits: (0..code.insns.len()).map(|_| Edit::default()).collect(),
+        };
+    }
+
+    /// Instructions of the original code, which edits refer to by position.
+    pub fn insns(&self) -> &[Instruction] {
+        return &self.code.insns;
+    }
+
+    fn edit(&mut self, index: usize) -> Result<&mut Edit, InstrumentError> {
+        return self
+            .edits
+            .get_mut(index)
+            .ok_or(InstrumentError::NoSuchInstruction(index));
+    }
+
+    /// Inserts code, given as 16-bit code units, before the instruction at
+    /// `index`. Branches in it may only target the inserted code itself.
+    pub fn insert_before(&mut self, index: usize, code: &[u16]) -> Result<(), InstrumentError> {
+        self.edit(index)?.before.push(code.to_vec());
+        return Ok(());
+    }
+
+    pub fn insert_after(&mut self, index: usize, code: &[u16]) -> Result<(), InstrumentError> {
+        self.edit(index)?.after.push(code.to_vec());
+        return Ok(());
+    }
+
+    /// Replaces the instruction at `index`. References to it move to the
+    /// replacement.
+    pub fn replace(&mut self, index: usize, code: &[u16]) -> Result<(), InstrumentError> {
+        self.edit(index)?.replacement = Some(code.to_vec());
+        return Ok(());
+    }
+
+    /// Removes the instruction at `index`. References to it move to the
+    /// instruction that follows.
+    pub fn remove(&mut self, index: usize) -> Result<(), InstrumentError> {
+        return self.replace(index, &[]);
+    }
+
+    pub fn is_modified(&self) -> bool {
+        return self
+            .edits
+            .iter()
+            .any(|e| !e.before.is_empty() || e.replacement.is_some() || !e.after.is_empty());
+    }
+
+    /// Applies the edits, returning the new code item and debug info.
+    /// Branches that no longer reach their target are widened.
+    pub fn finish(self) -> Result<(CodeItem, Option<DebugInfoItem>), InstrumentError> {
+        let CodeEditor {
+            mut code,
+            debug_info,
+            edits,
+        } = self;
+        let old_addrs = addresses(&code.insns);
+        let index_of = old_addrs
+            .iter()
+            .enumerate()
+            .map(|(i, a)| (*a as i64, i))
+            .collect::<HashMap<_, _>>();
+
+        // Original instructions keep their position as id. Nops that align a
+        // payload are dropped, as the layout pads again where needed.
+        let mut next_id = code.insns.len();
+        let mut nodes: Vec<Node> = vec![];
+        let mut first_node = vec![0; code.insns.len()];
+        let mut kept = vec![false; code.insns.len()];
+        let mut insertions = vec![];
+        for (i, edit) in edits.iter().enumerate() {
+            first_node[i] = nodes.len();
+            for before in edit.before.iter() {
+                let start = nodes.len();
+                nodes.extend(insertion(before, &mut next_id)?);
+                insertions.push(start..nodes.len());
+            }
+            match edit.replacement.as_ref() {
+                Some(replacement) => {
+                    let start = nodes.len();
+                    nodes.extend(insertion(replacement, &mut next_id)?);
+                    insertions.push(start..nodes.len());
+                }
+                None => {
+                    let padding = code.insns[i] == Instruction::nop()
+                        && code.insns.get(i + 1).is_some_and(|next| next.is_payload());
+                    if !padding {
+                        kept[i] = true;
+                        nodes.push(Node {
+                            id: i,
+                            insn: code.insns[i].clone(),
+                            target: Target::None,
+                        });
+                    }
+                }
+            }
+            for after in edit.after.iter() {
+                let start = nodes.len();
+                nodes.extend(insertion(after, &mut next_id)?);
+                insertions.push(start..nodes.len());
+            }
+        }
+        // The node that takes the place of each original instruction.
+        let anchors = first_node
+            .iter()
+            .map(|k| nodes.get(*k).map_or(END, |node| node.id))
+            .collect::<Vec<_>>();
+        let anchor_at = |address: i64| -> Option<usize> {
+            if address == code.insns_size as i64 {
+                return Some(END);
+            }
+            return index_of.get(&address).map(|i| anchors[*i]);
+        };
+
+        let payload_at = |address: i64| index_of.get(&address).copied();
+        let targets = resolve(
+            &code.insns,
+            &(0..code.insns.len()).collect::<Vec<_>>(),
+            &anchor_at,
+            &payload_at,
+        )
+        .map_err(|address| InstrumentError::InvalidTarget {
+            address: address as u32,
+        })?;
+        for node in nodes.iter_mut().filter(|node| node.id < code.insns.len()) {
+            node.target = targets[node.id].clone();
+            match node.target {
+                Target::Payload(payload) if !kept[payload] => {
+                    return Err(InstrumentError::PayloadRemoved(payload));
+                }
+                Target::Switch { owner, .. } if !kept[owner] => {
+                    return Err(InstrumentError::SwitchRemoved(owner));
+                }
+                _ => {}
+            }
+        }
+
+        // Widen branches until every offset fits; widening only grows the
+        // code, so this settles.
+        let (positions, padded, size) = loop {
+            let (positions, padded, size) = layout(&nodes, next_id);
+            let position = |id: usize| if id == END { size } else { positions[id] };
+            let mut widened = false;
+            let mut k = 0;
+            while k < nodes.len() {
+                let target = match nodes[k].target {
+                    Target::Branch(target) | Target::Payload(target) => target,
+                    _ => {
+                        k += 1;
+                        continue;
+                    }
+                };
+                let offset = position(target) as i64 - positions[nodes[k].id] as i64;
+                let fits = nodes[k].insn.clone().set_branch_offset(offset as i32);
+                if fits {
+                    k += 1;
+                    continue;
+                }
+                let mut replacement = nodes[k].insn.widened().unwrap();
+                let goto = replacement.pop().unwrap();
+                let mut nodes_in = vec![];
+                if let Some(condition) = replacement.pop() {
+                    nodes_in.push(Node {
+                        id: nodes[k].id,
+                        insn: condition,
+                        target: Target::None,
+                    });
+                    nodes_in.push(Node {
+                        id: next_id,
+                        insn: goto,
+                        target: Target::Branch(target),
+                    });
+                    next_id += 1;
+                } else {
+                    nodes_in.push(Node {
+                        id: nodes[k].id,
+                        insn: goto,
+                        target: Target::Branch(target),
+                    });
+                }
+                let count = nodes_in.len();
+                nodes.splice(k..k + 1, nodes_in);
+                k += count;
+                widened = true;
+            }
+            if !widened {
+                break (positions, padded, size);
+            }
+        };
+        let position = |id: usize| if id == END { size } else { positions[id] };
+
+        let mut insns = vec![];
+        for (k, node) in nodes.iter().enumerate() {
+            if padded[k] {
+                insns.push(Instruction::nop());
+            }
+            let mut insn = node.insn.clone();
+            let here = positions[node.id] as i64;
+            match &node.target {
+                Target::None => {}
+                Target::Branch(target) | Target::Payload(target) => {
+                    insn.set_branch_offset((position(*target) as i64 - here) as i32);
+                }
+                Target::Switch { owner, cases } => {
+                    let base = positions[*owner] as i64;
+                    let cases = cases
+                        .iter()
+                        .map(|case| (position(*case) as i64 - base) as i32)
+                        .collect();
+                    insn.set_switch_targets(cases);
+                }
+            }
+            insns.push(insn);
+        }
+
+        // Maps an address of the original code to the new code.
+        let map_address = |address: u32| -> u32 {
+            let i = old_addrs.partition_point(|a| *a < address);
+            return match anchors.get(i) {
+                Some(anchor) => position(*anchor),
+                None => size,
+            };
+        };
+
+        let mut tries = vec![];
+        for try_item in code.tries.iter() {
+            let start = map_address(try_item.start_addr);
+            let end = map_address(try_item.start_addr + try_item.insn_count as u32);
+            if end == start {
+                continue;
+            }
+            let insn_count =
+                u16::try_from(end - start).map_err(|_| InstrumentError::TryTooLarge {
+                    start_addr: try_item.start_addr,
+                })?;
+            let mut try_item = try_item.clone();
+            try_item.start_addr = start;
+            try_item.insn_count = insn_count;
+            tries.push(try_item);
+        }
+        if let Some(handlers) = code.handlers.as_mut() {
+            let before = handler_offsets(handlers);
+            for handler in handlers.list.iter_mut() {
+                for pair in handler.handlers.iter_mut() {
+                    if !index_of.contains_key(&(pair.addr as i64)) {
+                        return Err(InstrumentError::InvalidTarget { address: pair.addr });
+                    }
+                    pair.addr = map_address(pair.addr);
+                }
+                if let Some(addr) = handler.catch_all_addr.as_mut() {
+                    *addr = map_address(*addr);
+                }
+            }
+            let after = handler_offsets(handlers);
+            for try_item in tries.iter_mut() {
+                if let Some(i) = before
+                    .iter()
+                    .position(|offset| *offset == try_item.handler_off as usize)
+                {
+                    try_item.handler_off = after[i] as u16;
+                }
+            }
+        }
+        if tries.is_empty() {
+            code.handlers = None;
+        }
+
+        let debug_info = debug_info.map(|item| {
+            let mut info = item.decode();
+            for entry in info.positions.iter_mut() {
+                entry.address = map_address(entry.address);
+            }
+            for local in info.locals.iter_mut() {
+                local.start_address = map_address(local.start_address);
+                local.end_address = local.end_address.map(map_address);
+            }
+            return DebugInfoItem::from_debug_info(item.line_start, item.parameter_names, &info);
+        });
+
+        // Inserted code may name registers and pass arguments the method did
+        // not use before.
+        let mut registers_size = code.registers_size;
+        for range in insertions {
+            for node in nodes[range].iter() {
+                let info = opcode_info(node.insn.opcode());
+                for (k, register) in node.insn.registers().into_iter().enumerate() {
+                    registers_size = registers_size.max(register + info.register_width(k));
+                }
+            }
+        }
+        if registers_size > code.registers_size && code.ins_size > 0 {
+            return Err(InstrumentError::TooManyRegisters {
+                needed: registers_size,
+                available: code.registers_size,
+            });
+        }
+        code.registers_size = registers_size;
+        for insn in insns.iter() {
+            if opcode_info(insn.opcode()).has(INVOKE) {
+                code.outs_size = code.outs_size.max(insn.invoke_registers().len() as u16);
+            }
+        }
+        code.insns_size = insns.iter().map(|insn| insn.size() as u32 / 2).sum();
+        code.insns = insns;
+        code.tries = tries;
+        return Ok((code, debug_info));
+    }
+}
+
+/// Calls `edit` with the index and an editor of every method that has code,
+/// then applies the edits and lays out the file again.
+pub fn instrument(
+    dex: &mut DexModel,
+    edit: &mut dyn FnMut(u32, &mut CodeEditor),
+) -> Result<(), InstrumentError> {
+    let offsets = ItemOffsets::of(dex);
+    let index = offsets.index();
+    let resolver = DexResolver::new(dex);
+    let mut methods = vec![];
+    for class_def in dex.class_defs.iter() {
+        for (method_idx, method) in resolver.class_methods(class_def) {
+            if let Some(i) = index.get(&(TypeCode::TypeCodeItem, method.code_off)) {
+                methods.push((method_idx, *i));
+            }
+        }
+    }
+
+    let mut edited = vec![];
+    for (method_idx, i) in methods {
+        let code = &dex.code_items[i];
+        let debug_info = index
+            .get(&(TypeCode::TypeDebugInfoItem, code.debug_info_off))
+            .copied();
+        let mut editor = CodeEditor::new(code, debug_info.map(|d| &dex.debug_info_items[d]));
+        edit(method_idx, &mut editor);
+        if editor.is_modified() {
+            edited.push((i, debug_info, editor.finish()?));
+        }
+    }
+    for (i, d, (code, debug_info)) in edited {
+        dex.code_items[i] = code;
+        if let (Some(d), Some(debug_info)) = (d, debug_info) {
+            dex.debug_info_items[d] = debug_info;
+        }
+    }
+    relayout(dex);
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        debug_info::{DebugInfo, PositionEntry},
+        deserialize_vec,
+        dex_structs::{
+            EncodedCatchHandler, EncodedCatchHandlerList, EncodedTypeAddressPair, TryItem,
+        },
+        serialize,
+        test_utils::{build_dex, insns, TestClass},
+    };
+
+    fn code_item(units: &[u16]) -> CodeItem {
+        return CodeItem {
+            registers_size: 2,
+            ins_size: 0,
+            outs_size: 0,
+            debug_info_off: 0,
+            insns_size: units.len() as u32,
+            insns: insns(units),
+            tries: vec![],
+            handlers: None,
+        };
+    }
+
+    fn units(insns: &[Instruction]) -> Vec<u16> {
+        let mut bytes = vec![];
+        for insn in insns {
+            insn.serialize(&mut bytes);
+        }
+        return bytes
+            .chunks(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+            .collect();
+    }
+
+    #[test]
+    fn test_insert_and_widen() {
+        // 0: if-eqz v0, +3; 2: goto -2; 3: return-void
+        let mut code = code_item(&[0x0038, 0x0003, 0xfe28, 0x000e]);
+        code.tries = vec![TryItem {
+            start_addr: 0,
+            insn_count: 3,
+            handler_off: 1,
+        }];
+        code.handlers = Some(EncodedCatchHandlerList {
+            list: vec![EncodedCatchHandler {
+                handlers: vec![EncodedTypeAddressPair {
+                    type_idx: 0,
+                    addr: 3,
+                }],
+                catch_all_addr: None,
+            }],
+        });
+        let info = DebugInfo {
+            positions: [(0, 10), (2, 11), (3, 12)]
+                .iter()
+                .map(|(address, line)| PositionEntry {
+                    address: *address,
+                    line: *line,
+                    source_file_idx: None,
+                    prologue_end: false,
+                    epilogue_begin: false,
+                })
+                .collect(),
+            locals: vec![],
+        };
+        let debug_info = DebugInfoItem::from_debug_info(10, vec![], &info);
+
+        let mut editor = CodeEditor::new(&code, Some(&debug_info));
+        // const/4 v1, 0
+        editor.insert_before(0, &[0x0112]).unwrap();
+        // 200 nops put the goto out of reach of its 8-bit offset.
+        editor.insert_after(0, &[0x0000; 200]).unwrap();
+        let (code, debug_info) = editor.finish().unwrap();
+
+        let mut expected = vec![0x0112, 0x0038, 0x00cc];
+        expected.extend([0x0000; 200]);
+        // goto/16 -203; return-void
+        expected.extend([0x0029, (-203i16) as u16, 0x000e]);
+        assert_eq!(units(&code.insns), expected);
+        assert_eq!(code.insns_size, 206);
+        assert_eq!(
+            code.tries,
+            vec![TryItem {
+                start_addr: 0,
+                insn_count: 205,
+                handler_off: 1,
+            }]
+        );
+        let handlers = code.handlers.unwrap();
+        assert_eq!(handlers.list[0].handlers[0].addr, 205);
+        let lines = debug_info.unwrap().decode().positions;
+        assert_eq!(
+            lines
+                .iter()
+                .map(|p| (p.address, p.line))
+                .collect::<Vec<_>>(),
+            vec![(0, 10), (203, 11), (205, 12)]
+        );
+    }
+
+    #[test]
+    fn test_widen_conditional_and_switch() {
+        // 0: packed-switch v0, +6; 3: if-eqz v0, +2; 5: return-void
+        // 6: payload, one case to 3
+        let code = code_item(&[
+            0x002b, 0x0006, 0x0000, 0x0038, 0x0002, 0x000e, 0x0100, 0x0001, 0x0000, 0x0000, 0x0003,
+            0x0000,
+        ]);
+        let mut editor = CodeEditor::new(&code, None);
+        editor.insert_after(1, &vec![0x0000; 0x8000]).unwrap();
+        let (code, _) = editor.finish().unwrap();
+        let units = units(&code.insns);
+        // The if-eqz turns into an if-nez over a goto/32 to the return.
+        assert_eq!(&units[3..8], &[0x0039, 0x0005, 0x002a, 0x8003, 0x0000]);
+        // The payload is realigned and its case still targets the if.
+        let payload = units.len() - 6;
+        assert_eq!(payload % 2, 0);
+        assert_eq!(units[1] as usize, payload);
+        assert_eq!(units[payload + 4], 3);
+        assert_eq!(code.insns_size as usize, units.len());
+    }
+
+    #[test]
+    fn test_invalid_edits() {
+        // invoke-static missing its operands, a fill-array-data-payload
+        // missing its size, and a nop with unknown high bits.
+        for (code, unit) in [
+            (&[0x0071][..], 0),
+            (&[0x000e, 0x0300, 0x0001], 1),
+            (&[0x0500], 0),
+        ] {
+            let mut editor = CodeEditor::new(&code_item(&[0x000e]), None);
+            editor.insert_before(0, code).unwrap();
+            assert!(matches!(
+                editor.finish(),
+                Err(InstrumentError::InvalidCode(u)) if u == unit
+            ));
+        }
+
+        // 0: packed-switch v0, +4; 3: return-void; 4: payload, one case to 3
+        let code = code_item(&[
+            0x002b, 0x0004, 0x0000, 0x000e, 0x0100, 0x0001, 0x0000, 0x0000, 0x0003, 0x0000,
+        ]);
+        let mut editor = CodeEditor::new(&code, None);
+        editor.remove(0).unwrap();
+        assert!(matches!(
+            editor.finish(),
+            Err(InstrumentError::SwitchRemoved(0))
+        ));
+        let mut editor = CodeEditor::new(&code, None);
+        editor.remove(0).unwrap();
+        editor.remove(2).unwrap();
+        let (code, _) = editor.finish().unwrap();
+        assert_eq!(units(&code.insns), vec![0x000e]);
+    }
+
+    #[test]
+    fn test_wide_registers() {
+        // add-long v0, v2, v4 uses v5; long-to-int v0, v2 uses v3;
+        // cmp-long v0, v2, v6 uses v7.
+        for (insert, registers) in [
+            (&[0x009b, 0x0402][..], 6),
+            (&[0x2084], 4),
+            (&[0x0031, 0x0602], 8),
+        ] {
+            let mut editor = CodeEditor::new(&code_item(&[0x000e]), None);
+            editor.insert_before(0, insert).unwrap();
+            let (code, _) = editor.finish().unwrap();
+            assert_eq!(code.registers_size, registers);
+        }
+    }
+
+    #[test]
+    fn test_instrument() {
+        let mut dex = build_dex(
+            vec![TestClass::new("La/Trace;")
+                .method("enter", &["I"], "V", &[0x000e])
+                .method("run", &[], "V", &[0x000e])],
+            &[],
+        );
+        instrument(&mut dex, &mut |method_idx, editor| {
+            if method_idx == 1 {
+                // const/4 v0, 1; invoke-static {v0}, La/Trace;->enter(I)V
+                editor
+                    .insert_before(0, &[0x1012, 0x1071, 0x0000, 0x0000])
+                    .unwrap();
+            }
+        })
+        .unwrap();
+        let dex = deserialize_vec(serialize(dex)).unwrap();
+        let resolver = DexResolver::new(&dex);
+        let class_def = &dex.class_defs[0];
+        let (_, run) = resolver.class_methods(class_def)[1];
+        let code = resolver.code_item(run.code_off).unwrap();
+        assert_eq!(
+            units(&code.insns),
+            vec![0x1012, 0x1071, 0x0000, 0x0000, 0x000e]
+        );
+    }
+}
@@ -0,0 +1,1634 @@
+//! Kotlin declarations decoded from `@kotlin.Metadata` annotations.
+//!
+//! The Kotlin compiler annotates every class it emits with `kotlin.Metadata`:
+//! `d1` holds protobuf messages of `metadata.proto` packed into strings and
+//! `d2` the strings they refer to. `KotlinMetadata` decodes them into classes,
+//! functions and properties with their nullability and `suspend` markers.
+//! `KotlinReport` lists the metadata of every class alongside the dex view and
+//! counts per package the classes the compiler adds to the source: lambdas,
+//! `$$inlined` copies and `$DefaultImpls`.
+
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display, Formatter},
+};
+
+use serde::Serialize;
+
+use crate::{
+    annotations::{annotation_set, Annotation, Value},
+    dex_model::DexModel,
+    dex_structs::ClassDefItem,
+    protobuf::{Message, ProtobufError},
+    resolver::{java_to_descriptor, DexResolver},
+};
+
+const KOTLIN_METADATA: &str = "Lkotlin/Metadata;";
+
+/// Superclasses of the classes compiled from lambdas before Kotlin 2.0
+/// switched to `invokedynamic`.
+const LAMBDA_SUPERCLASSES: [&str; 3] = [
+    "Lkotlin/jvm/internal/Lambda;",
+    "Lkotlin/coroutines/jvm/internal/SuspendLambda;",
+    "Lkotlin/coroutines/jvm/internal/RestrictedSuspendLambda;",
+];
+
+/// Strings that string table records may refer to by index instead of
+/// storing them in `d2`.
+const PREDEFINED_STRINGS: [&str; 44] = [
+    "kotlin/Any",
+    "kotlin/Nothing",
+    "kotlin/Unit",
+    "kotlin/Throwable",
+    "kotlin/Number",
+    "kotlin/Byte",
+    "kotlin/Double",
+    "kotlin/Float",
+    "kotlin/Int",
+    "kotlin/Long",
+    "kotlin/Short",
+    "kotlin/Boolean",
+    "kotlin/Char",
+    "kotlin/CharSequence",
+    "kotlin/String",
+    "kotlin/Comparable",
+    "kotlin/Enum",
+    "kotlin/Array",
+    "kotlin/ByteArray",
+    "kotlin/DoubleArray",
+    "kotlin/FloatArray",
+    "kotlin/IntArray",
+    "kotlin/LongArray",
+    "kotlin/ShortArray",
+    "kotlin/BooleanArray",
+    "kotlin/CharArray",
+    "kotlin/Cloneable",
+    "kotlin/Annotation",
+    "kotlin/collections/Iterable",
+    "kotlin/collections/MutableIterable",
+    "kotlin/collections/Collection",
+    "kotlin/collections/MutableCollection",
+    "kotlin/collections/List",
+    "kotlin/collections/MutableList",
+    "kotlin/collections/Set",
+    "kotlin/collections/MutableSet",
+    "kotlin/collections/Map",
+    "kotlin/collections/MutableMap",
+    "kotlin/collections/Map.Entry",
+    "kotlin/collections/MutableMap.MutableEntry",
+    "kotlin/collections/Iterator",
+    "kotlin/collections/MutableIterator",
+    "kotlin/collections/ListIterator",
+    "kotlin/collections/MutableListIterator",
+];
+
+/// `StringTableTypes.Record.Operation`.
+const OPERATION_INTERNAL_TO_CLASS_ID: u64 = 1;
+const OPERATION_DESC_TO_CLASS_ID: u64 = 2;
+
+/// Default flags of classes, functions and constructors: public and final.
+const DEFAULT_FLAGS: u64 = 6;
+/// Default flags of properties: public, final, with a getter.
+const DEFAULT_PROPERTY_FLAGS: u64 = 518;
+
+/// Keyword of each flag bit, in source order.
+const CLASS_MODIFIERS: [(u32, &str); 6] = [
+    (12, "expect"),
+    (11, "external"),
+    (9, "inner"),
+    (10, "data"),
+    (13, "value"),
+    (14, "fun"),
+];
+const FUNCTION_MODIFIERS: [(u32, &str); 7] = [
+    (14, "expect"),
+    (12, "external"),
+    (11, "tailrec"),
+    (10, "inline"),
+    (9, "infix"),
+    (8, "operator"),
+    (13, "suspend"),
+];
+const PROPERTY_MODIFIERS: [(u32, &str); 3] = [(14, "external"), (11, "const"), (12, "lateinit")];
+const PARAMETER_MODIFIERS: [(u32, &str); 2] = [(2, "crossinline"), (3, "noinline")];
+
+const PROPERTY_VAR: u32 = 8;
+const PROPERTY_DELEGATED: u32 = 15;
+const CONSTRUCTOR_SECONDARY: u32 = 4;
+const PARAMETER_DEFAULT: u32 = 1;
+const TYPE_SUSPEND: u64 = 1;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum KotlinError {
+    /// An element of the annotation is missing or has the wrong type.
+    InvalidAnnotation(String),
+    InvalidProtobuf(ProtobufError),
+    /// A type has neither a class nor a type parameter, or a required type
+    /// field is missing.
+    InvalidType(String),
+    /// A name refers past the string table.
+    InvalidStringIndex(u64),
+    /// A type refers past the type table.
+    InvalidTypeIndex(u64),
+}
+
+impl From<ProtobufError> for KotlinError {
+    fn from(err: ProtobufError) -> Self {
+        KotlinError::InvalidProtobuf(err)
+    }
+}
+
+impl Display for KotlinError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            KotlinError::InvalidAnnotation(reason) => {
+                write!(f, "invalid kotlin.Metadata annotation: {}", reason)
+            }
+            KotlinError::InvalidProtobuf(err) => write!(f, "invalid metadata: {}", err),
+            KotlinError::InvalidType(reason) => write!(f, "invalid metadata type: {}", reason),
+            KotlinError::InvalidStringIndex(idx) => write!(f, "invalid string index {}", idx),
+            KotlinError::InvalidTypeIndex(idx) => write!(f, "invalid type index {}", idx),
+        }
+    }
+}
+
+/// What the class holding the annotation was compiled from, by the `k`
+/// element.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MetadataKind {
+    Class,
+    /// Top-level declarations of a file, e.g. `UtilsKt`.
+    FileFacade,
+    /// A lambda, function reference or other class without a Kotlin
+    /// counterpart, e.g. `$WhenMappings`.
+    SyntheticClass,
+    /// A `@JvmMultifileClass` facade delegating to its parts.
+    MultiFileClassFacade,
+    MultiFileClassPart,
+}
+
+impl MetadataKind {
+    fn from_i32(kind: i32) -> Option<MetadataKind> {
+        return match kind {
+            1 => Some(MetadataKind::Class),
+            2 => Some(MetadataKind::FileFacade),
+            3 => Some(MetadataKind::SyntheticClass),
+            4 => Some(MetadataKind::MultiFileClassFacade),
+            5 => Some(MetadataKind::MultiFileClassPart),
+            _ => None,
+        };
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum KotlinVisibility {
+    Internal,
+    Private,
+    Protected,
+    Public,
+    PrivateToThis,
+    Local,
+}
+
+impl KotlinVisibility {
+    fn from_flags(flags: u64) -> KotlinVisibility {
+        return match (flags >> 1) & 7 {
+            0 => KotlinVisibility::Internal,
+            1 => KotlinVisibility::Private,
+            2 => KotlinVisibility::Protected,
+            4 => KotlinVisibility::PrivateToThis,
+            5 => KotlinVisibility::Local,
+            _ => KotlinVisibility::Public,
+        };
+    }
+
+    /// The keyword, or `None` for the implicit `public`.
+    fn keyword(&self) -> Option<&'static str> {
+        return match self {
+            KotlinVisibility::Internal => Some("internal"),
+            KotlinVisibility::Private | KotlinVisibility::PrivateToThis => Some("private"),
+            KotlinVisibility::Protected => Some("protected"),
+            KotlinVisibility::Public | KotlinVisibility::Local => None,
+        };
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Modality {
+    Final,
+    Open,
+    Abstract,
+    Sealed,
+}
+
+impl Modality {
+    fn from_flags(flags: u64) -> Modality {
+        return match (flags >> 4) & 3 {
+            1 => Modality::Open,
+            2 => Modality::Abstract,
+            3 => Modality::Sealed,
+            _ => Modality::Final,
+        };
+    }
+
+    /// The keyword, or `None` for the implicit `final`.
+    fn keyword(&self) -> Option<&'static str> {
+        return match self {
+            Modality::Final => None,
+            Modality::Open => Some("open"),
+            Modality::Abstract => Some("abstract"),
+            Modality::Sealed => Some("sealed"),
+        };
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ClassKind {
+    Class,
+    Interface,
+    EnumClass,
+    EnumEntry,
+    AnnotationClass,
+    Object,
+    CompanionObject,
+}
+
+impl ClassKind {
+    fn from_flags(flags: u64) -> ClassKind {
+        return match (flags >> 6) & 7 {
+            1 => ClassKind::Interface,
+            2 => ClassKind::EnumClass,
+            3 => ClassKind::EnumEntry,
+            4 => ClassKind::AnnotationClass,
+            5 => ClassKind::Object,
+            6 => ClassKind::CompanionObject,
+            _ => ClassKind::Class,
+        };
+    }
+
+    pub fn keyword(&self) -> &'static str {
+        return match self {
+            ClassKind::Class => "class",
+            ClassKind::Interface => "interface",
+            ClassKind::EnumClass => "enum class",
+            ClassKind::EnumEntry => "enum entry",
+            ClassKind::AnnotationClass => "annotation class",
+            ClassKind::Object => "object",
+            ClassKind::CompanionObject => "companion object",
+        };
+    }
+}
+
+fn modifiers(flags: u64, table: &[(u32, &'static str)]) -> Vec<&'static str> {
+    return table
+        .iter()
+        .filter(|(bit, _)| flags & (1 << bit) != 0)
+        .map(|(_, keyword)| *keyword)
+        .collect();
+}
+
+fn flag(flags: u64, bit: u32) -> bool {
+    return flags & (1 << bit) != 0;
+}
+
+/// Visibility, modality and modifiers as written in source, each followed
+/// by a space.
+fn write_modifiers(
+    f: &mut Formatter,
+    visibility: KotlinVisibility,
+    modality: Option<Modality>,
+    modifiers: &[&str],
+) -> fmt::Result {
+    let keywords = visibility
+        .keyword()
+        .into_iter()
+        .chain(modality.and_then(|m| m.keyword()))
+        .chain(modifiers.iter().copied());
+    for keyword in keywords {
+        write!(f, "{} ", keyword)?;
+    }
+    return Ok(());
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Projection {
+    In,
+    Out,
+    Invariant,
+    Star,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TypeArgument {
+    pub projection: Projection,
+    /// `None` for the star projection.
+    #[serde(rename = "type")]
+    pub argument_type: Option<KotlinType>,
+}
+
+impl Display for TypeArgument {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let argument_type = match &self.argument_type {
+            Some(argument_type) => argument_type,
+            None => return write!(f, "*"),
+        };
+        return match self.projection {
+            Projection::In => write!(f, "in {}", argument_type),
+            Projection::Out => write!(f, "out {}", argument_type),
+            Projection::Invariant | Projection::Star => write!(f, "{}", argument_type),
+        };
+    }
+}
+
+/// A type as written in Kotlin, e.g. `kotlin.collections.List<out T>?`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct KotlinType {
+    /// Qualified class name with `.` separators, or the name of a type
+    /// parameter.
+    pub name: String,
+    pub arguments: Vec<TypeArgument>,
+    pub nullable: bool,
+    /// Whether a function type is `suspend`.
+    pub suspend: bool,
+}
+
+impl KotlinType {
+    /// Arity of a `kotlin.FunctionN` type, which is written `(A) -> R`.
+    fn function_arity(&self) -> Option<usize> {
+        let arity = self.name.strip_prefix("kotlin.Function")?.parse().ok()?;
+        return (self.arguments.len() == arity + 1).then_some(arity);
+    }
+}
+
+impl Display for KotlinType {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let mut text = match self.function_arity() {
+            Some(arity) => {
+                let parameters = self.arguments[..arity]
+                    .iter()
+                    .map(|a| a.to_string())
+                    .collect::<Vec<_>>();
+                format!("({}) -> {}", parameters.join(", "), self.arguments[arity])
+            }
+            None if self.arguments.is_empty() => self.name.clone(),
+            None => {
+                let arguments = self
+                    .arguments
+                    .iter()
+                    .map(|a| a.to_string())
+                    .collect::<Vec<_>>();
+                format!("{}<{}>", self.name, arguments.join(", "))
+            }
+        };
+        if self.suspend {
+            text = format!("suspend {}", text);
+        }
+        if self.nullable && (self.suspend || self.function_arity().is_some()) {
+            text = format!("({})", text);
+        }
+        write!(f, "{}", text)?;
+        if self.nullable {
+            write!(f, "?")?;
+        }
+        return Ok(());
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct KotlinTypeParameter {
+    pub name: String,
+    pub reified: bool,
+    pub projection: Projection,
+    pub upper_bounds: Vec<KotlinType>,
+}
+
+impl Display for KotlinTypeParameter {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if self.reified {
+            write!(f, "reified ")?;
+        }
+        match self.projection {
+            Projection::In => write!(f, "in ")?,
+            Projection::Out => write!(f, "out ")?,
+            Projection::Invariant | Projection::Star => {}
+        }
+        write!(f, "{}", self.name)?;
+        if let [bound] = &self.upper_bounds[..] {
+            write!(f, " : {}", bound)?;
+        }
+        return Ok(());
+    }
+}
+
+fn write_type_parameters(f: &mut Formatter, parameters: &[KotlinTypeParameter]) -> fmt::Result {
+    if parameters.is_empty() {
+        return Ok(());
+    }
+    let parameters = parameters.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+    return write!(f, "<{}>", parameters.join(", "));
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct KotlinParameter {
+    pub name: String,
+    /// For `vararg` parameters, the array type.
+    pub parameter_type: KotlinType,
+    /// For `vararg` parameters, the element type.
+    pub vararg_element_type: Option<KotlinType>,
+    pub declares_default: bool,
+    pub modifiers: Vec<&'static str>,
+}
+
+impl Display for KotlinParameter {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for modifier in self.modifiers.iter() {
+            write!(f, "{} ", modifier)?;
+        }
+        match &self.vararg_element_type {
+            Some(element_type) => write!(f, "vararg {}: {}", self.name, element_type)?,
+            None => write!(f, "{}: {}", self.name, self.parameter_type)?,
+        }
+        if self.declares_default {
+            write!(f, " = …")?;
+        }
+        return Ok(());
+    }
+}
+
+fn write_parameters(f: &mut Formatter, parameters: &[KotlinParameter]) -> fmt::Result {
+    let parameters = parameters.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+    return write!(f, "({})", parameters.join(", "));
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct KotlinConstructor {
+    pub visibility: KotlinVisibility,
+    pub secondary: bool,
+    pub parameters: Vec<KotlinParameter>,
+}
+
+impl Display for KotlinConstructor {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write_modifiers(f, self.visibility, None, &[])?;
+        write!(f, "constructor")?;
+        return write_parameters(f, &self.parameters);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct KotlinFunction {
+    pub name: String,
+    pub visibility: KotlinVisibility,
+    pub modality: Modality,
+    /// Keywords such as `suspend`, `inline` and `operator`.
+    pub modifiers: Vec<&'static str>,
+    pub type_parameters: Vec<KotlinTypeParameter>,
+    pub receiver_type: Option<KotlinType>,
+    pub parameters: Vec<KotlinParameter>,
+    pub return_type: KotlinType,
+}
+
+impl KotlinFunction {
+    pub fn is_suspend(&self) -> bool {
+        return self.modifiers.contains(&"suspend");
+    }
+}
+
+impl Display for KotlinFunction {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write_modifiers(f, self.visibility, Some(self.modality), &self.modifiers)?;
+        write!(f, "fun ")?;
+        if !self.type_parameters.is_empty() {
+            write_type_parameters(f, &self.type_parameters)?;
+            write!(f, " ")?;
+        }
+        if let Some(receiver_type) = &self.receiver_type {
+            write!(f, "{}.", receiver_type)?;
+        }
+        write!(f, "{}", self.name)?;
+        write_parameters(f, &self.parameters)?;
+        return write!(f, ": {}", self.return_type);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct KotlinProperty {
+    pub name: String,
+    pub visibility: KotlinVisibility,
+    pub modality: Modality,
+    /// Keywords such as `const` and `lateinit`.
+    pub modifiers: Vec<&'static str>,
+    /// Whether the property is a `var` rather than a `val`.
+    pub mutable: bool,
+    /// Whether the property is declared with `by`.
+    pub delegated: bool,
+    pub type_parameters: Vec<KotlinTypeParameter>,
+    pub receiver_type: Option<KotlinType>,
+    pub property_type: KotlinType,
+}
+
+impl Display for KotlinProperty {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write_modifiers(f, self.visibility, Some(self.modality), &self.modifiers)?;
+        write!(f, "{} ", if self.mutable { "var" } else { "val" })?;
+        if !self.type_parameters.is_empty() {
+            write_type_parameters(f, &self.type_parameters)?;
+            write!(f, " ")?;
+        }
+        if let Some(receiver_type) = &self.receiver_type {
+            write!(f, "{}.", receiver_type)?;
+        }
+        write!(f, "{}: {}", self.name, self.property_type)?;
+        if self.delegated {
+            write!(f, " by …")?;
+        }
+        return Ok(());
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct KotlinClass {
+    /// Qualified name with `.` separators, e.g. `com.example.Outer.Inner`.
+    pub name: String,
+    pub kind: ClassKind,
+    pub visibility: KotlinVisibility,
+    pub modality: Modality,
+    /// Keywords such as `data`, `inner` and `value`.
+    pub modifiers: Vec<&'static str>,
+    pub type_parameters: Vec<KotlinTypeParameter>,
+    pub supertypes: Vec<KotlinType>,
+    /// Simple name of the companion object.
+    pub companion_object: Option<String>,
+    /// Simple names of the nested classes.
+    pub nested_classes: Vec<String>,
+    pub enum_entries: Vec<String>,
+    /// Qualified names of the direct subclasses of a sealed class.
+    pub sealed_subclasses: Vec<String>,
+    pub constructors: Vec<KotlinConstructor>,
+    pub functions: Vec<KotlinFunction>,
+    pub properties: Vec<KotlinProperty>,
+}
+
+impl Display for KotlinClass {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        // Interfaces are implicitly abstract.
+        let modality = Some(self.modality).filter(|_| self.kind != ClassKind::Interface);
+        write_modifiers(f, self.visibility, modality, &self.modifiers)?;
+        write!(f, "{} {}", self.kind.keyword(), self.name)?;
+        write_type_parameters(f, &self.type_parameters)?;
+        let supertypes = self
+            .supertypes
+            .iter()
+            .filter(|t| t.name != "kotlin.Any")
+            .map(|t| t.to_string())
+            .collect::<Vec<_>>();
+        if !supertypes.is_empty() {
+            write!(f, " : {}", supertypes.join(", "))?;
+        }
+        writeln!(f, " {{")?;
+        for entry in self.enum_entries.iter() {
+            writeln!(f, "    {},", entry)?;
+        }
+        for constructor in self.constructors.iter() {
+            writeln!(f, "    {}", constructor)?;
+        }
+        for property in self.properties.iter() {
+            writeln!(f, "    {}", property)?;
+        }
+        for function in self.functions.iter() {
+            writeln!(f, "    {}", function)?;
+        }
+        if let Some(companion) = &self.companion_object {
+            writeln!(f, "    companion object {}", companion)?;
+        }
+        for nested in self.nested_classes.iter() {
+            if Some(nested) != self.companion_object.as_ref() {
+                writeln!(f, "    class {}", nested)?;
+            }
+        }
+        return writeln!(f, "}}");
+    }
+}
+
+/// Top-level declarations of a file facade or a multi-file class part.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct KotlinPackage {
+    pub functions: Vec<KotlinFunction>,
+    pub properties: Vec<KotlinProperty>,
+    /// Qualified names of the type aliases.
+    pub type_aliases: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", content = "value", rename_all = "kebab-case")]
+pub enum Declarations {
+    Class(KotlinClass),
+    Package(KotlinPackage),
+    /// The function of a lambda or function reference.
+    Lambda(KotlinFunction),
+    /// JVM names of the parts of a multi-file class facade.
+    FacadeParts(Vec<String>),
+    /// Synthetic classes other than lambdas, e.g. `$WhenMappings`.
+    None,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct KotlinMetadata {
+    pub kind: MetadataKind,
+    /// Version of the metadata format, e.g. `[1, 9, 0]`.
+    pub version: Vec<i32>,
+    /// Kotlin package of a file facade, when it differs from the JVM
+    /// package.
+    pub package_name: Option<String>,
+    pub declarations: Declarations,
+}
+
+impl KotlinMetadata {
+    /// The metadata of a class, or `None` if it has no `kotlin.Metadata`
+    /// annotation.
+    pub fn of_class(
+        resolver: &DexResolver,
+        class_def: &ClassDefItem,
+    ) -> Result<Option<KotlinMetadata>, KotlinError> {
+        let directory = match resolver.annotations_directory(class_def.annotations_off) {
+            Some(directory) if class_def.annotations_off != 0 => directory,
+            _ => return Ok(None),
+        };
+        let annotations = annotation_set(resolver, directory.class_annotations_off);
+        return match annotations
+            .iter()
+            .find(|a| a.type_descriptor == KOTLIN_METADATA)
+        {
+            Some(annotation) => KotlinMetadata::from_annotation(annotation).map(Some),
+            None => Ok(None),
+        };
+    }
+
+    pub fn from_annotation(annotation: &Annotation) -> Result<KotlinMetadata, KotlinError> {
+        let invalid = |name: &str| KotlinError::InvalidAnnotation(format!("bad {}", name));
+        let kind = match annotation.get("k") {
+            Some(value) => value.as_int().ok_or_else(|| invalid("k"))?,
+            None => 1,
+        };
+        let kind = MetadataKind::from_i32(kind)
+            .ok_or_else(|| KotlinError::InvalidAnnotation(format!("unknown kind {}", kind)))?;
+        let version = match annotation.get("mv") {
+            Some(value) => value
+                .as_array()
+                .and_then(|values| values.iter().map(|v| v.as_int()).collect())
+                .ok_or_else(|| invalid("mv"))?,
+            None => vec![],
+        };
+        let strings = |name: &str| -> Result<Vec<String>, KotlinError> {
+            return match annotation.get(name) {
+                Some(value) => Ok(value
+                    .as_strings()
+                    .ok_or_else(|| invalid(name))?
+                    .into_iter()
+                    .map(|s| s.unwrap_or_default().to_string())
+                    .collect()),
+                None => Ok(vec![]),
+            };
+        };
+        let d1 = strings("d1")?;
+        let d2 = strings("d2")?;
+        let package_name = annotation
+            .get("pn")
+            .and_then(Value::as_str)
+            .filter(|pn| !pn.is_empty())
+            .map(|pn| pn.to_string());
+
+        let bytes = decode_bytes(&d1);
+        let declarations = match kind {
+            MetadataKind::MultiFileClassFacade => Declarations::FacadeParts(d1),
+            _ if d1.is_empty() => Declarations::None,
+            MetadataKind::Class => {
+                let (mut decoder, message) = Decoder::new(&bytes, d2)?;
+                Declarations::Class(decoder.class(&message)?)
+            }
+            MetadataKind::FileFacade | MetadataKind::MultiFileClassPart => {
+                let (mut decoder, message) = Decoder::new(&bytes, d2)?;
+                Declarations::Package(decoder.package(&message)?)
+            }
+            MetadataKind::SyntheticClass => {
+                let (mut decoder, message) = Decoder::new(&bytes, d2)?;
+                Declarations::Lambda(decoder.function(&message)?)
+            }
+        };
+        return Ok(KotlinMetadata {
+            kind,
+            version,
+            package_name,
+            declarations,
+        });
+    }
+
+    pub fn lambda(&self) -> Option<&KotlinFunction> {
+        return match &self.declarations {
+            Declarations::Lambda(function) => Some(function),
+            _ => None,
+        };
+    }
+}
+
+impl Display for KotlinMetadata {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        return match &self.declarations {
+            Declarations::Class(class) => write!(f, "{}", class),
+            Declarations::Package(package) => {
+                writeln!(f, "file {{")?;
+                for property in package.properties.iter() {
+                    writeln!(f, "    {}", property)?;
+                }
+                for function in package.functions.iter() {
+                    writeln!(f, "    {}", function)?;
+                }
+                for type_alias in package.type_aliases.iter() {
+                    writeln!(f, "    typealias {}", type_alias)?;
+                }
+                writeln!(f, "}}")
+            }
+            Declarations::Lambda(function) => writeln!(f, "lambda {}", function),
+            Declarations::FacadeParts(parts) => {
+                writeln!(f, "multi-file class of {}", parts.join(", "))
+            }
+            Declarations::None => writeln!(f, "synthetic class"),
+        };
+    }
+}
+
+/// Bytes of the `d1` strings. Since Kotlin 1.4 every char holds one byte
+/// after a leading `\0`; before, chars held 7 bits each, offset by 1.
+fn decode_bytes(d1: &[String]) -> Vec<u8> {
+    if d1.first().is_some_and(|s| s.starts_with('\0')) {
+        return d1
+            .iter()
+            .flat_map(|s| s.chars())
+            .skip(1)
+            .map(|c| c as u32 as u8)
+            .collect();
+    }
+    let packed = d1
+        .iter()
+        .flat_map(|s| s.chars())
+        .map(|c| (c as u32 as u8).wrapping_add(0x7f) & 0x7f)
+        .collect::<Vec<_>>();
+    let mut bytes = Vec::with_capacity(packed.len() * 7 / 8);
+    let mut index = 0;
+    let mut bit = 0;
+    for _ in 0..packed.len() * 7 / 8 {
+        let low = packed[index] >> bit;
+        index += 1;
+        let high = (packed[index] & ((1 << (bit + 1)) - 1)) << (7 - bit);
+        bytes.push(low.wrapping_add(high));
+        if bit == 6 {
+            index += 1;
+            bit = 0;
+        } else {
+            bit += 1;
+        }
+    }
+    return bytes;
+}
+
+/// A `StringTableTypes.Record`, which describes how to derive strings from
+/// `d2` or the predefined strings.
+struct Record {
+    predefined_index: Option<usize>,
+    string: Option<String>,
+    operation: u64,
+    substring_index: Vec<u64>,
+    replace_char: Vec<u64>,
+}
+
+/// Resolves names in the protobuf messages to strings.
+struct NameResolver {
+    records: Vec<Record>,
+    /// Index into `records` of every string, after expanding ranges.
+    string_records: Vec<usize>,
+    d2: Vec<String>,
+}
+
+impl NameResolver {
+    fn new(table: &Message, d2: Vec<String>) -> Result<NameResolver, KotlinError> {
+        let mut records = vec![];
+        let mut string_records = vec![];
+        for (i, record) in table.messages(1)?.iter().enumerate() {
+            let range = if record.has(1) { record.varint(1) } else { 1 };
+            // Strings past `d2` are those of a record, one each, so a range
+            // cannot take the table further than that.
+            let end = usize::try_from(range)
+                .ok()
+                .and_then(|range| string_records.len().checked_add(range))
+                .filter(|end| *end <= d2.len() + i + 1)
+                .ok_or_else(|| {
+                    let last = range - 1;
+                    KotlinError::InvalidStringIndex(
+                        last.saturating_add(string_records.len() as u64),
+                    )
+                })?;
+            string_records.resize(end, i);
+            records.push(Record {
+                predefined_index: record
+                    .has(2)
+                    .then(|| record.varint(2) as usize)
+                    .filter(|idx| *idx < PREDEFINED_STRINGS.len()),
+                string: record.has(6).then(|| record.string(6)),
+                operation: record.varint(3),
+                substring_index: record.varints(4)?,
+                replace_char: record.varints(5)?,
+            });
+        }
+        return Ok(NameResolver {
+            records,
+            string_records,
+            d2,
+        });
+    }
+
+    fn string(&self, idx: u64) -> Result<String, KotlinError> {
+        let record = self
+            .string_records
+            .get(idx as usize)
+            .map(|r| &self.records[*r]);
+        let mut string = match record {
+            Some(Record {
+                string: Some(string),
+                ..
+            }) => string.clone(),
+            Some(Record {
+                predefined_index: Some(predefined),
+                ..
+            }) => PREDEFINED_STRINGS[*predefined].to_string(),
+            _ => self
+                .d2
+                .get(idx as usize)
+                .ok_or(KotlinError::InvalidStringIndex(idx))?
+                .clone(),
+        };
+        let record = match record {
+            Some(record) => record,
+            None => return Ok(string),
+        };
+        if let [begin, end, ..] = record.substring_index[..] {
+            let chars = string.chars().collect::<Vec<_>>();
+            if begin <= end && end as usize <= chars.len() {
+                string = chars[begin as usize..end as usize].iter().collect();
+            }
+        }
+        if let [from, to, ..] = record.replace_char[..] {
+            if let (Some(from), Some(to)) = (char::from_u32(from as u32), char::from_u32(to as u32))
+            {
+                string = string.replace(from, &to.to_string());
+            }
+        }
+        match record.operation {
+            OPERATION_INTERNAL_TO_CLASS_ID => string = string.replace('$', "."),
+            OPERATION_DESC_TO_CLASS_ID => {
+                let mut chars = string.chars();
+                if string.chars().count() >= 2 {
+                    chars.next();
+                    chars.next_back();
+                }
+                string = chars.as_str().replace('$', ".");
+            }
+            _ => {}
+        }
+        return Ok(string);
+    }
+
+    /// A class name such as `kotlin/collections/Map.Entry` with `.`
+    /// separators.
+    fn class_name(&self, idx: u64) -> Result<String, KotlinError> {
+        return Ok(self.string(idx)?.replace('/', "."));
+    }
+}
+
+/// Decodes the messages of `metadata.proto` against a string table and the
+/// type table of the enclosing class or file.
+struct Decoder<'a> {
+    names: NameResolver,
+    types: Vec<Message<'a>>,
+    /// Index of the first type of the table that is nullable.
+    first_nullable: Option<usize>,
+    /// Ids and names of the type parameters in scope.
+    type_parameters: Vec<(u64, String)>,
+}
+
+impl<'a> Decoder<'a> {
+    /// A decoder for the bytes of `d1`: a length-delimited string table
+    /// followed by the message of the declarations.
+    fn new(bytes: &'a [u8], d2: Vec<String>) -> Result<(Decoder<'a>, Message<'a>), KotlinError> {
+        let (table, rest) = Message::parse_delimited(bytes)?;
+        let message = Message::parse(rest)?;
+        let mut decoder = Decoder {
+            names: NameResolver::new(&table, d2)?,
+            types: vec![],
+            first_nullable: None,
+            type_parameters: vec![],
+        };
+        decoder.set_type_table(&message)?;
+        return Ok((decoder, message));
+    }
+
+    fn set_type_table(&mut self, message: &Message<'a>) -> Result<(), KotlinError> {
+        let table = message.message(30)?;
+        self.types = table.messages(1)?;
+        // `first_nullable` defaults to -1, which wraps to a huge varint.
+        self.first_nullable = table
+            .has(2)
+            .then(|| table.varint(2) as i64)
+            .filter(|first| *first >= 0)
+            .map(|first| first as usize);
+        return Ok(());
+    }
+
+    /// The type in the message field `number`, or else the one of the type
+    /// table that `id_number` refers to.
+    fn type_field(
+        &self,
+        message: &Message<'a>,
+        number: u32,
+        id_number: u32,
+    ) -> Result<Option<KotlinType>, KotlinError> {
+        if message.has(number) {
+            return self.kotlin_type(&message.message(number)?, false).map(Some);
+        }
+        if message.has(id_number) {
+            let id = message.varint(id_number);
+            let table_type = self
+                .types
+                .get(id as usize)
+                .ok_or(KotlinError::InvalidTypeIndex(id))?;
+            let nullable = self.first_nullable.is_some_and(|f| id as usize >= f);
+            return self.kotlin_type(table_type, nullable).map(Some);
+        }
+        return Ok(None);
+    }
+
+    fn kotlin_type(
+        &self,
+        message: &Message<'a>,
+        nullable: bool,
+    ) -> Result<KotlinType, KotlinError> {
+        let name = if message.has(6) {
+            self.names.class_name(message.varint(6))?
+        } else if message.has(12) {
+            self.names.class_name(message.varint(12))?
+        } else if message.has(9) {
+            self.names.string(message.varint(9))?
+        } else if message.has(7) {
+            let id = message.varint(7);
+            self.type_parameters
+                .iter()
+                .rev()
+                .find(|(i, _)| *i == id)
+                .map(|(_, name)| name.clone())
+                .unwrap_or_else(|| format!("T{}", id))
+        } else {
+            return Err(KotlinError::InvalidType(
+                "type without a class or type parameter".to_string(),
+            ));
+        };
+        let mut arguments = vec![];
+        for argument in message.messages(2)? {
+            let projection = match argument.has(1).then(|| argument.varint(1)) {
+                Some(0) => Projection::In,
+                Some(1) => Projection::Out,
+                Some(3) => Projection::Star,
+                _ => Projection::Invariant,
+            };
+            arguments.push(TypeArgument {
+                projection,
+                argument_type: match projection {
+                    Projection::Star => None,
+                    _ => self.type_field(&argument, 2, 3)?,
+                },
+            });
+        }
+        return Ok(KotlinType {
+            name,
+            arguments,
+            nullable: nullable || message.bool(3),
+            suspend: message.varint(1) & TYPE_SUSPEND != 0,
+        });
+    }
+
+    /// Decodes type parameters and brings them into scope.
+    fn type_parameters(
+        &mut self,
+        message: &Message<'a>,
+        number: u32,
+    ) -> Result<Vec<KotlinTypeParameter>, KotlinError> {
+        let parameters = message.messages(number)?;
+        let mut names = vec![];
+        for parameter in parameters.iter() {
+            let name = self.names.string(parameter.varint(2))?;
+            self.type_parameters
+                .push((parameter.varint(1), name.clone()));
+            names.push(name);
+        }
+        let mut decoded = vec![];
+        for (parameter, name) in parameters.iter().zip(names) {
+            let mut upper_bounds = vec![];
+            for bound in parameter.messages(5)? {
+                upper_bounds.push(self.kotlin_type(&bound, false)?);
+            }
+            for id in parameter.varints(6)? {
+                let bound = self
+                    .types
+                    .get(id as usize)
+                    .ok_or(KotlinError::InvalidTypeIndex(id))?;
+                let nullable = self.first_nullable.is_some_and(|f| id as usize >= f);
+                upper_bounds.push(self.kotlin_type(bound, nullable)?);
+            }
+            decoded.push(KotlinTypeParameter {
+                name,
+                reified: parameter.bool(3),
+                projection: match parameter.has(4).then(|| parameter.varint(4)) {
+                    Some(0) => Projection::In,
+                    Some(1) => Projection::Out,
+                    _ => Projection::Invariant,
+                },
+                upper_bounds,
+            });
+        }
+        return Ok(decoded);
+    }
+
+    fn parameters(
+        &self,
+        message: &Message<'a>,
+        number: u32,
+    ) -> Result<Vec<KotlinParameter>, KotlinError> {
+        let mut parameters = vec![];
+        for parameter in message.messages(number)? {
+            let flags = parameter.varint(1);
+            parameters.push(KotlinParameter {
+                name: self.names.string(parameter.varint(2))?,
+                parameter_type: self.required_type(&parameter, 3, 5)?,
+                vararg_element_type: self.type_field(&parameter, 4, 6)?,
+                declares_default: flag(flags, PARAMETER_DEFAULT),
+                modifiers: modifiers(flags, &PARAMETER_MODIFIERS),
+            });
+        }
+        return Ok(parameters);
+    }
+
+    fn required_type(
+        &self,
+        message: &Message<'a>,
+        number: u32,
+        id_number: u32,
+    ) -> Result<KotlinType, KotlinError> {
+        return self
+            .type_field(message, number, id_number)?
+            .ok_or_else(|| KotlinError::InvalidType(format!("missing type field {}", number)));
+    }
+
+    fn flags(message: &Message<'a>, number: u32, default: u64) -> u64 {
+        return match message.has(number) {
+            true => message.varint(number),
+            false => default,
+        };
+    }
+
+    fn function(&mut self, message: &Message<'a>) -> Result<KotlinFunction, KotlinError> {
+        let flags = Decoder::flags(message, 9, DEFAULT_FLAGS);
+        let scope = self.type_parameters.len();
+        let type_parameters = self.type_parameters(message, 4)?;
+        let function = KotlinFunction {
+            name: self.names.string(message.varint(2))?,
+            visibility: KotlinVisibility::from_flags(flags),
+            modality: Modality::from_flags(flags),
+            modifiers: modifiers(flags, &FUNCTION_MODIFIERS),
+            type_parameters,
+            receiver_type: self.type_field(message, 5, 8)?,
+            parameters: self.parameters(message, 6)?,
+            return_type: self.required_type(message, 3, 7)?,
+        };
+        self.type_parameters.truncate(scope);
+        return Ok(function);
+    }
+
+    fn property(&mut self, message: &Message<'a>) -> Result<KotlinProperty, KotlinError> {
+        let flags = Decoder::flags(message, 11, DEFAULT_PROPERTY_FLAGS);
+        let scope = self.type_parameters.len();
+        let type_parameters = self.type_parameters(message, 4)?;
+        let property = KotlinProperty {
+            name: self.names.string(message.varint(2))?,
+            visibility: KotlinVisibility::from_flags(flags),
+            modality: Modality::from_flags(flags),
+            modifiers: modifiers(flags, &PROPERTY_MODIFIERS),
+            mutable: flag(flags, PROPERTY_VAR),
+            delegated: flag(flags, PROPERTY_DELEGATED),
+            type_parameters,
+            receiver_type: self.type_field(message, 5, 10)?,
+            property_type: self.required_type(message, 3, 9)?,
+        };
+        self.type_parameters.truncate(scope);
+        return Ok(property);
+    }
+
+    fn members(
+        &mut self,
+        message: &Message<'a>,
+        function_number: u32,
+        property_number: u32,
+    ) -> Result<(Vec<KotlinFunction>, Vec<KotlinProperty>), KotlinError> {
+        let mut functions = vec![];
+        for function in message.messages(function_number)? {
+            functions.push(self.function(&function)?);
+        }
+        let mut properties = vec![];
+        for property in message.messages(property_number)? {
+            properties.push(self.property(&property)?);
+        }
+        return Ok((functions, properties));
+    }
+
+    fn class(&mut self, message: &Message<'a>) -> Result<KotlinClass, KotlinError> {
+        let flags = Decoder::flags(message, 1, DEFAULT_FLAGS);
+        let type_parameters = self.type_parameters(message, 5)?;
+        let mut supertypes = vec![];
+        for supertype in message.messages(6)? {
+            supertypes.push(self.kotlin_type(&supertype, false)?);
+        }
+        for id in message.varints(2)? {
+            let supertype = self
+                .types
+                .get(id as usize)
+                .ok_or(KotlinError::InvalidTypeIndex(id))?;
+            supertypes.push(self.kotlin_type(supertype, false)?);
+        }
+        let mut constructors = vec![];
+        for constructor in message.messages(8)? {
+            let flags = Decoder::flags(&constructor, 1, DEFAULT_FLAGS);
+            constructors.push(KotlinConstructor {
+                visibility: KotlinVisibility::from_flags(flags),
+                secondary: flag(flags, CONSTRUCTOR_SECONDARY),
+                parameters: self.parameters(&constructor, 2)?,
+            });
+        }
+        let (functions, properties) = self.members(message, 9, 10)?;
+        let names = |numbers: Vec<u64>, class_names: bool| {
+            return numbers
+                .into_iter()
+                .map(|idx| match class_names {
+                    true => self.names.class_name(idx),
+                    false => self.names.string(idx),
+                })
+                .collect::<Result<Vec<_>, _>>();
+        };
+        return Ok(KotlinClass {
+            name: self.names.class_name(message.varint(3))?,
+            kind: ClassKind::from_flags(flags),
+            visibility: KotlinVisibility::from_flags(flags),
+            modality: Modality::from_flags(flags),
+            modifiers: modifiers(flags, &CLASS_MODIFIERS),
+            type_parameters,
+            supertypes,
+            companion_object: match message.has(4) {
+                true => Some(self.names.string(message.varint(4))?),
+                false => None,
+            },
+            nested_classes: names(message.varints(7)?, false)?,
+            enum_entries: names(
+                message
+                    .messages(13)?
+                    .iter()
+                    .map(|entry| entry.varint(1))
+                    .collect(),
+                false,
+            )?,
+            sealed_subclasses: names(message.varints(16)?, true)?,
+            constructors,
+            functions,
+            properties,
+        });
+    }
+
+    fn package(&mut self, message: &Message<'a>) -> Result<KotlinPackage, KotlinError> {
+        let (functions, properties) = self.members(message, 3, 4)?;
+        let mut type_aliases = vec![];
+        for type_alias in message.messages(5)? {
+            type_aliases.push(self.names.string(type_alias.varint(2))?);
+        }
+        return Ok(KotlinPackage {
+            functions,
+            properties,
+            type_aliases,
+        });
+    }
+}
+
+/// The metadata of one class.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct KotlinClassMetadata {
+    pub dex: String,
+    /// Java name of the class.
+    pub class: String,
+    pub metadata: KotlinMetadata,
+}
+
+/// A class whose metadata could not be decoded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct KotlinFailure {
+    pub dex: String,
+    pub class: String,
+    pub error: String,
+}
+
+/// Classes the Kotlin compiler generated in one package.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct KotlinPackageStats {
+    pub package: String,
+    pub classes: usize,
+    /// Classes with Kotlin metadata.
+    pub kotlin_classes: usize,
+    /// Classes of lambdas and function references.
+    pub lambdas: usize,
+    /// Copies of lambdas and objects made when inlining a function, named
+    /// `…$$inlined$…`.
+    pub inlined_copies: usize,
+    /// `$DefaultImpls` classes holding the bodies of interface methods.
+    pub default_impls: usize,
+    /// Other synthetic classes, e.g. `$WhenMappings`.
+    pub synthetic_classes: usize,
+    /// Methods of lambdas, inlined copies and `DefaultImpls`.
+    pub generated_methods: usize,
+}
+
+/// Kotlin metadata of every class, and the generated classes per package.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct KotlinReport {
+    pub classes: Vec<KotlinClassMetadata>,
+    pub failures: Vec<KotlinFailure>,
+    /// Packages with Kotlin or compiler-generated classes, by name.
+    pub packages: Vec<KotlinPackageStats>,
+}
+
+impl KotlinReport {
+    pub fn new(dexes: &[(String, DexModel)]) -> KotlinReport {
+        let resolvers = dexes
+            .iter()
+            .map(|(name, dex)| (name.clone(), DexResolver::new(dex)))
+            .collect::<Vec<_>>();
+        return KotlinReport::from_resolvers(&resolvers);
+    }
+
+    /// The report of each `(entry name, resolver)` pair. Classes and
+    /// packages have their original names when the resolvers have a
+    /// mapping, and so does the superclass a lambda is recognised by.
+    pub fn from_resolvers(dexes: &[(String, DexResolver)]) -> KotlinReport {
+        let mut report = KotlinReport::default();
+        let mut packages: BTreeMap<String, KotlinPackageStats> = BTreeMap::new();
+        for (name, resolver) in dexes {
+            let dex = resolver.dex;
+            for class_def in dex.class_defs.iter() {
+                let class = resolver.type_name(class_def.class_idx);
+                let (package, simple_name) = class.rsplit_once('.').unwrap_or(("", &class));
+                let stats =
+                    packages
+                        .entry(package.to_string())
+                        .or_insert_with(|| KotlinPackageStats {
+                            package: package.to_string(),
+                            ..KotlinPackageStats::default()
+                        });
+                stats.classes += 1;
+                let metadata = match KotlinMetadata::of_class(resolver, class_def) {
+                    Ok(metadata) => metadata,
+                    Err(err) => {
+                        report.failures.push(KotlinFailure {
+                            dex: name.clone(),
+                            class: class.clone(),
+                            error: err.to_string(),
+                        });
+                        None
+                    }
+                };
+                let superclass = match class_def.superclass_idx {
+                    idx if (idx as usize) < dex.type_ids.len() => {
+                        java_to_descriptor(&resolver.type_name(idx))
+                    }
+                    _ => String::new(),
+                };
+                let synthetic = metadata
+                    .as_ref()
+                    .is_some_and(|m| m.kind == MetadataKind::SyntheticClass);
+                let generated = if simple_name.contains("$$inlined") {
+                    stats.inlined_copies += 1;
+                    true
+                } else if simple_name.ends_with("$DefaultImpls") {
+                    stats.default_impls += 1;
+                    true
+                } else if LAMBDA_SUPERCLASSES.contains(&superclass.as_str())
+                    || metadata.as_ref().and_then(|m| m.lambda()).is_some()
+                    || (synthetic && simple_name.contains("$lambda"))
+                {
+                    stats.lambdas += 1;
+                    true
+                } else {
+                    if synthetic {
+                        stats.synthetic_classes += 1;
+                    }
+                    false
+                };
+                if generated {
+                    stats.generated_methods += resolver.class_methods(class_def).len();
+                }
+                if let Some(metadata) = metadata {
+                    stats.kotlin_classes += 1;
+                    report.classes.push(KotlinClassMetadata {
+                        dex: name.clone(),
+                        class,
+                        metadata,
+                    });
+                }
+            }
+        }
+        report.packages = packages
+            .into_values()
+            .filter(|p| p.kotlin_classes + p.lambdas + p.inlined_copies + p.default_impls > 0)
+            .collect();
+        return report;
+    }
+
+    /// The metadata of the class with Java name `class`.
+    pub fn class(&self, class: &str) -> Option<&KotlinMetadata> {
+        return self
+            .classes
+            .iter()
+            .find(|c| c.class == class)
+            .map(|c| &c.metadata);
+    }
+
+    pub fn to_json(&self) -> String {
+        return serde_json::to_string_pretty(self).unwrap();
+    }
+}
+
+impl Display for KotlinReport {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<40} {:>7} {:>7} {:>7} {:>7} {:>12} {:>9} {:>7}",
+            "package",
+            "classes",
+            "kotlin",
+            "lambdas",
+            "inlined",
+            "DefaultImpls",
+            "synthetic",
+            "methods"
+        )?;
+        for stats in self.packages.iter() {
+            writeln!(
+                f,
+                "{:<40} {:>7} {:>7} {:>7} {:>7} {:>12} {:>9} {:>7}",
+                if stats.package.is_empty() {
+                    "<default>"
+                } else {
+                    &stats.package
+                },
+                stats.classes,
+                stats.kotlin_classes,
+                stats.lambdas,
+                stats.inlined_copies,
+                stats.default_impls,
+                stats.synthetic_classes,
+                stats.generated_methods
+            )?;
+        }
+        for failure in self.failures.iter() {
+            writeln!(f, "{}: {}: {}", failure.dex, failure.class, failure.error)?;
+        }
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        dex_structs::{EncodedArray, EncodedValue},
+        test_utils::{
+            add_annotations, annotation_item, build_dex, string_array, ProtoBuilder, TestClass,
+        },
+    };
+
+    /// `d1` strings for a message, in the format of Kotlin 1.4 and later.
+    fn d1(table: &ProtoBuilder, message: &ProtoBuilder) -> Vec<String> {
+        let mut bytes = table.build_delimited();
+        bytes.extend(message.build());
+        let text = std::iter::once('\0')
+            .chain(bytes.iter().map(|b| *b as char))
+            .collect::<String>();
+        return vec![text];
+    }
+
+    fn type_message(class_name: u64, nullable: bool) -> ProtoBuilder {
+        let mut message = ProtoBuilder::new();
+        message.varint(6, class_name);
+        if nullable {
+            message.varint(3, 1);
+        }
+        return message;
+    }
+
+    #[test]
+    fn test_class_metadata() {
+        let d2 = ["com/example/User", "name", "load", "id", "Companion"];
+        // Strings 0 to 4 are those of d2, 5 and 6 the predefined kotlin/String
+        // and kotlin/Long, and 7 a class name derived from a descriptor.
+        let mut table = ProtoBuilder::new();
+        table
+            .message(1, ProtoBuilder::new().varint(1, 5))
+            .message(1, ProtoBuilder::new().varint(2, 14))
+            .message(1, ProtoBuilder::new().varint(2, 9))
+            .message(
+                1,
+                ProtoBuilder::new()
+                    .varint(3, 2)
+                    .string(6, "Lcom/example/User$Kind;"),
+            );
+        let mut type_table = ProtoBuilder::new();
+        type_table
+            .message(1, &type_message(5, false))
+            .message(1, &type_message(0, false))
+            .varint(2, 1);
+
+        let mut name = ProtoBuilder::new();
+        name.varint(2, 1).varint(9, 0);
+        let mut id = ProtoBuilder::new();
+        id.varint(2, 3).message(3, &type_message(6, false));
+        let mut load = ProtoBuilder::new();
+        // Public final suspend.
+        load.varint(9, 6 | 1 << 13)
+            .varint(2, 2)
+            .message(6, &id)
+            .varint(7, 1);
+        let mut constructor = ProtoBuilder::new();
+        constructor.message(
+            2,
+            ProtoBuilder::new().varint(1, 2).varint(2, 1).varint(5, 0),
+        );
+        let mut class = ProtoBuilder::new();
+        // Public final data class.
+        class
+            .varint(1, 6 | 1 << 10)
+            .varint(3, 0)
+            .varint(4, 4)
+            .packed(7, &[4])
+            .packed(16, &[7])
+            .message(8, &constructor)
+            .message(9, &load)
+            .message(10, &name)
+            .message(30, &type_table);
+        let d1 = d1(&table, &class);
+
+        let mut strings = vec!["k", "mv", "d1", "d2"];
+        strings.extend(d2);
+        strings.push(&d1[0]);
+        let mut dex = build_dex(
+            vec![
+                TestClass::new("Lcom/example/User;"),
+                TestClass::new("Lkotlin/Metadata;"),
+            ],
+            &strings,
+        );
+        let resolver = DexResolver::new(&dex);
+        let metadata = annotation_item(
+            &resolver,
+            KOTLIN_METADATA,
+            vec![
+                ("k", EncodedValue::ValueInt(1)),
+                (
+                    "mv",
+                    EncodedValue::ValueArray(EncodedArray {
+                        values: vec![EncodedValue::ValueInt(1), EncodedValue::ValueInt(9)],
+                    }),
+                ),
+                ("d1", string_array(&resolver, &[&d1[0]])),
+                ("d2", string_array(&resolver, &d2)),
+            ],
+        );
+        add_annotations(&mut dex, 0, vec![metadata], vec![], vec![], vec![]);
+
+        let report = KotlinReport::new(&[("classes.dex".to_string(), dex)]);
+        assert!(report.failures.is_empty());
+        let metadata = report.class("com.example.User").unwrap();
+        assert_eq!(metadata.kind, MetadataKind::Class);
+        assert_eq!(metadata.version, vec![1, 9]);
+        let class = match &metadata.declarations {
+            Declarations::Class(class) => class,
+            declarations => panic!("unexpected {:?}", declarations),
+        };
+        assert_eq!(class.kind, ClassKind::Class);
+        assert_eq!(class.modifiers, vec!["data"]);
+        assert_eq!(class.companion_object.as_deref(), Some("Companion"));
+        assert_eq!(class.sealed_subclasses, vec!["com.example.User.Kind"]);
+        assert!(class.functions[0].is_suspend());
+        assert!(class.functions[0].return_type.nullable);
+        assert!(!class.properties[0].property_type.nullable);
+        assert_eq!(
+            metadata.to_string(),
+            "data class com.example.User {\n    \
+             constructor(name: kotlin.String = …)\n    \
+             val name: kotlin.String\n    \
+             suspend fun load(id: kotlin.Long): com.example.User?\n    \
+             companion object Companion\n\
+             }\n"
+        );
+        assert_eq!(report.packages[0].package, "com.example");
+        assert_eq!(report.packages[0].kotlin_classes, 1);
+    }
+
+    #[test]
+    fn test_generated_classes() {
+        let mut lambda =
+            TestClass::new("Lcom/example/Main$run$1;").method("invoke", &[], "V", &[0x000e]);
+        lambda.superclass = Some("Lkotlin/jvm/internal/Lambda;");
+        let dex = build_dex(
+            vec![
+                TestClass::new("Lcom/example/Main;"),
+                lambda,
+                TestClass::new("Lcom/example/Main$run$$inlined$map$1;")
+                    .method("invoke", &[], "V", &[0x000e])
+                    .method("apply", &[], "V", &[0x000e]),
+                TestClass::new("Lcom/example/Api$DefaultImpls;"),
+                TestClass::new("Lcom/other/Plain;"),
+            ],
+            &[],
+        );
+        let report = KotlinReport::new(&[("classes.dex".to_string(), dex)]);
+        assert_eq!(
+            report.packages,
+            vec![KotlinPackageStats {
+                package: "com.example".to_string(),
+                classes: 4,
+                kotlin_classes: 0,
+                lambdas: 1,
+                inlined_copies: 1,
+                default_impls: 1,
+                synthetic_classes: 0,
+                generated_methods: 3,
+            }]
+        );
+        assert_eq!(report.to_string().lines().count(), 2);
+    }
+
+    #[test]
+    fn test_string_range() {
+        let d2 = vec!["a".to_string(), "b".to_string()];
+        let table = |ranges: &[u64]| {
+            let mut table = ProtoBuilder::new();
+            for range in ranges {
+                table.message(1, ProtoBuilder::new().varint(1, *range));
+            }
+            return table.build();
+        };
+        let bytes = table(&[2, 1]);
+        let resolver = NameResolver::new(&Message::parse(&bytes).unwrap(), d2.clone()).unwrap();
+        assert_eq!(resolver.string(1).unwrap(), "b");
+        for ranges in [&[4][..], &[1, u64::MAX], &[2, 3]] {
+            let bytes = table(ranges);
+            assert!(matches!(
+                NameResolver::new(&Message::parse(&bytes).unwrap(), d2.clone()),
+                Err(KotlinError::InvalidStringIndex(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_decode_bytes() {
+        let bytes = [0x08u8, 0x96, 0x01, 0x7f, 0x00, 0xff, 0x10];
+        let utf8 = std::iter::once('\0')
+            .chain(bytes.iter().map(|b| *b as char))
+            .collect::<String>();
+        assert_eq!(decode_bytes(&[utf8]), bytes);
+
+        // Pack 7 bits per char, offset by one as the compiler does.
+        let mut bits = 0u64;
+        let mut count = 0;
+        let mut packed = String::new();
+        for byte in bytes {
+            bits |= (byte as u64) << count;
+            count += 8;
+            while count >= 7 {
+                packed.push(char::from(((bits & 0x7f) as u8 + 1) & 0x7f));
+                bits >>= 7;
+                count -= 7;
+            }
+        }
+        if count > 0 {
+            packed.push(char::from(((bits & 0x7f) as u8 + 1) & 0x7f));
+        }
+        assert_eq!(decode_bytes(&[packed]), bytes);
+    }
+}
@@ -0,0 +1,420 @@
+//! Recomputes the file layout of a `DexModel` after its sections were
+//! edited: section and item offsets, the map list, the header and the
+//! checksums.
+
+use std::collections::HashMap;
+
+use sha1::{Digest, Sha1};
+
+use crate::{
+    dex_model::DexModel,
+    dex_structs::{DexStruct, MapItem, MapList, TypeCode},
+    serialize_model,
+};
+
+const HEADER_SIZE: u32 = 0x70;
+
+/// Id sections in file order, with the encoded size of one item.
+const ID_SECTIONS: [(TypeCode, u32); 8] = [
+    (TypeCode::TypeStringIdItem, 4),
+    (TypeCode::TypeTypeIdItem, 4),
+    (TypeCode::TypeProtoIdItem, 12),
+    (TypeCode::TypeFieldIdItem, 8),
+    (TypeCode::TypeMethodIdItem, 8),
+    (TypeCode::TypeClassDefItem, 32),
+    (TypeCode::TypeCallSiteIdItem, 4),
+    (TypeCode::TypeMethodHandleItem, 8),
+];
+
+/// File offset of every item of every data section, in section order.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct ItemOffsets {
+    pub(crate) sections: HashMap<TypeCode, Vec<u32>>,
+}
+
+impl ItemOffsets {
+    /// Offsets of the items of `dex` as currently laid out by its map list.
+    pub(crate) fn of(dex: &DexModel) -> Self {
+        let mut sections = HashMap::new();
+        for (type_code, alignment, sizes) in data_sections(dex) {
+            if let Some(map_item) = dex.map_list.get(type_code) {
+                sections.insert(type_code, walk(map_item.offset, alignment, &sizes).0);
+            }
+        }
+        return Self { sections };
+    }
+
+    pub(crate) fn get(&self, type_code: TypeCode) -> &[u32] {
+        return self
+            .sections
+            .get(&type_code)
+            .map_or(&[], |offsets| offsets.as_slice());
+    }
+
+    /// Registers an item appended to section `type_code`, returning a
+    /// placeholder offset for references to it until the next layout.
+    pub(crate) fn append(&mut self, type_code: TypeCode) -> u32 {
+        let section = self.sections.entry(type_code).or_default();
+        let offset = section.iter().max().map_or(1, |max| max + 1);
+        section.push(offset);
+        return offset;
+    }
+
+    /// Position of each item within its section, keyed by offset.
+    pub(crate) fn index(&self) -> HashMap<(TypeCode, u32), usize> {
+        return self
+            .sections
+            .iter()
+            .flat_map(|(type_code, offsets)| {
+                offsets
+                    .iter()
+                    .enumerate()
+                    .map(|(i, offset)| ((*type_code, *offset), i))
+            })
+            .collect();
+    }
+}
+
+fn sizes<T: DexStruct>(items: &[T]) -> (u64, Vec<usize>) {
+    return (T::ALIGNMENT, items.iter().map(|item| item.size()).collect());
+}
+
+/// Alignment and item sizes of every data section, in the order they are
+/// laid out. Class data follows code items because its `code_off` values
+/// are variable-length.
+fn data_sections(dex: &DexModel) -> Vec<(TypeCode, u64, Vec<usize>)> {
+    return [
+        (TypeCode::TypeTypeList, sizes(&dex.type_lists)),
+        (
+            TypeCode::TypeAnnotationSetRefList,
+            sizes(&dex.annotation_set_ref_lists),
+        ),
+        (
+            TypeCode::TypeAnnotationSetItem,
+            sizes(&dex.annotation_set_items),
+        ),
+        (TypeCode::TypeCodeItem, sizes(&dex.code_items)),
+        (TypeCode::TypeDebugInfoItem, sizes(&dex.debug_info_items)),
+        (TypeCode::TypeStringDataItem, sizes(&dex.string_data_items)),
+        (TypeCode::TypeAnnotationItem, sizes(&dex.annotation_items)),
+        (
+            TypeCode::TypeEncodedArrayItem,
+            sizes(&dex.encoded_array_items),
+        ),
+        (
+            TypeCode::TypeAnnotationsDirectoryItem,
+            sizes(&dex.annotations_directory_items),
+        ),
+        (TypeCode::TypeClassDataItem, sizes(&dex.class_data_items)),
+        (
+            TypeCode::TypeHiddenapiClassDataItem,
+            sizes(&dex.hiddenapi_class_data_items),
+        ),
+    ]
+    .into_iter()
+    .map(|(type_code, (alignment, sizes))| (type_code, alignment, sizes))
+    .collect();
+}
+
+fn align(offset: u32, alignment: u64) -> u32 {
+    return offset.next_multiple_of(alignment as u32);
+}
+
+/// Lays out items of the given sizes from `start`, returning their offsets
+/// and the end of the section.
+fn walk(start: u32, alignment: u64, sizes: &[usize]) -> (Vec<u32>, u32) {
+    let mut offset = start;
+    let mut offsets = Vec::with_capacity(sizes.len());
+    for size in sizes {
+        offset = align(offset, alignment);
+        offsets.push(offset);
+        offset += *size as u32;
+    }
+    return (offsets, offset);
+}
+
+struct Layout {
+    offsets: ItemOffsets,
+    map_list: MapList,
+    data_off: u32,
+    file_size: u32,
+}
+
+/// Packs the sections of `dex` one after another, in the usual order.
+fn plan(dex: &DexModel) -> Layout {
+    let mut map_items = vec![(TypeCode::TypeHeaderItem, 1, 0)];
+    let mut offset = HEADER_SIZE;
+    let id_counts = [
+        dex.string_ids.len(),
+        dex.type_ids.len(),
+        dex.proto_ids.len(),
+        dex.field_ids.len(),
+        dex.method_ids.len(),
+        dex.class_defs.len(),
+        dex.call_site_ids.len(),
+        dex.method_handles.len(),
+    ];
+    for ((type_code, item_size), count) in ID_SECTIONS.iter().zip(id_counts) {
+        if count > 0 {
+            map_items.push((*type_code, count, offset));
+            offset += item_size * count as u32;
+        }
+    }
+    let data_off = offset;
+
+    let mut offsets = ItemOffsets::default();
+    for (type_code, alignment, sizes) in data_sections(dex) {
+        if sizes.is_empty() {
+            continue;
+        }
+        offset = align(offset, alignment);
+        map_items.push((type_code, sizes.len(), offset));
+        let (items, end) = walk(offset, alignment, &sizes);
+        offsets.sections.insert(type_code, items);
+        offset = end;
+    }
+
+    offset = align(offset, 4);
+    map_items.push((TypeCode::TypeMapList, 1, offset));
+    let map_list = MapList {
+        list: map_items
+            .into_iter()
+            .map(|(type_code, size, offset)| MapItem {
+                type_code,
+                unused: 0,
+                size: size as u32,
+                offset,
+            })
+            .collect(),
+    };
+    offset += map_list.size() as u32;
+
+    return Layout {
+        offsets,
+        map_list,
+        data_off,
+        file_size: offset,
+    };
+}
+
+/// Applies `f(type_code, offset)` to every nonzero offset stored in `dex`
+/// that points at an item of the data section `type_code`.
+pub(crate) fn rewrite_offsets(dex: &mut DexModel, f: &mut dyn FnMut(TypeCode, u32) -> u32) {
+    let mut rewrite = |type_code: TypeCode, offset: &mut u32| {
+        if *offset != 0 {
+            *offset = f(type_code, *offset);
+        }
+    };
+    for string_id in dex.string_ids.iter_mut() {
+        rewrite(TypeCode::TypeStringDataItem, &mut string_id.string_data_off);
+    }
+    for proto in dex.proto_ids.iter_mut() {
+        rewrite(TypeCode::TypeTypeList, &mut proto.parameters_off);
+    }
+    for class_def in dex.class_defs.iter_mut() {
+        rewrite(TypeCode::TypeTypeList, &mut class_def.interfaces_off);
+        rewrite(
+            TypeCode::TypeAnnotationsDirectoryItem,
+            &mut class_def.annotations_off,
+        );
+        rewrite(TypeCode::TypeClassDataItem, &mut class_def.class_data_off);
+        rewrite(
+            TypeCode::TypeEncodedArrayItem,
+            &mut class_def.static_values_off,
+        );
+    }
+    for call_site in dex.call_site_ids.iter_mut() {
+        rewrite(TypeCode::TypeEncodedArrayItem, &mut call_site.call_site_off);
+    }
+    for class_data in dex.class_data_items.iter_mut() {
+        let methods = class_data
+            .direct_methods
+            .iter_mut()
+            .chain(class_data.virtual_methods.iter_mut());
+        for method in methods {
+            rewrite(TypeCode::TypeCodeItem, &mut method.code_off);
+        }
+    }
+    for code_item in dex.code_items.iter_mut() {
+        rewrite(TypeCode::TypeDebugInfoItem, &mut code_item.debug_info_off);
+    }
+    for ref_list in dex.annotation_set_ref_lists.iter_mut() {
+        for item in ref_list.list.iter_mut() {
+            rewrite(TypeCode::TypeAnnotationSetItem, &mut item.annotations_off);
+        }
+    }
+    for set in dex.annotation_set_items.iter_mut() {
+        for entry in set.entries.iter_mut() {
+            rewrite(TypeCode::TypeAnnotationItem, &mut entry.annotation_off);
+        }
+    }
+    for directory in dex.annotations_directory_items.iter_mut() {
+        rewrite(
+            TypeCode::TypeAnnotationSetItem,
+            &mut directory.class_annotations_off,
+        );
+        for field in directory.field_annotations.iter_mut() {
+            rewrite(TypeCode::TypeAnnotationSetItem, &mut field.annotations_off);
+        }
+        for method in directory.method_annotations.iter_mut() {
+            rewrite(TypeCode::TypeAnnotationSetItem, &mut method.annotations_off);
+        }
+        for parameter in directory.parameter_annotations.iter_mut() {
+            rewrite(
+                TypeCode::TypeAnnotationSetRefList,
+                &mut parameter.annotations_off,
+            );
+        }
+    }
+}
+
+/// Adler-32 of `data`, as stored in the dex header checksum.
+pub(crate) fn adler32(data: &[u8]) -> u32 {
+    const MOD_ADLER: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    // The largest run that cannot overflow `b` before reducing.
+    for chunk in data.chunks(5552) {
+        for byte in chunk {
+            a += *byte as u32;
+            b += a;
+        }
+        a %= MOD_ADLER;
+        b %= MOD_ADLER;
+    }
+    return (b << 16) | a;
+}
+
+/// Computes the SHA-1 signature and Adler-32 checksum of an encoded dex file
+/// and stores them in its header.
+pub fn update_checksums(bytes: &mut [u8]) {
+    let signature = Sha1::digest(&bytes[32..]);
+    bytes[12..32].copy_from_slice(&signature);
+    let checksum = adler32(&bytes[12..]);
+    bytes[8..12].copy_from_slice(&checksum.to_le_bytes());
+}
+
+/// Lays `dex` out from scratch: every section is packed in the usual order,
+/// the offsets stored in items are updated to follow, and the map list,
+/// header and checksums are rebuilt. Link data is dropped.
+pub fn relayout(dex: &mut DexModel) {
+    let offsets = ItemOffsets::of(dex);
+    relayout_from(dex, offsets);
+}
+
+/// Like `relayout`, for a model whose stored offsets refer to the items at
+/// `current` rather than to its map list, e.g. one assembled from several
+/// files. Offsets that match no item are cleared.
+pub(crate) fn relayout_from(dex: &mut DexModel, mut current: ItemOffsets) {
+    // Rewriting `code_off` can resize class data, so repeat until the
+    // layout is stable; the second pass only moves what follows class data.
+    let layout = loop {
+        let layout = plan(dex);
+        if layout.offsets == current {
+            break layout;
+        }
+        let index = current.index();
+        let target = &layout.offsets;
+        rewrite_offsets(dex, &mut |type_code, offset| {
+            return index
+                .get(&(type_code, offset))
+                .map_or(0, |i| target.get(type_code)[*i]);
+        });
+        current = layout.offsets;
+    };
+
+    let header = &mut dex.header;
+    let section = |type_code| layout.map_list.get(type_code);
+    let id = |type_code| section(type_code).map_or((0, 0), |item| (item.size, item.offset));
+    (header.string_ids_size, header.string_ids_off) = id(TypeCode::TypeStringIdItem);
+    (header.type_ids_size, header.type_ids_off) = id(TypeCode::TypeTypeIdItem);
+    (header.proto_ids_size, header.proto_ids_off) = id(TypeCode::TypeProtoIdItem);
+    (header.field_ids_size, header.field_ids_off) = id(TypeCode::TypeFieldIdItem);
+    (header.method_ids_size, header.method_ids_off) = id(TypeCode::TypeMethodIdItem);
+    (header.class_defs_size, header.class_defs_off) = id(TypeCode::TypeClassDefItem);
+    header.map_off = section(TypeCode::TypeMapList).unwrap().offset;
+    header.header_size = HEADER_SIZE;
+    header.link_size = 0;
+    header.link_off = 0;
+    header.data_off = layout.data_off;
+    header.data_size = layout.file_size - layout.data_off;
+    header.file_size = layout.file_size;
+    dex.link_data.clear();
+    dex.map_list = layout.map_list;
+
+    let mut bytes = serialize_model(dex);
+    update_checksums(&mut bytes);
+    dex.header.checksum = u32::from_le_bytes(bytes[8..12].try_into().unwrap());
+    dex.header.signature = bytes[12..32].try_into().unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        deserialize_vec,
+        resolver::DexResolver,
+        serialize,
+        test_utils::{build_dex, TestClass},
+    };
+
+    #[test]
+    fn test_relayout_round_trip() {
+        let mut dex = build_dex(
+            vec![TestClass::new("La/Main;")
+                .field("count", "I")
+                .method("run", &["J"], "V", &[0x000e])
+                .implements("Ljava/lang/Runnable;")],
+            &["zzz"],
+        );
+        // Drop the last string and let the layout close the gap.
+        let last = DexResolver::new(&dex)
+            .strings()
+            .iter()
+            .position(|s| s == "zzz")
+            .unwrap();
+        let mut current = ItemOffsets::of(&dex);
+        dex.string_ids.remove(last);
+        dex.string_data_items.remove(last);
+        current
+            .sections
+            .get_mut(&TypeCode::TypeStringDataItem)
+            .unwrap()
+            .remove(last);
+        relayout_from(&mut dex, current);
+        let offsets = ItemOffsets::of(&dex);
+
+        let bytes = serialize(dex);
+        assert_eq!(
+            bytes.len(),
+            u32::from_le_bytes(bytes[32..36].try_into().unwrap()) as usize
+        );
+        assert_eq!(
+            u32::from_le_bytes(bytes[8..12].try_into().unwrap()),
+            adler32(&bytes[12..])
+        );
+        let mut dex = deserialize_vec(bytes).unwrap();
+        {
+            let resolver = DexResolver::new(&dex);
+            assert!(resolver.strings().iter().all(|s| s != "zzz"));
+            let class = resolver.class_def_by_descriptor("La/Main;").unwrap();
+            assert_eq!(
+                resolver.type_list_descriptors(class.interfaces_off),
+                vec!["Ljava/lang/Runnable;"]
+            );
+            let methods = resolver.class_methods(class);
+            assert_eq!(
+                resolver.method_ref(methods[0].0).signature(),
+                "La/Main;->run(J)V"
+            );
+            assert!(resolver.code_item(methods[0].1.code_off).is_some());
+        }
+        // A packed file is already laid out.
+        relayout(&mut dex);
+        assert_eq!(ItemOffsets::of(&dex), offsets);
+    }
+
+    #[test]
+    fn test_adler32() {
+        assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+    }
+}
@@ -1,9 +1,9 @@
 use std::{
     array::TryFromSliceError,
     fmt::Debug,
-    fs::File,
-    io::{self, Cursor, Read},
-    os::unix::prelude::MetadataExt,
+    fs,
+    io::{self, Cursor, Read, Write},
+    path::Path,
 };
 
 use decode::decode_u8;
@@ -22,12 +22,50 @@ use jemallocator::Jemalloc;
 #[global_allocator]
 static GLOBAL: Jemalloc = Jemalloc;
 
+pub mod aab;
+pub mod annotations;
+pub mod apk;
+pub mod arsc;
+pub mod axml;
+pub mod compact;
+mod const_tracker;
+pub mod dangerous_apis;
+pub mod debug_info;
 mod decode;
+pub mod der;
+pub mod dex_file;
 pub mod dex_model;
 pub mod dex_structs;
+pub mod diff;
+pub mod disasm;
+pub mod doctor;
+pub mod edit;
 mod encode;
 mod encoded_value_utils;
+pub mod findings;
+pub mod info;
 mod instructions;
+pub mod instrument;
+pub mod kotlin_metadata;
+pub mod layout;
+pub mod manifest;
+pub mod mapping;
+pub mod merge;
+pub mod opcodes;
+pub mod package_tree;
+#[cfg(feature = "parallel")]
+mod parallel;
+pub mod protobuf;
+pub mod ref_counts;
+pub mod reflection;
+mod remap;
+pub mod resolver;
+pub mod signing;
+pub mod size_attribution;
+pub mod split;
+pub mod static_values;
+#[cfg(test)]
+mod test_utils;
 
 #[allow(non_camel_case_types)]
 type uleb128 = u32;
@@ -57,7 +95,7 @@ impl From<TryFromSliceError> for DeserializeError {
 
 fn deserialize_dex_section<T: DexStruct>(
     map_item: &MapItem,
-    cursor: &mut Cursor<Vec<u8>>,
+    cursor: &mut Cursor<impl AsRef<[u8]>>,
 ) -> Result<Vec<T>, DeserializeError> {
     let MapItem { size, offset, .. } = map_item;
     let mut items: Vec<T> = vec![];
@@ -74,12 +112,56 @@ fn deserialize_dex_section<T: DexStruct>(
     return Ok(items);
 }
 
-pub fn deserialize(filepath: String) -> Result<DexModel, DeserializeError> {
-    let mut file = File::open(filepath)?;
-    let file_size = file.metadata().unwrap().size();
-    let mut bv: Vec<u8> = Vec::with_capacity(file_size as usize);
-    file.read_to_end(&mut bv).unwrap();
+pub fn deserialize<P: AsRef<Path>>(filepath: P) -> Result<DexModel, DeserializeError> {
+    return deserialize_vec(fs::read(filepath)?);
+}
+
+impl DexModel {
+    /// Decodes a dex file held in memory, e.g. a ZIP entry or a network
+    /// buffer.
+    pub fn from_bytes(bytes: &[u8]) -> Result<DexModel, DeserializeError> {
+        return deserialize_vec(bytes.to_vec());
+    }
+
+    pub fn from_reader<R: Read>(mut reader: R) -> Result<DexModel, DeserializeError> {
+        let mut bv = vec![];
+        reader.read_to_end(&mut bv)?;
+        return deserialize_vec(bv);
+    }
+
+    /// Writes the encoded dex file to `writer`.
+    pub fn serialize_into<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        return writer.write_all(&serialize_model(self));
+    }
+}
+
+/// Sections whose item offsets can be collected from the items that refer
+/// to them, so they can be decoded concurrently once those are decoded.
+const DEFERRED_SECTIONS: [TypeCode; 4] = [
+    TypeCode::TypeStringDataItem,
+    TypeCode::TypeClassDataItem,
+    TypeCode::TypeCodeItem,
+    TypeCode::TypeDebugInfoItem,
+];
+
+/// Decodes a dex file that is already in memory, e.g. an APK entry.
+pub(crate) fn deserialize_vec(bv: Vec<u8>) -> Result<DexModel, DeserializeError> {
+    return deserialize_sections(bv, cfg!(feature = "parallel"));
+}
+
+/// Decodes `bytes` serially or with the deferred sections in parallel, so
+/// the benchmarks can compare both on the same build.
+#[cfg(feature = "parallel")]
+#[doc(hidden)]
+pub fn deserialize_with(bytes: &[u8], parallel: bool) -> Result<DexModel, DeserializeError> {
+    return deserialize_sections(bytes.to_vec(), parallel);
+}
+
+/// Decodes every section, leaving the `DEFERRED_SECTIONS` to the `parallel`
+/// module if `parallel` is set.
+fn deserialize_sections(bv: Vec<u8>, parallel: bool) -> Result<DexModel, DeserializeError> {
     let mut cursor = Cursor::new(bv);
+    let mut deferred = vec![];
 
     let mut dex_model_builder = DexModelBuilder::new();
 
@@ -90,6 +172,10 @@ pub fn deserialize(filepath: String) -> Result<DexModel, DeserializeError> {
     let map_list = MapList::deserialize(&mut cursor);
 
     for map_item in map_list.list.iter() {
+        if parallel && DEFERRED_SECTIONS.contains(&map_item.type_code) {
+            deferred.push(*map_item);
+            continue;
+        }
         match map_item.type_code {
             TypeCode::TypeHeaderItem | TypeCode::TypeMapList => {
                 // These structs were parsed earlier, no need to redo them.
@@ -213,13 +299,16 @@ pub fn deserialize(filepath: String) -> Result<DexModel, DeserializeError> {
 
     dex_model_builder.set_map_list(map_list);
 
+    let bv = cursor.into_inner();
     dex_model_builder.set_link_data(
-        cursor.into_inner()
-            [(header.link_off as usize)..(header.link_off as usize + header.link_size as usize)]
+        bv[(header.link_off as usize)..(header.link_off as usize + header.link_size as usize)]
             .to_vec(),
     );
 
-    return Ok(dex_model_builder.build());
+    let dex = dex_model_builder.build();
+    #[cfg(feature = "parallel")]
+    let dex = parallel::deserialize_deferred(dex, &deferred, &bv)?;
+    return Ok(dex);
 }
 
 fn serialize_dex_section<T: DexStruct>(
@@ -241,6 +330,10 @@ fn serialize_dex_section<T: DexStruct>(
 }
 
 pub fn serialize(dex: DexModel) -> Vec<u8> {
+    return serialize_model(&dex);
+}
+
+pub(crate) fn serialize_model(dex: &DexModel) -> Vec<u8> {
     let mut cursor = Cursor::new(vec![0u8; dex.header.file_size as usize]);
     dex.header.serialize(&mut cursor);
 
@@ -358,6 +451,7 @@ pub fn serialize(dex: DexModel) -> Vec<u8> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_utils::{build_dex, TestClass};
 
     #[test]
     fn test() {
@@ -368,4 +462,20 @@ mod tests {
             unreachable!()
         }
     }
+
+    #[test]
+    fn test_bytes_and_readers() {
+        let dex = build_dex(
+            vec![TestClass::new("La/A;").method("run", &[], "V", &[0x000e])],
+            &[],
+        );
+        let mut bytes = vec![];
+        dex.serialize_into(&mut bytes).unwrap();
+        assert_eq!(bytes, serialize(dex));
+
+        let from_bytes = DexModel::from_bytes(&bytes).unwrap();
+        let from_reader = DexModel::from_reader(Cursor::new(bytes.clone())).unwrap();
+        assert_eq!(serialize(from_bytes), bytes);
+        assert_eq!(serialize(from_reader), bytes);
+    }
 }
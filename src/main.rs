@@ -0,0 +1,573 @@
+//! `apkdoctor` command-line tool. Every subcommand accepts a `.dex` file or a
+//! zip archive holding `classesN.dex` entries (`.apk`, `.aab`, `.jar`).
+
+use std::{
+    fs,
+    io::{self, Read},
+    process::ExitCode,
+};
+
+use clap::{Args, Parser, Subcommand};
+use serde_json::json;
+
+use apkdoctor::{
+    aab::{Aab, BUNDLE_CONFIG},
+    apk::{load_dex_files, Apk},
+    dangerous_apis::{DangerousApiReport, RuleSet},
+    dex_model::DexModel,
+    diff::DexDiff,
+    disasm::disassemble,
+    doctor::{AppContext, Doctor, DoctorConfig, Severity},
+    findings::{to_junit, to_sarif, Baseline, Issue, RuleInfo},
+    info::{DexInfo, DexVerification},
+    kotlin_metadata::KotlinReport,
+    manifest::ManifestError,
+    mapping::Mapping,
+    resolver::DexResolver,
+    signing::SigningReport,
+    size_attribution::{SharedPolicy, SizeAttribution},
+    static_values::{build_configs_from_resolvers, ClassConstants},
+};
+
+#[derive(Parser)]
+#[command(name = "apkdoctor", version, about = "Inspect dex files and APKs")]
+struct Cli {
+    /// Print machine-readable JSON instead of text.
+    #[arg(long, global = true)]
+    json: bool,
+    /// Only analyze the dex files of this module of an `.aab`.
+    #[arg(long, global = true)]
+    module: Option<String>,
+    /// Report de-obfuscated names using this R8 or ProGuard mapping file.
+    #[arg(long, global = true)]
+    mapping: Option<String>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List the modules of an `.aab` and its bundle configuration.
+    Modules { input: String },
+    /// Summarize the header and map list of each dex file.
+    Info { input: String },
+    /// List the classes defined in each dex file.
+    Classes { input: String },
+    /// List the methods defined in each dex file.
+    Methods {
+        input: String,
+        /// Only list methods of this class, e.g. `com.example.Foo`.
+        #[arg(long)]
+        class: Option<String>,
+    },
+    /// List the string pool of each dex file.
+    Strings { input: String },
+    /// Disassemble a class (`com.Foo`) or method (`com.Foo.bar`).
+    Disasm { input: String, target: String },
+    /// Attribute dex bytes to classes and packages.
+    Size {
+        input: String,
+        /// Only show packages this many segments deep.
+        #[arg(long)]
+        depth: Option<usize>,
+        /// Split shared pool entries between the classes referencing them.
+        #[arg(long)]
+        apportion: bool,
+    },
+    /// Compare the classes, methods and strings of two inputs.
+    Diff { old: String, new: String },
+    /// Retrace an obfuscated stack trace read from a file, or from standard
+    /// input, with the file given by `--mapping`.
+    Retrace {
+        trace: Option<String>,
+        /// Disambiguate overloads with the line tables of this input's dex
+        /// files.
+        #[arg(long)]
+        input: Option<String>,
+    },
+    /// Print the initial values of the static fields of `BuildConfig`
+    /// classes, or of another class.
+    Constants {
+        input: String,
+        /// Print this class instead, e.g. `com.example.Config`.
+        #[arg(long)]
+        class: Option<String>,
+    },
+    /// Decode Kotlin metadata and count compiler-generated classes per package.
+    Kotlin {
+        input: String,
+        /// Print the declarations of this class, e.g. `com.example.Foo`.
+        #[arg(long)]
+        class: Option<String>,
+    },
+    /// Check dex checksums and signatures, and APK signing digests.
+    Verify { input: String },
+    /// Report uses of dangerous APIs.
+    Lint {
+        input: String,
+        /// Additional rules in TOML or JSON.
+        #[arg(long)]
+        rules: Option<String>,
+        #[command(flatten)]
+        ci: CiArgs,
+    },
+    /// Run every check and print a scored report with remediation advice.
+    /// Exits non-zero when a high severity problem is found.
+    Doctor {
+        input: String,
+        /// Check settings in TOML or JSON.
+        #[arg(long)]
+        config: Option<String>,
+        #[command(flatten)]
+        ci: CiArgs,
+    },
+}
+
+/// Options of the subcommands whose findings feed CI systems.
+#[derive(Args)]
+struct CiArgs {
+    /// Ignore the findings listed in this baseline file.
+    #[arg(long)]
+    baseline: Option<String>,
+    /// Write a baseline of every current finding to this file.
+    #[arg(long)]
+    write_baseline: Option<String>,
+    /// Also write the findings as SARIF 2.1.0 to this file.
+    #[arg(long)]
+    sarif: Option<String>,
+    /// Also write the findings as JUnit XML to this file.
+    #[arg(long)]
+    junit: Option<String>,
+}
+
+impl CiArgs {
+    fn baseline(&self) -> Result<Option<Baseline>, String> {
+        return match &self.baseline {
+            Some(path) => Baseline::from_file(path)
+                .map(Some)
+                .map_err(|err| format!("{}: {}", path, err)),
+            None => Ok(None),
+        };
+    }
+
+    fn write(
+        &self,
+        rules: &[RuleInfo],
+        issues: &[Issue],
+        suppressed: &[Issue],
+    ) -> Result<(), String> {
+        let write = |path: &String, contents: String| {
+            return fs::write(path, contents).map_err(|err| format!("{}: {}", path, err));
+        };
+        if let Some(path) = &self.write_baseline {
+            let all = issues.iter().chain(suppressed).cloned().collect::<Vec<_>>();
+            write(path, Baseline::from_issues(&all).to_json())?;
+        }
+        if let Some(path) = &self.sarif {
+            let sarif = to_sarif(rules, issues, suppressed);
+            write(path, serde_json::to_string_pretty(&sarif).unwrap())?;
+        }
+        if let Some(path) = &self.junit {
+            write(path, to_junit(rules, issues, suppressed))?;
+        }
+        return Ok(());
+    }
+}
+
+/// Raw bytes of every dex file in `path`, or in `module` of an `.aab`, in
+/// multidex order.
+fn dex_entries(
+    path: &str,
+    bytes: Vec<u8>,
+    module: Option<&str>,
+) -> Result<Vec<(String, Vec<u8>)>, String> {
+    if bytes.starts_with(b"dex\n") && module.is_none() {
+        let name = path.rsplit('/').next().unwrap_or(path).to_string();
+        return Ok(vec![(name, bytes)]);
+    }
+    let apk = Apk::from_bytes(bytes).map_err(|err| format!("{}: {}", path, err))?;
+    let mut aab = Aab::from_apk(apk);
+    let names = match module {
+        Some(module) => {
+            aab.check_module(module)
+                .map_err(|err| format!("{}: {}", path, err))?;
+            aab.dex_entry_names(module)
+        }
+        None if aab.archive().has_entry(BUNDLE_CONFIG) => aab.all_dex_entry_names(),
+        None => aab.archive().dex_entry_names(),
+    };
+    let mut entries = vec![];
+    for name in names {
+        let bytes = aab
+            .archive()
+            .read_entry(&name)
+            .map_err(|err| err.to_string())?;
+        entries.push((name, bytes));
+    }
+    return Ok(entries);
+}
+
+fn load(path: &str, module: Option<&str>) -> Result<Vec<(String, DexModel)>, String> {
+    let dexes = match module {
+        Some(module) => Aab::open(path).and_then(|mut aab| aab.dex_files(module)),
+        None => load_dex_files(path),
+    };
+    return dexes.map_err(|err| format!("{}: {}", path, err));
+}
+
+/// Attaches `mapping`, if any, to a resolver for each dex file.
+fn resolvers<'a>(
+    dexes: &'a [(String, DexModel)],
+    mapping: Option<&'a Mapping>,
+) -> Vec<(String, DexResolver<'a>)> {
+    return dexes
+        .iter()
+        .map(|(name, dex)| {
+            let resolver = DexResolver::new(dex);
+            let resolver = match mapping {
+                Some(mapping) => resolver.with_mapping(mapping),
+                None => resolver,
+            };
+            (name.clone(), resolver)
+        })
+        .collect();
+}
+
+fn print_json(value: &serde_json::Value) {
+    println!("{}", serde_json::to_string_pretty(value).unwrap());
+}
+
+fn run(cli: Cli) -> Result<ExitCode, String> {
+    let module = cli.module.as_deref();
+    let loaded_mapping = match &cli.mapping {
+        Some(path) => Some(Mapping::from_file(path).map_err(|err| format!("{}: {}", path, err))?),
+        None => None,
+    };
+    let mapping = loaded_mapping.as_ref();
+    match cli.command {
+        Command::Modules { input } => {
+            let mut aab = Aab::open(&input).map_err(|err| format!("{}: {}", input, err))?;
+            let config = aab.bundle_config().map_err(|err| err.to_string())?;
+            let mut modules = vec![];
+            for name in aab.modules() {
+                let dex_files = aab.dex_files(&name).map_err(|err| err.to_string())?.len();
+                let manifest = aab.manifest(&name).map_err(|err| err.to_string())?;
+                if !cli.json {
+                    println!(
+                        "{}: {} dex file(s), package {}",
+                        name, dex_files, manifest.package
+                    );
+                }
+                modules.push(json!({
+                    "module": name,
+                    "dex_files": dex_files,
+                    "manifest": manifest,
+                }));
+            }
+            if cli.json {
+                print_json(&json!({ "bundle_config": config, "modules": modules }));
+            } else {
+                print!("{}", config);
+            }
+        }
+        Command::Info { input } => {
+            let infos = load(&input, module)?
+                .iter()
+                .map(|(name, dex)| DexInfo::new(name, dex))
+                .collect::<Vec<_>>();
+            if cli.json {
+                print_json(&json!(infos));
+            } else {
+                infos.iter().for_each(|info| print!("{}", info));
+            }
+        }
+        Command::Classes { input } => {
+            let dexes = load(&input, module)?;
+            let mut listing = vec![];
+            for (name, resolver) in resolvers(&dexes, mapping).iter() {
+                let classes = resolver
+                    .dex
+                    .class_defs
+                    .iter()
+                    .map(|class_def| resolver.type_name(class_def.class_idx))
+                    .collect::<Vec<_>>();
+                listing.push(json!({ "dex": name, "classes": classes }));
+                if !cli.json {
+                    classes.iter().for_each(|class| println!("{}", class));
+                }
+            }
+            if cli.json {
+                print_json(&json!(listing));
+            }
+        }
+        Command::Methods { input, class } => {
+            let dexes = load(&input, module)?;
+            let mut listing = vec![];
+            for (name, resolver) in resolvers(&dexes, mapping).iter() {
+                let mut methods = vec![];
+                for class_def in resolver.dex.class_defs.iter() {
+                    let class_name = resolver.type_name(class_def.class_idx);
+                    if class.as_ref().is_some_and(|class| *class != class_name) {
+                        continue;
+                    }
+                    for (method_idx, _) in resolver.class_methods(class_def) {
+                        methods.push(match mapping {
+                            Some(_) => resolver.method_display(method_idx),
+                            None => resolver.method_ref(method_idx).signature(),
+                        });
+                    }
+                }
+                if !cli.json {
+                    methods.iter().for_each(|method| println!("{}", method));
+                }
+                listing.push(json!({ "dex": name, "methods": methods }));
+            }
+            if cli.json {
+                print_json(&json!(listing));
+            }
+        }
+        Command::Strings { input } => {
+            let dexes = load(&input, module)?;
+            let mut listing = vec![];
+            for (name, dex) in dexes.iter() {
+                let resolver = DexResolver::new(dex);
+                if !cli.json {
+                    resolver
+                        .strings()
+                        .iter()
+                        .for_each(|string| println!("{:?}", string));
+                }
+                listing.push(json!({ "dex": name, "strings": resolver.strings() }));
+            }
+            if cli.json {
+                print_json(&json!(listing));
+            }
+        }
+        Command::Disasm { input, target } => {
+            let dexes = load(&input, module)?;
+            let mut listings = vec![];
+            for (name, resolver) in resolvers(&dexes, mapping).iter() {
+                if let Some(text) = disassemble(resolver, &target) {
+                    listings.push(json!({ "dex": name, "disassembly": text }));
+                    if !cli.json {
+                        print!("{}", text);
+                    }
+                }
+            }
+            if listings.is_empty() {
+                return Err(format!("{}: no class or method named {}", input, target));
+            }
+            if cli.json {
+                print_json(&json!(listings));
+            }
+        }
+        Command::Size {
+            input,
+            depth,
+            apportion,
+        } => {
+            let policy = if apportion {
+                SharedPolicy::Apportion
+            } else {
+                SharedPolicy::Shared
+            };
+            let dexes = load(&input, module)?;
+            let reports = resolvers(&dexes, mapping)
+                .iter()
+                .map(|(name, resolver)| SizeAttribution::from_resolver(name, resolver, policy))
+                .collect::<Vec<_>>();
+            if cli.json {
+                print_json(&json!(reports));
+            } else {
+                reports
+                    .iter()
+                    .for_each(|report| print!("{}", report.to_text(depth)));
+            }
+        }
+        Command::Diff { old, new } => {
+            let (old, new) = (load(&old, module)?, load(&new, module)?);
+            let diff =
+                DexDiff::from_resolvers(&resolvers(&old, mapping), &resolvers(&new, mapping));
+            if cli.json {
+                println!("{}", diff.to_json());
+            } else {
+                print!("{}", diff);
+            }
+        }
+        Command::Retrace { trace, input } => {
+            let mapping = mapping.ok_or("retrace needs a mapping file, given with --mapping")?;
+            let text = match &trace {
+                Some(path) => {
+                    fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?
+                }
+                None => {
+                    let mut text = String::new();
+                    io::stdin()
+                        .read_to_string(&mut text)
+                        .map_err(|err| err.to_string())?;
+                    text
+                }
+            };
+            let retraced = match &input {
+                Some(input) => {
+                    let dexes = load(input, module)?;
+                    let resolvers = dexes
+                        .iter()
+                        .map(|(_, dex)| DexResolver::new(dex))
+                        .collect::<Vec<_>>();
+                    mapping.retrace_stack_trace_with_dex(&text, &resolvers)
+                }
+                None => mapping.retrace_stack_trace(&text),
+            };
+            if cli.json {
+                print_json(&json!({ "retraced": retraced }));
+            } else {
+                print!("{}", retraced);
+            }
+        }
+        Command::Constants { input, class } => {
+            let dexes = load(&input, module)?;
+            let resolvers = resolvers(&dexes, mapping);
+            let constants = match &class {
+                Some(class) => resolvers
+                    .iter()
+                    .filter_map(|(name, resolver)| {
+                        let class_def = resolver.class_def_by_name(class)?;
+                        Some(ClassConstants::new(name, resolver, class_def))
+                    })
+                    .collect::<Vec<_>>(),
+                None => build_configs_from_resolvers(&resolvers),
+            };
+            if let (Some(class), true) = (&class, constants.is_empty()) {
+                return Err(format!("{}: no class named {}", input, class));
+            }
+            if cli.json {
+                print_json(&json!(constants));
+            } else {
+                constants.iter().for_each(|c| print!("{}", c));
+            }
+        }
+        Command::Kotlin { input, class } => {
+            let dexes = load(&input, module)?;
+            let report = KotlinReport::from_resolvers(&resolvers(&dexes, mapping));
+            if let Some(class) = class {
+                let metadata = report
+                    .class(&class)
+                    .ok_or_else(|| format!("{}: no Kotlin metadata for {}", input, class))?;
+                if cli.json {
+                    print_json(&json!(metadata));
+                } else {
+                    print!("{}", metadata);
+                }
+            } else if cli.json {
+                println!("{}", report.to_json());
+            } else {
+                print!("{}", report);
+            }
+        }
+        Command::Verify { input } => {
+            let bytes = fs::read(&input).map_err(|err| format!("{}: {}", input, err))?;
+            // Archives without an APK signing block still get a v1 check.
+            let signing = match bytes.starts_with(b"dex\n") {
+                true => None,
+                false => {
+                    let idsig = fs::read(format!("{}.idsig", input)).ok();
+                    Some(
+                        SigningReport::inspect(&bytes, idsig.as_deref())
+                            .map_err(|err| format!("{}: {}", input, err))?,
+                    )
+                }
+            };
+            let verifications = dex_entries(&input, bytes, module)?
+                .iter()
+                .map(|(name, bytes)| DexVerification::new(name, bytes))
+                .collect::<Vec<_>>();
+            let mut ok = verifications.iter().all(|v| v.is_ok());
+            if let Some(report) = signing.as_ref() {
+                ok &= report.is_verified();
+            }
+            if cli.json {
+                print_json(&json!({ "dex": verifications, "signing": signing }));
+            } else {
+                verifications.iter().for_each(|v| print!("{}", v));
+                if let Some(report) = signing {
+                    print!("{}", report);
+                }
+            }
+            if !ok {
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+        Command::Lint { input, rules, ci } => {
+            let mut rule_set = RuleSet::builtin();
+            if let Some(path) = rules {
+                rule_set
+                    .extend(RuleSet::from_file(&path).map_err(|err| format!("{}: {}", path, err))?);
+            }
+            let dexes = load(&input, module)?;
+            let mut report =
+                DangerousApiReport::from_resolvers(&resolvers(&dexes, mapping), &rule_set);
+            if let Some(baseline) = ci.baseline()? {
+                report.apply_baseline(&baseline);
+            }
+            let rules = rule_set
+                .rules
+                .iter()
+                .map(RuleInfo::from)
+                .collect::<Vec<_>>();
+            ci.write(&rules, &report.issues(), &report.suppressed_issues())?;
+            if cli.json {
+                println!("{}", report.to_json());
+            } else {
+                print!("{}", report);
+            }
+        }
+        Command::Doctor { input, config, ci } => {
+            let mut doctor = Doctor::new();
+            if let Some(path) = config {
+                DoctorConfig::from_file(&path)
+                    .and_then(|config| doctor.configure(config))
+                    .map_err(|err| format!("{}: {}", path, err))?;
+            }
+            let context = match module {
+                Some(module) => Aab::open(&input)
+                    .map_err(ManifestError::from)
+                    .and_then(|aab| AppContext::from_aab_module(aab, module)),
+                None => AppContext::open(&input),
+            };
+            let context = context.map_err(|err| format!("{}: {}", input, err))?;
+            let context = match loaded_mapping {
+                Some(mapping) => context.with_mapping(mapping),
+                None => context,
+            };
+            let mut report = doctor.run(&context);
+            if let Some(baseline) = ci.baseline()? {
+                report.apply_baseline(&baseline);
+            }
+            ci.write(
+                &report.rules(),
+                &report.issues(),
+                &report.suppressed_issues(),
+            )?;
+            if cli.json {
+                println!("{}", report.to_json());
+            } else {
+                print!("{}", report);
+            }
+            if report.findings.iter().any(|f| f.severity == Severity::High) {
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+    }
+    return Ok(ExitCode::SUCCESS);
+}
+
+fn main() -> ExitCode {
+    return match run(Cli::parse()) {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::FAILURE
+        }
+    };
+}
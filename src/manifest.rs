@@ -0,0 +1,398 @@
+use std::{
+    collections::HashSet,
+    fmt::{self, Display, Formatter},
+};
+
+use serde::Serialize;
+
+use crate::{
+    apk::{Apk, ApkError},
+    axml::{self, AxmlError, XmlDocument, XmlElement},
+    dex_model::DexModel,
+    resolver::{descriptor_to_java, DexResolver},
+};
+
+#[derive(Debug)]
+pub enum ManifestError {
+    ApkError(ApkError),
+    AxmlError(AxmlError),
+    /// The document is well-formed but is not an Android manifest.
+    InvalidManifest(String),
+}
+
+impl From<ApkError> for ManifestError {
+    fn from(err: ApkError) -> Self {
+        ManifestError::ApkError(err)
+    }
+}
+
+impl From<AxmlError> for ManifestError {
+    fn from(err: AxmlError) -> Self {
+        ManifestError::AxmlError(err)
+    }
+}
+
+impl Display for ManifestError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            ManifestError::ApkError(err) => write!(f, "{}", err),
+            ManifestError::AxmlError(err) => write!(f, "{}", err),
+            ManifestError::InvalidManifest(reason) => write!(f, "invalid manifest: {}", reason),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ComponentKind {
+    Activity,
+    ActivityAlias,
+    Service,
+    Receiver,
+    Provider,
+}
+
+impl ComponentKind {
+    fn from_tag(tag: &str) -> Option<ComponentKind> {
+        return match tag {
+            "activity" => Some(ComponentKind::Activity),
+            "activity-alias" => Some(ComponentKind::ActivityAlias),
+            "service" => Some(ComponentKind::Service),
+            "receiver" => Some(ComponentKind::Receiver),
+            "provider" => Some(ComponentKind::Provider),
+            _ => None,
+        };
+    }
+
+    /// The manifest tag declaring the component.
+    pub fn tag(&self) -> &'static str {
+        return match self {
+            ComponentKind::Activity => "activity",
+            ComponentKind::ActivityAlias => "activity-alias",
+            ComponentKind::Service => "service",
+            ComponentKind::Receiver => "receiver",
+            ComponentKind::Provider => "provider",
+        };
+    }
+}
+
+/// One `<data>` element of an intent filter.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct IntentData {
+    pub scheme: Option<String>,
+    pub host: Option<String>,
+    pub port: Option<String>,
+    pub path: Option<String>,
+    pub path_prefix: Option<String>,
+    pub path_pattern: Option<String>,
+    pub mime_type: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct IntentFilter {
+    pub actions: Vec<String>,
+    pub categories: Vec<String>,
+    pub data: Vec<IntentData>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Component {
+    pub kind: ComponentKind,
+    /// Fully qualified class name (the alias name for activity aliases).
+    pub name: String,
+    /// The `android:exported` attribute, when present.
+    pub exported: Option<bool>,
+    pub enabled: Option<bool>,
+    pub permission: Option<String>,
+    /// Fully qualified target of an activity alias.
+    pub target_activity: Option<String>,
+    /// Authorities of a content provider.
+    pub authorities: Option<String>,
+    pub intent_filters: Vec<IntentFilter>,
+}
+
+impl Component {
+    /// Whether other apps can start the component: the explicit
+    /// `android:exported` value, else whether it declares intent filters
+    /// (the platform default before Android 12).
+    pub fn is_exported(&self) -> bool {
+        return self.exported.unwrap_or(!self.intent_filters.is_empty());
+    }
+}
+
+/// Typed view of `AndroidManifest.xml`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Manifest {
+    pub package: String,
+    pub version_code: Option<i64>,
+    pub version_name: Option<String>,
+    pub min_sdk: Option<i64>,
+    pub target_sdk: Option<i64>,
+    pub compile_sdk: Option<i64>,
+    /// Permissions requested with `<uses-permission>`.
+    pub permissions: Vec<String>,
+    /// Permissions defined with `<permission>`.
+    pub declared_permissions: Vec<String>,
+    /// Fully qualified `Application` subclass, if any.
+    pub application_class: Option<String>,
+    /// The application label, a reference such as `@0x7f0e001b` when it
+    /// comes from resources; see `ResourceTable::resolve_str`.
+    pub label: Option<String>,
+    pub debuggable: Option<bool>,
+    pub allow_backup: Option<bool>,
+    pub uses_cleartext_traffic: Option<bool>,
+    pub components: Vec<Component>,
+}
+
+/// Resolves a manifest class name relative to the package: `.Foo` and `Foo`
+/// both become `<package>.Foo`.
+pub fn qualify_class_name(package: &str, name: &str) -> String {
+    if name.starts_with('.') {
+        return format!("{}{}", package, name);
+    }
+    if !name.contains('.') {
+        return format!("{}.{}", package, name);
+    }
+    return name.to_string();
+}
+
+fn intent_filter(element: &XmlElement) -> IntentFilter {
+    let names = |tag: &'static str| {
+        element
+            .children_named(tag)
+            .filter_map(|c| c.android_string("name"))
+            .collect::<Vec<_>>()
+    };
+    return IntentFilter {
+        actions: names("action"),
+        categories: names("category"),
+        data: element
+            .children_named("data")
+            .map(|data| IntentData {
+                scheme: data.android_string("scheme"),
+                host: data.android_string("host"),
+                port: data.android_string("port"),
+                path: data.android_string("path"),
+                path_prefix: data.android_string("pathPrefix"),
+                path_pattern: data.android_string("pathPattern"),
+                mime_type: data.android_string("mimeType"),
+            })
+            .collect(),
+    };
+}
+
+impl Manifest {
+    /// Decodes a binary `AndroidManifest.xml`.
+    pub fn parse(bytes: &[u8]) -> Result<Manifest, ManifestError> {
+        return Manifest::from_document(&axml::parse(bytes)?);
+    }
+
+    pub fn from_apk(apk: &mut Apk) -> Result<Manifest, ManifestError> {
+        return Manifest::parse(&apk.read_entry("AndroidManifest.xml")?);
+    }
+
+    pub fn from_document(document: &XmlDocument) -> Result<Manifest, ManifestError> {
+        let root = &document.root;
+        if root.name != "manifest" {
+            return Err(ManifestError::InvalidManifest(format!(
+                "root element is <{}>",
+                root.name
+            )));
+        }
+        let package = root
+            .attribute("package")
+            .map(|v| v.to_string())
+            .unwrap_or_default();
+        let int = |element: &XmlElement, name: &str| {
+            element.android_attribute(name).and_then(|v| v.as_int())
+        };
+        let uses_sdk = root.children_named("uses-sdk").next();
+
+        let mut manifest = Manifest {
+            version_code: int(root, "versionCode"),
+            version_name: root.android_string("versionName"),
+            min_sdk: uses_sdk.and_then(|e| int(e, "minSdkVersion")),
+            target_sdk: uses_sdk.and_then(|e| int(e, "targetSdkVersion")),
+            compile_sdk: int(root, "compileSdkVersion"),
+            ..Default::default()
+        };
+        for tag in ["uses-permission", "uses-permission-sdk-23"] {
+            manifest.permissions.extend(
+                root.children_named(tag)
+                    .filter_map(|e| e.android_string("name")),
+            );
+        }
+        manifest.declared_permissions = root
+            .children_named("permission")
+            .filter_map(|e| e.android_string("name"))
+            .collect();
+
+        if let Some(application) = root.children_named("application").next() {
+            let bool = |name: &str| {
+                application
+                    .android_attribute(name)
+                    .and_then(|v| v.as_bool())
+            };
+            manifest.debuggable = bool("debuggable");
+            manifest.allow_backup = bool("allowBackup");
+            manifest.uses_cleartext_traffic = bool("usesCleartextTraffic");
+            manifest.label = application.android_string("label");
+            manifest.application_class = application
+                .android_string("name")
+                .map(|name| qualify_class_name(&package, &name));
+
+            for element in application.children.iter() {
+                let kind = match ComponentKind::from_tag(&element.name) {
+                    Some(kind) => kind,
+                    None => continue,
+                };
+                let name = match element.android_string("name") {
+                    Some(name) => qualify_class_name(&package, &name),
+                    None => continue,
+                };
+                manifest.components.push(Component {
+                    kind,
+                    name,
+                    exported: element
+                        .android_attribute("exported")
+                        .and_then(|v| v.as_bool()),
+                    enabled: element
+                        .android_attribute("enabled")
+                        .and_then(|v| v.as_bool()),
+                    permission: element.android_string("permission"),
+                    target_activity: element
+                        .android_string("targetActivity")
+                        .map(|name| qualify_class_name(&package, &name)),
+                    authorities: element.android_string("authorities"),
+                    intent_filters: element
+                        .children_named("intent-filter")
+                        .map(intent_filter)
+                        .collect(),
+                });
+            }
+        }
+        manifest.package = package;
+        return Ok(manifest);
+    }
+
+    /// Classes the manifest names: the application class and every component
+    /// except activity aliases, which name their target instead.
+    pub fn declared_classes(&self) -> Vec<&str> {
+        let mut classes = vec![];
+        classes.extend(self.application_class.as_deref());
+        for component in self.components.iter() {
+            match component.kind {
+                ComponentKind::ActivityAlias => {
+                    classes.extend(component.target_activity.as_deref())
+                }
+                _ => classes.push(&component.name),
+            }
+        }
+        return classes;
+    }
+
+    /// Declared classes that no dex file defines, which crash the app with a
+    /// `ClassNotFoundException` when the component is started.
+    pub fn missing_classes(&self, dexes: &[(String, DexModel)]) -> Vec<String> {
+        let mut defined = HashSet::new();
+        for (_, dex) in dexes {
+            let resolver = DexResolver::new(dex);
+            defined.extend(
+                dex.class_defs
+                    .iter()
+                    .map(|c| descriptor_to_java(resolver.type_descriptor(c.class_idx))),
+            );
+        }
+        let mut missing = vec![];
+        for class in self.declared_classes() {
+            if !defined.contains(class) && !missing.iter().any(|m| m == class) {
+                missing.push(class.to_string());
+            }
+        }
+        return missing;
+    }
+
+    pub fn to_json(&self) -> String {
+        return serde_json::to_string_pretty(self).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        axml::Value,
+        test_utils::{build_dex, AxmlBuilder, TestClass},
+    };
+
+    fn string(s: &str) -> Value {
+        return Value::String(s.to_string());
+    }
+
+    #[test]
+    fn test_manifest() {
+        let mut builder = AxmlBuilder::new();
+        builder
+            .start("manifest")
+            .attr("package", string("com.example"))
+            .android("versionCode", Value::Int(42))
+            .android("versionName", string("1.2"))
+            .android("compileSdkVersion", Value::Int(34))
+            .start("uses-sdk")
+            .android("minSdkVersion", Value::Int(21))
+            .android("targetSdkVersion", string("34"))
+            .end()
+            .start("uses-permission")
+            .android("name", string("android.permission.INTERNET"))
+            .end()
+            .start("application")
+            .android("name", string(".App"))
+            .start("activity")
+            .android("name", string(".MainActivity"))
+            .start("intent-filter")
+            .start("action")
+            .android("name", string("android.intent.action.MAIN"))
+            .end()
+            .start("data")
+            .android("scheme", string("https"))
+            .android("host", string("example.com"))
+            .end()
+            .end()
+            .end()
+            .start("service")
+            .android("name", string("com.vendor.Sync"))
+            .android("exported", Value::Boolean(false))
+            .end()
+            .end()
+            .end();
+        let manifest = Manifest::parse(&builder.build()).unwrap();
+
+        assert_eq!(manifest.package, "com.example");
+        assert_eq!(manifest.version_code, Some(42));
+        assert_eq!(manifest.version_name.as_deref(), Some("1.2"));
+        assert_eq!(manifest.min_sdk, Some(21));
+        assert_eq!(manifest.target_sdk, Some(34));
+        assert_eq!(manifest.compile_sdk, Some(34));
+        assert_eq!(manifest.permissions, vec!["android.permission.INTERNET"]);
+        assert_eq!(
+            manifest.application_class.as_deref(),
+            Some("com.example.App")
+        );
+
+        let activity = &manifest.components[0];
+        assert_eq!(activity.kind, ComponentKind::Activity);
+        assert_eq!(activity.name, "com.example.MainActivity");
+        assert!(activity.is_exported());
+        assert_eq!(
+            activity.intent_filters[0].data[0].host.as_deref(),
+            Some("example.com")
+        );
+        assert!(!manifest.components[1].is_exported());
+
+        let dex = build_dex(vec![TestClass::new("Lcom/example/MainActivity;")], &[]);
+        assert_eq!(
+            manifest.missing_classes(&[("classes.dex".to_string(), dex)]),
+            vec!["com.example.App", "com.vendor.Sync"]
+        );
+    }
+}
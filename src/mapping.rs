@@ -0,0 +1,813 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::{self, Display, Formatter},
+    fs, io,
+    path::Path,
+};
+
+use serde_json::Value;
+
+use crate::resolver::{descriptor_to_java, java_to_descriptor, DexResolver};
+
+/// ProGuard/R8 `mapping.txt` contents.
+#[derive(Debug, Default)]
+pub struct Mapping {
+    pub classes: Vec<ClassMapping>,
+    /// Metadata appearing before the first class, e.g. the map version.
+    pub metadata: Vec<MappingMetadata>,
+    by_obfuscated: HashMap<String, usize>,
+    by_original: HashMap<String, usize>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ClassMapping {
+    pub original: String,
+    pub obfuscated: String,
+    pub fields: Vec<FieldMapping>,
+    pub methods: Vec<MethodMapping>,
+    pub metadata: Vec<MappingMetadata>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct FieldMapping {
+    pub field_type: String,
+    pub original: String,
+    pub obfuscated: String,
+    pub metadata: Vec<MappingMetadata>,
+}
+
+/// One method line.  A method with inlined callees is described by several
+/// consecutive lines sharing the same obfuscated name and line range; the
+/// first is the innermost inlined frame and the last is the method itself.
+#[derive(Debug, PartialEq)]
+pub struct MethodMapping {
+    pub return_type: String,
+    /// Holder of the original method when it was inlined from another class,
+    /// written as `com.example.Other.method` in the mapping file.
+    pub original_class: Option<String>,
+    pub original: String,
+    pub parameters: Vec<String>,
+    pub obfuscated: String,
+    pub obfuscated_range: Option<(u32, u32)>,
+    pub original_range: Option<(u32, u32)>,
+    /// False for the inlined frames that precede the outer method of a group.
+    pub outer_frame: bool,
+    pub metadata: Vec<MappingMetadata>,
+}
+
+/// R8 metadata carried in `# {"id": ...}` comments.
+#[derive(Debug, PartialEq)]
+pub enum MappingMetadata {
+    MapVersion(String),
+    SourceFile(String),
+    Synthesized,
+    Outline,
+    OutlineCallsite {
+        positions: BTreeMap<u32, u32>,
+        outline: Option<String>,
+    },
+    RewriteFrame {
+        conditions: Vec<String>,
+        actions: Vec<String>,
+    },
+    ResidualSignature(String),
+    Other(Value),
+}
+
+#[derive(Debug)]
+pub enum MappingError {
+    FileOpenError(io::Error),
+    ParseError { line: usize, reason: String },
+}
+
+impl From<io::Error> for MappingError {
+    fn from(err: io::Error) -> Self {
+        MappingError::FileOpenError(err)
+    }
+}
+
+impl Display for MappingError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            MappingError::FileOpenError(err) => write!(f, "{}", err),
+            MappingError::ParseError { line, reason } => {
+                write!(f, "mapping line {}: {}", line, reason)
+            }
+        }
+    }
+}
+
+fn parse_error(line: usize, reason: &str) -> MappingError {
+    MappingError::ParseError {
+        line,
+        reason: reason.to_string(),
+    }
+}
+
+fn parse_metadata(json: &str) -> Option<MappingMetadata> {
+    let value: Value = serde_json::from_str(json).ok()?;
+    let string_field = |name: &str| value.get(name).and_then(|v| v.as_str()).map(String::from);
+    let string_list = |name: &str| -> Vec<String> {
+        value
+            .get(name)
+            .and_then(|v| v.as_array())
+            .map(|list| {
+                list.iter()
+                    .filter_map(|v| v.as_str().map(String::from))
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+    let metadata = match value.get("id")?.as_str()? {
+        "com.android.tools.r8.mapping" => MappingMetadata::MapVersion(string_field("version")?),
+        "sourceFile" => MappingMetadata::SourceFile(string_field("fileName")?),
+        "com.android.tools.r8.synthesized" => MappingMetadata::Synthesized,
+        "com.android.tools.r8.outline" => MappingMetadata::Outline,
+        "com.android.tools.r8.outlineCallsite" => {
+            let positions = value
+                .get("positions")
+                .and_then(|v| v.as_object())
+                .map(|positions| {
+                    positions
+                        .iter()
+                        .filter_map(|(k, v)| Some((k.parse().ok()?, v.as_u64()? as u32)))
+                        .collect()
+                })
+                .unwrap_or_default();
+            MappingMetadata::OutlineCallsite {
+                positions,
+                outline: string_field("outline"),
+            }
+        }
+        "com.android.tools.r8.rewriteFrame" => MappingMetadata::RewriteFrame {
+            conditions: string_list("conditions"),
+            actions: string_list("actions"),
+        },
+        "com.android.tools.r8.residualsignature" => {
+            MappingMetadata::ResidualSignature(string_field("signature")?)
+        }
+        _ => MappingMetadata::Other(value),
+    };
+    return Some(metadata);
+}
+
+fn parse_range(text: &str, line: usize) -> Result<(u32, u32), MappingError> {
+    let mut parts = text.split(':');
+    let start = parts
+        .next()
+        .and_then(|p| p.parse().ok())
+        .ok_or_else(|| parse_error(line, "bad line range"))?;
+    let end = match parts.next() {
+        Some(p) => p.parse().map_err(|_| parse_error(line, "bad line range"))?,
+        None => start,
+    };
+    if end < start {
+        return Err(parse_error(line, "line range ends before it starts"));
+    }
+    return Ok((start, end));
+}
+
+/// Parses a member line such as `1:3:void run(int):10:12 -> a`.
+fn parse_member(text: &str, line: usize) -> Result<Member, MappingError> {
+    let (left, obfuscated) = text
+        .split_once(" -> ")
+        .ok_or_else(|| parse_error(line, "missing ' -> '"))?;
+    let obfuscated = obfuscated.trim().to_string();
+    let mut left = left.trim();
+
+    // Optional leading `start:end:` obfuscated line range.
+    let mut obfuscated_range = None;
+    if left.starts_with(|c: char| c.is_ascii_digit()) {
+        let mut parts = left.splitn(3, ':');
+        let start = parts.next().unwrap();
+        let end = parts
+            .next()
+            .ok_or_else(|| parse_error(line, "bad line range"))?;
+        left = parts
+            .next()
+            .ok_or_else(|| parse_error(line, "missing member after line range"))?;
+        obfuscated_range = Some(parse_range(&format!("{}:{}", start, end), line)?);
+    }
+
+    let (member_type, rest) = left
+        .split_once(' ')
+        .ok_or_else(|| parse_error(line, "missing member type"))?;
+    let member_type = member_type.to_string();
+
+    let open = match rest.find('(') {
+        None => {
+            return Ok(Member::Field(FieldMapping {
+                field_type: member_type,
+                original: rest.trim().to_string(),
+                obfuscated,
+                metadata: vec![],
+            }));
+        }
+        Some(open) => open,
+    };
+    let close = rest
+        .find(')')
+        .ok_or_else(|| parse_error(line, "unterminated parameter list"))?;
+    let qualified_name = &rest[..open];
+    let parameters = rest[open + 1..close]
+        .split(',')
+        .map(|p| p.trim())
+        .filter(|p| !p.is_empty())
+        .map(String::from)
+        .collect();
+    let original_range = match rest[close + 1..].strip_prefix(':') {
+        Some(range) => Some(parse_range(range, line)?),
+        None => None,
+    };
+    let (original_class, original) = match qualified_name.rsplit_once('.') {
+        Some((class, name)) => (Some(class.to_string()), name.to_string()),
+        None => (None, qualified_name.to_string()),
+    };
+
+    return Ok(Member::Method(MethodMapping {
+        return_type: member_type,
+        original_class,
+        original,
+        parameters,
+        obfuscated,
+        obfuscated_range,
+        original_range,
+        outer_frame: true,
+        metadata: vec![],
+    }));
+}
+
+enum Member {
+    Field(FieldMapping),
+    Method(MethodMapping),
+}
+
+/// The member most recently parsed, which trailing metadata comments apply to.
+enum LastMember {
+    None,
+    Field,
+    Method,
+}
+
+impl Mapping {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Mapping, MappingError> {
+        let text = fs::read_to_string(path)?;
+        return Mapping::parse(&text);
+    }
+
+    pub fn parse(text: &str) -> Result<Mapping, MappingError> {
+        let mut mapping = Mapping::default();
+        let mut last_member = LastMember::None;
+
+        for (i, raw_line) in text.lines().enumerate() {
+            let line_number = i + 1;
+            let line = raw_line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(comment) = line.strip_prefix('#') {
+                let comment = comment.trim();
+                if !comment.starts_with('{') {
+                    continue;
+                }
+                let metadata = match parse_metadata(comment) {
+                    Some(metadata) => metadata,
+                    None => continue,
+                };
+                match (mapping.classes.last_mut(), &last_member) {
+                    (None, _) => mapping.metadata.push(metadata),
+                    (Some(class), LastMember::None) => class.metadata.push(metadata),
+                    (Some(class), LastMember::Field) => {
+                        class.fields.last_mut().unwrap().metadata.push(metadata)
+                    }
+                    (Some(class), LastMember::Method) => {
+                        class.methods.last_mut().unwrap().metadata.push(metadata)
+                    }
+                }
+                continue;
+            }
+
+            if !raw_line.starts_with(char::is_whitespace) {
+                let line = line
+                    .strip_suffix(':')
+                    .ok_or_else(|| parse_error(line_number, "class line must end with ':'"))?;
+                let (original, obfuscated) = line
+                    .split_once(" -> ")
+                    .ok_or_else(|| parse_error(line_number, "missing ' -> '"))?;
+                mapping.classes.push(ClassMapping {
+                    original: original.trim().to_string(),
+                    obfuscated: obfuscated.trim().to_string(),
+                    fields: vec![],
+                    methods: vec![],
+                    metadata: vec![],
+                });
+                last_member = LastMember::None;
+                continue;
+            }
+
+            let class = mapping
+                .classes
+                .last_mut()
+                .ok_or_else(|| parse_error(line_number, "member outside of a class"))?;
+            match parse_member(line, line_number)? {
+                Member::Field(field) => {
+                    class.fields.push(field);
+                    last_member = LastMember::Field;
+                }
+                Member::Method(method) => {
+                    if let Some(prev) = class.methods.last_mut() {
+                        if prev.obfuscated_range.is_some()
+                            && prev.obfuscated_range == method.obfuscated_range
+                            && prev.obfuscated == method.obfuscated
+                        {
+                            prev.outer_frame = false;
+                        }
+                    }
+                    class.methods.push(method);
+                    last_member = LastMember::Method;
+                }
+            }
+        }
+
+        for (i, class) in mapping.classes.iter().enumerate() {
+            mapping.by_obfuscated.insert(class.obfuscated.clone(), i);
+            mapping.by_original.insert(class.original.clone(), i);
+        }
+        return Ok(mapping);
+    }
+
+    /// Looks up a class by its obfuscated Java name, e.g. `a.b`.
+    pub fn class_by_obfuscated(&self, obfuscated: &str) -> Option<&ClassMapping> {
+        return self
+            .by_obfuscated
+            .get(obfuscated)
+            .map(|i| &self.classes[*i]);
+    }
+
+    /// Looks up a class by its original Java name, e.g. `com.example.Foo`.
+    pub fn class_by_original(&self, original: &str) -> Option<&ClassMapping> {
+        return self.by_original.get(original).map(|i| &self.classes[*i]);
+    }
+
+    /// Original Java name of a class, or `obfuscated` when it is not renamed.
+    pub fn original_class_name<'a>(&'a self, obfuscated: &'a str) -> &'a str {
+        return match self.class_by_obfuscated(obfuscated) {
+            Some(class) => &class.original,
+            None => obfuscated,
+        };
+    }
+
+    /// Maps a Java type name such as `a.b[][]` to its original name.
+    pub fn original_type_name(&self, obfuscated: &str) -> String {
+        let element = obfuscated.trim_end_matches("[]");
+        let dims = &obfuscated[element.len()..];
+        return format!("{}{}", self.original_class_name(element), dims);
+    }
+
+    /// Original name of a field of the obfuscated class.
+    pub fn original_field_name(&self, class: &str, obfuscated: &str) -> Option<&str> {
+        return self
+            .class_by_obfuscated(class)?
+            .fields
+            .iter()
+            .find(|f| f.obfuscated == obfuscated)
+            .map(|f| f.original.as_str());
+    }
+
+    /// Original name of a method of the obfuscated class.  `parameters` are
+    /// the method's parameter types with already de-obfuscated Java names, and
+    /// disambiguate overloads that share an obfuscated name.
+    pub fn original_method_name(
+        &self,
+        class: &str,
+        obfuscated: &str,
+        parameters: &[String],
+    ) -> Option<&str> {
+        let candidates = self
+            .class_by_obfuscated(class)?
+            .methods
+            .iter()
+            .filter(|m| m.obfuscated == obfuscated && m.outer_frame && m.original_class.is_none())
+            .collect::<Vec<_>>();
+        return candidates
+            .iter()
+            .find(|m| m.parameters == parameters)
+            .or_else(|| candidates.first())
+            .map(|m| m.original.as_str());
+    }
+}
+
+impl ClassMapping {
+    /// Source file recorded by R8, if any.
+    pub fn source_file(&self) -> Option<&str> {
+        return self.metadata.iter().find_map(|m| match m {
+            MappingMetadata::SourceFile(name) => Some(name.as_str()),
+            _ => None,
+        });
+    }
+}
+
+impl MethodMapping {
+    /// Maps an obfuscated line number within `obfuscated_range` to the
+    /// original line number.
+    pub fn original_line(&self, line: u32) -> Option<u32> {
+        return match (self.obfuscated_range, self.original_range) {
+            (None, _) => None,
+            (Some(_), None) => Some(line),
+            (Some((obf_start, obf_end)), Some((orig_start, orig_end))) => {
+                let same_length =
+                    orig_end.checked_sub(orig_start) == obf_end.checked_sub(obf_start);
+                match line.checked_sub(obf_start) {
+                    Some(offset) if same_length => orig_start.checked_add(offset),
+                    _ => Some(orig_start),
+                }
+            }
+        };
+    }
+
+    fn covers(&self, line: u32) -> bool {
+        return match self.obfuscated_range {
+            Some((start, end)) => start <= line && line <= end,
+            None => false,
+        };
+    }
+}
+
+/// A de-obfuscated stack frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetracedFrame {
+    pub class: String,
+    pub method: String,
+    pub source_file: String,
+    pub line: Option<u32>,
+}
+
+impl Display for RetracedFrame {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(
+                f,
+                "{}.{}({}:{})",
+                self.class, self.method, self.source_file, line
+            ),
+            None => write!(f, "{}.{}({})", self.class, self.method, self.source_file),
+        }
+    }
+}
+
+/// File name the original class most likely came from: the R8 `sourceFile`
+/// metadata when present, otherwise the outermost class name with the
+/// extension of the obfuscated frame's file name (or `.java`).
+fn source_file_for(mapping: &Mapping, original_class: &str, frame_file: &str) -> String {
+    if let Some(name) = mapping
+        .class_by_original(original_class)
+        .and_then(|c| c.source_file())
+    {
+        return name.to_string();
+    }
+    let simple = original_class.rsplit('.').next().unwrap_or(original_class);
+    let outer = simple.split('$').next().unwrap_or(simple);
+    let extension = match frame_file.rsplit_once('.') {
+        Some((_, ext)) if frame_file != "SourceFile" && !frame_file.contains(' ') => ext,
+        _ => "java",
+    };
+    return format!("{}.{}", outer, extension);
+}
+
+impl Mapping {
+    /// Retraces a single frame of obfuscated `class`.`method` at `line`.
+    /// Each returned chain lists the inlined frames for the position,
+    /// innermost first.  Ambiguous positions produce one chain per candidate.
+    pub fn retrace_frame(
+        &self,
+        class: &str,
+        method: &str,
+        frame_file: &str,
+        line: Option<u32>,
+    ) -> Vec<Vec<RetracedFrame>> {
+        return self.retrace_frame_matching(class, method, frame_file, line, &[]);
+    }
+
+    /// Like `retrace_frame`, but when `signatures` is not empty only outer
+    /// methods whose original parameter types appear in it are considered,
+    /// unless that would leave no candidate at all.
+    fn retrace_frame_matching(
+        &self,
+        class: &str,
+        method: &str,
+        frame_file: &str,
+        line: Option<u32>,
+        signatures: &[Vec<String>],
+    ) -> Vec<Vec<RetracedFrame>> {
+        let class_mapping = match self.class_by_obfuscated(class) {
+            Some(class_mapping) => class_mapping,
+            None => {
+                return vec![vec![RetracedFrame {
+                    class: class.to_string(),
+                    method: method.to_string(),
+                    source_file: frame_file.to_string(),
+                    line,
+                }]];
+            }
+        };
+        let frame = |m: &MethodMapping, line: Option<u32>| {
+            let holder = m
+                .original_class
+                .as_deref()
+                .unwrap_or(&class_mapping.original);
+            RetracedFrame {
+                class: holder.to_string(),
+                method: m.original.clone(),
+                source_file: source_file_for(self, holder, frame_file),
+                line,
+            }
+        };
+
+        let mut named = class_mapping
+            .methods
+            .iter()
+            .filter(|m| m.obfuscated == method)
+            .collect::<Vec<_>>();
+        if !signatures.is_empty() {
+            // Drop every group whose outer method does not match.
+            let mut matching = vec![];
+            let mut group = vec![];
+            for m in named.iter() {
+                group.push(*m);
+                if m.outer_frame {
+                    if signatures.contains(&m.parameters) {
+                        matching.append(&mut group);
+                    }
+                    group.clear();
+                }
+            }
+            if !matching.is_empty() {
+                named = matching;
+            }
+        }
+
+        let mut chains = vec![];
+        if let Some(line) = line {
+            let mut chain = vec![];
+            for m in named.iter().filter(|m| m.covers(line)) {
+                chain.push(frame(m, m.original_line(line)));
+                if m.outer_frame {
+                    chains.push(std::mem::take(&mut chain));
+                }
+            }
+        }
+        if chains.is_empty() {
+            // No range information: every distinct original method is a
+            // candidate, and the line number is kept as is.
+            let mut seen = vec![];
+            for m in named.iter().filter(|m| m.outer_frame) {
+                let key = (m.original_class.clone(), m.original.clone());
+                if !seen.contains(&key) {
+                    seen.push(key);
+                    chains.push(vec![frame(m, line)]);
+                }
+            }
+        }
+        if chains.is_empty() {
+            chains.push(vec![RetracedFrame {
+                class: class_mapping.original.clone(),
+                method: method.to_string(),
+                source_file: source_file_for(self, &class_mapping.original, frame_file),
+                line,
+            }]);
+        }
+        return chains;
+    }
+}
+
+/// A parsed `at pkg.Class.method(File:line)` stack trace line.
+struct StackFrameLine<'a> {
+    indent: &'a str,
+    class: &'a str,
+    method: &'a str,
+    file: &'a str,
+    line: Option<u32>,
+}
+
+fn parse_stack_frame_line(line: &str) -> Option<StackFrameLine<'_>> {
+    let trimmed = line.trim_start();
+    let indent = &line[..line.len() - trimmed.len()];
+    let rest = trimmed.strip_prefix("at ")?;
+    let (qualified, location) = rest.split_once('(')?;
+    let location = location.strip_suffix(')')?;
+    let (class, method) = qualified.trim().rsplit_once('.')?;
+    let (file, line_number) = match location.rsplit_once(':') {
+        Some((file, number)) => match number.parse() {
+            Ok(number) => (file, Some(number)),
+            Err(_) => (location, None),
+        },
+        None => (location, None),
+    };
+    return Some(StackFrameLine {
+        indent,
+        class,
+        method,
+        file,
+        line: line_number,
+    });
+}
+
+impl Mapping {
+    /// Retraces every frame and exception class name in a stack trace.
+    /// Ambiguous frames are followed by their alternatives prefixed with
+    /// `<OR>`, matching the output of R8's retrace tool.
+    pub fn retrace_stack_trace(&self, trace: &str) -> String {
+        return self.retrace_stack_trace_with(trace, |frame| {
+            self.retrace_frame(frame.class, frame.method, frame.file, frame.line)
+        });
+    }
+
+    /// Retraces a stack trace like `retrace_stack_trace`, using the line
+    /// tables of the obfuscated dex files to disambiguate overloaded methods
+    /// that share an obfuscated name: only methods whose `DebugInfoItem`
+    /// contains the frame's line number are considered.
+    pub fn retrace_stack_trace_with_dex(&self, trace: &str, dexes: &[DexResolver]) -> String {
+        return self.retrace_stack_trace_with(trace, |frame| {
+            let signatures = match frame.line {
+                Some(line) => self.signatures_at_line(dexes, frame.class, frame.method, line),
+                None => vec![],
+            };
+            self.retrace_frame_matching(
+                frame.class,
+                frame.method,
+                frame.file,
+                frame.line,
+                &signatures,
+            )
+        });
+    }
+
+    /// Original parameter types of every dex method named `method` in the
+    /// obfuscated `class` whose line table contains `line`.
+    fn signatures_at_line(
+        &self,
+        dexes: &[DexResolver],
+        class: &str,
+        method: &str,
+        line: u32,
+    ) -> Vec<Vec<String>> {
+        let descriptor = java_to_descriptor(class);
+        let mut signatures = vec![];
+        for dex in dexes {
+            let class_def = match dex.class_def_by_descriptor(&descriptor) {
+                Some(class_def) => class_def,
+                None => continue,
+            };
+            for (method_idx, encoded_method) in dex.class_methods(class_def) {
+                let method_ref = dex.method_ref(method_idx);
+                if method_ref.name != method {
+                    continue;
+                }
+                let has_line = dex
+                    .code_item(encoded_method.code_off)
+                    .and_then(|code| dex.debug_info(code))
+                    .map(|debug_info| debug_info.decode())
+                    .is_some_and(|info| info.positions.iter().any(|p| p.line == line));
+                if has_line {
+                    signatures.push(
+                        method_ref
+                            .parameters
+                            .iter()
+                            .map(|p| self.original_type_name(&descriptor_to_java(p)))
+                            .collect(),
+                    );
+                }
+            }
+        }
+        return signatures;
+    }
+
+    fn retrace_stack_trace_with<F>(&self, trace: &str, mut retrace: F) -> String
+    where
+        F: FnMut(&StackFrameLine) -> Vec<Vec<RetracedFrame>>,
+    {
+        let mut out = String::new();
+        for line in trace.lines() {
+            match parse_stack_frame_line(line) {
+                Some(frame) => {
+                    for (i, chain) in retrace(&frame).iter().enumerate() {
+                        for retraced in chain {
+                            let or = if i > 0 { "<OR> " } else { "" };
+                            out.push_str(&format!("{}{}at {}\n", frame.indent, or, retraced));
+                        }
+                    }
+                }
+                None => {
+                    out.push_str(&self.retrace_exception_line(line));
+                    out.push('\n');
+                }
+            }
+        }
+        return out;
+    }
+
+    /// Replaces the exception class in lines like `Caused by: a.b: message`.
+    fn retrace_exception_line(&self, line: &str) -> String {
+        let prefixes = ["Caused by: ", "Suppressed: "];
+        let trimmed = line.trim_start();
+        let indent = &line[..line.len() - trimmed.len()];
+        let mut head = "";
+        let mut rest = trimmed;
+        for prefix in prefixes {
+            if let Some(stripped) = rest.strip_prefix(prefix) {
+                head = prefix;
+                rest = stripped;
+            }
+        }
+        if rest.starts_with("Exception in thread \"") {
+            if let Some(end) = rest[21..].find("\" ") {
+                let split = 21 + end + 2;
+                head = &rest[..split];
+                rest = &rest[split..];
+            }
+        }
+        let (class, tail) = match rest.find(": ") {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, ""),
+        };
+        if class.contains(' ') || class.is_empty() {
+            return line.to_string();
+        }
+        return format!(
+            "{}{}{}{}",
+            indent,
+            head,
+            self.original_class_name(class),
+            tail
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAPPING: &str = "\
+# compiler: R8
+# {\"id\":\"com.android.tools.r8.mapping\",\"version\":\"2.1\"}
+com.example.Main -> a.a:
+# {\"id\":\"sourceFile\",\"fileName\":\"Main.kt\"}
+    java.lang.String name -> a
+    1:3:void com.example.Util.log(int):40:42 -> a
+    1:3:void run(int):10 -> a
+    4:6:void run(int):11:13 -> a
+    java.lang.String describe() -> b
+    # {\"id\":\"com.android.tools.r8.synthesized\"}
+com.example.Error -> a.b:
+";
+
+    #[test]
+    fn test_parse() {
+        let mapping = Mapping::parse(MAPPING).unwrap();
+        assert_eq!(
+            mapping.metadata,
+            vec![MappingMetadata::MapVersion("2.1".to_string())]
+        );
+        let main = mapping.class_by_obfuscated("a.a").unwrap();
+        assert_eq!(main.source_file(), Some("Main.kt"));
+        assert_eq!(main.fields[0].original, "name");
+        assert_eq!(main.methods.len(), 4);
+        assert!(!main.methods[0].outer_frame);
+        assert!(main.methods[1].outer_frame);
+        assert_eq!(
+            main.methods[0].original_class.as_deref(),
+            Some("com.example.Util")
+        );
+        assert_eq!(main.methods[3].metadata, vec![MappingMetadata::Synthesized]);
+        assert_eq!(
+            mapping.original_method_name("a.a", "b", &[]),
+            Some("describe")
+        );
+        assert_eq!(mapping.original_type_name("a.b[]"), "com.example.Error[]");
+
+        let reversed = "a.A -> a:\n    3:1:void run():10:12 -> a\n";
+        assert!(matches!(
+            Mapping::parse(reversed),
+            Err(MappingError::ParseError { line: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn test_retrace_stack_trace() {
+        let mapping = Mapping::parse(MAPPING).unwrap();
+        let trace = "\
+Exception in thread \"main\" a.b: boom
+\tat a.a.a(SourceFile:2)
+\tat a.a.a(SourceFile:5)
+\tat android.os.Looper.loop(Looper.java:100)";
+        let expected = "\
+Exception in thread \"main\" com.example.Error: boom
+\tat com.example.Util.log(Util.java:41)
+\tat com.example.Main.run(Main.kt:10)
+\tat com.example.Main.run(Main.kt:12)
+\tat android.os.Looper.loop(Looper.java:100)
+";
+        assert_eq!(mapping.retrace_stack_trace(trace), expected);
+    }
+}
@@ -0,0 +1,552 @@
+//! Combines several dex files into one, as d8 does when merging prebuilt
+//! dex archives: the id pools are deduplicated and re-sorted, and every
+//! index and offset stored in the inputs is rewritten to match.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt::{self, Display, Formatter},
+};
+
+use crate::{
+    dex_model::DexModel,
+    dex_structs::{
+        ClassDefItem, FieldIdItem, MapList, MethodHandleItem, MethodIdItem, ProtoIdItem,
+        StringIdItem, TypeCode, TypeIdItem,
+    },
+    instructions::IndexKind,
+    layout::{relayout_from, rewrite_offsets, ItemOffsets},
+    remap::{pool_name, remap_indices, string_key, METHOD_HANDLE_TYPE_INVOKE_STATIC},
+};
+
+/// Number of ids a pool can hold when referenced by 16-bit indices.
+const MAX_IDS: usize = 0x10000;
+
+#[derive(Debug)]
+pub enum MergeError {
+    /// No dex files were given.
+    NoInput,
+    /// A class is defined by more than one input.
+    DuplicateClass(String),
+    /// A pool referenced by 16-bit indices would hold too many ids.
+    TooManyIndices { pool: &'static str, count: usize },
+    /// An instruction operand cannot hold its merged index, e.g. a
+    /// `const-string` that would need `const-string/jumbo`.
+    IndexOverflow { pool: &'static str, index: u32 },
+}
+
+impl Display for MergeError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            MergeError::NoInput => write!(f, "no dex files to merge"),
+            MergeError::DuplicateClass(descriptor) => {
+                write!(f, "class {} is defined more than once", descriptor)
+            }
+            MergeError::TooManyIndices { pool, count } => write!(
+                f,
+                "too many {} ids: {} exceeds the limit of {}",
+                pool, count, MAX_IDS
+            ),
+            MergeError::IndexOverflow { pool, index } => write!(
+                f,
+                "{} index {} does not fit its 16-bit instruction operand",
+                pool, index
+            ),
+        }
+    }
+}
+
+fn check_limit(pool: &'static str, count: usize) -> Result<(), MergeError> {
+    if count > MAX_IDS {
+        return Err(MergeError::TooManyIndices { pool, count });
+    }
+    return Ok(());
+}
+
+/// New index of every id of one input, per pool.
+#[derive(Default)]
+struct IndexMaps {
+    strings: Vec<u32>,
+    types: Vec<u32>,
+    protos: Vec<u32>,
+    fields: Vec<u32>,
+    methods: Vec<u32>,
+    method_handles: Vec<u32>,
+    call_site_base: u32,
+}
+
+impl IndexMaps {
+    fn get(&self, kind: IndexKind, index: u32) -> u32 {
+        let i = index as usize;
+        return match kind {
+            IndexKind::String => self.strings[i],
+            IndexKind::Type => self.types[i],
+            IndexKind::Field => self.fields[i],
+            IndexKind::Method => self.methods[i],
+            IndexKind::Proto => self.protos[i],
+            IndexKind::CallSite => self.call_site_base + index,
+            IndexKind::MethodHandle => self.method_handles[i],
+        };
+    }
+}
+
+/// Merges `dexes` into a single file. The result is laid out from scratch,
+/// and hidden API flags and link data are dropped.
+pub fn merge(dexes: Vec<DexModel>) -> Result<DexModel, MergeError> {
+    if dexes.is_empty() {
+        return Err(MergeError::NoInput);
+    }
+
+    // Inputs are placed side by side in one virtual offset space, so that
+    // their offsets stay distinct until the final layout.
+    let mut bases = vec![];
+    let mut base = 0u32;
+    let offsets = dexes.iter().map(ItemOffsets::of).collect::<Vec<_>>();
+    let indexes = offsets.iter().map(|o| o.index()).collect::<Vec<_>>();
+    for dex in dexes.iter() {
+        bases.push(base);
+        base += dex.header.file_size.next_multiple_of(4);
+    }
+    let mut maps = dexes
+        .iter()
+        .map(|_| IndexMaps::default())
+        .collect::<Vec<_>>();
+
+    // Strings, keeping the first copy of each string's data.
+    let mut string_pool = BTreeMap::new();
+    let mut string_keys = vec![];
+    for (d, dex) in dexes.iter().enumerate() {
+        let keys = dex
+            .string_ids
+            .iter()
+            .map(|id| {
+                let k = indexes[d][&(TypeCode::TypeStringDataItem, id.string_data_off)];
+                let key = string_key(&dex.string_data_items[k]);
+                string_pool.entry(key.clone()).or_insert((d, k));
+                key
+            })
+            .collect::<Vec<_>>();
+        string_keys.push(keys);
+    }
+    let strings = string_pool.keys().collect::<Vec<_>>();
+    for (d, keys) in string_keys.iter().enumerate() {
+        maps[d].strings = keys
+            .iter()
+            .map(|key| strings.binary_search(&key).unwrap() as u32)
+            .collect();
+    }
+
+    // Types, ordered by descriptor string index.
+    let mut type_pool = BTreeSet::new();
+    for (d, dex) in dexes.iter().enumerate() {
+        for type_id in dex.type_ids.iter() {
+            type_pool.insert(maps[d].strings[type_id.descriptor_idx as usize]);
+        }
+    }
+    check_limit("type", type_pool.len())?;
+    let types = type_pool.into_iter().collect::<Vec<_>>();
+    for (d, dex) in dexes.iter().enumerate() {
+        maps[d].types = dex
+            .type_ids
+            .iter()
+            .map(|t| {
+                let string_idx = maps[d].strings[t.descriptor_idx as usize];
+                types.binary_search(&string_idx).unwrap() as u32
+            })
+            .collect();
+    }
+
+    // Protos, ordered by return type then parameter list, reusing the first
+    // parameter list seen for each.
+    let mut proto_pool = BTreeMap::new();
+    let mut proto_keys = vec![];
+    for (d, dex) in dexes.iter().enumerate() {
+        let m = &maps[d];
+        let keys = dex
+            .proto_ids
+            .iter()
+            .map(|proto| {
+                let parameters =
+                    match indexes[d].get(&(TypeCode::TypeTypeList, proto.parameters_off)) {
+                        Some(i) => dex.type_lists[*i]
+                            .list
+                            .iter()
+                            .map(|t| m.types[t.type_idx as usize])
+                            .collect::<Vec<_>>(),
+                        None => vec![],
+                    };
+                let key = (m.types[proto.return_type_idx as usize], parameters);
+                let parameters_off = match proto.parameters_off {
+                    0 => 0,
+                    off => off + bases[d],
+                };
+                proto_pool
+                    .entry(key.clone())
+                    .or_insert((m.strings[proto.shorty_idx as usize], parameters_off));
+                key
+            })
+            .collect::<Vec<_>>();
+        proto_keys.push(keys);
+    }
+    check_limit("proto", proto_pool.len())?;
+    let protos = proto_pool.keys().collect::<Vec<_>>();
+    for (d, keys) in proto_keys.iter().enumerate() {
+        maps[d].protos = keys
+            .iter()
+            .map(|key| protos.binary_search(&key).unwrap() as u32)
+            .collect();
+    }
+
+    // Fields and methods, ordered by class, name and type or proto.
+    let mut field_pool = BTreeSet::new();
+    let mut method_pool = BTreeSet::new();
+    for (d, dex) in dexes.iter().enumerate() {
+        let m = &maps[d];
+        for field in dex.field_ids.iter() {
+            field_pool.insert(field_key(m, field));
+        }
+        for method in dex.method_ids.iter() {
+            method_pool.insert(method_key(m, method));
+        }
+    }
+    check_limit("field", field_pool.len())?;
+    check_limit("method", method_pool.len())?;
+    let fields = field_pool.into_iter().collect::<Vec<_>>();
+    let methods = method_pool.into_iter().collect::<Vec<_>>();
+    for (d, dex) in dexes.iter().enumerate() {
+        let m = &maps[d];
+        let field_map = dex
+            .field_ids
+            .iter()
+            .map(|f| fields.binary_search(&field_key(m, f)).unwrap() as u32)
+            .collect();
+        let method_map = dex
+            .method_ids
+            .iter()
+            .map(|f| methods.binary_search(&method_key(m, f)).unwrap() as u32)
+            .collect();
+        maps[d].fields = field_map;
+        maps[d].methods = method_map;
+    }
+
+    // Method handles, in order of first appearance, and call sites, which
+    // are never shared.
+    let mut method_handles: Vec<(u16, u32)> = vec![];
+    let mut method_handle_pool = HashMap::new();
+    let mut call_site_count = 0u32;
+    for (d, dex) in dexes.iter().enumerate() {
+        let mut handle_map = vec![];
+        for handle in dex.method_handles.iter() {
+            let key = method_handle_key(&maps[d], handle);
+            let index = *method_handle_pool.entry(key).or_insert_with(|| {
+                method_handles.push(key);
+                method_handles.len() as u32 - 1
+            });
+            handle_map.push(index);
+        }
+        maps[d].method_handles = handle_map;
+        maps[d].call_site_base = call_site_count;
+        call_site_count += dex.call_site_ids.len() as u32;
+    }
+    check_limit("method handle", method_handles.len())?;
+
+    let mut header = dexes[0].header;
+    header.magic = dexes.iter().map(|dex| dex.header.magic).max().unwrap();
+    let mut merged = DexModel {
+        header,
+        string_ids: vec![],
+        type_ids: vec![],
+        proto_ids: vec![],
+        field_ids: vec![],
+        method_ids: vec![],
+        class_defs: vec![],
+        call_site_ids: vec![],
+        method_handles: vec![],
+        type_lists: vec![],
+        string_data_items: vec![],
+        annotation_set_ref_lists: vec![],
+        annotation_set_items: vec![],
+        annotation_items: vec![],
+        annotations_directory_items: vec![],
+        hiddenapi_class_data_items: vec![],
+        encoded_array_items: vec![],
+        class_data_items: vec![],
+        debug_info_items: vec![],
+        code_items: vec![],
+        link_data: vec![],
+        map_list: MapList { list: vec![] },
+    };
+    let mut merged_offsets = ItemOffsets::default();
+    let mut string_data = vec![];
+    let mut defined = HashSet::new();
+
+    for (d, mut dex) in dexes.into_iter().enumerate() {
+        let m = &maps[d];
+        remap_indices(&mut dex, &mut |kind, index| m.get(kind, index)).map_err(|r| {
+            MergeError::IndexOverflow {
+                pool: pool_name(r.kind),
+                index: r.index,
+            }
+        })?;
+        let base = bases[d];
+        rewrite_offsets(&mut dex, &mut |_, offset| offset + base);
+        for (type_code, section) in offsets[d].sections.iter() {
+            if *type_code == TypeCode::TypeStringDataItem
+                || *type_code == TypeCode::TypeHiddenapiClassDataItem
+            {
+                continue;
+            }
+            merged_offsets
+                .sections
+                .entry(*type_code)
+                .or_default()
+                .extend(section.iter().map(|offset| offset + base));
+        }
+        string_data.push(
+            dex.string_data_items
+                .into_iter()
+                .map(Some)
+                .collect::<Vec<_>>(),
+        );
+
+        for class_def in dex.class_defs.iter() {
+            if !defined.insert(class_def.class_idx) {
+                let descriptor = strings[types[class_def.class_idx as usize] as usize];
+                return Err(MergeError::DuplicateClass(String::from_utf16_lossy(
+                    &descriptor.0,
+                )));
+            }
+        }
+        merged.class_defs.extend(dex.class_defs);
+        merged.class_data_items.extend(dex.class_data_items);
+        merged.code_items.extend(dex.code_items);
+        merged.debug_info_items.extend(dex.debug_info_items);
+        merged.type_lists.extend(dex.type_lists);
+        merged.encoded_array_items.extend(dex.encoded_array_items);
+        merged.annotation_items.extend(dex.annotation_items);
+        merged
+            .annotations_directory_items
+            .extend(dex.annotations_directory_items);
+        merged
+            .annotation_set_ref_lists
+            .extend(dex.annotation_set_ref_lists);
+        merged.annotation_set_items.extend(dex.annotation_set_items);
+        merged.call_site_ids.extend(dex.call_site_ids);
+    }
+
+    // The id pools, in their sorted order.
+    let mut string_data_offsets = vec![];
+    for (d, k) in string_pool.values() {
+        let offset = offsets[*d].get(TypeCode::TypeStringDataItem)[*k] + bases[*d];
+        merged.string_ids.push(StringIdItem {
+            string_data_off: offset,
+        });
+        merged
+            .string_data_items
+            .push(string_data[*d][*k].take().unwrap());
+        string_data_offsets.push(offset);
+    }
+    merged_offsets
+        .sections
+        .insert(TypeCode::TypeStringDataItem, string_data_offsets);
+    merged.type_ids = types
+        .iter()
+        .map(|descriptor_idx| TypeIdItem {
+            descriptor_idx: *descriptor_idx,
+        })
+        .collect();
+    merged.proto_ids = proto_pool
+        .iter()
+        .map(
+            |((return_type_idx, _), (shorty_idx, parameters_off))| ProtoIdItem {
+                shorty_idx: *shorty_idx,
+                return_type_idx: *return_type_idx,
+                parameters_off: *parameters_off,
+            },
+        )
+        .collect();
+    merged.field_ids = fields
+        .iter()
+        .map(|(class_idx, name_idx, type_idx)| FieldIdItem {
+            class_idx: *class_idx as u16,
+            type_idx: *type_idx as u16,
+            name_idx: *name_idx,
+        })
+        .collect();
+    merged.method_ids = methods
+        .iter()
+        .map(|(class_idx, name_idx, proto_idx)| MethodIdItem {
+            class_idx: *class_idx as u16,
+            proto_idx: *proto_idx as u16,
+            name_idx: *name_idx,
+        })
+        .collect();
+    merged.method_handles = method_handles
+        .iter()
+        .map(|(method_handle_type, id)| MethodHandleItem {
+            method_handle_type: *method_handle_type,
+            unused1: 0,
+            field_or_method_id: *id as u16,
+            unused2: 0,
+        })
+        .collect();
+
+    let type_list_index = merged_offsets
+        .get(TypeCode::TypeTypeList)
+        .iter()
+        .enumerate()
+        .map(|(i, offset)| (*offset, i))
+        .collect::<HashMap<_, _>>();
+    let class_defs = std::mem::take(&mut merged.class_defs);
+    merged.class_defs = order_classes(class_defs, |class_def| {
+        let mut supertypes = vec![class_def.superclass_idx];
+        if let Some(i) = type_list_index.get(&class_def.interfaces_off) {
+            supertypes.extend(merged.type_lists[*i].list.iter().map(|t| t.type_idx as u32));
+        }
+        return supertypes;
+    });
+
+    relayout_from(&mut merged, merged_offsets);
+    return Ok(merged);
+}
+
+fn field_key(m: &IndexMaps, field: &FieldIdItem) -> (u32, u32, u32) {
+    return (
+        m.types[field.class_idx as usize],
+        m.strings[field.name_idx as usize],
+        m.types[field.type_idx as usize],
+    );
+}
+
+fn method_key(m: &IndexMaps, method: &MethodIdItem) -> (u32, u32, u32) {
+    return (
+        m.types[method.class_idx as usize],
+        m.strings[method.name_idx as usize],
+        m.protos[method.proto_idx as usize],
+    );
+}
+
+fn method_handle_key(m: &IndexMaps, handle: &MethodHandleItem) -> (u16, u32) {
+    let id = handle.field_or_method_id as usize;
+    let target = if handle.method_handle_type < METHOD_HANDLE_TYPE_INVOKE_STATIC {
+        m.fields[id]
+    } else {
+        m.methods[id]
+    };
+    return (handle.method_handle_type, target);
+}
+
+/// Orders classes so that every superclass and interface defined in the
+/// file precedes its subtypes, as the runtime requires, keeping the input
+/// order otherwise.
+fn order_classes(
+    class_defs: Vec<ClassDefItem>,
+    supertypes: impl Fn(&ClassDefItem) -> Vec<u32>,
+) -> Vec<ClassDefItem> {
+    let position = class_defs
+        .iter()
+        .enumerate()
+        .map(|(i, class_def)| (class_def.class_idx, i))
+        .collect::<HashMap<_, _>>();
+    let mut visited = vec![false; class_defs.len()];
+    let mut order = vec![];
+    for start in 0..class_defs.len() {
+        let mut stack = vec![(start, false)];
+        while let Some((i, done)) = stack.pop() {
+            if done {
+                order.push(i);
+                continue;
+            }
+            if visited[i] {
+                continue;
+            }
+            visited[i] = true;
+            stack.push((i, true));
+            for supertype in supertypes(&class_defs[i]).into_iter().rev() {
+                if let Some(j) = position.get(&supertype) {
+                    if !visited[*j] {
+                        stack.push((*j, false));
+                    }
+                }
+            }
+        }
+    }
+    let mut class_defs = class_defs.into_iter().map(Some).collect::<Vec<_>>();
+    return order
+        .into_iter()
+        .map(|i| class_defs[i].take().unwrap())
+        .collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        deserialize_vec,
+        resolver::DexResolver,
+        serialize,
+        test_utils::{build_dex, TestClass},
+    };
+
+    fn main_dex() -> DexModel {
+        let mut main = TestClass::new("La/Main;")
+            // invoke-static {}, La/Main;->helper()V; return-void
+            .method("main", &[], "V", &[0x0071, 0x0000, 0x0000, 0x000e])
+            .method("helper", &[], "V", &[0x000e]);
+        main.superclass = Some("La/Base;");
+        let dex = build_dex(vec![main], &[]);
+        assert_eq!(
+            DexResolver::new(&dex).method_ref(0).signature(),
+            "La/Main;->helper()V"
+        );
+        return dex;
+    }
+
+    #[test]
+    fn test_merge() {
+        let base = build_dex(
+            vec![TestClass::new("La/Base;")
+                // const-string v0, "zzz"; return-void
+                .method("base", &[], "V", &[0x001a, 0x0004, 0x000e])],
+            &["zzz"],
+        );
+        assert_eq!(DexResolver::new(&base).string(4), "zzz");
+        let merged = merge(vec![main_dex(), base]).unwrap();
+        let dex = deserialize_vec(serialize(merged)).unwrap();
+        let resolver = DexResolver::new(&dex);
+
+        let classes = dex
+            .class_defs
+            .iter()
+            .map(|c| resolver.type_descriptor(c.class_idx))
+            .collect::<Vec<_>>();
+        assert_eq!(classes, vec!["La/Base;", "La/Main;"]);
+        let mut strings = resolver.strings().to_vec();
+        strings.sort();
+        assert_eq!(resolver.strings(), strings.as_slice());
+
+        let code = |class: &str, name: &str| {
+            let class_def = resolver.class_def_by_descriptor(class).unwrap();
+            let (_, method) = resolver
+                .class_methods(class_def)
+                .into_iter()
+                .find(|(idx, _)| resolver.method_ref(*idx).name == name)
+                .unwrap();
+            return resolver.code_item(method.code_off).unwrap();
+        };
+        let invoke = code("La/Main;", "main").insns[0].index_refs()[0];
+        assert_eq!(
+            resolver.method_ref(invoke.index).signature(),
+            "La/Main;->helper()V"
+        );
+        let string = code("La/Base;", "base").insns[0].index_refs()[0];
+        assert_eq!(resolver.string(string.index), "zzz");
+    }
+
+    #[test]
+    fn test_duplicate_class() {
+        match merge(vec![main_dex(), main_dex()]) {
+            Err(MergeError::DuplicateClass(descriptor)) => assert_eq!(descriptor, "La/Main;"),
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+    }
+}
@@ -0,0 +1,474 @@
+//! Static knowledge about each Dalvik opcode: its mnemonic, encoding format,
+//! the pool its index operand refers to, its control flow and register
+//! effects, and the dex version that introduced it. Decoding, display and
+//! the analyses read this table rather than matching on opcode ranges.
+
+use Format::*;
+
+/// The pool an instruction index operand points into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum IndexKind {
+    String,
+    Type,
+    Field,
+    Method,
+    Proto,
+    CallSite,
+    MethodHandle,
+}
+
+/// Instruction formats, named as in the Dalvik bytecode specification: the
+/// size in 16-bit code units, the number of registers and the kind of extra
+/// operand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Format {
+    F10x,
+    F12x,
+    F11n,
+    F11x,
+    F10t,
+    F20t,
+    F20bc,
+    F22x,
+    F21t,
+    F21s,
+    F21h,
+    F21c,
+    F23x,
+    F22b,
+    F22t,
+    F22s,
+    F22c,
+    F22cs,
+    F30t,
+    F32x,
+    F31i,
+    F31t,
+    F31c,
+    F35c,
+    F35ms,
+    F35mi,
+    F3rc,
+    F3rms,
+    F3rmi,
+    F45cc,
+    F4rcc,
+    F51l,
+}
+
+impl Format {
+    /// Size of an instruction of the format in 16-bit code units, the first
+    /// digit of its name.
+    pub fn units(&self) -> usize {
+        return match self {
+            F10x | F12x | F11n | F11x | F10t => 1,
+            F20t | F20bc | F22x | F21t | F21s | F21h | F21c | F23x | F22b | F22t | F22s | F22c
+            | F22cs => 2,
+            F30t | F32x | F31i | F31t | F31c | F35c | F35ms | F35mi | F3rc | F3rms | F3rmi => 3,
+            F45cc | F4rcc => 4,
+            F51l => 5,
+        };
+    }
+}
+
+/// Execution may fall through to the next instruction.
+pub const CONTINUE: u16 = 0x0001;
+/// The instruction may throw, so it can reach the handlers covering it.
+pub const THROW: u16 = 0x0002;
+/// A `goto` or `if-*` with a relative branch target.
+pub const BRANCH: u16 = 0x0004;
+/// A switch, whose targets are in a payload.
+pub const SWITCH: u16 = 0x0008;
+pub const RETURN: u16 = 0x0010;
+pub const INVOKE: u16 = 0x0020;
+/// The result is picked up by a following `move-result*`.
+pub const SETS_RESULT: u16 = 0x0040;
+/// The instruction writes register `vA`.
+pub const SETS_REGISTER: u16 = 0x0080;
+/// Register `vA` holds a wide (long or double) value, so the instruction
+/// also uses `vA + 1`.
+pub const WIDE: u16 = 0x0100;
+/// Register `vB` holds a wide value, e.g. the source of `long-to-int`.
+pub const WIDE_B: u16 = 0x2000;
+/// Register `vC` holds a wide value, e.g. the second operand of `add-long`.
+pub const WIDE_C: u16 = 0x4000;
+/// Loads a constant into `vA`: the literal operand, or the pool entry of
+/// the index operand.
+pub const CONST: u16 = 0x0200;
+/// Copies register `vB` into `vA`.
+pub const MOVE: u16 = 0x0400;
+/// Stores the result of the preceding instruction in `vA`.
+pub const MOVE_RESULT: u16 = 0x0800;
+/// An invoke without a receiver, so the arguments start at the first
+/// parameter.
+pub const STATIC: u16 = 0x1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpcodeInfo {
+    pub mnemonic: &'static str,
+    pub format: Format,
+    pub index_kind: Option<IndexKind>,
+    pub flags: u16,
+    /// Lowest dex file version, e.g. 35 for `035`, that may contain the
+    /// opcode.
+    pub min_dex_version: u32,
+}
+
+impl OpcodeInfo {
+    pub fn has(&self, flag: u16) -> bool {
+        return self.flags & flag != 0;
+    }
+
+    /// Number of registers that operand `operand` (0 for `vA`) spans: two
+    /// for a wide value, else one.
+    pub fn register_width(&self, operand: usize) -> u16 {
+        let flag = match operand {
+            0 => WIDE,
+            1 => WIDE_B,
+            2 => WIDE_C,
+            _ => return 1,
+        };
+        return if self.has(flag) { 2 } else { 1 };
+    }
+}
+
+const fn info(
+    mnemonic: &'static str,
+    format: Format,
+    index_kind: Option<IndexKind>,
+    flags: u16,
+    min_dex_version: u32,
+) -> OpcodeInfo {
+    return OpcodeInfo {
+        mnemonic,
+        format,
+        index_kind,
+        flags,
+        min_dex_version,
+    };
+}
+
+pub fn opcode_info(op: u8) -> &'static OpcodeInfo {
+    return &OPCODES[op as usize];
+}
+
+/// Indexed by opcode. Unused opcodes decode as `10x` and have no flags.
+#[rustfmt::skip]
+pub static OPCODES: [OpcodeInfo; 256] = [
+    info("nop", F10x, None, CONTINUE, 35),
+    info("move", F12x, None, CONTINUE | SETS_REGISTER | MOVE, 35),
+    info("move/from16", F22x, None, CONTINUE | SETS_REGISTER | MOVE, 35),
+    info("move/16", F32x, None, CONTINUE | SETS_REGISTER | MOVE, 35),
+    info("move-wide", F12x, None, CONTINUE | SETS_REGISTER | WIDE | MOVE | WIDE_B, 35),
+    info("move-wide/from16", F22x, None, CONTINUE | SETS_REGISTER | WIDE | MOVE | WIDE_B, 35),
+    info("move-wide/16", F32x, None, CONTINUE | SETS_REGISTER | WIDE | MOVE | WIDE_B, 35),
+    info("move-object", F12x, None, CONTINUE | SETS_REGISTER | MOVE, 35),
+    info("move-object/from16", F22x, None, CONTINUE | SETS_REGISTER | MOVE, 35),
+    info("move-object/16", F32x, None, CONTINUE | SETS_REGISTER | MOVE, 35),
+    info("move-result", F11x, None, CONTINUE | SETS_REGISTER | MOVE_RESULT, 35),
+    info("move-result-wide", F11x, None, CONTINUE | SETS_REGISTER | WIDE | MOVE_RESULT, 35),
+    info("move-result-object", F11x, None, CONTINUE | SETS_REGISTER | MOVE_RESULT, 35),
+    info("move-exception", F11x, None, CONTINUE | SETS_REGISTER, 35),
+    info("return-void", F10x, None, RETURN, 35),
+    info("return", F11x, None, RETURN, 35),
+    info("return-wide", F11x, None, RETURN | WIDE, 35),
+    info("return-object", F11x, None, RETURN, 35),
+    info("const/4", F11n, None, CONTINUE | SETS_REGISTER | CONST, 35),
+    info("const/16", F21s, None, CONTINUE | SETS_REGISTER | CONST, 35),
+    info("const", F31i, None, CONTINUE | SETS_REGISTER | CONST, 35),
+    info("const/high16", F21h, None, CONTINUE | SETS_REGISTER | CONST, 35),
+    info("const-wide/16", F21s, None, CONTINUE | SETS_REGISTER | WIDE | CONST, 35),
+    info("const-wide/32", F31i, None, CONTINUE | SETS_REGISTER | WIDE | CONST, 35),
+    info("const-wide", F51l, None, CONTINUE | SETS_REGISTER | WIDE | CONST, 35),
+    info("const-wide/high16", F21h, None, CONTINUE | SETS_REGISTER | WIDE | CONST, 35),
+    info("const-string", F21c, Some(IndexKind::String), CONTINUE | THROW | SETS_REGISTER | CONST, 35),
+    info("const-string/jumbo", F31c, Some(IndexKind::String), CONTINUE | THROW | SETS_REGISTER | CONST, 35),
+    info("const-class", F21c, Some(IndexKind::Type), CONTINUE | THROW | SETS_REGISTER | CONST, 35),
+    info("monitor-enter", F11x, None, CONTINUE | THROW, 35),
+    info("monitor-exit", F11x, None, CONTINUE | THROW, 35),
+    info("check-cast", F21c, Some(IndexKind::Type), CONTINUE | THROW, 35),
+    info("instance-of", F22c, Some(IndexKind::Type), CONTINUE | THROW | SETS_REGISTER, 35),
+    info("array-length", F12x, None, CONTINUE | THROW | SETS_REGISTER, 35),
+    info("new-instance", F21c, Some(IndexKind::Type), CONTINUE | THROW | SETS_REGISTER, 35),
+    info("new-array", F22c, Some(IndexKind::Type), CONTINUE | THROW | SETS_REGISTER, 35),
+    info("filled-new-array", F35c, Some(IndexKind::Type), CONTINUE | THROW | SETS_RESULT, 35),
+    info("filled-new-array/range", F3rc, Some(IndexKind::Type), CONTINUE | THROW | SETS_RESULT, 35),
+    info("fill-array-data", F31t, None, CONTINUE | THROW, 35),
+    info("throw", F11x, None, THROW, 35),
+    info("goto", F10t, None, BRANCH, 35),
+    info("goto/16", F20t, None, BRANCH, 35),
+    info("goto/32", F30t, None, BRANCH, 35),
+    info("packed-switch", F31t, None, CONTINUE | SWITCH, 35),
+    info("sparse-switch", F31t, None, CONTINUE | SWITCH, 35),
+    info("cmpl-float", F23x, None, CONTINUE | SETS_REGISTER, 35), // lt bias: NaN compares as -1
+    info("cmpg-float", F23x, None, CONTINUE | SETS_REGISTER, 35), // gt bias: NaN compares as 1
+    info("cmpl-double", F23x, None, CONTINUE | SETS_REGISTER | WIDE_B | WIDE_C, 35), // lt bias: NaN compares as -1
+    info("cmpg-double", F23x, None, CONTINUE | SETS_REGISTER | WIDE_B | WIDE_C, 35), // gt bias: NaN compares as 1
+    info("cmp-long", F23x, None, CONTINUE | SETS_REGISTER | WIDE_B | WIDE_C, 35),
+    info("if-eq", F22t, None, CONTINUE | BRANCH, 35),
+    info("if-ne", F22t, None, CONTINUE | BRANCH, 35),
+    info("if-lt", F22t, None, CONTINUE | BRANCH, 35),
+    info("if-ge", F22t, None, CONTINUE | BRANCH, 35),
+    info("if-gt", F22t, None, CONTINUE | BRANCH, 35),
+    info("if-le", F22t, None, CONTINUE | BRANCH, 35),
+    info("if-eqz", F21t, None, CONTINUE | BRANCH, 35),
+    info("if-nez", F21t, None, CONTINUE | BRANCH, 35),
+    info("if-ltz", F21t, None, CONTINUE | BRANCH, 35),
+    info("if-gez", F21t, None, CONTINUE | BRANCH, 35),
+    info("if-gtz", F21t, None, CONTINUE | BRANCH, 35),
+    info("if-lez", F21t, None, CONTINUE | BRANCH, 35),
+    info("[unk]", F10x, None, 0, 35),
+    info("[unk]", F10x, None, 0, 35),
+    info("[unk]", F10x, None, 0, 35),
+    info("[unk]", F10x, None, 0, 35),
+    info("[unk]", F10x, None, 0, 35),
+    info("[unk]", F10x, None, 0, 35),
+    info("aget", F23x, None, CONTINUE | THROW | SETS_REGISTER, 35),
+    info("aget-wide", F23x, None, CONTINUE | THROW | SETS_REGISTER | WIDE, 35),
+    info("aget-object", F23x, None, CONTINUE | THROW | SETS_REGISTER, 35),
+    info("aget-boolean", F23x, None, CONTINUE | THROW | SETS_REGISTER, 35),
+    info("aget-byte", F23x, None, CONTINUE | THROW | SETS_REGISTER, 35),
+    info("aget-char", F23x, None, CONTINUE | THROW | SETS_REGISTER, 35),
+    info("aget-short", F23x, None, CONTINUE | THROW | SETS_REGISTER, 35),
+    info("aput", F23x, None, CONTINUE | THROW, 35),
+    info("aput-wide", F23x, None, CONTINUE | THROW | WIDE, 35),
+    info("aput-object", F23x, None, CONTINUE | THROW, 35),
+    info("aput-boolean", F23x, None, CONTINUE | THROW, 35),
+    info("aput-byte", F23x, None, CONTINUE | THROW, 35),
+    info("aput-char", F23x, None, CONTINUE | THROW, 35),
+    info("aput-short", F23x, None, CONTINUE | THROW, 35),
+    info("iget", F22c, Some(IndexKind::Field), CONTINUE | THROW | SETS_REGISTER, 35),
+    info("iget-wide", F22c, Some(IndexKind::Field), CONTINUE | THROW | SETS_REGISTER | WIDE, 35),
+    info("iget-object", F22c, Some(IndexKind::Field), CONTINUE | THROW | SETS_REGISTER, 35),
+    info("iget-boolean", F22c, Some(IndexKind::Field), CONTINUE | THROW | SETS_REGISTER, 35),
+    info("iget-byte", F22c, Some(IndexKind::Field), CONTINUE | THROW | SETS_REGISTER, 35),
+    info("iget-char", F22c, Some(IndexKind::Field), CONTINUE | THROW | SETS_REGISTER, 35),
+    info("iget-short", F22c, Some(IndexKind::Field), CONTINUE | THROW | SETS_REGISTER, 35),
+    info("iput", F22c, Some(IndexKind::Field), CONTINUE | THROW, 35),
+    info("iput-wide", F22c, Some(IndexKind::Field), CONTINUE | THROW | WIDE, 35),
+    info("iput-object", F22c, Some(IndexKind::Field), CONTINUE | THROW, 35),
+    info("iput-boolean", F22c, Some(IndexKind::Field), CONTINUE | THROW, 35),
+    info("iput-byte", F22c, Some(IndexKind::Field), CONTINUE | THROW, 35),
+    info("iput-char", F22c, Some(IndexKind::Field), CONTINUE | THROW, 35),
+    info("iput-short", F22c, Some(IndexKind::Field), CONTINUE | THROW, 35),
+    info("sget", F21c, Some(IndexKind::Field), CONTINUE | THROW | SETS_REGISTER, 35),
+    info("sget-wide", F21c, Some(IndexKind::Field), CONTINUE | THROW | SETS_REGISTER | WIDE, 35),
+    info("sget-object", F21c, Some(IndexKind::Field), CONTINUE | THROW | SETS_REGISTER, 35),
+    info("sget-boolean", F21c, Some(IndexKind::Field), CONTINUE | THROW | SETS_REGISTER, 35),
+    info("sget-byte", F21c, Some(IndexKind::Field), CONTINUE | THROW | SETS_REGISTER, 35),
+    info("sget-char", F21c, Some(IndexKind::Field), CONTINUE | THROW | SETS_REGISTER, 35),
+    info("sget-short", F21c, Some(IndexKind::Field), CONTINUE | THROW | SETS_REGISTER, 35),
+    info("sput", F21c, Some(IndexKind::Field), CONTINUE | THROW, 35),
+    info("sput-wide", F21c, Some(IndexKind::Field), CONTINUE | THROW | WIDE, 35),
+    info("sput-object", F21c, Some(IndexKind::Field), CONTINUE | THROW, 35),
+    info("sput-boolean", F21c, Some(IndexKind::Field), CONTINUE | THROW, 35),
+    info("sput-byte", F21c, Some(IndexKind::Field), CONTINUE | THROW, 35),
+    info("sput-char", F21c, Some(IndexKind::Field), CONTINUE | THROW, 35),
+    info("sput-short", F21c, Some(IndexKind::Field), CONTINUE | THROW, 35),
+    info("invoke-virtual", F35c, Some(IndexKind::Method), CONTINUE | THROW | INVOKE | SETS_RESULT, 35),
+    info("invoke-super", F35c, Some(IndexKind::Method), CONTINUE | THROW | INVOKE | SETS_RESULT, 35),
+    info("invoke-direct", F35c, Some(IndexKind::Method), CONTINUE | THROW | INVOKE | SETS_RESULT, 35),
+    info("invoke-static", F35c, Some(IndexKind::Method), CONTINUE | THROW | INVOKE | SETS_RESULT | STATIC, 35),
+    info("invoke-interface", F35c, Some(IndexKind::Method), CONTINUE | THROW | INVOKE | SETS_RESULT, 35),
+    info("[unk]", F10x, None, 0, 35),
+    info("invoke-virtual/range", F3rc, Some(IndexKind::Method), CONTINUE | THROW | INVOKE | SETS_RESULT, 35),
+    info("invoke-super/range", F3rc, Some(IndexKind::Method), CONTINUE | THROW | INVOKE | SETS_RESULT, 35),
+    info("invoke-direct/range", F3rc, Some(IndexKind::Method), CONTINUE | THROW | INVOKE | SETS_RESULT, 35),
+    info("invoke-static/range", F3rc, Some(IndexKind::Method), CONTINUE | THROW | INVOKE | SETS_RESULT | STATIC, 35),
+    info("invoke-interface/range", F3rc, Some(IndexKind::Method), CONTINUE | THROW | INVOKE | SETS_RESULT, 35),
+    info("[unk]", F10x, None, 0, 35),
+    info("[unk]", F10x, None, 0, 35),
+    info("neg-int", F12x, None, CONTINUE | SETS_REGISTER, 35),
+    info("not-int", F12x, None, CONTINUE | SETS_REGISTER, 35),
+    info("neg-long", F12x, None, CONTINUE | SETS_REGISTER | WIDE | WIDE_B, 35),
+    info("not-long", F12x, None, CONTINUE | SETS_REGISTER | WIDE | WIDE_B, 35),
+    info("neg-float", F12x, None, CONTINUE | SETS_REGISTER, 35),
+    info("neg-double", F12x, None, CONTINUE | SETS_REGISTER | WIDE | WIDE_B, 35),
+    info("int-to-long", F12x, None, CONTINUE | SETS_REGISTER | WIDE, 35),
+    info("int-to-float", F12x, None, CONTINUE | SETS_REGISTER, 35),
+    info("int-to-double", F12x, None, CONTINUE | SETS_REGISTER | WIDE, 35),
+    info("long-to-int", F12x, None, CONTINUE | SETS_REGISTER | WIDE_B, 35),
+    info("long-to-float", F12x, None, CONTINUE | SETS_REGISTER | WIDE_B, 35),
+    info("long-to-double", F12x, None, CONTINUE | SETS_REGISTER | WIDE | WIDE_B, 35),
+    info("float-to-int", F12x, None, CONTINUE | SETS_REGISTER, 35),
+    info("float-to-long", F12x, None, CONTINUE | SETS_REGISTER | WIDE, 35),
+    info("float-to-double", F12x, None, CONTINUE | SETS_REGISTER | WIDE, 35),
+    info("double-to-int", F12x, None, CONTINUE | SETS_REGISTER | WIDE_B, 35),
+    info("double-to-long", F12x, None, CONTINUE | SETS_REGISTER | WIDE | WIDE_B, 35),
+    info("double-to-float", F12x, None, CONTINUE | SETS_REGISTER | WIDE_B, 35),
+    info("int-to-byte", F12x, None, CONTINUE | SETS_REGISTER, 35),
+    info("int-to-char", F12x, None, CONTINUE | SETS_REGISTER, 35),
+    info("int-to-short", F12x, None, CONTINUE | SETS_REGISTER, 35),
+    info("add-int", F23x, None, CONTINUE | SETS_REGISTER, 35),
+    info("sub-int", F23x, None, CONTINUE | SETS_REGISTER, 35),
+    info("mul-int", F23x, None, CONTINUE | SETS_REGISTER, 35),
+    info("div-int", F23x, None, CONTINUE | THROW | SETS_REGISTER, 35),
+    info("rem-int", F23x, None, CONTINUE | THROW | SETS_REGISTER, 35),
+    info("and-int", F23x, None, CONTINUE | SETS_REGISTER, 35),
+    info("or-int", F23x, None, CONTINUE | SETS_REGISTER, 35),
+    info("xor-int", F23x, None, CONTINUE | SETS_REGISTER, 35),
+    info("shl-int", F23x, None, CONTINUE | SETS_REGISTER, 35),
+    info("shr-int", F23x, None, CONTINUE | SETS_REGISTER, 35),
+    info("ushr-int", F23x, None, CONTINUE | SETS_REGISTER, 35),
+    info("add-long", F23x, None, CONTINUE | SETS_REGISTER | WIDE | WIDE_B | WIDE_C, 35),
+    info("sub-long", F23x, None, CONTINUE | SETS_REGISTER | WIDE | WIDE_B | WIDE_C, 35),
+    info("mul-long", F23x, None, CONTINUE | SETS_REGISTER | WIDE | WIDE_B | WIDE_C, 35),
+    info("div-long", F23x, None, CONTINUE | THROW | SETS_REGISTER | WIDE | WIDE_B | WIDE_C, 35),
+    info("rem-long", F23x, None, CONTINUE | THROW | SETS_REGISTER | WIDE | WIDE_B | WIDE_C, 35),
+    info("and-long", F23x, None, CONTINUE | SETS_REGISTER | WIDE | WIDE_B | WIDE_C, 35),
+    info("or-long", F23x, None, CONTINUE | SETS_REGISTER | WIDE | WIDE_B | WIDE_C, 35),
+    info("xor-long", F23x, None, CONTINUE | SETS_REGISTER | WIDE | WIDE_B | WIDE_C, 35),
+    info("shl-long", F23x, None, CONTINUE | SETS_REGISTER | WIDE | WIDE_B, 35),
+    info("shr-long", F23x, None, CONTINUE | SETS_REGISTER | WIDE | WIDE_B, 35),
+    info("ushr-long", F23x, None, CONTINUE | SETS_REGISTER | WIDE | WIDE_B, 35),
+    info("add-float", F23x, None, CONTINUE | SETS_REGISTER, 35),
+    info("sub-float", F23x, None, CONTINUE | SETS_REGISTER, 35),
+    info("mul-float", F23x, None, CONTINUE | SETS_REGISTER, 35),
+    info("div-float", F23x, None, CONTINUE | SETS_REGISTER, 35),
+    info("rem-float", F23x, None, CONTINUE | SETS_REGISTER, 35),
+    info("add-double", F23x, None, CONTINUE | SETS_REGISTER | WIDE | WIDE_B | WIDE_C, 35),
+    info("sub-double", F23x, None, CONTINUE | SETS_REGISTER | WIDE | WIDE_B | WIDE_C, 35),
+    info("mul-double", F23x, None, CONTINUE | SETS_REGISTER | WIDE | WIDE_B | WIDE_C, 35),
+    info("div-double", F23x, None, CONTINUE | SETS_REGISTER | WIDE | WIDE_B | WIDE_C, 35),
+    info("rem-double", F23x, None, CONTINUE | SETS_REGISTER | WIDE | WIDE_B | WIDE_C, 35),
+    info("add-int/2addr", F12x, None, CONTINUE | SETS_REGISTER, 35),
+    info("sub-int/2addr", F12x, None, CONTINUE | SETS_REGISTER, 35),
+    info("mul-int/2addr", F12x, None, CONTINUE | SETS_REGISTER, 35),
+    info("div-int/2addr", F12x, None, CONTINUE | THROW | SETS_REGISTER, 35),
+    info("rem-int/2addr", F12x, None, CONTINUE | THROW | SETS_REGISTER, 35),
+    info("and-int/2addr", F12x, None, CONTINUE | SETS_REGISTER, 35),
+    info("or-int/2addr", F12x, None, CONTINUE | SETS_REGISTER, 35),
+    info("xor-int/2addr", F12x, None, CONTINUE | SETS_REGISTER, 35),
+    info("shl-int/2addr", F12x, None, CONTINUE | SETS_REGISTER, 35),
+    info("shr-int/2addr", F12x, None, CONTINUE | SETS_REGISTER, 35),
+    info("ushr-int/2addr", F12x, None, CONTINUE | SETS_REGISTER, 35),
+    info("add-long/2addr", F12x, None, CONTINUE | SETS_REGISTER | WIDE | WIDE_B, 35),
+    info("sub-long/2addr", F12x, None, CONTINUE | SETS_REGISTER | WIDE | WIDE_B, 35),
+    info("mul-long/2addr", F12x, None, CONTINUE | SETS_REGISTER | WIDE | WIDE_B, 35),
+    info("div-long/2addr", F12x, None, CONTINUE | THROW | SETS_REGISTER | WIDE | WIDE_B, 35),
+    info("rem-long/2addr", F12x, None, CONTINUE | THROW | SETS_REGISTER | WIDE | WIDE_B, 35),
+    info("and-long/2addr", F12x, None, CONTINUE | SETS_REGISTER | WIDE | WIDE_B, 35),
+    info("or-long/2addr", F12x, None, CONTINUE | SETS_REGISTER | WIDE | WIDE_B, 35),
+    info("xor-long/2addr", F12x, None, CONTINUE | SETS_REGISTER | WIDE | WIDE_B, 35),
+    info("shl-long/2addr", F12x, None, CONTINUE | SETS_REGISTER | WIDE, 35),
+    info("shr-long/2addr", F12x, None, CONTINUE | SETS_REGISTER | WIDE, 35),
+    info("ushr-long/2addr", F12x, None, CONTINUE | SETS_REGISTER | WIDE, 35),
+    info("add-float/2addr", F12x, None, CONTINUE | SETS_REGISTER, 35),
+    info("sub-float/2addr", F12x, None, CONTINUE | SETS_REGISTER, 35),
+    info("mul-float/2addr", F12x, None, CONTINUE | SETS_REGISTER, 35),
+    info("div-float/2addr", F12x, None, CONTINUE | SETS_REGISTER, 35),
+    info("rem-float/2addr", F12x, None, CONTINUE | SETS_REGISTER, 35),
+    info("add-double/2addr", F12x, None, CONTINUE | SETS_REGISTER | WIDE | WIDE_B, 35),
+    info("sub-double/2addr", F12x, None, CONTINUE | SETS_REGISTER | WIDE | WIDE_B, 35),
+    info("mul-double/2addr", F12x, None, CONTINUE | SETS_REGISTER | WIDE | WIDE_B, 35),
+    info("div-double/2addr", F12x, None, CONTINUE | SETS_REGISTER | WIDE | WIDE_B, 35),
+    info("rem-double/2addr", F12x, None, CONTINUE | SETS_REGISTER | WIDE | WIDE_B, 35),
+    info("add-int/lit16", F22s, None, CONTINUE | SETS_REGISTER, 35),
+    info("rsub-int", F22s, None, CONTINUE | SETS_REGISTER, 35),
+    info("mul-int/lit16", F22s, None, CONTINUE | SETS_REGISTER, 35),
+    info("div-int/lit16", F22s, None, CONTINUE | THROW | SETS_REGISTER, 35),
+    info("rem-int/lit16", F22s, None, CONTINUE | THROW | SETS_REGISTER, 35),
+    info("and-int/lit16", F22s, None, CONTINUE | SETS_REGISTER, 35),
+    info("or-int/lit16", F22s, None, CONTINUE | SETS_REGISTER, 35),
+    info("xor-int/lit16", F22s, None, CONTINUE | SETS_REGISTER, 35),
+    info("add-int/lit8", F22b, None, CONTINUE | SETS_REGISTER, 35),
+    info("rsub-int/lit8", F22b, None, CONTINUE | SETS_REGISTER, 35),
+    info("mul-int/lit8", F22b, None, CONTINUE | SETS_REGISTER, 35),
+    info("div-int/lit8", F22b, None, CONTINUE | THROW | SETS_REGISTER, 35),
+    info("rem-int/lit8", F22b, None, CONTINUE | THROW | SETS_REGISTER, 35),
+    info("and-int/lit8", F22b, None, CONTINUE | SETS_REGISTER, 35),
+    info("or-int/lit8", F22b, None, CONTINUE | SETS_REGISTER, 35),
+    info("xor-int/lit8", F22b, None, CONTINUE | SETS_REGISTER, 35),
+    info("shl-int/lit8", F22b, None, CONTINUE | SETS_REGISTER, 35),
+    info("shr-int/lit8", F22b, None, CONTINUE | SETS_REGISTER, 35),
+    info("ushr-int/lit8", F22b, None, CONTINUE | SETS_REGISTER, 35),
+    info("[unk]", F10x, None, 0, 35),
+    info("[unk]", F10x, None, 0, 35),
+    info("[unk]", F10x, None, 0, 35),
+    info("[unk]", F10x, None, 0, 35),
+    info("[unk]", F10x, None, 0, 35),
+    info("[unk]", F10x, None, 0, 35),
+    info("[unk]", F10x, None, 0, 35),
+    info("[unk]", F10x, None, 0, 35),
+    info("[unk]", F10x, None, 0, 35),
+    info("[unk]", F10x, None, 0, 35),
+    info("[unk]", F10x, None, 0, 35),
+    info("[unk]", F10x, None, 0, 35),
+    info("[unk]", F10x, None, 0, 35),
+    info("[unk]", F10x, None, 0, 35),
+    info("[unk]", F10x, None, 0, 35),
+    info("[unk]", F10x, None, 0, 35),
+    info("[unk]", F10x, None, 0, 35),
+    info("[unk]", F10x, None, 0, 35),
+    info("[unk]", F10x, None, 0, 35),
+    info("[unk]", F10x, None, 0, 35),
+    info("[unk]", F10x, None, 0, 35),
+    info("[unk]", F10x, None, 0, 35),
+    info("[unk]", F10x, None, 0, 35),
+    info("invoke-polymorphic", F45cc, Some(IndexKind::Method), CONTINUE | THROW | INVOKE | SETS_RESULT, 38),
+    info("invoke-polymorphic/range", F4rcc, Some(IndexKind::Method), CONTINUE | THROW | INVOKE | SETS_RESULT, 38),
+    info("invoke-custom", F35c, Some(IndexKind::CallSite), CONTINUE | THROW | INVOKE | SETS_RESULT, 38),
+    info("invoke-custom/range", F3rc, Some(IndexKind::CallSite), CONTINUE | THROW | INVOKE | SETS_RESULT, 38),
+    info("const-method-handle", F21c, Some(IndexKind::MethodHandle), CONTINUE | THROW | SETS_REGISTER | CONST, 39),
+    info("const-method-type", F21c, Some(IndexKind::Proto), CONTINUE | THROW | SETS_REGISTER | CONST, 39),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::insns;
+
+    #[test]
+    fn test_formats_match_decoding() {
+        for op in 0x01..=0xffu16 {
+            let info = opcode_info(op as u8);
+            let mut code = vec![0; info.format.units()];
+            code[0] = op;
+            let decoded = insns(&code);
+            assert_eq!(decoded.len(), 1, "{}", info.mnemonic);
+            assert_eq!(decoded[0].opcode() as u16, op);
+        }
+    }
+
+    #[test]
+    fn test_flags() {
+        let invoke = opcode_info(0x6e);
+        assert_eq!(invoke.mnemonic, "invoke-virtual");
+        assert_eq!(invoke.index_kind, Some(IndexKind::Method));
+        assert!(invoke.has(INVOKE) && invoke.has(SETS_RESULT) && invoke.has(THROW));
+        assert!(!opcode_info(0x28).has(CONTINUE));
+        assert!(opcode_info(0x38).has(BRANCH) && opcode_info(0x38).has(CONTINUE));
+        assert!(opcode_info(0x9b).has(WIDE));
+        assert_eq!(opcode_info(0x51).mnemonic, "aput-short");
+        let compares = (0x2d..=0x30)
+            .map(|op| opcode_info(op).mnemonic)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            compares,
+            ["cmpl-float", "cmpg-float", "cmpl-double", "cmpg-double"]
+        );
+        for info in OPCODES.iter() {
+            assert!(
+                info.mnemonic.bytes().all(|b| b.is_ascii_graphic()),
+                "{:?}",
+                info.mnemonic
+            );
+        }
+        let widths = |op: u8| {
+            (0..3)
+                .map(|k| opcode_info(op).register_width(k))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(widths(0x9b), vec![2, 2, 2]);
+        assert_eq!(widths(0xa3), vec![2, 2, 1]);
+        assert_eq!(widths(0x84), vec![1, 2, 1]);
+        assert_eq!(widths(0x31), vec![1, 2, 2]);
+        assert_eq!(widths(0x4c), vec![2, 1, 1]);
+        assert!(opcode_info(0x1c).has(CONST) && opcode_info(0x19).has(CONST));
+        assert!(opcode_info(0x08).has(MOVE) && !opcode_info(0x0c).has(MOVE));
+        assert!(opcode_info(0x0c).has(MOVE_RESULT));
+        assert!(opcode_info(0x77).has(STATIC) && !opcode_info(0x74).has(STATIC));
+        assert_eq!(opcode_info(0xfa).min_dex_version, 38);
+        assert_eq!(opcode_info(0xfe).min_dex_version, 39);
+    }
+}
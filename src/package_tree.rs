@@ -0,0 +1,114 @@
+use std::{collections::BTreeMap, ops::AddAssign};
+
+use serde::Serialize;
+
+/// Values rolled up by Java package: every value added for a class is
+/// accumulated in the root and in each enclosing package, so `com` includes
+/// `com.example` which includes `com.example.ui`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PackageTree<T> {
+    pub total: T,
+    pub children: BTreeMap<String, PackageTree<T>>,
+}
+
+/// Package of a Java class name, e.g. `com.example` for `com.example.Foo`.
+/// Array and primitive types have no package.
+pub fn package_of(class_name: &str) -> Option<&str> {
+    if class_name.ends_with(']') {
+        return None;
+    }
+    return class_name.rsplit_once('.').map(|(package, _)| package);
+}
+
+impl<T: Default + AddAssign + Clone> PackageTree<T> {
+    pub fn new() -> Self {
+        return Self {
+            total: T::default(),
+            children: BTreeMap::new(),
+        };
+    }
+
+    /// Adds `value` for the class `class_name` to the root and every package
+    /// enclosing the class.
+    pub fn add(&mut self, class_name: &str, value: T) {
+        self.total += value.clone();
+        let mut node = self;
+        if let Some(package) = package_of(class_name) {
+            for segment in package.split('.') {
+                node = node.children.entry(segment.to_string()).or_default();
+                node.total += value.clone();
+            }
+        }
+    }
+
+    /// Looks up the node of a package, e.g. `com.example`.
+    pub fn get(&self, package: &str) -> Option<&PackageTree<T>> {
+        let mut node = self;
+        for segment in package.split('.') {
+            node = node.children.get(segment)?;
+        }
+        return Some(node);
+    }
+
+    /// Every package in depth-first order as `(full package name, depth,
+    /// total)`, excluding the root and packages deeper than `max_depth`.
+    pub fn packages(&self, max_depth: Option<usize>) -> Vec<(String, usize, &T)> {
+        let mut packages = vec![];
+        self.collect_packages("", 1, max_depth, &mut packages);
+        return packages;
+    }
+
+    fn collect_packages<'a>(
+        &'a self,
+        prefix: &str,
+        depth: usize,
+        max_depth: Option<usize>,
+        packages: &mut Vec<(String, usize, &'a T)>,
+    ) {
+        if max_depth.is_some_and(|max_depth| depth > max_depth) {
+            return;
+        }
+        for (segment, child) in self.children.iter() {
+            let name = if prefix.is_empty() {
+                segment.clone()
+            } else {
+                format!("{}.{}", prefix, segment)
+            };
+            packages.push((name.clone(), depth, &child.total));
+            child.collect_packages(&name, depth + 1, max_depth, packages);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rollup() {
+        let mut tree = PackageTree::<u32>::new();
+        tree.add("com.example.Foo", 2);
+        tree.add("com.example.ui.Bar", 3);
+        tree.add("org.Baz", 1);
+        tree.add("Default", 4);
+        tree.add("com.example.Foo[]", 5);
+
+        assert_eq!(tree.total, 15);
+        assert_eq!(tree.get("com").unwrap().total, 5);
+        assert_eq!(tree.get("com.example.ui").unwrap().total, 3);
+        assert!(tree.get("com.other").is_none());
+        let packages = tree
+            .packages(Some(2))
+            .into_iter()
+            .map(|(name, depth, total)| (name, depth, *total))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            packages,
+            vec![
+                ("com".to_string(), 1, 5),
+                ("com.example".to_string(), 2, 5),
+                ("org".to_string(), 1, 1),
+            ]
+        );
+    }
+}
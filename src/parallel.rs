@@ -0,0 +1,99 @@
+//! Concurrent decoding, enabled by the `parallel` feature. The string data,
+//! class data, code and debug info sections hold variable-size items, but
+//! their offsets can be collected from the items that refer to them, so each
+//! item can be decoded on its own.
+
+use std::io::Cursor;
+
+use rayon::prelude::*;
+
+use crate::{
+    deserialize_dex_section,
+    dex_model::DexModel,
+    dex_structs::{DexStruct, MapItem, TypeCode},
+    DeserializeError,
+};
+
+/// Decodes the items of a section at `offsets` concurrently. Walks the
+/// section instead if the offsets do not account for every item, e.g. when a
+/// code item is not referenced by any method.
+fn deserialize_at<T: DexStruct + Send>(
+    bytes: &[u8],
+    map_item: &MapItem,
+    mut offsets: Vec<u32>,
+) -> Result<Vec<T>, DeserializeError> {
+    offsets.retain(|offset| *offset != 0);
+    offsets.sort_unstable();
+    offsets.dedup();
+    let complete = offsets.len() == map_item.size as usize
+        && offsets.first() == Some(&map_item.offset)
+        && offsets
+            .last()
+            .is_some_and(|offset| (*offset as usize) < bytes.len());
+    if !complete {
+        return deserialize_dex_section(map_item, &mut Cursor::new(bytes));
+    }
+    return Ok(offsets
+        .par_iter()
+        .map(|offset| T::deserialize(&mut Cursor::new(&bytes[*offset as usize..])))
+        .collect());
+}
+
+/// Decodes the sections `deserialize_sections` skipped. Each depends on the
+/// one before it for its offsets.
+pub(crate) fn deserialize_deferred(
+    mut dex: DexModel,
+    deferred: &[MapItem],
+    bytes: &[u8],
+) -> Result<DexModel, DeserializeError> {
+    let section = |type_code| deferred.iter().find(|m| m.type_code == type_code);
+    if let Some(map_item) = section(TypeCode::TypeStringDataItem) {
+        let offsets = dex.string_ids.iter().map(|s| s.string_data_off).collect();
+        dex.string_data_items = deserialize_at(bytes, map_item, offsets)?;
+    }
+    if let Some(map_item) = section(TypeCode::TypeClassDataItem) {
+        let offsets = dex.class_defs.iter().map(|c| c.class_data_off).collect();
+        dex.class_data_items = deserialize_at(bytes, map_item, offsets)?;
+    }
+    if let Some(map_item) = section(TypeCode::TypeCodeItem) {
+        let offsets = dex
+            .class_data_items
+            .iter()
+            .flat_map(|c| c.direct_methods.iter().chain(c.virtual_methods.iter()))
+            .map(|m| m.code_off)
+            .collect();
+        dex.code_items = deserialize_at(bytes, map_item, offsets)?;
+    }
+    if let Some(map_item) = section(TypeCode::TypeDebugInfoItem) {
+        let offsets = dex.code_items.iter().map(|c| c.debug_info_off).collect();
+        dex.debug_info_items = deserialize_at(bytes, map_item, offsets)?;
+    }
+    return Ok(dex);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        deserialize_sections, serialize,
+        test_utils::{build_dex, TestClass},
+    };
+
+    #[test]
+    fn test_matches_serial() {
+        let dex = build_dex(
+            vec![
+                TestClass::new("La/A;")
+                    .field("count", "I")
+                    .method("run", &[], "V", &[0x000e])
+                    .method("stop", &["I"], "V", &[0x000e]),
+                TestClass::new("Lb/B;").method("help", &[], "V", &[0x000e]),
+            ],
+            &["extra"],
+        );
+        let bytes = serialize(dex);
+        let serial = deserialize_sections(bytes.clone(), false).unwrap();
+        let parallel = deserialize_sections(bytes, true).unwrap();
+        assert_eq!(format!("{:?}", parallel), format!("{:?}", serial));
+        assert!(!parallel.code_items.is_empty());
+    }
+}
@@ -0,0 +1,288 @@
+//! Minimal decoder for the protobuf wire format, enough to walk the
+//! messages of an Android App Bundle (`Resources.proto`, `Config.proto`)
+//! and of Kotlin metadata without generated code.
+
+use std::fmt::{self, Display, Formatter};
+
+const WIRE_VARINT: u8 = 0;
+const WIRE_FIXED64: u8 = 1;
+const WIRE_BYTES: u8 = 2;
+const WIRE_FIXED32: u8 = 5;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ProtobufError {
+    /// A field or length prefix runs past the end of the message.
+    Truncated(usize),
+    /// A message is malformed, e.g. uses an unknown wire type.
+    InvalidMessage(usize, String),
+}
+
+impl Display for ProtobufError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            ProtobufError::Truncated(offset) => {
+                write!(f, "truncated protobuf message at offset {:#x}", offset)
+            }
+            ProtobufError::InvalidMessage(offset, reason) => {
+                write!(
+                    f,
+                    "invalid protobuf message at offset {:#x}: {}",
+                    offset, reason
+                )
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum FieldValue<'a> {
+    Varint(u64),
+    Fixed64(u64),
+    Bytes(&'a [u8]),
+    Fixed32(u32),
+}
+
+/// The fields of one message in wire order. Offsets in errors are relative
+/// to the outermost message.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Message<'a> {
+    fields: Vec<(u32, usize, FieldValue<'a>)>,
+}
+
+fn read_varint(bytes: &[u8], pos: &mut usize, base: usize) -> Result<u64, ProtobufError> {
+    let start = *pos;
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = *bytes
+            .get(*pos)
+            .ok_or(ProtobufError::Truncated(base + start))?;
+        *pos += 1;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    return Err(ProtobufError::InvalidMessage(
+        base + start,
+        "varint longer than 10 bytes".to_string(),
+    ));
+}
+
+impl<'a> Message<'a> {
+    pub(crate) fn parse(bytes: &'a [u8]) -> Result<Message<'a>, ProtobufError> {
+        return Message::parse_at(bytes, 0);
+    }
+
+    /// A message prefixed with its length, as written by `writeDelimitedTo`,
+    /// and the bytes following it.
+    pub(crate) fn parse_delimited(
+        bytes: &'a [u8],
+    ) -> Result<(Message<'a>, &'a [u8]), ProtobufError> {
+        let mut pos = 0;
+        let len = read_varint(bytes, &mut pos, 0)? as usize;
+        let message = pos
+            .checked_add(len)
+            .and_then(|end| bytes.get(pos..end))
+            .ok_or(ProtobufError::Truncated(pos))?;
+        return Ok((
+            Message::parse_at(message, pos)?,
+            &bytes[pos + message.len()..],
+        ));
+    }
+
+    fn parse_at(bytes: &'a [u8], base: usize) -> Result<Message<'a>, ProtobufError> {
+        let mut fields = vec![];
+        let mut pos = 0;
+        while pos < bytes.len() {
+            let key = read_varint(bytes, &mut pos, base)?;
+            let offset = base + pos;
+            let value = match (key & 7) as u8 {
+                WIRE_VARINT => FieldValue::Varint(read_varint(bytes, &mut pos, base)?),
+                WIRE_FIXED64 => {
+                    let value = bytes
+                        .get(pos..pos + 8)
+                        .ok_or(ProtobufError::Truncated(offset))?;
+                    pos += 8;
+                    FieldValue::Fixed64(u64::from_le_bytes(value.try_into().unwrap()))
+                }
+                WIRE_BYTES => {
+                    let len = read_varint(bytes, &mut pos, base)? as usize;
+                    let value = pos
+                        .checked_add(len)
+                        .and_then(|end| bytes.get(pos..end))
+                        .ok_or(ProtobufError::Truncated(offset))?;
+                    pos += len;
+                    FieldValue::Bytes(value)
+                }
+                WIRE_FIXED32 => {
+                    let value = bytes
+                        .get(pos..pos + 4)
+                        .ok_or(ProtobufError::Truncated(offset))?;
+                    pos += 4;
+                    FieldValue::Fixed32(u32::from_le_bytes(value.try_into().unwrap()))
+                }
+                wire_type => {
+                    return Err(ProtobufError::InvalidMessage(
+                        offset,
+                        format!("unsupported wire type {}", wire_type),
+                    ))
+                }
+            };
+            // The offset of a bytes field is that of its payload.
+            let offset = match value {
+                FieldValue::Bytes(value) => base + pos - value.len(),
+                _ => offset,
+            };
+            fields.push(((key >> 3) as u32, offset, value));
+        }
+        return Ok(Message { fields });
+    }
+
+    fn values(&self, number: u32) -> impl Iterator<Item = (usize, FieldValue<'a>)> + '_ {
+        return self
+            .fields
+            .iter()
+            .filter(move |(n, _, _)| *n == number)
+            .map(|(_, offset, value)| (*offset, *value));
+    }
+
+    /// Whether the field is present, for `optional` and `oneof` members.
+    pub(crate) fn has(&self, number: u32) -> bool {
+        return self.values(number).next().is_some();
+    }
+
+    /// Last value of a varint field (`int32`, `uint32`, `bool`, enums), or
+    /// zero when absent.
+    pub(crate) fn varint(&self, number: u32) -> u64 {
+        return self
+            .values(number)
+            .filter_map(|(_, value)| match value {
+                FieldValue::Varint(v) => Some(v),
+                _ => None,
+            })
+            .last()
+            .unwrap_or(0);
+    }
+
+    /// Every value of a repeated varint field, whether packed or not.
+    pub(crate) fn varints(&self, number: u32) -> Result<Vec<u64>, ProtobufError> {
+        let mut values = vec![];
+        for (offset, value) in self.values(number) {
+            match value {
+                FieldValue::Varint(v) => values.push(v),
+                FieldValue::Bytes(bytes) => {
+                    let mut pos = 0;
+                    while pos < bytes.len() {
+                        values.push(read_varint(bytes, &mut pos, offset)?);
+                    }
+                }
+                _ => {}
+            }
+        }
+        return Ok(values);
+    }
+
+    pub(crate) fn uint32(&self, number: u32) -> u32 {
+        return self.varint(number) as u32;
+    }
+
+    pub(crate) fn bool(&self, number: u32) -> bool {
+        return self.varint(number) != 0;
+    }
+
+    /// Last value of a `fixed32` or `float` field, or zero when absent.
+    pub(crate) fn fixed32(&self, number: u32) -> u32 {
+        return self
+            .values(number)
+            .filter_map(|(_, value)| match value {
+                FieldValue::Fixed32(v) => Some(v),
+                _ => None,
+            })
+            .last()
+            .unwrap_or(0);
+    }
+
+    fn all_bytes(&self, number: u32) -> impl Iterator<Item = (usize, &'a [u8])> + '_ {
+        return self
+            .values(number)
+            .filter_map(|(offset, value)| match value {
+                FieldValue::Bytes(v) => Some((offset, v)),
+                _ => None,
+            });
+    }
+
+    /// Last value of a `string` field, or the empty string when absent.
+    pub(crate) fn string(&self, number: u32) -> String {
+        return self
+            .all_bytes(number)
+            .last()
+            .map(|(_, bytes)| String::from_utf8_lossy(bytes).to_string())
+            .unwrap_or_default();
+    }
+
+    pub(crate) fn strings(&self, number: u32) -> Vec<String> {
+        return self
+            .all_bytes(number)
+            .map(|(_, bytes)| String::from_utf8_lossy(bytes).to_string())
+            .collect();
+    }
+
+    /// The embedded message field `number`; an empty message when absent.
+    pub(crate) fn message(&self, number: u32) -> Result<Message<'a>, ProtobufError> {
+        return match self.all_bytes(number).last() {
+            Some((offset, bytes)) => Message::parse_at(bytes, offset),
+            None => Ok(Message::default()),
+        };
+    }
+
+    pub(crate) fn messages(&self, number: u32) -> Result<Vec<Message<'a>>, ProtobufError> {
+        return self
+            .all_bytes(number)
+            .map(|(offset, bytes)| Message::parse_at(bytes, offset))
+            .collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::ProtoBuilder;
+
+    #[test]
+    fn test_parse() {
+        let mut inner = ProtoBuilder::new();
+        inner.string(1, "inner").varint(2, 300);
+        let mut outer = ProtoBuilder::new();
+        outer
+            .varint(1, 1)
+            .message(2, &inner)
+            .message(2, &inner)
+            .string(3, "a")
+            .string(3, "b")
+            .fixed32(4, 1.5f32.to_bits())
+            .varint(6, 1)
+            .packed(6, &[2, 300]);
+        let bytes = outer.build();
+
+        let message = Message::parse(&bytes).unwrap();
+        assert!(message.bool(1));
+        assert_eq!(message.messages(2).unwrap().len(), 2);
+        assert_eq!(message.message(2).unwrap().string(1), "inner");
+        assert_eq!(message.message(2).unwrap().uint32(2), 300);
+        assert_eq!(message.strings(3), vec!["a", "b"]);
+        assert_eq!(message.string(3), "b");
+        assert_eq!(f32::from_bits(message.fixed32(4)), 1.5);
+        assert!(!message.has(5));
+        assert_eq!(message.varints(6).unwrap(), vec![1, 2, 300]);
+        assert_eq!(message.message(5).unwrap().string(1), "");
+
+        assert_eq!(
+            Message::parse(&bytes[..bytes.len() - 1]).unwrap_err(),
+            ProtobufError::Truncated(bytes.len() - 4)
+        );
+        assert_eq!(
+            Message::parse(&[0x0f]).unwrap_err().to_string(),
+            "invalid protobuf message at offset 0x1: unsupported wire type 7"
+        );
+    }
+}
@@ -0,0 +1,243 @@
+use std::{
+    collections::HashSet,
+    fmt::{self, Display, Formatter},
+    ops::AddAssign,
+};
+
+use serde::Serialize;
+
+use crate::{dex_model::DexModel, package_tree::PackageTree, resolver::DexResolver};
+
+/// Maximum number of entries in each of the `*_ids` sections of a dex file.
+pub const INDEX_LIMIT: usize = 65536;
+
+/// Per-package reference counts. A method or field is counted once for every
+/// reference in `method_ids` / `field_ids`; it is "defined" when the dex also
+/// contains its implementation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct RefCounts {
+    pub methods: usize,
+    pub defined_methods: usize,
+    pub fields: usize,
+    pub defined_fields: usize,
+    pub types: usize,
+}
+
+impl AddAssign for RefCounts {
+    fn add_assign(&mut self, other: Self) {
+        self.methods += other.methods;
+        self.defined_methods += other.defined_methods;
+        self.fields += other.fields;
+        self.defined_fields += other.defined_fields;
+        self.types += other.types;
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DexRefCounts {
+    pub dex: String,
+    pub method_ids: usize,
+    pub field_ids: usize,
+    pub type_ids: usize,
+    pub string_ids: usize,
+    pub packages: PackageTree<RefCounts>,
+}
+
+impl DexRefCounts {
+    pub fn new(name: &str, resolver: &DexResolver) -> DexRefCounts {
+        let dex = resolver.dex;
+        let mut defined_methods = HashSet::new();
+        let mut defined_fields = HashSet::new();
+        for class_def in dex.class_defs.iter() {
+            defined_methods.extend(resolver.class_methods(class_def).iter().map(|(i, _)| *i));
+            defined_fields.extend(resolver.class_fields(class_def).iter().map(|(i, _)| *i));
+        }
+
+        let mut packages = PackageTree::new();
+        for (method_idx, method_id) in dex.method_ids.iter().enumerate() {
+            let defined = defined_methods.contains(&(method_idx as u32));
+            packages.add(
+                &resolver.type_name(method_id.class_idx as u32),
+                RefCounts {
+                    methods: 1,
+                    defined_methods: defined as usize,
+                    ..Default::default()
+                },
+            );
+        }
+        for (field_idx, field_id) in dex.field_ids.iter().enumerate() {
+            let defined = defined_fields.contains(&(field_idx as u32));
+            packages.add(
+                &resolver.type_name(field_id.class_idx as u32),
+                RefCounts {
+                    fields: 1,
+                    defined_fields: defined as usize,
+                    ..Default::default()
+                },
+            );
+        }
+        for type_idx in 0..dex.type_ids.len() {
+            packages.add(
+                &resolver.type_name(type_idx as u32),
+                RefCounts {
+                    types: 1,
+                    ..Default::default()
+                },
+            );
+        }
+
+        return DexRefCounts {
+            dex: name.to_string(),
+            method_ids: dex.method_ids.len(),
+            field_ids: dex.field_ids.len(),
+            type_ids: dex.type_ids.len(),
+            string_ids: dex.string_ids.len(),
+            packages,
+        };
+    }
+}
+
+/// Reference counts of every dex in a build against the 64K index limits,
+/// broken down by package.
+#[derive(Debug, Clone, Serialize)]
+pub struct RefCountReport {
+    pub dexes: Vec<DexRefCounts>,
+}
+
+impl RefCountReport {
+    /// Counts references in `(entry name, dex)` pairs, e.g. the output of
+    /// `apk::load_dex_files`.
+    pub fn new(dexes: &[(String, DexModel)]) -> RefCountReport {
+        return RefCountReport {
+            dexes: dexes
+                .iter()
+                .map(|(name, dex)| DexRefCounts::new(name, &DexResolver::new(dex)))
+                .collect(),
+        };
+    }
+
+    /// Counts references in `(entry name, resolver)` pairs, grouping each
+    /// referenced member under the package of its class as the resolver
+    /// names it.
+    pub fn from_resolvers(dexes: &[(String, DexResolver)]) -> RefCountReport {
+        return RefCountReport {
+            dexes: dexes
+                .iter()
+                .map(|(name, resolver)| DexRefCounts::new(name, resolver))
+                .collect(),
+        };
+    }
+
+    /// One row per dex and package, plus a `<total>` row per dex.
+    pub fn to_csv(&self, max_depth: Option<usize>) -> String {
+        let mut csv =
+            String::from("dex,package,methods,defined_methods,fields,defined_fields,types\n");
+        for dex in self.dexes.iter() {
+            let mut rows = vec![("<total>".to_string(), &dex.packages.total)];
+            rows.extend(
+                dex.packages
+                    .packages(max_depth)
+                    .into_iter()
+                    .map(|(name, _, counts)| (name, counts)),
+            );
+            for (package, counts) in rows {
+                csv.push_str(&format!(
+                    "{},{},{},{},{},{},{}\n",
+                    dex.dex,
+                    package,
+                    counts.methods,
+                    counts.defined_methods,
+                    counts.fields,
+                    counts.defined_fields,
+                    counts.types
+                ));
+            }
+        }
+        return csv;
+    }
+
+    pub fn to_json(&self) -> String {
+        return serde_json::to_string_pretty(self).unwrap();
+    }
+
+    /// Text report limited to packages at most `max_depth` segments deep.
+    pub fn to_text(&self, max_depth: Option<usize>) -> String {
+        let mut text = String::new();
+        for dex in self.dexes.iter() {
+            text.push_str(&format!("{}\n", dex.dex));
+            for (label, count) in [
+                ("method_ids", dex.method_ids),
+                ("field_ids", dex.field_ids),
+                ("type_ids", dex.type_ids),
+                ("string_ids", dex.string_ids),
+            ] {
+                text.push_str(&format!(
+                    "  {:<10} {:>6} / {} ({:.1}%)\n",
+                    label,
+                    count,
+                    INDEX_LIMIT,
+                    count as f64 * 100.0 / INDEX_LIMIT as f64
+                ));
+            }
+            text.push_str(&format!(
+                "  {:<40} {:>8} {:>8} {:>8} {:>8} {:>8}\n",
+                "package", "methods", "defined", "fields", "defined", "types"
+            ));
+            for (package, depth, counts) in dex.packages.packages(max_depth) {
+                let segment = package.rsplit('.').next().unwrap_or(&package);
+                text.push_str(&format!(
+                    "  {:<40} {:>8} {:>8} {:>8} {:>8} {:>8}\n",
+                    format!("{}{}", "  ".repeat(depth - 1), segment),
+                    counts.methods,
+                    counts.defined_methods,
+                    counts.fields,
+                    counts.defined_fields,
+                    counts.types
+                ));
+            }
+        }
+        return text;
+    }
+}
+
+impl Display for RefCountReport {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        return write!(f, "{}", self.to_text(None));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{build_dex, TestClass};
+
+    #[test]
+    fn test_ref_counts() {
+        let dex = build_dex(
+            vec![
+                TestClass::new("Lcom/example/Foo;")
+                    .method("run", &[], "V", &[0x000e])
+                    .field("count", "I"),
+                TestClass::new("Lcom/example/ui/Bar;").method("draw", &[], "V", &[0x000e]),
+            ],
+            &[],
+        );
+        let report = RefCountReport::new(&[("classes.dex".to_string(), dex)]);
+        let counts = &report.dexes[0];
+        assert_eq!(counts.method_ids, 2);
+        assert_eq!(counts.field_ids, 1);
+
+        let com = counts.packages.get("com.example").unwrap().total;
+        assert_eq!(com.methods, 2);
+        assert_eq!(com.defined_methods, 2);
+        assert_eq!(com.fields, 1);
+        assert_eq!(com.defined_fields, 1);
+        assert_eq!(com.types, 2);
+        assert_eq!(counts.packages.get("java.lang").unwrap().total.types, 1);
+
+        let csv = report.to_csv(Some(2));
+        assert!(csv.contains("classes.dex,com.example,2,2,1,1,2\n"));
+        assert!(!csv.contains("com.example.ui"));
+        assert!(report.to_text(None).contains("method_ids      2 / 65536"));
+    }
+}
@@ -0,0 +1,394 @@
+//! Call sites that reach code by name at runtime: reflection, class loading
+//! and native library loading.
+//!
+//! Names are recovered from constants feeding the argument registers within
+//! the calling method; see `ConstTracker` for the limits of that analysis.
+
+use std::{
+    collections::BTreeSet,
+    fmt::{self, Display, Formatter},
+};
+
+use serde::Serialize;
+
+use crate::{
+    apk::{Apk, ApkError},
+    const_tracker::{parameter_registers, ConstTracker, ConstValue},
+    dex_model::DexModel,
+    instructions::IndexKind,
+    opcodes::{opcode_info, MOVE_RESULT, STATIC},
+    resolver::{descriptor_to_java, java_to_descriptor, DexResolver, MethodRef},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReflectionKind {
+    /// `Class.forName`
+    ForName,
+    /// `ClassLoader.loadClass` and overrides in loader subclasses.
+    LoadClass,
+    /// `Class.getMethod` and `Class.getDeclaredMethod`.
+    GetMethod,
+    /// `Class.getField` and `Class.getDeclaredField`.
+    GetField,
+    /// `Method.invoke`
+    Invoke,
+    /// `System.loadLibrary` and `Runtime.loadLibrary`.
+    LoadLibrary,
+}
+
+impl ReflectionKind {
+    fn of(method: &MethodRef) -> Option<ReflectionKind> {
+        let string_parameter =
+            method.parameters.first().map(|p| p.as_str()) == Some("Ljava/lang/String;");
+        return match (method.class.as_str(), method.name.as_str()) {
+            ("Ljava/lang/Class;", "forName") => Some(ReflectionKind::ForName),
+            ("Ljava/lang/Class;", "getMethod" | "getDeclaredMethod") => {
+                Some(ReflectionKind::GetMethod)
+            }
+            ("Ljava/lang/Class;", "getField" | "getDeclaredField") => {
+                Some(ReflectionKind::GetField)
+            }
+            ("Ljava/lang/reflect/Method;", "invoke") => Some(ReflectionKind::Invoke),
+            ("Ljava/lang/System;" | "Ljava/lang/Runtime;", "loadLibrary") => {
+                Some(ReflectionKind::LoadLibrary)
+            }
+            (_, "loadClass") if string_parameter && method.return_type == "Ljava/lang/Class;" => {
+                Some(ReflectionKind::LoadClass)
+            }
+            _ => None,
+        };
+    }
+}
+
+impl Display for ReflectionKind {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let name = match self {
+            ReflectionKind::ForName => "Class.forName",
+            ReflectionKind::LoadClass => "ClassLoader.loadClass",
+            ReflectionKind::GetMethod => "Class.getMethod",
+            ReflectionKind::GetField => "Class.getField",
+            ReflectionKind::Invoke => "Method.invoke",
+            ReflectionKind::LoadLibrary => "System.loadLibrary",
+        };
+        return write!(f, "{}", name);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ReflectionSite {
+    pub kind: ReflectionKind,
+    pub dex: String,
+    /// Java name of the class containing the call.
+    pub class: String,
+    pub method: String,
+    /// Code unit offset of the call within the method.
+    pub address: u32,
+    pub line: Option<u32>,
+    /// Java name of the class looked up, or whose member is looked up, when
+    /// it is a constant.
+    pub target_class: Option<String>,
+    /// Name of the method or field looked up, or of the native library
+    /// loaded, when it is a constant.
+    pub name: Option<String>,
+}
+
+/// Every reflection and dynamic loading call site of a build, with the
+/// names they use where they are constants.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ReflectionReport {
+    pub sites: Vec<ReflectionSite>,
+    /// Native libraries loaded by name that no `lib/<abi>/` entry provides;
+    /// only filled by `from_apk`.
+    pub missing_libraries: Vec<String>,
+}
+
+fn scan_dex(dex: &str, resolver: &DexResolver, sites: &mut Vec<ReflectionSite>) {
+    for class_def in resolver.dex.class_defs.iter() {
+        let class = resolver.type_name(class_def.class_idx);
+        for (method_idx, method) in resolver.class_methods(class_def) {
+            let code = match resolver.code_item(method.code_off) {
+                Some(code) if method.code_off != 0 => code,
+                _ => continue,
+            };
+            let mut tracker = ConstTracker::new();
+            // Class named by the last `Class.forName` with a constant name,
+            // until its result is moved into a register.
+            let mut loaded_class = None;
+            let mut address = 0;
+            for insn in code.insns.iter() {
+                let target = insn
+                    .index_refs()
+                    .into_iter()
+                    .find(|r| r.kind == IndexKind::Method)
+                    .map(|r| resolver.method_ref(r.index));
+                let kind = target.as_ref().and_then(ReflectionKind::of);
+                if let (Some(target), Some(kind)) = (target, kind) {
+                    let is_static = opcode_info(insn.opcode()).has(STATIC);
+                    let registers = parameter_registers(&target.parameters, is_static);
+                    let arguments = tracker.arguments(insn);
+                    let string_argument = registers
+                        .first()
+                        .and_then(|r| arguments.get(*r).copied().flatten())
+                        .and_then(|v| v.as_str())
+                        .map(String::from);
+                    let receiver_class = match arguments.first().copied().flatten() {
+                        Some(ConstValue::Class(descriptor)) if !is_static => {
+                            Some(descriptor_to_java(descriptor))
+                        }
+                        _ => None,
+                    };
+                    let (target_class, name) = match kind {
+                        ReflectionKind::ForName | ReflectionKind::LoadClass => {
+                            (string_argument, None)
+                        }
+                        ReflectionKind::GetMethod | ReflectionKind::GetField => {
+                            (receiver_class, string_argument)
+                        }
+                        ReflectionKind::Invoke => (None, None),
+                        ReflectionKind::LoadLibrary => (None, string_argument),
+                    };
+                    loaded_class = match kind {
+                        ReflectionKind::ForName | ReflectionKind::LoadClass => target_class.clone(),
+                        _ => None,
+                    };
+                    sites.push(ReflectionSite {
+                        kind,
+                        dex: dex.to_string(),
+                        class: class.clone(),
+                        method: resolver.method_display(method_idx),
+                        address,
+                        line: resolver.line_for_address(code, address),
+                        target_class,
+                        name,
+                    });
+                    tracker.step(insn, resolver);
+                    address += insn.size() as u32 / 2;
+                    continue;
+                }
+
+                tracker.step(insn, resolver);
+                // The class returned by a constant `forName` is known once
+                // the following move-result-object stores it.
+                if let Some(class_name) = loaded_class.take() {
+                    if opcode_info(insn.opcode()).has(MOVE_RESULT) {
+                        let dest = insn.written_register().unwrap();
+                        tracker.set(dest, ConstValue::Class(java_to_descriptor(&class_name)));
+                    }
+                }
+                address += insn.size() as u32 / 2;
+            }
+        }
+    }
+}
+
+impl ReflectionReport {
+    pub fn new(dexes: &[(String, DexModel)]) -> ReflectionReport {
+        let resolvers = dexes
+            .iter()
+            .map(|(name, dex)| (name.clone(), DexResolver::new(dex)))
+            .collect::<Vec<_>>();
+        return ReflectionReport::from_resolvers(&resolvers);
+    }
+
+    /// Collects the reflection, class loading and native library calls of
+    /// each `(entry name, resolver)` pair, attributed to their calling
+    /// method.
+    pub fn from_resolvers(dexes: &[(String, DexResolver)]) -> ReflectionReport {
+        let mut sites = vec![];
+        for (name, resolver) in dexes {
+            scan_dex(name, resolver, &mut sites);
+        }
+        return ReflectionReport {
+            sites,
+            missing_libraries: vec![],
+        };
+    }
+
+    /// Scans the dex files of an APK and checks the native libraries it
+    /// loads against its `lib/<abi>/` entries.
+    pub fn from_apk(apk: &mut Apk) -> Result<ReflectionReport, ApkError> {
+        let mut report = ReflectionReport::new(&apk.dex_files()?);
+        report.missing_libraries = report.missing_libraries(&apk.entry_names());
+        return Ok(report);
+    }
+
+    /// Classes looked up by constant name, which must survive shrinking.
+    pub fn classes(&self) -> BTreeSet<&str> {
+        return self
+            .sites
+            .iter()
+            .filter_map(|site| site.target_class.as_deref())
+            .collect();
+    }
+
+    /// Native libraries loaded by constant name, e.g. `native-lib` for
+    /// `lib/<abi>/libnative-lib.so`.
+    pub fn libraries(&self) -> BTreeSet<&str> {
+        return self
+            .sites
+            .iter()
+            .filter(|site| site.kind == ReflectionKind::LoadLibrary)
+            .filter_map(|site| site.name.as_deref())
+            .collect();
+    }
+
+    /// Libraries loaded by name for which none of `entry_names` is a
+    /// `lib/<abi>/lib<name>.so`.
+    pub fn missing_libraries(&self, entry_names: &[String]) -> Vec<String> {
+        return self
+            .libraries()
+            .into_iter()
+            .filter(|library| {
+                let file_name = format!("lib{}.so", library);
+                !entry_names.iter().any(|entry| {
+                    let parts = entry.split('/').collect::<Vec<_>>();
+                    parts.len() == 3 && parts[0] == "lib" && parts[2] == file_name
+                })
+            })
+            .map(String::from)
+            .collect();
+    }
+
+    /// ProGuard/R8 rules keeping the classes and members looked up by
+    /// constant name.
+    pub fn keep_rules(&self) -> String {
+        let mut rules = BTreeSet::new();
+        for site in self.sites.iter() {
+            let class = match &site.target_class {
+                Some(class) => class,
+                None => continue,
+            };
+            let rule = match (site.kind, &site.name) {
+                (ReflectionKind::GetMethod, Some(name)) => {
+                    format!("-keep class {} {{ *** {}(...); }}", class, name)
+                }
+                (ReflectionKind::GetField, Some(name)) => {
+                    format!("-keep class {} {{ *** {}; }}", class, name)
+                }
+                _ => format!("-keep class {}", class),
+            };
+            rules.insert(rule);
+        }
+        return rules.into_iter().map(|r| r + "\n").collect();
+    }
+
+    pub fn to_json(&self) -> String {
+        return serde_json::to_string_pretty(self).unwrap();
+    }
+}
+
+impl Display for ReflectionReport {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for site in self.sites.iter() {
+            let line = site.line.map(|l| format!(":{}", l)).unwrap_or_default();
+            write!(f, "{} in {} ({}{})", site.kind, site.method, site.dex, line)?;
+            match (&site.target_class, &site.name) {
+                (Some(class), Some(name)) => write!(f, ": {}.{}", class, name)?,
+                (Some(class), None) => write!(f, ": {}", class)?,
+                (None, Some(name)) => write!(f, ": {}", name)?,
+                (None, None) => {}
+            }
+            writeln!(f)?;
+        }
+        for library in self.missing_libraries.iter() {
+            writeln!(f, "missing native library: lib{}.so", library)?;
+        }
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{build_dex, TestClass};
+
+    #[test]
+    fn test_reflection_sites() {
+        let dex = build_dex(
+            vec![
+                // const-string v0, "com.example.Plugin";
+                // invoke-static {v0}, Class.forName; move-result-object v1;
+                // const-string v0, "start"; const/4 v2, 0;
+                // invoke-virtual {v1, v0, v2}, Class.getMethod;
+                // const-string v0, "native-lib";
+                // invoke-static {v0}, System.loadLibrary; return-void
+                TestClass::new("Lcom/app/Main;").method(
+                    "run",
+                    &[],
+                    "V",
+                    &[
+                        0x001a, 0x000b, 0x1071, 0x0001, 0x0000, 0x010c, 0x001a, 0x0011, 0x0212,
+                        0x306e, 0x0002, 0x0201, 0x001a, 0x000f, 0x1071, 0x0003, 0x0000, 0x000e,
+                    ],
+                ),
+                TestClass::new("Ljava/lang/Class;")
+                    .method(
+                        "forName",
+                        &["Ljava/lang/String;"],
+                        "Ljava/lang/Class;",
+                        &[0x000e],
+                    )
+                    .method(
+                        "getMethod",
+                        &["Ljava/lang/String;", "[Ljava/lang/Class;"],
+                        "Ljava/lang/reflect/Method;",
+                        &[0x000e],
+                    ),
+                TestClass::new("Ljava/lang/System;").method(
+                    "loadLibrary",
+                    &["Ljava/lang/String;"],
+                    "V",
+                    &[0x000e],
+                ),
+            ],
+            &["com.example.Plugin", "start", "native-lib"],
+        );
+        let resolver = DexResolver::new(&dex);
+        assert_eq!(resolver.string(0x0b), "com.example.Plugin");
+        assert_eq!(resolver.string(0x0f), "native-lib");
+        assert_eq!(resolver.string(0x11), "start");
+        assert_eq!(resolver.method_ref(1).name, "forName");
+        assert_eq!(resolver.method_ref(2).name, "getMethod");
+        assert_eq!(resolver.method_ref(3).name, "loadLibrary");
+
+        let report = ReflectionReport::new(&[("classes.dex".to_string(), dex)]);
+        let sites = report
+            .sites
+            .iter()
+            .map(|s| {
+                (
+                    s.kind,
+                    s.address,
+                    s.target_class.as_deref(),
+                    s.name.as_deref(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sites,
+            vec![
+                (ReflectionKind::ForName, 2, Some("com.example.Plugin"), None),
+                (
+                    ReflectionKind::GetMethod,
+                    9,
+                    Some("com.example.Plugin"),
+                    Some("start")
+                ),
+                (ReflectionKind::LoadLibrary, 14, None, Some("native-lib")),
+            ]
+        );
+        assert_eq!(
+            report.keep_rules(),
+            "-keep class com.example.Plugin\n\
+             -keep class com.example.Plugin { *** start(...); }\n"
+        );
+        assert_eq!(
+            report.missing_libraries(&["lib/arm64-v8a/libother.so".to_string()]),
+            vec!["native-lib"]
+        );
+        assert!(report
+            .missing_libraries(&["lib/x86/libnative-lib.so".to_string()])
+            .is_empty());
+    }
+}
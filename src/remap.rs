@@ -0,0 +1,279 @@
+//! Rewrites the pool indices stored throughout a `DexModel`, for
+//! transformations that rebuild the id pools such as merging and pruning.
+
+use std::collections::HashMap;
+
+use crate::{
+    dex_model::DexModel,
+    dex_structs::{
+        CodeItem, DexStruct, EncodedAnnotation, EncodedCatchHandlerList, EncodedField,
+        EncodedMethod, EncodedValue, StringDataItem, TypeCode,
+    },
+    encode::size_uleb128,
+    instructions::{IndexKind, IndexRef},
+    layout::ItemOffsets,
+    resolver::NO_INDEX,
+};
+
+/// Method handle types below this access a field; the others invoke a
+/// method.
+pub(crate) const METHOD_HANDLE_TYPE_INVOKE_STATIC: u16 = 0x04;
+
+pub(crate) fn pool_name(kind: IndexKind) -> &'static str {
+    return match kind {
+        IndexKind::String => "string",
+        IndexKind::Type => "type",
+        IndexKind::Field => "field",
+        IndexKind::Method => "method",
+        IndexKind::Proto => "proto",
+        IndexKind::CallSite => "call site",
+        IndexKind::MethodHandle => "method handle",
+    };
+}
+
+/// Identity of a string: its UTF-16 code units, which give the pool order,
+/// and its encoded bytes.
+pub(crate) type StringKey = (Vec<u16>, Vec<u8>);
+
+pub(crate) fn string_key(item: &StringDataItem) -> StringKey {
+    return (
+        item.to_string_lossy().encode_utf16().collect(),
+        item.data.clone(),
+    );
+}
+
+/// Maps an index that may be `NO_INDEX`.
+fn optional(kind: IndexKind, index: u32, f: &mut dyn FnMut(IndexKind, u32) -> u32) -> u32 {
+    if index == NO_INDEX {
+        return NO_INDEX;
+    }
+    return f(kind, index);
+}
+
+/// Replaces every index stored outside the id pools with `f(kind, index)`:
+/// in class definitions, class data, code, debug info, type lists, encoded
+/// values and annotations. Lists the format keeps sorted by index are sorted
+/// again, so `f` need not preserve order. Fails if an instruction operand
+/// cannot hold its new index.
+pub(crate) fn remap_indices(
+    dex: &mut DexModel,
+    f: &mut dyn FnMut(IndexKind, u32) -> u32,
+) -> Result<(), IndexRef> {
+    for class_def in dex.class_defs.iter_mut() {
+        class_def.class_idx = f(IndexKind::Type, class_def.class_idx);
+        class_def.superclass_idx = optional(IndexKind::Type, class_def.superclass_idx, f);
+        class_def.source_file_idx = optional(IndexKind::String, class_def.source_file_idx, f);
+    }
+    for class_data in dex.class_data_items.iter_mut() {
+        remap_fields(&mut class_data.static_fields, f);
+        remap_fields(&mut class_data.instance_fields, f);
+        remap_methods(&mut class_data.direct_methods, f);
+        remap_methods(&mut class_data.virtual_methods, f);
+    }
+    for code_item in dex.code_items.iter_mut() {
+        for insn in code_item.insns.iter_mut() {
+            insn.remap_indices(f)?;
+        }
+        remap_handlers(code_item, f);
+    }
+    for debug_info in dex.debug_info_items.iter_mut() {
+        debug_info.remap_indices(f);
+    }
+    for type_list in dex.type_lists.iter_mut() {
+        for item in type_list.list.iter_mut() {
+            item.type_idx = f(IndexKind::Type, item.type_idx as u32) as u16;
+        }
+    }
+    for array in dex.encoded_array_items.iter_mut() {
+        for value in array.value.values.iter_mut() {
+            remap_value(value, f);
+        }
+    }
+    for annotation in dex.annotation_items.iter_mut() {
+        remap_annotation(&mut annotation.annotation, f);
+    }
+    for directory in dex.annotations_directory_items.iter_mut() {
+        for field in directory.field_annotations.iter_mut() {
+            field.field_idx = f(IndexKind::Field, field.field_idx);
+        }
+        for method in directory.method_annotations.iter_mut() {
+            method.method_idx = f(IndexKind::Method, method.method_idx);
+        }
+        for parameter in directory.parameter_annotations.iter_mut() {
+            parameter.method_idx = f(IndexKind::Method, parameter.method_idx);
+        }
+        directory.field_annotations.sort_by_key(|a| a.field_idx);
+        directory.method_annotations.sort_by_key(|a| a.method_idx);
+        directory
+            .parameter_annotations
+            .sort_by_key(|a| a.method_idx);
+    }
+
+    // Annotation sets are sorted by the type of their annotations.
+    let annotation_index = ItemOffsets::of(dex)
+        .get(TypeCode::TypeAnnotationItem)
+        .iter()
+        .enumerate()
+        .map(|(i, offset)| (*offset, i))
+        .collect::<HashMap<_, _>>();
+    let annotations = &dex.annotation_items;
+    for set in dex.annotation_set_items.iter_mut() {
+        set.entries.sort_by_key(|entry| {
+            annotation_index
+                .get(&entry.annotation_off)
+                .map(|i| annotations[*i].annotation.type_idx)
+        });
+    }
+    return Ok(());
+}
+
+/// Remaps the ids of diff-encoded class data members, keeping them sorted.
+fn remap_fields(fields: &mut Vec<EncodedField>, f: &mut dyn FnMut(IndexKind, u32) -> u32) {
+    let mut index = 0;
+    let mut members = fields
+        .iter()
+        .map(|field| {
+            index += field.field_idx_off;
+            (f(IndexKind::Field, index), field.access_flags)
+        })
+        .collect::<Vec<_>>();
+    members.sort();
+    let mut prev = 0;
+    *fields = members
+        .into_iter()
+        .map(|(index, access_flags)| {
+            let field = EncodedField {
+                field_idx_off: index - prev,
+                access_flags,
+            };
+            prev = index;
+            field
+        })
+        .collect();
+}
+
+fn remap_methods(methods: &mut Vec<EncodedMethod>, f: &mut dyn FnMut(IndexKind, u32) -> u32) {
+    let mut index = 0;
+    let mut members = methods
+        .iter()
+        .map(|method| {
+            index += method.method_idx_off;
+            (
+                f(IndexKind::Method, index),
+                method.access_flags,
+                method.code_off,
+            )
+        })
+        .collect::<Vec<_>>();
+    members.sort();
+    let mut prev = 0;
+    *methods = members
+        .into_iter()
+        .map(|(index, access_flags, code_off)| {
+            let method = EncodedMethod {
+                method_idx_off: index - prev,
+                access_flags,
+                code_off,
+            };
+            prev = index;
+            method
+        })
+        .collect();
+}
+
+/// Byte offset of each handler within the encoded handler list, as
+/// referenced by `TryItem::handler_off`.
+pub(crate) fn handler_offsets(handlers: &EncodedCatchHandlerList) -> Vec<usize> {
+    let mut offset = size_uleb128(handlers.list.len() as u32);
+    let mut offsets = vec![];
+    for handler in handlers.list.iter() {
+        offsets.push(offset);
+        offset += handler.size();
+    }
+    return offsets;
+}
+
+/// Remaps the caught types, which are variable-length, so the handler
+/// offsets of the try blocks follow.
+fn remap_handlers(code_item: &mut CodeItem, f: &mut dyn FnMut(IndexKind, u32) -> u32) {
+    let handlers = match code_item.handlers.as_mut() {
+        Some(handlers) => handlers,
+        None => return,
+    };
+    let before = handler_offsets(handlers);
+    for handler in handlers.list.iter_mut() {
+        for pair in handler.handlers.iter_mut() {
+            pair.type_idx = f(IndexKind::Type, pair.type_idx);
+        }
+    }
+    let after = handler_offsets(handlers);
+    for try_item in code_item.tries.iter_mut() {
+        if let Some(i) = before
+            .iter()
+            .position(|offset| *offset == try_item.handler_off as usize)
+        {
+            try_item.handler_off = after[i] as u16;
+        }
+    }
+}
+
+fn remap_value(value: &mut EncodedValue, f: &mut dyn FnMut(IndexKind, u32) -> u32) {
+    match value {
+        EncodedValue::ValueMethodType(index) => *index = f(IndexKind::Proto, *index),
+        EncodedValue::ValueMethodHandle(index) => *index = f(IndexKind::MethodHandle, *index),
+        EncodedValue::ValueString(index) => *index = f(IndexKind::String, *index),
+        EncodedValue::ValueType(index) => *index = f(IndexKind::Type, *index),
+        EncodedValue::ValueField(index) | EncodedValue::ValueEnum(index) => {
+            *index = f(IndexKind::Field, *index)
+        }
+        EncodedValue::ValueMethod(index) => *index = f(IndexKind::Method, *index),
+        EncodedValue::ValueArray(array) => {
+            for value in array.values.iter_mut() {
+                remap_value(value, f);
+            }
+        }
+        EncodedValue::ValueAnnotation(annotation) => remap_annotation(annotation, f),
+        _ => {}
+    }
+}
+
+/// Remaps an annotation, keeping its elements sorted by name.
+fn remap_annotation(annotation: &mut EncodedAnnotation, f: &mut dyn FnMut(IndexKind, u32) -> u32) {
+    annotation.type_idx = f(IndexKind::Type, annotation.type_idx);
+    for element in annotation.elements.iter_mut() {
+        element.name_idx = f(IndexKind::String, element.name_idx);
+        remap_value(&mut element.value, f);
+    }
+    annotation.elements.sort_by_key(|element| element.name_idx);
+}
+
+/// Replaces the indices stored in the id pools themselves: descriptors,
+/// proto components, member ids and method handle targets. Parameter lists
+/// are type lists, which `remap_indices` covers.
+pub(crate) fn remap_pool_indices(dex: &mut DexModel, f: &mut dyn FnMut(IndexKind, u32) -> u32) {
+    for type_id in dex.type_ids.iter_mut() {
+        type_id.descriptor_idx = f(IndexKind::String, type_id.descriptor_idx);
+    }
+    for proto in dex.proto_ids.iter_mut() {
+        proto.shorty_idx = f(IndexKind::String, proto.shorty_idx);
+        proto.return_type_idx = f(IndexKind::Type, proto.return_type_idx);
+    }
+    for field in dex.field_ids.iter_mut() {
+        field.class_idx = f(IndexKind::Type, field.class_idx as u32) as u16;
+        field.type_idx = f(IndexKind::Type, field.type_idx as u32) as u16;
+        field.name_idx = f(IndexKind::String, field.name_idx);
+    }
+    for method in dex.method_ids.iter_mut() {
+        method.class_idx = f(IndexKind::Type, method.class_idx as u32) as u16;
+        method.proto_idx = f(IndexKind::Proto, method.proto_idx as u32) as u16;
+        method.name_idx = f(IndexKind::String, method.name_idx);
+    }
+    for handle in dex.method_handles.iter_mut() {
+        let kind = if handle.method_handle_type < METHOD_HANDLE_TYPE_INVOKE_STATIC {
+            IndexKind::Field
+        } else {
+            IndexKind::Method
+        };
+        handle.field_or_method_id = f(kind, handle.field_or_method_id as u32) as u16;
+    }
+}
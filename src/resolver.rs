@@ -0,0 +1,549 @@
+use std::collections::HashMap;
+
+use crate::{
+    dex_model::DexModel,
+    dex_structs::{
+        AnnotationItem, AnnotationSetItem, AnnotationSetRefList, AnnotationsDirectoryItem,
+        ClassDataItem, ClassDefItem, CodeItem, DebugInfoItem, DexStruct, EncodedArrayItem,
+        EncodedField, EncodedMethod, MapList, StringDataItem, TypeCode, TypeList,
+    },
+    mapping::Mapping,
+};
+
+/// Marker for an absent index, e.g. `ClassDefItem::superclass_idx` of
+/// `java.lang.Object`.
+pub const NO_INDEX: u32 = 0xffffffff;
+
+/// Computes the file offset of every item in a data section by walking the
+/// section from its map offset, honoring the item alignment.
+pub(crate) fn section_offsets<T: DexStruct>(
+    map_list: &MapList,
+    type_code: TypeCode,
+    items: &[T],
+) -> Vec<u32> {
+    let mut offsets = Vec::with_capacity(items.len());
+    let mut offset = match map_list.get(type_code) {
+        Some(map_item) => map_item.offset as u64,
+        None => return offsets,
+    };
+    for item in items {
+        while offset % T::ALIGNMENT != 0 {
+            offset += 1;
+        }
+        offsets.push(offset as u32);
+        offset += item.size() as u64;
+    }
+    return offsets;
+}
+
+fn offset_index<T: DexStruct>(
+    map_list: &MapList,
+    type_code: TypeCode,
+    items: &[T],
+) -> HashMap<u32, usize> {
+    return section_offsets(map_list, type_code, items)
+        .into_iter()
+        .enumerate()
+        .map(|(i, off)| (off, i))
+        .collect();
+}
+
+impl StringDataItem {
+    /// Decodes the MUTF-8 payload, replacing invalid sequences.
+    pub fn to_string_lossy(&self) -> String {
+        let bytes = self.data.strip_suffix(&[0]).unwrap_or(&self.data);
+        return match mutf8::decode(bytes) {
+            Ok(s) => s.into_owned(),
+            Err(_) => String::from_utf8_lossy(bytes).into_owned(),
+        };
+    }
+}
+
+/// Converts a type descriptor such as `[Ljava/lang/String;` to its Java name,
+/// `java.lang.String[]`.
+pub fn descriptor_to_java(descriptor: &str) -> String {
+    let element = descriptor.trim_start_matches('[');
+    let dims = descriptor.len() - element.len();
+    let name = match element {
+        "V" => "void".to_string(),
+        "Z" => "boolean".to_string(),
+        "B" => "byte".to_string(),
+        "S" => "short".to_string(),
+        "C" => "char".to_string(),
+        "I" => "int".to_string(),
+        "J" => "long".to_string(),
+        "F" => "float".to_string(),
+        "D" => "double".to_string(),
+        _ => element
+            .strip_prefix('L')
+            .and_then(|e| e.strip_suffix(';'))
+            .unwrap_or(element)
+            .replace('/', "."),
+    };
+    return format!("{}{}", name, "[]".repeat(dims));
+}
+
+/// Converts a Java type name such as `int[]` to its descriptor, `[I`.
+pub fn java_to_descriptor(name: &str) -> String {
+    let element = name.trim_end_matches("[]");
+    let dims = (name.len() - element.len()) / 2;
+    let descriptor = match element {
+        "void" => "V".to_string(),
+        "boolean" => "Z".to_string(),
+        "byte" => "B".to_string(),
+        "short" => "S".to_string(),
+        "char" => "C".to_string(),
+        "int" => "I".to_string(),
+        "long" => "J".to_string(),
+        "float" => "F".to_string(),
+        "double" => "D".to_string(),
+        _ => format!("L{};", element.replace('.', "/")),
+    };
+    return format!("{}{}", "[".repeat(dims), descriptor);
+}
+
+/// Pairs each encoded method with its absolute `method_ids` index, undoing the
+/// delta encoding of `method_idx_off`.
+pub fn method_indices(methods: &[EncodedMethod]) -> Vec<(u32, &EncodedMethod)> {
+    let mut idx = 0u32;
+    return methods
+        .iter()
+        .map(|method| {
+            idx += method.method_idx_off;
+            (idx, method)
+        })
+        .collect();
+}
+
+/// Pairs each encoded field with its absolute `field_ids` index, undoing the
+/// delta encoding of `field_idx_off`.
+pub fn field_indices(fields: &[EncodedField]) -> Vec<(u32, &EncodedField)> {
+    let mut idx = 0u32;
+    return fields
+        .iter()
+        .map(|field| {
+            idx += field.field_idx_off;
+            (idx, field)
+        })
+        .collect();
+}
+
+/// A method reference with every index resolved to a descriptor or name.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MethodRef {
+    pub class: String,
+    pub name: String,
+    pub parameters: Vec<String>,
+    pub return_type: String,
+}
+
+impl MethodRef {
+    /// Smali-style signature, e.g. `Lcom/Foo;->bar(I)V`.
+    pub fn signature(&self) -> String {
+        return format!(
+            "{}->{}({}){}",
+            self.class,
+            self.name,
+            self.parameters.join(""),
+            self.return_type
+        );
+    }
+}
+
+/// A field reference with every index resolved to a descriptor or name.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FieldRef {
+    pub class: String,
+    pub name: String,
+    pub field_type: String,
+}
+
+impl FieldRef {
+    /// Smali-style signature, e.g. `Lcom/Foo;->bar:I`.
+    pub fn signature(&self) -> String {
+        return format!("{}->{}:{}", self.class, self.name, self.field_type);
+    }
+}
+
+/// Resolves the raw indices and file offsets stored in a `DexModel` into the
+/// strings and items they refer to.  When a `Mapping` is attached, the
+/// `*_name` and `*_display` methods report de-obfuscated names.
+pub struct DexResolver<'a> {
+    pub dex: &'a DexModel,
+    mapping: Option<&'a Mapping>,
+    strings: Vec<String>,
+    class_defs_by_type: HashMap<u32, usize>,
+    string_data_items: HashMap<u32, usize>,
+    type_lists: HashMap<u32, usize>,
+    class_data_items: HashMap<u32, usize>,
+    code_items: HashMap<u32, usize>,
+    debug_info_items: HashMap<u32, usize>,
+    encoded_array_items: HashMap<u32, usize>,
+    annotations_directory_items: HashMap<u32, usize>,
+    annotation_set_items: HashMap<u32, usize>,
+    annotation_set_ref_lists: HashMap<u32, usize>,
+    annotation_items: HashMap<u32, usize>,
+}
+
+impl<'a> DexResolver<'a> {
+    pub fn new(dex: &'a DexModel) -> Self {
+        let map = &dex.map_list;
+        let string_data = offset_index(map, TypeCode::TypeStringDataItem, &dex.string_data_items);
+        let strings = dex
+            .string_ids
+            .iter()
+            .map(|id| match string_data.get(&id.string_data_off) {
+                Some(i) => dex.string_data_items[*i].to_string_lossy(),
+                None => String::new(),
+            })
+            .collect();
+        let class_defs_by_type = dex
+            .class_defs
+            .iter()
+            .enumerate()
+            .map(|(i, class_def)| (class_def.class_idx, i))
+            .collect();
+
+        return Self {
+            dex,
+            mapping: None,
+            strings,
+            class_defs_by_type,
+            string_data_items: string_data,
+            type_lists: offset_index(map, TypeCode::TypeTypeList, &dex.type_lists),
+            class_data_items: offset_index(map, TypeCode::TypeClassDataItem, &dex.class_data_items),
+            code_items: offset_index(map, TypeCode::TypeCodeItem, &dex.code_items),
+            debug_info_items: offset_index(map, TypeCode::TypeDebugInfoItem, &dex.debug_info_items),
+            encoded_array_items: offset_index(
+                map,
+                TypeCode::TypeEncodedArrayItem,
+                &dex.encoded_array_items,
+            ),
+            annotations_directory_items: offset_index(
+                map,
+                TypeCode::TypeAnnotationsDirectoryItem,
+                &dex.annotations_directory_items,
+            ),
+            annotation_set_items: offset_index(
+                map,
+                TypeCode::TypeAnnotationSetItem,
+                &dex.annotation_set_items,
+            ),
+            annotation_set_ref_lists: offset_index(
+                map,
+                TypeCode::TypeAnnotationSetRefList,
+                &dex.annotation_set_ref_lists,
+            ),
+            annotation_items: offset_index(
+                map,
+                TypeCode::TypeAnnotationItem,
+                &dex.annotation_items,
+            ),
+        };
+    }
+
+    /// Reports de-obfuscated names using `mapping`.
+    pub fn with_mapping(mut self, mapping: &'a Mapping) -> Self {
+        self.mapping = Some(mapping);
+        return self;
+    }
+
+    pub fn mapping(&self) -> Option<&'a Mapping> {
+        return self.mapping;
+    }
+
+    pub fn strings(&self) -> &[String] {
+        return &self.strings;
+    }
+
+    pub fn string(&self, string_idx: u32) -> &str {
+        return self
+            .strings
+            .get(string_idx as usize)
+            .map(|s| s.as_str())
+            .unwrap_or("");
+    }
+
+    pub fn string_data(&self, string_idx: u32) -> Option<&'a StringDataItem> {
+        let string_id = self.dex.string_ids.get(string_idx as usize)?;
+        return self
+            .string_data_items
+            .get(&string_id.string_data_off)
+            .map(|i| &self.dex.string_data_items[*i]);
+    }
+
+    pub fn type_descriptor(&self, type_idx: u32) -> &str {
+        return match self.dex.type_ids.get(type_idx as usize) {
+            Some(type_id) => self.string(type_id.descriptor_idx),
+            None => "",
+        };
+    }
+
+    /// Java name of a type as it appears in the dex, e.g. `a.b[]`.
+    pub fn raw_type_name(&self, type_idx: u32) -> String {
+        return descriptor_to_java(self.type_descriptor(type_idx));
+    }
+
+    /// Java name of a type, de-obfuscated when a mapping is attached.
+    pub fn type_name(&self, type_idx: u32) -> String {
+        return self.java_type_name(self.type_descriptor(type_idx));
+    }
+
+    /// Java name of a descriptor, de-obfuscated when a mapping is attached.
+    pub fn java_type_name(&self, descriptor: &str) -> String {
+        let name = descriptor_to_java(descriptor);
+        return match self.mapping {
+            Some(mapping) => mapping.original_type_name(&name),
+            None => name,
+        };
+    }
+
+    pub fn method_ref(&self, method_idx: u32) -> MethodRef {
+        let method_id = &self.dex.method_ids[method_idx as usize];
+        let proto = &self.dex.proto_ids[method_id.proto_idx as usize];
+        return MethodRef {
+            class: self.type_descriptor(method_id.class_idx as u32).to_string(),
+            name: self.string(method_id.name_idx).to_string(),
+            parameters: self.type_list_descriptors(proto.parameters_off),
+            return_type: self.type_descriptor(proto.return_type_idx).to_string(),
+        };
+    }
+
+    pub fn field_ref(&self, field_idx: u32) -> FieldRef {
+        let field_id = &self.dex.field_ids[field_idx as usize];
+        return FieldRef {
+            class: self.type_descriptor(field_id.class_idx as u32).to_string(),
+            name: self.string(field_id.name_idx).to_string(),
+            field_type: self.type_descriptor(field_id.type_idx as u32).to_string(),
+        };
+    }
+
+    /// Descriptors of the types in the type list at `type_list_off`.
+    pub fn type_list_descriptors(&self, type_list_off: u32) -> Vec<String> {
+        return match self.type_list(type_list_off) {
+            Some(list) => list
+                .list
+                .iter()
+                .map(|t| self.type_descriptor(t.type_idx as u32).to_string())
+                .collect(),
+            None => vec![],
+        };
+    }
+
+    /// Method name, de-obfuscated when a mapping is attached.
+    pub fn method_name(&self, method_idx: u32) -> String {
+        let method = self.method_ref(method_idx);
+        if let Some(mapping) = self.mapping {
+            let parameters = method
+                .parameters
+                .iter()
+                .map(|p| self.java_type_name(p))
+                .collect::<Vec<_>>();
+            let class = descriptor_to_java(&method.class);
+            if let Some(name) = mapping.original_method_name(&class, &method.name, &parameters) {
+                return name.to_string();
+            }
+        }
+        return method.name;
+    }
+
+    /// Field name, de-obfuscated when a mapping is attached.
+    pub fn field_name(&self, field_idx: u32) -> String {
+        let field = self.field_ref(field_idx);
+        if let Some(mapping) = self.mapping {
+            let class = descriptor_to_java(&field.class);
+            if let Some(name) = mapping.original_field_name(&class, &field.name) {
+                return name.to_string();
+            }
+        }
+        return field.name;
+    }
+
+    /// Java-style method description, e.g. `com.Foo.bar(int, java.lang.String)`.
+    pub fn method_display(&self, method_idx: u32) -> String {
+        let method = self.method_ref(method_idx);
+        return format!(
+            "{}.{}({})",
+            self.java_type_name(&method.class),
+            self.method_name(method_idx),
+            method
+                .parameters
+                .iter()
+                .map(|p| self.java_type_name(p))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+
+    /// Java-style field description, e.g. `com.Foo.bar: int`.
+    pub fn field_display(&self, field_idx: u32) -> String {
+        let field = self.field_ref(field_idx);
+        return format!(
+            "{}.{}: {}",
+            self.java_type_name(&field.class),
+            self.field_name(field_idx),
+            self.java_type_name(&field.field_type)
+        );
+    }
+
+    /// Finds the class definition for a type, if it is defined in this dex.
+    pub fn class_def(&self, type_idx: u32) -> Option<&'a ClassDefItem> {
+        return self
+            .class_defs_by_type
+            .get(&type_idx)
+            .map(|i| &self.dex.class_defs[*i]);
+    }
+
+    /// Finds the class definition with the given descriptor, e.g. `Lcom/Foo;`.
+    pub fn class_def_by_descriptor(&self, descriptor: &str) -> Option<&'a ClassDefItem> {
+        return self
+            .dex
+            .class_defs
+            .iter()
+            .find(|c| self.type_descriptor(c.class_idx) == descriptor);
+    }
+
+    /// Finds the class definition of a Java name, e.g. `com.Foo`. With a
+    /// mapping attached the name may also be the original one.
+    pub fn class_def_by_name(&self, name: &str) -> Option<&'a ClassDefItem> {
+        let name = match self.mapping.and_then(|m| m.class_by_original(name)) {
+            Some(class) => class.obfuscated.as_str(),
+            None => name,
+        };
+        return self.class_def_by_descriptor(&java_to_descriptor(name));
+    }
+
+    pub fn type_list(&self, off: u32) -> Option<&'a TypeList> {
+        return self.type_lists.get(&off).map(|i| &self.dex.type_lists[*i]);
+    }
+
+    pub fn class_data(&self, class_def: &ClassDefItem) -> Option<&'a ClassDataItem> {
+        return self
+            .class_data_items
+            .get(&class_def.class_data_off)
+            .map(|i| &self.dex.class_data_items[*i]);
+    }
+
+    pub fn class_data_item(&self, off: u32) -> Option<&'a ClassDataItem> {
+        return self
+            .class_data_items
+            .get(&off)
+            .map(|i| &self.dex.class_data_items[*i]);
+    }
+
+    pub fn code_item(&self, code_off: u32) -> Option<&'a CodeItem> {
+        return self
+            .code_items
+            .get(&code_off)
+            .map(|i| &self.dex.code_items[*i]);
+    }
+
+    pub fn debug_info(&self, code_item: &CodeItem) -> Option<&'a DebugInfoItem> {
+        return self
+            .debug_info_items
+            .get(&code_item.debug_info_off)
+            .map(|i| &self.dex.debug_info_items[*i]);
+    }
+
+    pub fn debug_info_item(&self, off: u32) -> Option<&'a DebugInfoItem> {
+        return self
+            .debug_info_items
+            .get(&off)
+            .map(|i| &self.dex.debug_info_items[*i]);
+    }
+
+    pub fn encoded_array(&self, off: u32) -> Option<&'a EncodedArrayItem> {
+        return self
+            .encoded_array_items
+            .get(&off)
+            .map(|i| &self.dex.encoded_array_items[*i]);
+    }
+
+    pub fn annotations_directory(&self, off: u32) -> Option<&'a AnnotationsDirectoryItem> {
+        return self
+            .annotations_directory_items
+            .get(&off)
+            .map(|i| &self.dex.annotations_directory_items[*i]);
+    }
+
+    pub fn annotation_set(&self, off: u32) -> Option<&'a AnnotationSetItem> {
+        return self
+            .annotation_set_items
+            .get(&off)
+            .map(|i| &self.dex.annotation_set_items[*i]);
+    }
+
+    pub fn annotation_set_ref_list(&self, off: u32) -> Option<&'a AnnotationSetRefList> {
+        return self
+            .annotation_set_ref_lists
+            .get(&off)
+            .map(|i| &self.dex.annotation_set_ref_lists[*i]);
+    }
+
+    pub fn annotation(&self, off: u32) -> Option<&'a AnnotationItem> {
+        return self
+            .annotation_items
+            .get(&off)
+            .map(|i| &self.dex.annotation_items[*i]);
+    }
+
+    /// Every method defined by a class, direct methods first, with its
+    /// absolute `method_ids` index.
+    pub fn class_methods(&self, class_def: &ClassDefItem) -> Vec<(u32, &'a EncodedMethod)> {
+        return match self.class_data(class_def) {
+            Some(data) => {
+                let mut methods = method_indices(&data.direct_methods);
+                methods.extend(method_indices(&data.virtual_methods));
+                methods
+            }
+            None => vec![],
+        };
+    }
+
+    /// Every field defined by a class, static fields first, with its absolute
+    /// `field_ids` index.
+    pub fn class_fields(&self, class_def: &ClassDefItem) -> Vec<(u32, &'a EncodedField)> {
+        return match self.class_data(class_def) {
+            Some(data) => {
+                let mut fields = field_indices(&data.static_fields);
+                fields.extend(field_indices(&data.instance_fields));
+                fields
+            }
+            None => vec![],
+        };
+    }
+
+    /// Source line of the instruction at `address` according to the method's
+    /// line table.
+    pub fn line_for_address(&self, code_item: &CodeItem, address: u32) -> Option<u32> {
+        let info = self.debug_info(code_item)?.decode();
+        return info
+            .positions
+            .iter()
+            .take_while(|p| p.address <= address)
+            .last()
+            .map(|p| p.line);
+    }
+
+    /// Source file of the instruction at `address`: the file set by the line
+    /// table, else the `source_file_idx` of the class.
+    pub fn source_file_for_address(
+        &self,
+        class_def: &ClassDefItem,
+        code_item: &CodeItem,
+        address: u32,
+    ) -> Option<String> {
+        let from_debug_info = self.debug_info(code_item).and_then(|info| {
+            info.decode()
+                .positions
+                .iter()
+                .take_while(|p| p.address <= address)
+                .last()
+                .and_then(|p| p.source_file_idx)
+        });
+        return match from_debug_info.unwrap_or(class_def.source_file_idx) {
+            NO_INDEX => None,
+            string_idx => Some(self.string(string_idx).to_string()),
+        };
+    }
+}
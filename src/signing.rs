@@ -0,0 +1,1006 @@
+//! Inspection of APK signatures: JAR signing (v1), the APK Signing Block
+//! (v2, v3 and v3.1) and `.idsig` files (v4).
+//!
+//! Content digests are recomputed and compared offline. Signatures over the
+//! signed data are reported but not cryptographically verified.
+
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display, Formatter},
+    fs, io,
+    path::Path,
+};
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::Serialize;
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha512};
+
+use crate::{
+    apk::{Apk, ApkError},
+    der::{hex, pkcs7_certificates, Certificate, DerError},
+};
+
+const APK_SIG_BLOCK_MAGIC: &[u8; 16] = b"APK Sig Block 42";
+const EOCD_SIGNATURE: u32 = 0x06054b50;
+
+pub const V2_BLOCK_ID: u32 = 0x7109871a;
+pub const V3_BLOCK_ID: u32 = 0xf05368c0;
+pub const V31_BLOCK_ID: u32 = 0x1b93ad61;
+const PROOF_OF_ROTATION_ATTR_ID: u32 = 0x3ba06f8c;
+
+const CHUNK_SIZE: usize = 1024 * 1024;
+const VERITY_BLOCK_SIZE: usize = 4096;
+
+#[derive(Debug)]
+pub enum SigningError {
+    FileOpenError(io::Error),
+    ApkError(ApkError),
+    DerError(DerError),
+    /// The archive or a signature structure is malformed.
+    Malformed(String),
+}
+
+impl From<io::Error> for SigningError {
+    fn from(err: io::Error) -> Self {
+        SigningError::FileOpenError(err)
+    }
+}
+
+impl From<ApkError> for SigningError {
+    fn from(err: ApkError) -> Self {
+        SigningError::ApkError(err)
+    }
+}
+
+impl From<DerError> for SigningError {
+    fn from(err: DerError) -> Self {
+        SigningError::DerError(err)
+    }
+}
+
+impl Display for SigningError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            SigningError::FileOpenError(err) => write!(f, "{}", err),
+            SigningError::ApkError(err) => write!(f, "{}", err),
+            SigningError::DerError(err) => write!(f, "{}", err),
+            SigningError::Malformed(reason) => write!(f, "malformed signature: {}", reason),
+        }
+    }
+}
+
+fn malformed(reason: &str) -> SigningError {
+    return SigningError::Malformed(reason.to_string());
+}
+
+/// Little-endian reads over the length-prefixed structures of the APK
+/// Signing Block and `.idsig` files.
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        return Self { data };
+    }
+
+    fn is_empty(&self) -> bool {
+        return self.data.is_empty();
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], SigningError> {
+        if len > self.data.len() {
+            return Err(malformed("truncated length-prefixed data"));
+        }
+        let (head, tail) = self.data.split_at(len);
+        self.data = tail;
+        return Ok(head);
+    }
+
+    fn u8(&mut self) -> Result<u8, SigningError> {
+        return Ok(self.bytes(1)?[0]);
+    }
+
+    fn u32(&mut self) -> Result<u32, SigningError> {
+        return Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()));
+    }
+
+    fn u64(&mut self) -> Result<u64, SigningError> {
+        return Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()));
+    }
+
+    /// Reads a `u32` length followed by that many bytes.
+    fn prefixed(&mut self) -> Result<&'a [u8], SigningError> {
+        let len = self.u32()? as usize;
+        return self.bytes(len);
+    }
+
+    /// Reads a length-prefixed sequence of length-prefixed elements.
+    fn sequence(&mut self) -> Result<Vec<&'a [u8]>, SigningError> {
+        let mut sequence = Reader::new(self.prefixed()?);
+        let mut elements = vec![];
+        while !sequence.is_empty() {
+            elements.push(sequence.prefixed()?);
+        }
+        return Ok(elements);
+    }
+}
+
+/// Name of a signature algorithm id of the APK Signing Block.
+pub fn algorithm_name(algorithm: u32) -> &'static str {
+    return match algorithm {
+        0x0101 => "RSASSA-PSS with SHA2-256",
+        0x0102 => "RSASSA-PSS with SHA2-512",
+        0x0103 => "RSASSA-PKCS1-v1_5 with SHA2-256",
+        0x0104 => "RSASSA-PKCS1-v1_5 with SHA2-512",
+        0x0201 => "ECDSA with SHA2-256",
+        0x0202 => "ECDSA with SHA2-512",
+        0x0301 => "DSA with SHA2-256",
+        0x0421 => "RSASSA-PKCS1-v1_5 with SHA2-256 (verity)",
+        0x0423 => "ECDSA with SHA2-256 (verity)",
+        0x0425 => "DSA with SHA2-256 (verity)",
+        _ => "unknown",
+    };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ContentDigestAlgorithm {
+    ChunkedSha256,
+    ChunkedSha512,
+    VeritySha256,
+}
+
+fn content_digest_algorithm(algorithm: u32) -> Option<ContentDigestAlgorithm> {
+    return match algorithm {
+        0x0101 | 0x0103 | 0x0201 | 0x0301 => Some(ContentDigestAlgorithm::ChunkedSha256),
+        0x0102 | 0x0104 | 0x0202 => Some(ContentDigestAlgorithm::ChunkedSha512),
+        0x0421 | 0x0423 | 0x0425 => Some(ContentDigestAlgorithm::VeritySha256),
+        _ => None,
+    };
+}
+
+/// Where the parts of a zip archive are, as needed to locate the APK
+/// Signing Block and compute content digests.
+#[derive(Debug, Clone, Copy)]
+struct ZipSections {
+    central_directory_offset: usize,
+    eocd_offset: usize,
+    /// Start of the APK Signing Block, or the central directory if absent.
+    signing_block_offset: usize,
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    return data
+        .get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes(b.try_into().unwrap()));
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    return data
+        .get(offset..offset + 8)
+        .map(|b| u64::from_le_bytes(b.try_into().unwrap()));
+}
+
+fn zip_sections(apk: &[u8]) -> Result<ZipSections, SigningError> {
+    // The EOCD record is 22 bytes plus a comment of at most 65535 bytes.
+    let min = apk.len().saturating_sub(22 + 0xffff);
+    let eocd_offset = (min..apk.len().saturating_sub(21))
+        .rev()
+        .find(|o| read_u32(apk, *o) == Some(EOCD_SIGNATURE))
+        .ok_or_else(|| malformed("no zip end of central directory record"))?;
+    let central_directory_offset = read_u32(apk, eocd_offset + 16).unwrap() as usize;
+    if central_directory_offset > eocd_offset {
+        return Err(malformed("central directory offset out of range"));
+    }
+
+    let mut signing_block_offset = central_directory_offset;
+    if central_directory_offset >= 24
+        && &apk[central_directory_offset - 16..central_directory_offset] == APK_SIG_BLOCK_MAGIC
+    {
+        // The size excludes its own leading field but covers the pairs, the
+        // trailing copy of the size and the magic.
+        let size = read_u64(apk, central_directory_offset - 24).unwrap();
+        if size < 24 {
+            return Err(malformed("APK Signing Block size too small"));
+        }
+        let start = usize::try_from(size)
+            .ok()
+            .and_then(|size| size.checked_add(8))
+            .and_then(|len| central_directory_offset.checked_sub(len))
+            .ok_or_else(|| malformed("APK Signing Block size out of range"))?;
+        if read_u64(apk, start) != Some(size) {
+            return Err(malformed("APK Signing Block sizes disagree"));
+        }
+        signing_block_offset = start;
+    }
+    return Ok(ZipSections {
+        central_directory_offset,
+        eocd_offset,
+        signing_block_offset,
+    });
+}
+
+/// The ID-value pairs of the APK Signing Block.
+fn signing_block_pairs(
+    apk: &[u8],
+    sections: &ZipSections,
+) -> Result<BTreeMap<u32, Vec<u8>>, SigningError> {
+    let mut pairs = BTreeMap::new();
+    if sections.signing_block_offset == sections.central_directory_offset {
+        return Ok(pairs);
+    }
+    let pairs_start = sections.signing_block_offset.checked_add(8);
+    let pairs_end = sections.central_directory_offset.checked_sub(24);
+    let block = match (pairs_start, pairs_end) {
+        (Some(start), Some(end)) => apk.get(start..end),
+        _ => None,
+    };
+    let mut r = Reader::new(block.ok_or_else(|| malformed("APK Signing Block size out of range"))?);
+    while !r.is_empty() {
+        let len = r.u64()? as usize;
+        let pair = r.bytes(len)?;
+        let mut pair = Reader::new(pair);
+        let id = pair.u32()?;
+        pairs.insert(id, pair.data.to_vec());
+    }
+    return Ok(pairs);
+}
+
+/// The three signed sections of an APK: the zip entries, the central
+/// directory and the EOCD record with its central directory offset pointing
+/// at the APK Signing Block.
+fn signed_sections(apk: &[u8], sections: &ZipSections) -> [Vec<u8>; 3] {
+    let mut eocd = apk[sections.eocd_offset..].to_vec();
+    eocd[16..20].copy_from_slice(&(sections.signing_block_offset as u32).to_le_bytes());
+    return [
+        apk[..sections.signing_block_offset].to_vec(),
+        apk[sections.central_directory_offset..sections.eocd_offset].to_vec(),
+        eocd,
+    ];
+}
+
+fn chunked_digest<D: Digest>(sections: &[Vec<u8>]) -> Vec<u8> {
+    let mut chunk_digests = vec![];
+    let mut count = 0u32;
+    for section in sections {
+        for chunk in section.chunks(CHUNK_SIZE) {
+            let mut hasher = D::new();
+            hasher.update([0xa5]);
+            hasher.update((chunk.len() as u32).to_le_bytes());
+            hasher.update(chunk);
+            chunk_digests.extend_from_slice(&hasher.finalize());
+            count += 1;
+        }
+    }
+    let mut hasher = D::new();
+    hasher.update([0x5a]);
+    hasher.update(count.to_le_bytes());
+    hasher.update(&chunk_digests);
+    return hasher.finalize().to_vec();
+}
+
+/// Root hash of the SHA-256 Merkle tree over 4 KiB blocks used by
+/// fs-verity, zero-padding every level to whole blocks.
+pub(crate) fn verity_root_hash(data: &[u8], salt: &[u8]) -> Vec<u8> {
+    let hash_block = |block: &[u8]| {
+        let mut hasher = Sha256::new();
+        hasher.update(salt);
+        hasher.update(block);
+        if block.len() < VERITY_BLOCK_SIZE {
+            hasher.update(vec![0u8; VERITY_BLOCK_SIZE - block.len()]);
+        }
+        hasher.finalize().to_vec()
+    };
+    let mut level = data
+        .chunks(VERITY_BLOCK_SIZE)
+        .flat_map(hash_block)
+        .collect::<Vec<u8>>();
+    if level.is_empty() {
+        level = hash_block(&[]);
+    }
+    while level.len() > VERITY_BLOCK_SIZE {
+        level = level
+            .chunks(VERITY_BLOCK_SIZE)
+            .flat_map(hash_block)
+            .collect();
+    }
+    return hash_block(&level);
+}
+
+fn compute_content_digest(algorithm: ContentDigestAlgorithm, sections: &[Vec<u8>]) -> Vec<u8> {
+    return match algorithm {
+        ContentDigestAlgorithm::ChunkedSha256 => chunked_digest::<Sha256>(sections),
+        ContentDigestAlgorithm::ChunkedSha512 => chunked_digest::<Sha512>(sections),
+        ContentDigestAlgorithm::VeritySha256 => {
+            let data = sections.concat();
+            let mut digest = verity_root_hash(&data, &[]);
+            digest.extend_from_slice(&(data.len() as u64).to_le_bytes());
+            digest
+        }
+    };
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ContentDigest {
+    pub algorithm: u32,
+    pub algorithm_name: String,
+    /// Lowercase hex digest recorded in the signature.
+    pub digest: String,
+    /// Whether the recorded digest matches the APK contents; `None` when
+    /// the algorithm is unknown.
+    pub verified: Option<bool>,
+}
+
+/// A previous or current signing certificate in a v3 rotation lineage.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LineageNode {
+    pub certificate: Certificate,
+    /// Capabilities granted to the certificate, e.g. `0x1` installed data.
+    pub flags: u32,
+    pub signature_algorithm: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Signer {
+    pub certificates: Vec<Certificate>,
+    pub digests: Vec<ContentDigest>,
+    pub signature_algorithms: Vec<u32>,
+    /// Platform range the signer applies to (v3 and v3.1 only).
+    pub min_sdk: Option<u32>,
+    pub max_sdk: Option<u32>,
+    /// Key rotation history, oldest first (v3 and v3.1 only).
+    pub lineage: Vec<LineageNode>,
+}
+
+fn parse_lineage(value: &[u8]) -> Result<Vec<LineageNode>, SigningError> {
+    let mut r = Reader::new(value);
+    r.u32()?;
+    let mut nodes = vec![];
+    while !r.is_empty() {
+        let mut node = Reader::new(r.prefixed()?);
+        let mut signed_data = Reader::new(node.prefixed()?);
+        let certificate = Certificate::parse(signed_data.prefixed()?)?;
+        let flags = node.u32()?;
+        let signature_algorithm = node.u32()?;
+        nodes.push(LineageNode {
+            certificate,
+            flags,
+            signature_algorithm,
+        });
+    }
+    return Ok(nodes);
+}
+
+fn parse_signer(data: &[u8], v3: bool, sections: &[Vec<u8>; 3]) -> Result<Signer, SigningError> {
+    let mut r = Reader::new(data);
+    let mut signed_data = Reader::new(r.prefixed()?);
+    let (min_sdk, max_sdk) = if v3 {
+        (Some(r.u32()?), Some(r.u32()?))
+    } else {
+        (None, None)
+    };
+    let signature_algorithms = r
+        .sequence()?
+        .into_iter()
+        .map(|signature| Reader::new(signature).u32())
+        .collect::<Result<_, _>>()?;
+
+    let mut digests = vec![];
+    for digest in signed_data.sequence()? {
+        let mut digest = Reader::new(digest);
+        let algorithm = digest.u32()?;
+        let value = digest.prefixed()?;
+        let verified = content_digest_algorithm(algorithm)
+            .map(|content_algorithm| compute_content_digest(content_algorithm, sections) == value);
+        digests.push(ContentDigest {
+            algorithm,
+            algorithm_name: algorithm_name(algorithm).to_string(),
+            digest: hex(value),
+            verified,
+        });
+    }
+    let certificates = signed_data
+        .sequence()?
+        .into_iter()
+        .map(Certificate::parse)
+        .collect::<Result<_, _>>()?;
+    if v3 {
+        signed_data.u32()?;
+        signed_data.u32()?;
+    }
+    let mut lineage = vec![];
+    if !signed_data.is_empty() {
+        for attribute in signed_data.sequence()? {
+            let mut attribute = Reader::new(attribute);
+            if attribute.u32()? == PROOF_OF_ROTATION_ATTR_ID {
+                lineage = parse_lineage(attribute.data)?;
+            }
+        }
+    }
+
+    return Ok(Signer {
+        certificates,
+        digests,
+        signature_algorithms,
+        min_sdk,
+        max_sdk,
+        lineage,
+    });
+}
+
+fn parse_signers(
+    block: &[u8],
+    v3: bool,
+    sections: &[Vec<u8>; 3],
+) -> Result<Vec<Signer>, SigningError> {
+    return Reader::new(block)
+        .sequence()?
+        .into_iter()
+        .map(|signer| parse_signer(signer, v3, sections))
+        .collect();
+}
+
+/// A JAR signature: one `META-INF/*.SF` file and its signature block.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct V1Signer {
+    /// Base name of the signature files, e.g. `CERT` for `META-INF/CERT.SF`.
+    pub name: String,
+    pub certificates: Vec<Certificate>,
+    /// Whether the `.SF` digest of the whole `MANIFEST.MF` matches.
+    pub manifest_digest_verified: Option<bool>,
+    /// Schemes listed in `X-Android-APK-Signed`, which must also verify to
+    /// prevent stripping the newer signatures.
+    pub apk_signed_schemes: Vec<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct V1Signature {
+    pub signers: Vec<V1Signer>,
+    /// Entries whose `MANIFEST.MF` digest does not match their contents.
+    pub mismatched_entries: Vec<String>,
+    /// Entries that `MANIFEST.MF` does not cover.
+    pub unsigned_entries: Vec<String>,
+}
+
+/// Parses a JAR manifest into its sections of `name: value` attributes,
+/// joining continuation lines.
+fn parse_jar_manifest(text: &str) -> Vec<BTreeMap<String, String>> {
+    let mut sections = vec![];
+    let mut section: BTreeMap<String, String> = BTreeMap::new();
+    let mut last_key: Option<String> = None;
+    for line in text.split('\n').map(|l| l.trim_end_matches('\r')) {
+        if line.is_empty() {
+            if !section.is_empty() {
+                sections.push(std::mem::take(&mut section));
+            }
+            last_key = None;
+        } else if let Some(continuation) = line.strip_prefix(' ') {
+            if let Some(value) = last_key.as_ref().and_then(|key| section.get_mut(key)) {
+                value.push_str(continuation);
+            }
+        } else if let Some((key, value)) = line.split_once(": ") {
+            section.insert(key.to_string(), value.to_string());
+            last_key = Some(key.to_string());
+        }
+    }
+    if !section.is_empty() {
+        sections.push(section);
+    }
+    return sections;
+}
+
+/// Checks the `*-Digest` attributes of a manifest section against `data`;
+/// `None` when the section has no digest in a supported algorithm.
+fn check_digests(section: &BTreeMap<String, String>, suffix: &str, data: &[u8]) -> Option<bool> {
+    let mut result = None;
+    for (key, value) in section.iter() {
+        let algorithm = match key.strip_suffix(suffix) {
+            Some(algorithm) => algorithm,
+            None => continue,
+        };
+        let computed = match algorithm {
+            "SHA1" | "SHA-1" => Sha1::digest(data).to_vec(),
+            "SHA-256" => Sha256::digest(data).to_vec(),
+            "SHA-512" => Sha512::digest(data).to_vec(),
+            _ => continue,
+        };
+        let matches = BASE64.decode(value.trim()).ok() == Some(computed);
+        result = Some(result.unwrap_or(true) && matches);
+    }
+    return result;
+}
+
+fn is_signature_file(name: &str) -> bool {
+    let upper = name.to_ascii_uppercase();
+    return upper.starts_with("META-INF/")
+        && (upper == "META-INF/MANIFEST.MF"
+            || [".SF", ".RSA", ".DSA", ".EC"]
+                .iter()
+                .any(|ext| upper.ends_with(ext)));
+}
+
+fn inspect_v1(apk: &mut Apk) -> Result<Option<V1Signature>, SigningError> {
+    if !apk.has_entry("META-INF/MANIFEST.MF") {
+        return Ok(None);
+    }
+    let manifest_bytes = apk.read_entry("META-INF/MANIFEST.MF")?;
+    let manifest = parse_jar_manifest(&String::from_utf8_lossy(&manifest_bytes));
+
+    let mut signers = vec![];
+    for name in apk.entry_names() {
+        let base = match name
+            .strip_prefix("META-INF/")
+            .and_then(|n| n.strip_suffix(".SF"))
+        {
+            Some(base) if !base.contains('/') => base.to_string(),
+            _ => continue,
+        };
+        let sf = apk.read_entry(&name)?;
+        let sf_sections = parse_jar_manifest(&String::from_utf8_lossy(&sf));
+        let main = sf_sections.first().cloned().unwrap_or_default();
+        let mut certificates = vec![];
+        for ext in ["RSA", "DSA", "EC"] {
+            let block = format!("META-INF/{}.{}", base, ext);
+            if apk.has_entry(&block) {
+                certificates = pkcs7_certificates(&apk.read_entry(&block)?)?;
+            }
+        }
+        signers.push(V1Signer {
+            name: base,
+            certificates,
+            manifest_digest_verified: check_digests(&main, "-Digest-Manifest", &manifest_bytes),
+            apk_signed_schemes: main
+                .get("X-Android-APK-Signed")
+                .map(|v| v.split(',').filter_map(|s| s.trim().parse().ok()).collect())
+                .unwrap_or_default(),
+        });
+    }
+
+    let mut mismatched_entries = vec![];
+    let mut covered = vec![];
+    for section in manifest.iter().skip(1) {
+        let name = match section.get("Name") {
+            Some(name) => name.clone(),
+            None => continue,
+        };
+        if apk.has_entry(&name) {
+            let data = apk.read_entry(&name)?;
+            if check_digests(section, "-Digest", &data) == Some(false) {
+                mismatched_entries.push(name.clone());
+            }
+        } else {
+            mismatched_entries.push(name.clone());
+        }
+        covered.push(name);
+    }
+    let unsigned_entries = apk
+        .entry_names()
+        .into_iter()
+        .filter(|name| !name.ends_with('/') && !is_signature_file(name) && !covered.contains(name))
+        .collect();
+
+    return Ok(Some(V1Signature {
+        signers,
+        mismatched_entries,
+        unsigned_entries,
+    }));
+}
+
+/// An APK Signature Scheme v4 `.idsig` file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct V4Signature {
+    pub version: u32,
+    pub hash_algorithm: u32,
+    pub log2_block_size: u8,
+    pub salt: String,
+    pub root_hash: String,
+    /// Digest of the APK from its v2 or v3 signature.
+    pub apk_digest: String,
+    pub certificate: Certificate,
+    pub signature_algorithm: u32,
+    /// Whether the Merkle tree root hash matches the APK contents.
+    pub root_hash_verified: Option<bool>,
+    /// Whether `apk_digest` matches a content digest of the v2 or v3
+    /// signature.
+    pub apk_digest_verified: Option<bool>,
+}
+
+impl V4Signature {
+    pub fn parse(idsig: &[u8]) -> Result<V4Signature, SigningError> {
+        let mut r = Reader::new(idsig);
+        let version = r.u32()?;
+        let mut hashing_info = Reader::new(r.prefixed()?);
+        let hash_algorithm = hashing_info.u32()?;
+        let log2_block_size = hashing_info.u8()?;
+        let salt = hashing_info.prefixed()?;
+        let root_hash = hashing_info.prefixed()?;
+        let mut signing_info = Reader::new(r.prefixed()?);
+        let apk_digest = signing_info.prefixed()?;
+        let certificate = Certificate::parse(signing_info.prefixed()?)?;
+        signing_info.prefixed()?;
+        signing_info.prefixed()?;
+        let signature_algorithm = signing_info.u32()?;
+        return Ok(V4Signature {
+            version,
+            hash_algorithm,
+            log2_block_size,
+            salt: hex(salt),
+            root_hash: hex(root_hash),
+            apk_digest: hex(apk_digest),
+            certificate,
+            signature_algorithm,
+            root_hash_verified: None,
+            apk_digest_verified: None,
+        });
+    }
+}
+
+/// Signatures found in an APK, by scheme.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SigningReport {
+    pub v1: Option<V1Signature>,
+    pub v2: Option<Vec<Signer>>,
+    pub v3: Option<Vec<Signer>>,
+    pub v31: Option<Vec<Signer>>,
+    pub v4: Option<V4Signature>,
+}
+
+impl SigningReport {
+    /// Inspects the APK at `path` and, if present, its `<path>.idsig`.
+    pub fn inspect_file<P: AsRef<Path>>(path: P) -> Result<SigningReport, SigningError> {
+        let apk = fs::read(&path)?;
+        let mut idsig_path = path.as_ref().as_os_str().to_owned();
+        idsig_path.push(".idsig");
+        let idsig = fs::read(idsig_path).ok();
+        return SigningReport::inspect(&apk, idsig.as_deref());
+    }
+
+    pub fn inspect(apk_bytes: &[u8], idsig: Option<&[u8]>) -> Result<SigningReport, SigningError> {
+        let sections = zip_sections(apk_bytes)?;
+        let pairs = signing_block_pairs(apk_bytes, &sections)?;
+        let signed = signed_sections(apk_bytes, &sections);
+        let signers = |id: u32, v3: bool| {
+            pairs
+                .get(&id)
+                .map(|block| parse_signers(block, v3, &signed))
+                .transpose()
+        };
+
+        let mut apk = Apk::from_bytes(apk_bytes.to_vec())?;
+        let mut report = SigningReport {
+            v1: inspect_v1(&mut apk)?,
+            v2: signers(V2_BLOCK_ID, false)?,
+            v3: signers(V3_BLOCK_ID, true)?,
+            v31: signers(V31_BLOCK_ID, true)?,
+            v4: idsig.map(V4Signature::parse).transpose()?,
+        };
+
+        let digests = report
+            .v3
+            .iter()
+            .chain(report.v2.iter())
+            .flatten()
+            .flat_map(|signer| signer.digests.iter().map(|d| d.digest.clone()))
+            .collect::<Vec<_>>();
+        if let Some(v4) = report.v4.as_mut() {
+            // Hash algorithm 1 is SHA-256 over 4 KiB blocks.
+            if v4.hash_algorithm == 1 && v4.log2_block_size == 12 {
+                let salt = (0..v4.salt.len() / 2)
+                    .map(|i| u8::from_str_radix(&v4.salt[i * 2..i * 2 + 2], 16).unwrap())
+                    .collect::<Vec<_>>();
+                v4.root_hash_verified =
+                    Some(hex(&verity_root_hash(apk_bytes, &salt)) == v4.root_hash);
+            }
+            if !digests.is_empty() {
+                v4.apk_digest_verified = Some(digests.contains(&v4.apk_digest));
+            }
+        }
+        return Ok(report);
+    }
+
+    /// Names of the schemes present, e.g. `["v1", "v2", "v3"]`.
+    pub fn schemes(&self) -> Vec<&'static str> {
+        let mut schemes = vec![];
+        if self.v1.is_some() {
+            schemes.push("v1");
+        }
+        if self.v2.is_some() {
+            schemes.push("v2");
+        }
+        if self.v3.is_some() {
+            schemes.push("v3");
+        }
+        if self.v31.is_some() {
+            schemes.push("v3.1");
+        }
+        if self.v4.is_some() {
+            schemes.push("v4");
+        }
+        return schemes;
+    }
+
+    /// Schemes that a v1 signer lists in `X-Android-APK-Signed` but that
+    /// have no block, i.e. were stripped after signing.
+    pub fn stripped_schemes(&self) -> Vec<u32> {
+        let mut stripped = vec![];
+        let signers = self.v1.iter().flat_map(|v1| v1.signers.iter());
+        for scheme in signers.flat_map(|signer| signer.apk_signed_schemes.iter()) {
+            let present = match scheme {
+                2 => self.v2.is_some(),
+                3 => self.v3.is_some() || self.v31.is_some(),
+                _ => true,
+            };
+            if !present && !stripped.contains(scheme) {
+                stripped.push(*scheme);
+            }
+        }
+        return stripped;
+    }
+
+    /// Whether the APK is signed, no scheme was stripped and every
+    /// recomputable digest of every scheme matches.
+    pub fn is_verified(&self) -> bool {
+        if self.schemes().is_empty() || !self.stripped_schemes().is_empty() {
+            return false;
+        }
+        let block_signers = self
+            .v2
+            .iter()
+            .chain(self.v3.iter())
+            .chain(self.v31.iter())
+            .flatten();
+        let block_ok = block_signers
+            .flat_map(|signer| signer.digests.iter())
+            .all(|digest| digest.verified != Some(false));
+        let v1_ok = self.v1.as_ref().is_none_or(|v1| {
+            v1.mismatched_entries.is_empty()
+                && v1
+                    .signers
+                    .iter()
+                    .all(|signer| signer.manifest_digest_verified != Some(false))
+        });
+        let v4_ok = self.v4.as_ref().is_none_or(|v4| {
+            v4.root_hash_verified != Some(false) && v4.apk_digest_verified != Some(false)
+        });
+        return block_ok && v1_ok && v4_ok;
+    }
+
+    pub fn to_json(&self) -> String {
+        return serde_json::to_string_pretty(self).unwrap();
+    }
+}
+
+fn write_certificate(f: &mut Formatter, indent: &str, certificate: &Certificate) -> fmt::Result {
+    writeln!(f, "{}subject: {}", indent, certificate.subject)?;
+    writeln!(f, "{}issuer: {}", indent, certificate.issuer)?;
+    writeln!(
+        f,
+        "{}valid: {} to {}",
+        indent, certificate.not_before, certificate.not_after
+    )?;
+    writeln!(f, "{}SHA-256: {}", indent, certificate.sha256)?;
+    return Ok(());
+}
+
+impl Display for SigningReport {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, "Schemes: {}", self.schemes().join(", "))?;
+        let digests = if self.schemes().is_empty() {
+            "unsigned"
+        } else if self.is_verified() {
+            "verified"
+        } else {
+            "MISMATCH"
+        };
+        writeln!(f, "Digests: {}", digests)?;
+        for scheme in self.stripped_schemes() {
+            writeln!(f, "Stripped: v{} is listed by v1 but missing", scheme)?;
+        }
+        if let Some(v1) = &self.v1 {
+            writeln!(f, "v1:")?;
+            for signer in v1.signers.iter() {
+                writeln!(f, "  signer {}", signer.name)?;
+                for certificate in signer.certificates.iter() {
+                    write_certificate(f, "    ", certificate)?;
+                }
+            }
+            for entry in v1.mismatched_entries.iter() {
+                writeln!(f, "  digest mismatch: {}", entry)?;
+            }
+            for entry in v1.unsigned_entries.iter() {
+                writeln!(f, "  unsigned entry: {}", entry)?;
+            }
+        }
+        for (scheme, signers) in [("v2", &self.v2), ("v3", &self.v3), ("v3.1", &self.v31)] {
+            let signers = match signers {
+                Some(signers) => signers,
+                None => continue,
+            };
+            writeln!(f, "{}:", scheme)?;
+            for signer in signers.iter() {
+                writeln!(f, "  signer")?;
+                if let (Some(min_sdk), Some(max_sdk)) = (signer.min_sdk, signer.max_sdk) {
+                    writeln!(f, "    sdk: {} to {}", min_sdk, max_sdk)?;
+                }
+                for certificate in signer.certificates.iter() {
+                    write_certificate(f, "    ", certificate)?;
+                }
+                for digest in signer.digests.iter() {
+                    writeln!(
+                        f,
+                        "    digest {}: {}",
+                        digest.algorithm_name,
+                        match digest.verified {
+                            Some(true) => "verified",
+                            Some(false) => "MISMATCH",
+                            None => "not checked",
+                        }
+                    )?;
+                }
+                for (i, node) in signer.lineage.iter().enumerate() {
+                    writeln!(f, "    lineage {} (flags 0x{:x})", i, node.flags)?;
+                    write_certificate(f, "      ", &node.certificate)?;
+                }
+            }
+        }
+        if let Some(v4) = &self.v4 {
+            writeln!(f, "v4:")?;
+            write_certificate(f, "  ", &v4.certificate)?;
+            writeln!(f, "  root hash: {}", v4.root_hash)?;
+        }
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{build_zip, test_certificate};
+
+    /// Inserts an APK Signing Block holding one v2 signer before the
+    /// central directory, with a correct SHA-256 content digest.
+    fn sign_v2(zip: &[u8]) -> Vec<u8> {
+        let sections = zip_sections(zip).unwrap();
+        let prefixed = |data: &[u8]| [&(data.len() as u32).to_le_bytes()[..], data].concat();
+        let build = |digest: &[u8]| {
+            let digest_entry = [&0x0103u32.to_le_bytes()[..], &prefixed(digest)].concat();
+            let signed_data = [
+                prefixed(&prefixed(&digest_entry)),
+                prefixed(&prefixed(&test_certificate())),
+                prefixed(&[]),
+            ]
+            .concat();
+            let signature = [&0x0103u32.to_le_bytes()[..], &prefixed(&[0u8; 4])].concat();
+            let signer = [
+                prefixed(&signed_data),
+                prefixed(&prefixed(&signature)),
+                prefixed(&[1, 2, 3]),
+            ]
+            .concat();
+            let value = prefixed(&prefixed(&signer));
+            let pair = [&V2_BLOCK_ID.to_le_bytes()[..], &value].concat();
+            let size = (8 + pair.len() + 8 + 16) as u64;
+            let block = [
+                &size.to_le_bytes()[..],
+                &(pair.len() as u64).to_le_bytes(),
+                &pair,
+                &size.to_le_bytes(),
+                APK_SIG_BLOCK_MAGIC,
+            ]
+            .concat();
+            let cd = sections.central_directory_offset;
+            let mut apk = zip[..cd].to_vec();
+            apk.extend_from_slice(&block);
+            apk.extend_from_slice(&zip[cd..]);
+            let eocd = sections.eocd_offset + block.len();
+            apk[eocd + 16..eocd + 20].copy_from_slice(&((cd + block.len()) as u32).to_le_bytes());
+            apk
+        };
+        // The digest does not depend on the block contents, only its start.
+        let unsigned = build(&[0u8; 32]);
+        let digest = chunked_digest::<Sha256>(&signed_sections(
+            &unsigned,
+            &zip_sections(&unsigned).unwrap(),
+        ));
+        return build(&digest);
+    }
+
+    #[test]
+    fn test_v2_digest() {
+        let apk = sign_v2(&build_zip(&[("classes.dex", b"dex\n035\0")]));
+        let report = SigningReport::inspect(&apk, None).unwrap();
+        assert_eq!(report.schemes(), vec!["v2"]);
+        let signer = &report.v2.as_ref().unwrap()[0];
+        assert_eq!(signer.certificates[0].subject, "CN=Test");
+        assert_eq!(signer.digests[0].verified, Some(true));
+        assert!(report.is_verified());
+
+        // Tamper with the entry data, which is stored uncompressed.
+        let mut tampered = apk.clone();
+        let offset = tampered.windows(4).position(|w| w == b"dex\n").unwrap();
+        tampered[offset + 4] = b'9';
+        let report = SigningReport::inspect(&tampered, None).unwrap();
+        assert!(!report.is_verified());
+    }
+
+    #[test]
+    fn test_malformed_signing_block_size() {
+        let zip = build_zip(&[("classes.dex", b"dex\n035\0")]);
+        let sections = zip_sections(&zip).unwrap();
+        let cd = sections.central_directory_offset;
+        for size in [16u64, u64::MAX] {
+            // Only the trailing size and the magic, so that with a size of 16
+            // both copies of the size are the same field.
+            let block = [&size.to_le_bytes()[..], APK_SIG_BLOCK_MAGIC].concat();
+            let mut apk = zip[..cd].to_vec();
+            apk.extend_from_slice(&block);
+            apk.extend_from_slice(&zip[cd..]);
+            let eocd = sections.eocd_offset + block.len();
+            apk[eocd + 16..eocd + 20].copy_from_slice(&((cd + block.len()) as u32).to_le_bytes());
+            assert!(matches!(
+                SigningReport::inspect(&apk, None),
+                Err(SigningError::Malformed(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_v1_manifest() {
+        let data = b"hello";
+        let manifest = format!(
+            "Manifest-Version: 1.0\r\n\r\nName: a.txt\r\nSHA-256-Digest: {}\r\n\r\n",
+            BASE64.encode(Sha256::digest(data))
+        );
+        let apk = build_zip(&[
+            ("META-INF/MANIFEST.MF", manifest.as_bytes()),
+            ("a.txt", data),
+            ("b.txt", b"unsigned"),
+        ]);
+        let report = SigningReport::inspect(&apk, None).unwrap();
+        assert!(report.is_verified());
+        let v1 = report.v1.unwrap();
+        assert!(v1.mismatched_entries.is_empty());
+        assert_eq!(v1.unsigned_entries, vec!["b.txt"]);
+    }
+
+    #[test]
+    fn test_unsigned() {
+        let apk = build_zip(&[("classes.dex", b"dex\n035\0")]);
+        let report = SigningReport::inspect(&apk, None).unwrap();
+        assert!(report.schemes().is_empty());
+        assert!(!report.is_verified());
+        assert!(report.to_string().contains("Digests: unsigned"));
+    }
+
+    #[test]
+    fn test_stripped_scheme() {
+        let manifest = b"Manifest-Version: 1.0\r\n\r\n";
+        let sf = format!(
+            "Signature-Version: 1.0\r\nX-Android-APK-Signed: 2, 3\r\nSHA-256-Digest-Manifest: {}\r\n\r\n",
+            BASE64.encode(Sha256::digest(manifest))
+        );
+        let zip = build_zip(&[
+            ("META-INF/MANIFEST.MF", manifest),
+            ("META-INF/CERT.SF", sf.as_bytes()),
+        ]);
+        let report = SigningReport::inspect(&zip, None).unwrap();
+        assert_eq!(
+            report.v1.as_ref().unwrap().signers[0].apk_signed_schemes,
+            vec![2, 3]
+        );
+        assert_eq!(report.stripped_schemes(), vec![2, 3]);
+        assert!(!report.is_verified());
+
+        // With the v2 block in place only v3 is missing.
+        let report = SigningReport::inspect(&sign_v2(&zip), None).unwrap();
+        assert_eq!(report.stripped_schemes(), vec![3]);
+        assert!(!report.is_verified());
+    }
+
+    #[test]
+    fn test_jar_manifest_continuation() {
+        let sections = parse_jar_manifest("Name: very/long/na\r\n me.txt\r\nX: 1\r\n");
+        assert_eq!(sections[0]["Name"], "very/long/name.txt");
+    }
+}
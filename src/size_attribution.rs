@@ -0,0 +1,500 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::{self, Display, Formatter},
+};
+
+use serde::Serialize;
+
+use crate::{
+    dex_model::DexModel,
+    dex_structs::{ClassDefItem, DexStruct, EncodedAnnotation, EncodedArray, EncodedValue},
+    instructions::IndexKind,
+    package_tree::PackageTree,
+    resolver::{field_indices, method_indices, DexResolver, NO_INDEX},
+};
+
+/// How bytes of pool entries referenced by several classes are attributed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SharedPolicy {
+    /// Report them under the `shared` categories.
+    Shared,
+    /// Split them evenly between the referencing classes.
+    Apportion,
+}
+
+/// Every byte of a dex file assigned to the class that needs it, or to a
+/// shared category when no single class owns it.
+#[derive(Debug, Clone, Serialize)]
+pub struct SizeAttribution {
+    pub dex: String,
+    pub file_size: u64,
+    /// Bytes owned by each class, by Java name.
+    pub classes: BTreeMap<String, u64>,
+    /// Bytes not owned by a single class, by section.
+    pub shared: BTreeMap<String, u64>,
+    /// `classes` rolled up by package.
+    pub packages: PackageTree<u64>,
+}
+
+/// An item of the dex file that classes can reference. Data items are
+/// keyed by offset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Entry {
+    String(u32),
+    Type(u32),
+    Proto(u32),
+    Field(u32),
+    Method(u32),
+    CallSite(u32),
+    MethodHandle(u32),
+    TypeList(u32),
+    CodeItem(u32),
+    DebugInfo(u32),
+    AnnotationsDirectory(u32),
+    AnnotationSetRefList(u32),
+    AnnotationSet(u32),
+    Annotation(u32),
+    EncodedArray(u32),
+    ClassData(u32),
+}
+
+impl Entry {
+    fn section(&self) -> &'static str {
+        return match self {
+            Entry::String(_) => "strings",
+            Entry::Type(_) => "type_ids",
+            Entry::Proto(_) => "proto_ids",
+            Entry::Field(_) => "field_ids",
+            Entry::Method(_) => "method_ids",
+            Entry::CallSite(_) => "call_site_ids",
+            Entry::MethodHandle(_) => "method_handles",
+            Entry::TypeList(_) => "type_lists",
+            Entry::CodeItem(_) => "code_items",
+            Entry::DebugInfo(_) => "debug_info_items",
+            Entry::AnnotationsDirectory(_)
+            | Entry::AnnotationSetRefList(_)
+            | Entry::AnnotationSet(_)
+            | Entry::Annotation(_) => "annotations",
+            Entry::EncodedArray(_) => "encoded_arrays",
+            Entry::ClassData(_) => "class_data_items",
+        };
+    }
+}
+
+/// Walks the reference graph of a dex starting from its class definitions.
+struct Walker<'a, 'b> {
+    resolver: &'b DexResolver<'a>,
+}
+
+impl<'a, 'b> Walker<'a, 'b> {
+    fn size(&self, entry: Entry) -> u64 {
+        let r = self.resolver;
+        let size = match entry {
+            Entry::String(i) => 4 + r.string_data(i).map_or(0, |x| x.size()),
+            Entry::Type(_) => 4,
+            Entry::Proto(_) => 12,
+            Entry::Field(_) | Entry::Method(_) | Entry::MethodHandle(_) => 8,
+            Entry::CallSite(_) => 4,
+            Entry::TypeList(off) => r.type_list(off).map_or(0, |x| x.size()),
+            Entry::CodeItem(off) => r.code_item(off).map_or(0, |x| x.size()),
+            Entry::DebugInfo(off) => r.debug_info_item(off).map_or(0, |x| x.size()),
+            Entry::AnnotationsDirectory(off) => {
+                r.annotations_directory(off).map_or(0, |x| x.size())
+            }
+            Entry::AnnotationSetRefList(off) => {
+                r.annotation_set_ref_list(off).map_or(0, |x| x.size())
+            }
+            Entry::AnnotationSet(off) => r.annotation_set(off).map_or(0, |x| x.size()),
+            Entry::Annotation(off) => r.annotation(off).map_or(0, |x| x.size()),
+            Entry::EncodedArray(off) => r.encoded_array(off).map_or(0, |x| x.size()),
+            Entry::ClassData(off) => r.class_data_item(off).map_or(0, |x| x.size()),
+        };
+        return size as u64;
+    }
+
+    /// Entries directly referenced by `entry`.
+    fn children(&self, entry: Entry, out: &mut Vec<Entry>) {
+        let r = self.resolver;
+        let dex = r.dex;
+        match entry {
+            Entry::String(_) => {}
+            Entry::Type(i) => {
+                if let Some(type_id) = dex.type_ids.get(i as usize) {
+                    out.push(Entry::String(type_id.descriptor_idx));
+                }
+            }
+            Entry::Proto(i) => {
+                if let Some(proto) = dex.proto_ids.get(i as usize) {
+                    out.push(Entry::String(proto.shorty_idx));
+                    out.push(Entry::Type(proto.return_type_idx));
+                    if proto.parameters_off != 0 {
+                        out.push(Entry::TypeList(proto.parameters_off));
+                    }
+                }
+            }
+            Entry::Field(i) => {
+                if let Some(field) = dex.field_ids.get(i as usize) {
+                    out.push(Entry::Type(field.class_idx as u32));
+                    out.push(Entry::Type(field.type_idx as u32));
+                    out.push(Entry::String(field.name_idx));
+                }
+            }
+            Entry::Method(i) => {
+                if let Some(method) = dex.method_ids.get(i as usize) {
+                    out.push(Entry::Type(method.class_idx as u32));
+                    out.push(Entry::Proto(method.proto_idx as u32));
+                    out.push(Entry::String(method.name_idx));
+                }
+            }
+            Entry::CallSite(i) => {
+                if let Some(call_site) = dex.call_site_ids.get(i as usize) {
+                    out.push(Entry::EncodedArray(call_site.call_site_off));
+                }
+            }
+            Entry::MethodHandle(i) => {
+                if let Some(handle) = dex.method_handles.get(i as usize) {
+                    let id = handle.field_or_method_id as u32;
+                    // Types 0x00 to 0x03 are field accessors, the rest invoke methods.
+                    out.push(if handle.method_handle_type <= 0x03 {
+                        Entry::Field(id)
+                    } else {
+                        Entry::Method(id)
+                    });
+                }
+            }
+            Entry::TypeList(off) => {
+                if let Some(list) = r.type_list(off) {
+                    out.extend(list.list.iter().map(|t| Entry::Type(t.type_idx as u32)));
+                }
+            }
+            Entry::CodeItem(off) => {
+                if let Some(code) = r.code_item(off) {
+                    if code.debug_info_off != 0 {
+                        out.push(Entry::DebugInfo(code.debug_info_off));
+                    }
+                    for insn in code.insns.iter() {
+                        for index_ref in insn.index_refs() {
+                            out.push(match index_ref.kind {
+                                IndexKind::String => Entry::String(index_ref.index),
+                                IndexKind::Type => Entry::Type(index_ref.index),
+                                IndexKind::Field => Entry::Field(index_ref.index),
+                                IndexKind::Method => Entry::Method(index_ref.index),
+                                IndexKind::Proto => Entry::Proto(index_ref.index),
+                                IndexKind::CallSite => Entry::CallSite(index_ref.index),
+                                IndexKind::MethodHandle => Entry::MethodHandle(index_ref.index),
+                            });
+                        }
+                    }
+                    if let Some(handlers) = &code.handlers {
+                        for handler in handlers.list.iter() {
+                            out.extend(handler.handlers.iter().map(|h| Entry::Type(h.type_idx)));
+                        }
+                    }
+                }
+            }
+            Entry::DebugInfo(off) => {
+                if let Some(debug_info) = r.debug_info_item(off) {
+                    for name in debug_info.parameter_names.iter() {
+                        if *name >= 0 {
+                            out.push(Entry::String(*name as u32));
+                        }
+                    }
+                    let info = debug_info.decode();
+                    for position in info.positions.iter() {
+                        out.extend(position.source_file_idx.map(Entry::String));
+                    }
+                    for local in info.locals.iter() {
+                        out.extend(local.name_idx.map(Entry::String));
+                        out.extend(local.type_idx.map(Entry::Type));
+                        out.extend(local.sig_idx.map(Entry::String));
+                    }
+                }
+            }
+            Entry::AnnotationsDirectory(off) => {
+                if let Some(directory) = r.annotations_directory(off) {
+                    if directory.class_annotations_off != 0 {
+                        out.push(Entry::AnnotationSet(directory.class_annotations_off));
+                    }
+                    for a in directory.field_annotations.iter() {
+                        out.push(Entry::AnnotationSet(a.annotations_off));
+                    }
+                    for a in directory.method_annotations.iter() {
+                        out.push(Entry::AnnotationSet(a.annotations_off));
+                    }
+                    for a in directory.parameter_annotations.iter() {
+                        out.push(Entry::AnnotationSetRefList(a.annotations_off));
+                    }
+                }
+            }
+            Entry::AnnotationSetRefList(off) => {
+                if let Some(list) = r.annotation_set_ref_list(off) {
+                    for item in list.list.iter() {
+                        if item.annotations_off != 0 {
+                            out.push(Entry::AnnotationSet(item.annotations_off));
+                        }
+                    }
+                }
+            }
+            Entry::AnnotationSet(off) => {
+                if let Some(set) = r.annotation_set(off) {
+                    out.extend(
+                        set.entries
+                            .iter()
+                            .map(|e| Entry::Annotation(e.annotation_off)),
+                    );
+                }
+            }
+            Entry::Annotation(off) => {
+                if let Some(item) = r.annotation(off) {
+                    encoded_annotation_children(&item.annotation, out);
+                }
+            }
+            Entry::EncodedArray(off) => {
+                if let Some(item) = r.encoded_array(off) {
+                    encoded_array_children(&item.value, out);
+                }
+            }
+            Entry::ClassData(off) => {
+                if let Some(data) = r.class_data_item(off) {
+                    for (field_idx, _) in field_indices(&data.static_fields)
+                        .into_iter()
+                        .chain(field_indices(&data.instance_fields))
+                    {
+                        out.push(Entry::Field(field_idx));
+                    }
+                    for (method_idx, method) in method_indices(&data.direct_methods)
+                        .into_iter()
+                        .chain(method_indices(&data.virtual_methods))
+                    {
+                        out.push(Entry::Method(method_idx));
+                        if method.code_off != 0 {
+                            out.push(Entry::CodeItem(method.code_off));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Entries directly referenced by a class definition.
+    fn class_roots(&self, class_def: &ClassDefItem) -> Vec<Entry> {
+        let mut roots = vec![Entry::Type(class_def.class_idx)];
+        if class_def.superclass_idx != NO_INDEX {
+            roots.push(Entry::Type(class_def.superclass_idx));
+        }
+        if class_def.interfaces_off != 0 {
+            roots.push(Entry::TypeList(class_def.interfaces_off));
+        }
+        if class_def.source_file_idx != NO_INDEX {
+            roots.push(Entry::String(class_def.source_file_idx));
+        }
+        if class_def.annotations_off != 0 {
+            roots.push(Entry::AnnotationsDirectory(class_def.annotations_off));
+        }
+        if class_def.class_data_off != 0 {
+            roots.push(Entry::ClassData(class_def.class_data_off));
+        }
+        if class_def.static_values_off != 0 {
+            roots.push(Entry::EncodedArray(class_def.static_values_off));
+        }
+        return roots;
+    }
+}
+
+fn encoded_value_children(value: &EncodedValue, out: &mut Vec<Entry>) {
+    match value {
+        EncodedValue::ValueMethodType(i) => out.push(Entry::Proto(*i)),
+        EncodedValue::ValueMethodHandle(i) => out.push(Entry::MethodHandle(*i)),
+        EncodedValue::ValueString(i) => out.push(Entry::String(*i)),
+        EncodedValue::ValueType(i) => out.push(Entry::Type(*i)),
+        EncodedValue::ValueField(i) | EncodedValue::ValueEnum(i) => out.push(Entry::Field(*i)),
+        EncodedValue::ValueMethod(i) => out.push(Entry::Method(*i)),
+        EncodedValue::ValueArray(array) => encoded_array_children(array, out),
+        EncodedValue::ValueAnnotation(annotation) => encoded_annotation_children(annotation, out),
+        _ => {}
+    }
+}
+
+fn encoded_array_children(array: &EncodedArray, out: &mut Vec<Entry>) {
+    for value in array.values.iter() {
+        encoded_value_children(value, out);
+    }
+}
+
+fn encoded_annotation_children(annotation: &EncodedAnnotation, out: &mut Vec<Entry>) {
+    out.push(Entry::Type(annotation.type_idx));
+    for element in annotation.elements.iter() {
+        out.push(Entry::String(element.name_idx));
+        encoded_value_children(&element.value, out);
+    }
+}
+
+impl SizeAttribution {
+    pub fn new(name: &str, dex: &DexModel, policy: SharedPolicy) -> SizeAttribution {
+        return SizeAttribution::from_resolver(name, &DexResolver::new(dex), policy);
+    }
+
+    /// Walks the items each class reaches from its `class_def` and charges
+    /// them to the class under the name the resolver gives it.
+    pub fn from_resolver(
+        name: &str,
+        resolver: &DexResolver,
+        policy: SharedPolicy,
+    ) -> SizeAttribution {
+        let dex = resolver.dex;
+        let walker = Walker { resolver };
+
+        // Classes reaching each entry, by position in `class_defs`.
+        let mut referrers: HashMap<Entry, Vec<usize>> = HashMap::new();
+        let mut children = vec![];
+        for (class, class_def) in dex.class_defs.iter().enumerate() {
+            let mut visited = HashSet::new();
+            let mut stack = walker.class_roots(class_def);
+            while let Some(entry) = stack.pop() {
+                if !visited.insert(entry) {
+                    continue;
+                }
+                referrers.entry(entry).or_default().push(class);
+                walker.children(entry, &mut children);
+                stack.append(&mut children);
+            }
+        }
+
+        let class_names = dex
+            .class_defs
+            .iter()
+            .map(|class_def| resolver.type_name(class_def.class_idx))
+            .collect::<Vec<_>>();
+        let mut class_sizes = vec![32u64; dex.class_defs.len()];
+        let mut shared = BTreeMap::new();
+        let mut attributed = 32 * dex.class_defs.len() as u64;
+        for (entry, classes) in referrers.iter() {
+            let size = walker.size(*entry);
+            attributed += size;
+            if classes.len() == 1 {
+                class_sizes[classes[0]] += size;
+            } else if policy == SharedPolicy::Apportion {
+                let n = classes.len() as u64;
+                for (i, class) in classes.iter().enumerate() {
+                    class_sizes[*class] += size / n + if (i as u64) < size % n { 1 } else { 0 };
+                }
+            } else {
+                *shared.entry(entry.section().to_string()).or_insert(0) += size;
+            }
+        }
+
+        let file_size = dex.header.file_size as u64;
+        let header_size = dex.header.header_size as u64;
+        let map_list_size = dex.map_list.size() as u64;
+        shared.insert("header".to_string(), header_size);
+        shared.insert("map_list".to_string(), map_list_size);
+        attributed += header_size + map_list_size;
+        // Unreferenced items, alignment padding and link data.
+        shared.insert(
+            "unreferenced".to_string(),
+            file_size.saturating_sub(attributed),
+        );
+
+        let mut classes = BTreeMap::new();
+        let mut packages = PackageTree::new();
+        for (name, size) in class_names.into_iter().zip(class_sizes) {
+            packages.add(&name, size);
+            *classes.entry(name).or_insert(0) += size;
+        }
+
+        return SizeAttribution {
+            dex: name.to_string(),
+            file_size,
+            classes,
+            shared,
+            packages,
+        };
+    }
+
+    /// Total bytes of a package, e.g. `com.vendor.sdk`.
+    pub fn package_size(&self, package: &str) -> u64 {
+        return self.packages.get(package).map_or(0, |node| node.total);
+    }
+
+    pub fn to_json(&self) -> String {
+        return serde_json::to_string_pretty(self).unwrap();
+    }
+
+    /// Text report limited to packages at most `max_depth` segments deep.
+    pub fn to_text(&self, max_depth: Option<usize>) -> String {
+        let percent = |size: u64| size as f64 * 100.0 / self.file_size.max(1) as f64;
+        let mut text = format!("{}: {} bytes\n", self.dex, self.file_size);
+        text.push_str(&format!(
+            "  {:<40} {:>10} {:>6.1}%\n",
+            "<classes>",
+            self.packages.total,
+            percent(self.packages.total)
+        ));
+        for (package, depth, size) in self.packages.packages(max_depth) {
+            let segment = package.rsplit('.').next().unwrap_or(&package);
+            text.push_str(&format!(
+                "  {:<40} {:>10} {:>6.1}%\n",
+                format!("{}{}", "  ".repeat(depth), segment),
+                size,
+                percent(*size)
+            ));
+        }
+        for (section, size) in self.shared.iter() {
+            text.push_str(&format!(
+                "  {:<40} {:>10} {:>6.1}%\n",
+                format!("<shared {}>", section),
+                size,
+                percent(*size)
+            ));
+        }
+        return text;
+    }
+}
+
+impl Display for SizeAttribution {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        return write!(f, "{}", self.to_text(None));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{build_dex, TestClass};
+
+    #[test]
+    fn test_attribution_covers_file() {
+        // const-string v0, string@4 ("only foo"); return-void
+        let dex = build_dex(
+            vec![
+                TestClass::new("Lcom/vendor/sdk/Foo;").method(
+                    "run",
+                    &[],
+                    "V",
+                    &[0x001a, 0x0004, 0x000e],
+                ),
+                TestClass::new("Lcom/app/Main;").method("run", &[], "V", &[0x000e]),
+            ],
+            &["only foo"],
+        );
+        assert_eq!(DexResolver::new(&dex).string(4), "only foo");
+        let dex_file_size = dex.header.file_size as u64;
+
+        for policy in [SharedPolicy::Shared, SharedPolicy::Apportion] {
+            let attribution = SizeAttribution::new("classes.dex", &dex, policy);
+            let total = attribution.packages.total + attribution.shared.values().sum::<u64>();
+            assert_eq!(total, dex_file_size);
+        }
+
+        let shared = SizeAttribution::new("classes.dex", &dex, SharedPolicy::Shared);
+        let apportioned = SizeAttribution::new("classes.dex", &dex, SharedPolicy::Apportion);
+        // The "only foo" string is owned by Foo under both policies, while
+        // the shared `run` name and `()V` proto are only apportioned.
+        let string_size = 4 + 1 + "only foo".len() as u64 + 1;
+        assert!(
+            shared.classes["com.vendor.sdk.Foo"] >= shared.classes["com.app.Main"] + string_size
+        );
+        assert!(apportioned.package_size("com.vendor") > shared.package_size("com.vendor"));
+        assert_eq!(apportioned.package_size("com"), apportioned.packages.total);
+    }
+}
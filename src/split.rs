@@ -0,0 +1,589 @@
+//! Partitions the classes of a dex file into several files that each stay
+//! within the 64K id limits, as for legacy multidex, keeping a main-dex
+//! list in the first file.
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt::{self, Display, Formatter},
+};
+
+use crate::{
+    compact::compact_copy,
+    dex_model::DexModel,
+    dex_structs::EncodedValue,
+    instructions::IndexKind,
+    remap::METHOD_HANDLE_TYPE_INVOKE_STATIC,
+    resolver::{DexResolver, NO_INDEX},
+};
+
+#[derive(Debug)]
+pub enum SplitError {
+    /// A main-dex list entry is not defined in the input.
+    UnknownClass(String),
+    /// The main-dex classes and their dependencies exceed a limit on their
+    /// own.
+    MainDexTooLarge { pool: &'static str, count: usize },
+    /// A single class exceeds a limit on its own.
+    ClassTooLarge(String),
+}
+
+impl Display for SplitError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            SplitError::UnknownClass(class) => {
+                write!(f, "main dex class {} is not defined", class)
+            }
+            SplitError::MainDexTooLarge { pool, count } => write!(
+                f,
+                "main dex classes need {} {} ids, more than one file holds",
+                count, pool
+            ),
+            SplitError::ClassTooLarge(class) => {
+                write!(f, "class {} does not fit in a dex file", class)
+            }
+        }
+    }
+}
+
+pub struct SplitOptions {
+    /// Classes that must be in the first file, as type descriptors or as
+    /// `com/example/Foo.class` entries of a d8 main-dex list file.
+    pub main_dex_list: Vec<String>,
+    /// Most ids each output may have in its type, proto, field and method
+    /// pools.
+    pub max_ids: usize,
+}
+
+impl Default for SplitOptions {
+    fn default() -> Self {
+        return Self {
+            main_dex_list: vec![],
+            max_ids: 0x10000,
+        };
+    }
+}
+
+/// Normalizes a main-dex list entry to a type descriptor.
+fn main_dex_descriptor(entry: &str) -> String {
+    return match entry.trim().strip_suffix(".class") {
+        Some(path) => format!("L{};", path),
+        None => entry.trim().to_string(),
+    };
+}
+
+/// Ids a class needs in whichever file defines it, by pool, including those
+/// of the call sites its code invokes. Annotations and static values are not
+/// counted, so this is an estimate that the final pools are checked against.
+#[derive(Default)]
+struct ClassRefs {
+    types: HashSet<u32>,
+    protos: HashSet<u32>,
+    fields: HashSet<u32>,
+    methods: HashSet<u32>,
+}
+
+impl ClassRefs {
+    fn of(dex: &DexModel, resolver: &DexResolver, class: usize) -> Self {
+        let class_def = &dex.class_defs[class];
+        let mut refs = ClassRefs::default();
+        refs.types.insert(class_def.class_idx);
+        if class_def.superclass_idx != NO_INDEX {
+            refs.types.insert(class_def.superclass_idx);
+        }
+        if let Some(list) = resolver.type_list(class_def.interfaces_off) {
+            refs.types
+                .extend(list.list.iter().map(|t| t.type_idx as u32));
+        }
+        for (field_idx, _) in resolver.class_fields(class_def) {
+            refs.add(dex, resolver, IndexKind::Field, field_idx);
+        }
+        for (method_idx, method) in resolver.class_methods(class_def) {
+            refs.add(dex, resolver, IndexKind::Method, method_idx);
+            let code_item = match resolver.code_item(method.code_off) {
+                Some(code_item) => code_item,
+                None => continue,
+            };
+            for insn in code_item.insns.iter() {
+                for index_ref in insn.index_refs() {
+                    refs.add(dex, resolver, index_ref.kind, index_ref.index);
+                }
+            }
+            for handler in code_item.handlers.iter().flat_map(|h| h.list.iter()) {
+                refs.types
+                    .extend(handler.handlers.iter().map(|pair| pair.type_idx));
+            }
+        }
+        return refs;
+    }
+
+    /// Records an id with the ids it refers to.
+    fn add(&mut self, dex: &DexModel, resolver: &DexResolver, kind: IndexKind, index: u32) {
+        match kind {
+            IndexKind::Type => {
+                self.types.insert(index);
+            }
+            IndexKind::Field => {
+                let field = &dex.field_ids[index as usize];
+                self.fields.insert(index);
+                self.types.insert(field.class_idx as u32);
+                self.types.insert(field.type_idx as u32);
+            }
+            IndexKind::Method => {
+                let method = &dex.method_ids[index as usize];
+                self.methods.insert(index);
+                self.types.insert(method.class_idx as u32);
+                self.add(dex, resolver, IndexKind::Proto, method.proto_idx as u32);
+            }
+            IndexKind::Proto => {
+                let proto = &dex.proto_ids[index as usize];
+                self.protos.insert(index);
+                self.types.insert(proto.return_type_idx);
+                if let Some(list) = resolver.type_list(proto.parameters_off) {
+                    self.types
+                        .extend(list.list.iter().map(|t| t.type_idx as u32));
+                }
+            }
+            IndexKind::CallSite => {
+                let call_site = &dex.call_site_ids[index as usize];
+                if let Some(array) = resolver.encoded_array(call_site.call_site_off) {
+                    for value in array.value.values.iter() {
+                        self.add_value(dex, resolver, value);
+                    }
+                }
+            }
+            IndexKind::MethodHandle => {
+                let handle = &dex.method_handles[index as usize];
+                let kind = if handle.method_handle_type < METHOD_HANDLE_TYPE_INVOKE_STATIC {
+                    IndexKind::Field
+                } else {
+                    IndexKind::Method
+                };
+                self.add(dex, resolver, kind, handle.field_or_method_id as u32);
+            }
+            IndexKind::String => {}
+        }
+    }
+
+    /// Records the ids an argument of a call site refers to.
+    fn add_value(&mut self, dex: &DexModel, resolver: &DexResolver, value: &EncodedValue) {
+        match value {
+            EncodedValue::ValueMethodHandle(i) => {
+                self.add(dex, resolver, IndexKind::MethodHandle, *i)
+            }
+            EncodedValue::ValueMethodType(i) => self.add(dex, resolver, IndexKind::Proto, *i),
+            EncodedValue::ValueType(i) => self.add(dex, resolver, IndexKind::Type, *i),
+            EncodedValue::ValueField(i) | EncodedValue::ValueEnum(i) => {
+                self.add(dex, resolver, IndexKind::Field, *i)
+            }
+            EncodedValue::ValueMethod(i) => self.add(dex, resolver, IndexKind::Method, *i),
+            EncodedValue::ValueArray(array) => {
+                for value in array.values.iter() {
+                    self.add_value(dex, resolver, value);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Union of the ids needed by the classes of one output.
+#[derive(Default)]
+struct Pools {
+    types: HashSet<u32>,
+    protos: HashSet<u32>,
+    fields: HashSet<u32>,
+    methods: HashSet<u32>,
+}
+
+impl Pools {
+    /// The first pool that adding `refs` would grow past `max_ids`, with
+    /// its grown size.
+    fn overflow(&self, refs: &ClassRefs, max_ids: usize) -> Option<(&'static str, usize)> {
+        let grown = |pool: &HashSet<u32>, refs: &HashSet<u32>| {
+            pool.len() + refs.iter().filter(|i| !pool.contains(i)).count()
+        };
+        return [
+            ("type", grown(&self.types, &refs.types)),
+            ("proto", grown(&self.protos, &refs.protos)),
+            ("field", grown(&self.fields, &refs.fields)),
+            ("method", grown(&self.methods, &refs.methods)),
+        ]
+        .into_iter()
+        .find(|(_, count)| *count > max_ids);
+    }
+
+    fn add(&mut self, refs: &ClassRefs) {
+        self.types.extend(refs.types.iter());
+        self.protos.extend(refs.protos.iter());
+        self.fields.extend(refs.fields.iter());
+        self.methods.extend(refs.methods.iter());
+    }
+}
+
+/// The pool of `dex` over the limit, if any.
+fn over_limit(dex: &DexModel, max_ids: usize) -> Option<(&'static str, usize)> {
+    return [
+        ("type", dex.type_ids.len()),
+        ("proto", dex.proto_ids.len()),
+        ("field", dex.field_ids.len()),
+        ("method", dex.method_ids.len()),
+    ]
+    .into_iter()
+    .find(|(_, count)| *count > max_ids);
+}
+
+/// A copy of `dex` defining only the classes at the given `class_defs`
+/// positions, with its pools rebuilt.
+fn extract(dex: &DexModel, classes: &HashSet<usize>) -> DexModel {
+    let class_defs = dex
+        .class_defs
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| classes.contains(i))
+        .map(|(_, class_def)| class_def.clone())
+        .collect();
+    return compact_copy(dex, class_defs);
+}
+
+/// A copy of `dex` defining only the classes with the given descriptors.
+/// Descriptors the file does not define are ignored.
+pub fn extract_classes(dex: &DexModel, descriptors: &[&str]) -> DexModel {
+    let resolver = DexResolver::new(dex);
+    let classes = dex
+        .class_defs
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| descriptors.contains(&resolver.type_descriptor(c.class_idx)))
+        .map(|(i, _)| i)
+        .collect();
+    return extract(dex, &classes);
+}
+
+/// Classes that must be in the main dex: the listed classes, the classes
+/// they reference directly, and the supertypes of both, as class loading
+/// needs them.
+fn main_dex_classes(
+    dex: &DexModel,
+    resolver: &DexResolver,
+    refs: &[ClassRefs],
+    main_dex_list: &[String],
+) -> Result<HashSet<usize>, SplitError> {
+    let position = dex
+        .class_defs
+        .iter()
+        .enumerate()
+        .map(|(i, class_def)| (class_def.class_idx, i))
+        .collect::<HashMap<_, _>>();
+    let mut roots = vec![];
+    for entry in main_dex_list {
+        let descriptor = main_dex_descriptor(entry);
+        match resolver.class_def_by_descriptor(&descriptor) {
+            Some(class_def) => roots.push(position[&class_def.class_idx]),
+            None => return Err(SplitError::UnknownClass(descriptor)),
+        }
+    }
+    let direct = roots
+        .iter()
+        .flat_map(|root| refs[*root].types.iter())
+        .filter_map(|t| position.get(t).copied())
+        .collect::<Vec<_>>();
+
+    let mut classes = HashSet::new();
+    let mut pending = roots.into_iter().chain(direct).collect::<Vec<_>>();
+    while let Some(class) = pending.pop() {
+        if !classes.insert(class) {
+            continue;
+        }
+        let class_def = &dex.class_defs[class];
+        let mut supertypes = vec![class_def.superclass_idx];
+        if let Some(list) = resolver.type_list(class_def.interfaces_off) {
+            supertypes.extend(list.list.iter().map(|t| t.type_idx as u32));
+        }
+        pending.extend(supertypes.iter().filter_map(|t| position.get(t)));
+    }
+    return Ok(classes);
+}
+
+/// Splits `dex` into files that each stay within `options.max_ids` ids per
+/// pool. The main-dex classes and their dependencies go to the first file,
+/// which other classes then fill up in their original order. Every output
+/// has its own pools and is laid out ready to serialize.
+pub fn split(dex: &DexModel, options: &SplitOptions) -> Result<Vec<DexModel>, SplitError> {
+    let resolver = DexResolver::new(dex);
+    let refs = (0..dex.class_defs.len())
+        .map(|i| ClassRefs::of(dex, &resolver, i))
+        .collect::<Vec<_>>();
+    let main = main_dex_classes(dex, &resolver, &refs, &options.main_dex_list)?;
+    let mut pending = (0..dex.class_defs.len())
+        .filter(|i| main.contains(i))
+        .chain((0..dex.class_defs.len()).filter(|i| !main.contains(i)))
+        .collect::<VecDeque<_>>();
+    let descriptor = |i: usize| {
+        resolver
+            .type_descriptor(dex.class_defs[i].class_idx)
+            .to_string()
+    };
+
+    let mut outputs = vec![];
+    while !pending.is_empty() {
+        let mut chosen = vec![];
+        let mut pools = Pools::default();
+        while let Some(class) = pending.front().copied() {
+            if let Some((pool, count)) = pools.overflow(&refs[class], options.max_ids) {
+                if outputs.is_empty() && main.contains(&class) {
+                    return Err(SplitError::MainDexTooLarge { pool, count });
+                }
+                if !chosen.is_empty() {
+                    break;
+                }
+            }
+            pools.add(&refs[class]);
+            chosen.push(class);
+            pending.pop_front();
+        }
+
+        // The estimate leaves out some references, so hand the classes past
+        // the longest prefix whose real pools fit back to the next file. Pools
+        // only grow with the prefix, so a binary search finds it.
+        let extract_prefix = |len: usize| extract(dex, &chosen[..len].iter().copied().collect());
+        let mut out = extract_prefix(chosen.len());
+        if let Some(mut over) = over_limit(&out, options.max_ids) {
+            let (mut fits, mut overflows) = (0, chosen.len());
+            while overflows - fits > 1 {
+                let len = (fits + overflows) / 2;
+                let candidate = extract_prefix(len);
+                match over_limit(&candidate, options.max_ids) {
+                    Some(candidate_over) => {
+                        overflows = len;
+                        over = candidate_over;
+                    }
+                    None => {
+                        fits = len;
+                        out = candidate;
+                    }
+                }
+            }
+            if fits == 0 {
+                return Err(SplitError::ClassTooLarge(descriptor(chosen[0])));
+            }
+            if outputs.is_empty() && chosen[fits..].iter().any(|c| main.contains(c)) {
+                let (pool, count) = over;
+                return Err(SplitError::MainDexTooLarge { pool, count });
+            }
+            for class in chosen[fits..].iter().rev() {
+                pending.push_front(*class);
+            }
+        }
+        outputs.push(out);
+    }
+    return Ok(outputs);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        deserialize_vec, serialize,
+        test_utils::{build_dex, TestClass},
+    };
+
+    fn class(descriptor: &'static str, m1: &[u16]) -> TestClass {
+        return TestClass::new(descriptor)
+            .method("m1", &[], "V", m1)
+            .method("m2", &[], "V", &[0x000e]);
+    }
+
+    #[test]
+    fn test_split() {
+        let dex = build_dex(
+            vec![
+                class("La/A;", &[0x000e]),
+                class("Lb/B;", &[0x000e]),
+                class("Lc/C;", &[0x000e]),
+                // invoke-static {}, Lc/C;->m1()V; return-void
+                class("Ld/D;", &[0x0071, 0x0004, 0x0000, 0x000e]),
+            ],
+            &[],
+        );
+        let options = SplitOptions {
+            main_dex_list: vec!["d/D.class".to_string()],
+            max_ids: 4,
+        };
+        let outputs = split(&dex, &options).unwrap();
+
+        let classes = outputs
+            .into_iter()
+            .map(|out| {
+                let out = deserialize_vec(serialize(out)).unwrap();
+                let resolver = DexResolver::new(&out);
+                if let Some(d) = resolver.class_def_by_descriptor("Ld/D;") {
+                    let (_, m1) = resolver.class_methods(d)[0];
+                    let code = resolver.code_item(m1.code_off).unwrap();
+                    let invoke = code.insns[0].index_refs()[0];
+                    assert_eq!(
+                        resolver.method_ref(invoke.index).signature(),
+                        "Lc/C;->m1()V"
+                    );
+                }
+                assert!(out.method_ids.len() <= 4);
+                out.class_defs
+                    .iter()
+                    .map(|c| resolver.type_descriptor(c.class_idx).to_string())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            classes,
+            vec![vec!["Lc/C;", "Ld/D;"], vec!["La/A;", "Lb/B;"]]
+        );
+
+        let options = SplitOptions {
+            main_dex_list: vec!["Le/E;".to_string()],
+            ..SplitOptions::default()
+        };
+        assert!(matches!(
+            split(&dex, &options),
+            Err(SplitError::UnknownClass(_))
+        ));
+    }
+
+    fn descriptors(dex: &DexModel, classes: impl IntoIterator<Item = usize>) -> Vec<String> {
+        let resolver = DexResolver::new(dex);
+        let mut descriptors = classes
+            .into_iter()
+            .map(|i| {
+                resolver
+                    .type_descriptor(dex.class_defs[i].class_idx)
+                    .to_string()
+            })
+            .collect::<Vec<_>>();
+        descriptors.sort();
+        return descriptors;
+    }
+
+    #[test]
+    fn test_limits() {
+        let dex = build_dex(
+            vec![
+                TestClass::new("La/A;")
+                    .field("f", "I")
+                    .field("g", "J")
+                    .method("m", &["I"], "V", &[0x000e]),
+                TestClass::new("Lb/B;")
+                    .field("f", "Z")
+                    .method("m", &["Z"], "V", &[0x000e]),
+                TestClass::new("Lc/C;")
+                    .field("f", "B")
+                    .field("g", "S")
+                    .field("h", "C")
+                    .method("m", &[], "V", &[0x000e]),
+                TestClass::new("Ld/D;")
+                    .field("f", "F")
+                    .method("m", &["D"], "V", &[0x000e])
+                    .method("n", &["J"], "V", &[0x000e]),
+            ],
+            &[],
+        );
+        for max_ids in [6, 7, 8, 16] {
+            let options = SplitOptions {
+                max_ids,
+                ..SplitOptions::default()
+            };
+            let outputs = split(&dex, &options).unwrap();
+            let mut classes = vec![];
+            for out in outputs.iter() {
+                assert_eq!(over_limit(out, max_ids), None, "max_ids {}", max_ids);
+                classes.extend(descriptors(out, 0..out.class_defs.len()));
+            }
+            classes.sort();
+            assert_eq!(classes, descriptors(&dex, 0..dex.class_defs.len()));
+        }
+    }
+
+    #[test]
+    fn test_main_dex() {
+        // Methods: 0 A.m1, 2 C.m1, 4 D.m1, 6 E.m1, 8 I.m1, 10 S.m1 and m2
+        // after each.
+        let dex = build_dex(
+            vec![
+                class("La/A;", &[0x000e]),
+                // invoke-static {}, Le/E;->m1()V; return-void
+                class("Lc/C;", &[0x0071, 0x0006, 0x0000, 0x000e]).extends("Lp/S;"),
+                // invoke-static {}, Lc/C;->m1()V; return-void
+                class("Ld/D;", &[0x0071, 0x0002, 0x0000, 0x000e]).implements("Li/I;"),
+                class("Le/E;", &[0x000e]),
+                class("Li/I;", &[0x000e]),
+                class("Lp/S;", &[0x000e]),
+            ],
+            &[],
+        );
+        let resolver = DexResolver::new(&dex);
+        assert_eq!(resolver.method_ref(2).signature(), "Lc/C;->m1()V");
+        assert_eq!(resolver.method_ref(6).signature(), "Le/E;->m1()V");
+
+        // D, its direct dependency C, and their supertypes, but not E,
+        // which only C uses.
+        let refs = (0..dex.class_defs.len())
+            .map(|i| ClassRefs::of(&dex, &resolver, i))
+            .collect::<Vec<_>>();
+        let main = main_dex_classes(&dex, &resolver, &refs, &["Ld/D;".to_string()]).unwrap();
+        assert_eq!(
+            descriptors(&dex, main),
+            vec!["Lc/C;", "Ld/D;", "Li/I;", "Lp/S;"]
+        );
+
+        // The main-dex classes reference 9 methods, E.m1 included.
+        let options = |max_ids| SplitOptions {
+            main_dex_list: vec!["Ld/D;".to_string()],
+            max_ids,
+        };
+        let outputs = split(&dex, &options(9)).unwrap();
+        let classes = outputs
+            .iter()
+            .map(|out| descriptors(out, 0..out.class_defs.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            classes,
+            vec![
+                vec!["Lc/C;", "Ld/D;", "Li/I;", "Lp/S;"],
+                vec!["La/A;", "Le/E;"]
+            ]
+        );
+        assert!(matches!(
+            split(&dex, &options(8)),
+            Err(SplitError::MainDexTooLarge {
+                pool: "method",
+                count: 9
+            })
+        ));
+    }
+
+    #[test]
+    fn test_class_too_large() {
+        let dex = build_dex(
+            vec![
+                class("La/A;", &[0x000e]),
+                class("Lb/B;", &[0x000e])
+                    .method("m3", &[], "V", &[0x000e])
+                    .method("m4", &[], "V", &[0x000e]),
+            ],
+            &[],
+        );
+        let options = SplitOptions {
+            max_ids: 2,
+            ..SplitOptions::default()
+        };
+        assert!(matches!(
+            split(&dex, &options),
+            Err(SplitError::ClassTooLarge(class)) if class == "La/A;"
+        ));
+        let options = SplitOptions {
+            max_ids: 3,
+            ..SplitOptions::default()
+        };
+        assert!(matches!(
+            split(&dex, &options),
+            Err(SplitError::ClassTooLarge(class)) if class == "Lb/B;"
+        ));
+    }
+}
@@ -0,0 +1,432 @@
+//! Initial values of static fields.
+//!
+//! The `static_values_off` of a class points at an encoded array whose
+//! entries belong, in order, to the static fields of its class data. Fields
+//! past the end of the array start out with the default value of their type.
+//! `static_fields` pairs every static field with its value, resolved as an
+//! `annotations::Value`, and `DexModel::set_static_value` in `edit` writes
+//! modified values back.
+
+use std::fmt::{self, Display, Formatter};
+
+use serde::Serialize;
+
+use crate::{
+    annotations::Value,
+    dex_model::DexModel,
+    dex_structs::{ClassDefItem, EncodedValue},
+    resolver::{descriptor_to_java, field_indices, java_to_descriptor, DexResolver},
+};
+
+const ACC_FINAL: u32 = 0x10;
+
+/// The value a static field of type `descriptor` has when the encoded
+/// array does not reach it.
+pub(crate) fn default_value(descriptor: &str) -> EncodedValue {
+    return match descriptor {
+        "Z" => EncodedValue::ValueBoolean(false),
+        "B" => EncodedValue::ValueByte(0),
+        "S" => EncodedValue::ValueShort(0),
+        "C" => EncodedValue::ValueChar(0),
+        "I" => EncodedValue::ValueInt(0),
+        "J" => EncodedValue::ValueLong(0),
+        "F" => EncodedValue::ValueFloat(0.0),
+        "D" => EncodedValue::ValueDouble(0.0),
+        _ => EncodedValue::ValueNull,
+    };
+}
+
+/// Whether `value` is the default of `descriptor`, so that it may be left
+/// out at the end of the array. `-0.0` is not.
+pub(crate) fn is_default(descriptor: &str, value: &EncodedValue) -> bool {
+    return match (default_value(descriptor), value) {
+        (EncodedValue::ValueFloat(a), EncodedValue::ValueFloat(b)) => a.to_bits() == b.to_bits(),
+        (EncodedValue::ValueDouble(a), EncodedValue::ValueDouble(b)) => a.to_bits() == b.to_bits(),
+        (default, value) => default == *value,
+    };
+}
+
+/// Whether a field of type `descriptor` can hold `value`: primitive fields
+/// only values of their own type, reference fields `null` and values of
+/// their own class. `Ljava/lang/Object;` takes any reference; other
+/// supertypes are not known without the class hierarchy.
+pub(crate) fn accepts(descriptor: &str, value: &Value) -> bool {
+    if descriptor == "Ljava/lang/Object;" && !is_primitive(value) {
+        return true;
+    }
+    return match (descriptor, value) {
+        ("Z", Value::Boolean(_))
+        | ("B", Value::Byte(_))
+        | ("S", Value::Short(_))
+        | ("C", Value::Char(_))
+        | ("I", Value::Int(_))
+        | ("J", Value::Long(_))
+        | ("F", Value::Float(_))
+        | ("D", Value::Double(_))
+        | ("Ljava/lang/String;", Value::String(_))
+        | ("Ljava/lang/Class;", Value::Type(_))
+        | ("Ljava/lang/invoke/MethodType;", Value::MethodType(_))
+        | ("Ljava/lang/invoke/MethodHandle;", Value::MethodHandle(_))
+        | ("Ljava/lang/reflect/Field;", Value::Field(_))
+        | ("Ljava/lang/reflect/Method;", Value::Method(_)) => true,
+        ("Z" | "B" | "S" | "C" | "I" | "J" | "F" | "D", _) => false,
+        (_, Value::Null) => true,
+        // The signature of an enum constant ends with the enum type.
+        (_, Value::Enum(signature)) => signature.ends_with(&format!(":{}", descriptor)),
+        (_, Value::Annotation(annotation)) => annotation.type_descriptor == descriptor,
+        (_, Value::Array(values)) => match descriptor.strip_prefix('[') {
+            Some(element) => values.iter().all(|v| accepts(element, v)),
+            None => false,
+        },
+        _ => false,
+    };
+}
+
+/// Whether `value` is of a primitive type.
+fn is_primitive(value: &Value) -> bool {
+    return matches!(
+        value,
+        Value::Boolean(_)
+            | Value::Byte(_)
+            | Value::Short(_)
+            | Value::Char(_)
+            | Value::Int(_)
+            | Value::Long(_)
+            | Value::Float(_)
+            | Value::Double(_)
+    );
+}
+
+/// A static field and its initial value.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StaticField {
+    pub field_idx: u32,
+    pub name: String,
+    /// Type descriptor, e.g. `Ljava/lang/String;`.
+    pub field_type: String,
+    pub access_flags: u32,
+    pub value: Value,
+    /// Whether the value is the default of the type because the encoded
+    /// array ends before the field.
+    pub implicit: bool,
+}
+
+impl StaticField {
+    pub fn is_final(&self) -> bool {
+        return self.access_flags & ACC_FINAL != 0;
+    }
+}
+
+impl Display for StaticField {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if self.is_final() {
+            write!(f, "final ")?;
+        }
+        write!(
+            f,
+            "{} {} = {}",
+            descriptor_to_java(&self.field_type),
+            self.name,
+            self.value
+        )?;
+        if self.implicit {
+            write!(f, " (default)")?;
+        }
+        return Ok(());
+    }
+}
+
+/// The static fields of a class in class data order, with their initial
+/// values. Names and types are the original ones when the resolver has a
+/// mapping.
+pub fn static_fields(resolver: &DexResolver, class_def: &ClassDefItem) -> Vec<StaticField> {
+    let fields = match resolver.class_data(class_def) {
+        Some(data) => field_indices(&data.static_fields),
+        None => return vec![],
+    };
+    let values = match resolver.encoded_array(class_def.static_values_off) {
+        Some(array) if class_def.static_values_off != 0 => array.value.values.as_slice(),
+        _ => &[],
+    };
+    return fields
+        .into_iter()
+        .enumerate()
+        .map(|(k, (field_idx, field))| {
+            let field_ref = resolver.field_ref(field_idx);
+            let value = match values.get(k) {
+                Some(value) => Value::new(resolver, value),
+                None => Value::new(resolver, &default_value(&field_ref.field_type)),
+            };
+            StaticField {
+                field_idx,
+                name: resolver.field_name(field_idx),
+                field_type: java_to_descriptor(&resolver.java_type_name(&field_ref.field_type)),
+                access_flags: field.access_flags,
+                value,
+                implicit: k >= values.len(),
+            }
+        })
+        .collect();
+}
+
+/// The static fields of one class, e.g. the constants of a `BuildConfig`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ClassConstants {
+    pub dex: String,
+    /// Java name of the class.
+    pub class: String,
+    pub fields: Vec<StaticField>,
+}
+
+impl ClassConstants {
+    pub fn new(dex: &str, resolver: &DexResolver, class_def: &ClassDefItem) -> ClassConstants {
+        return ClassConstants {
+            dex: dex.to_string(),
+            class: resolver.type_name(class_def.class_idx),
+            fields: static_fields(resolver, class_def),
+        };
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        return self
+            .fields
+            .iter()
+            .find(|f| f.name == name)
+            .map(|f| &f.value);
+    }
+}
+
+impl Display for ClassConstants {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, "{} ({})", self.class, self.dex)?;
+        for field in self.fields.iter() {
+            writeln!(f, "    {}", field)?;
+        }
+        return Ok(());
+    }
+}
+
+/// The constants of every `BuildConfig` class, which the Android Gradle
+/// plugin generates with the version, build type and `buildConfigField`
+/// values of each module.
+pub fn build_configs(dexes: &[(String, DexModel)]) -> Vec<ClassConstants> {
+    let resolvers = dexes
+        .iter()
+        .map(|(name, dex)| (name.clone(), DexResolver::new(dex)))
+        .collect::<Vec<_>>();
+    return build_configs_from_resolvers(&resolvers);
+}
+
+/// `build_configs` of each `(entry name, resolver)` pair. With a mapping,
+/// a `BuildConfig` that the shrinker renamed is found by its original name.
+pub fn build_configs_from_resolvers(dexes: &[(String, DexResolver)]) -> Vec<ClassConstants> {
+    let mut configs = vec![];
+    for (name, resolver) in dexes {
+        for class_def in resolver.dex.class_defs.iter() {
+            let class = resolver.type_name(class_def.class_idx);
+            if class.ends_with(".BuildConfig") || class == "BuildConfig" {
+                configs.push(ClassConstants::new(name, resolver, class_def));
+            }
+        }
+    }
+    return configs;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        deserialize_vec,
+        edit::EditError,
+        mapping::Mapping,
+        serialize,
+        test_utils::{build_dex, TestClass},
+    };
+
+    fn sample() -> DexModel {
+        return build_dex(
+            vec![
+                TestClass::new("Lcom/example/BuildConfig;")
+                    .static_field("DEBUG", "Z")
+                    .static_field("VERSION_CODE", "I")
+                    .static_field("VERSION_NAME", "Ljava/lang/String;")
+                    .static_field("API_URL", "Ljava/lang/String;")
+                    .field("unused", "J"),
+                TestClass::new("Lcom/example/Main;"),
+            ],
+            &[],
+        );
+    }
+
+    fn static_values(dex: &DexModel) -> Vec<EncodedValue> {
+        let resolver = DexResolver::new(dex);
+        let class_def = resolver
+            .class_def_by_descriptor("Lcom/example/BuildConfig;")
+            .unwrap();
+        return resolver
+            .encoded_array(class_def.static_values_off)
+            .map_or(vec![], |array| array.value.values.clone());
+    }
+
+    #[test]
+    fn test_static_fields() {
+        let dex = sample();
+        let configs = build_configs(&[("classes.dex".to_string(), dex)]);
+        assert_eq!(configs.len(), 1);
+        let config = &configs[0];
+        assert_eq!(config.class, "com.example.BuildConfig");
+        // Static fields are in `field_ids` order, sorted by name.
+        let names = config
+            .fields
+            .iter()
+            .map(|f| f.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec!["API_URL", "DEBUG", "VERSION_CODE", "VERSION_NAME"]
+        );
+        assert!(config.fields.iter().all(|f| f.implicit));
+        assert_eq!(config.get("DEBUG"), Some(&Value::Boolean(false)));
+        assert_eq!(config.get("VERSION_NAME"), Some(&Value::Null));
+        assert_eq!(
+            config.fields[2].to_string(),
+            "final int VERSION_CODE = 0 (default)"
+        );
+
+        assert!(is_default("F", &EncodedValue::ValueFloat(0.0)));
+        assert!(!is_default("F", &EncodedValue::ValueFloat(-0.0)));
+        assert!(accepts("Ljava/lang/String;", &Value::Null));
+        assert!(!accepts("I", &Value::Long(1)));
+        assert!(!accepts("Ljava/lang/Object;", &Value::Int(1)));
+        assert!(accepts(
+            "Ljava/lang/Object;",
+            &Value::Type("LA;".to_string())
+        ));
+        assert!(accepts(
+            "Ljava/lang/Class;",
+            &Value::Type("LA;".to_string())
+        ));
+        assert!(!accepts(
+            "Ljava/lang/String;",
+            &Value::Type("LA;".to_string())
+        ));
+        assert!(!accepts(
+            "Ljava/lang/Class;",
+            &Value::String("A".to_string())
+        ));
+        assert!(accepts("LA;", &Value::Enum("LA;->X:LA;".to_string())));
+        assert!(!accepts("LB;", &Value::Enum("LA;->X:LA;".to_string())));
+        assert!(accepts("[I", &Value::Array(vec![Value::Int(1)])));
+        assert!(!accepts("[J", &Value::Array(vec![Value::Int(1)])));
+    }
+
+    #[test]
+    fn test_build_configs_with_mapping() {
+        let dex = build_dex(vec![TestClass::new("La/a;").static_field("a", "Z")], &[]);
+        let mapping =
+            Mapping::parse("com.example.BuildConfig -> a.a:\n    boolean DEBUG -> a\n").unwrap();
+        assert!(build_configs(&[("classes.dex".to_string(), dex.clone())]).is_empty());
+        let resolvers = vec![(
+            "classes.dex".to_string(),
+            DexResolver::new(&dex).with_mapping(&mapping),
+        )];
+        let configs = build_configs_from_resolvers(&resolvers);
+        assert_eq!(configs[0].class, "com.example.BuildConfig");
+        assert_eq!(configs[0].get("DEBUG"), Some(&Value::Boolean(false)));
+    }
+
+    #[test]
+    fn test_set_static_value() {
+        let class = "Lcom/example/BuildConfig;";
+        let mut dex = sample();
+        dex.set_static_value(class, "VERSION_CODE", &Value::Int(42))
+            .unwrap();
+        let dex = deserialize_vec(serialize(dex)).unwrap();
+        // Fields after the last non-default value stay implicit.
+        assert_eq!(
+            static_values(&dex),
+            vec![
+                EncodedValue::ValueNull,
+                EncodedValue::ValueBoolean(false),
+                EncodedValue::ValueInt(42),
+            ]
+        );
+
+        let mut dex = dex;
+        dex.set_static_value(class, "VERSION_NAME", &Value::String("1.2.3".to_string()))
+            .unwrap();
+        assert!(matches!(
+            dex.clone().set_static_value(
+                class,
+                "API_URL",
+                &Value::Type("Lcom/example/Main;".to_string()),
+            ),
+            Err(EditError::InvalidValue(_))
+        ));
+        dex.set_static_value(
+            class,
+            "API_URL",
+            &Value::String("https://example.com".to_string()),
+        )
+        .unwrap();
+        let dex = deserialize_vec(serialize(dex)).unwrap();
+        let resolver = DexResolver::new(&dex);
+        let class_def = resolver.class_def_by_descriptor(class).unwrap();
+        let config = ClassConstants::new("classes.dex", &resolver, class_def);
+        assert_eq!(config.get("VERSION_CODE"), Some(&Value::Int(42)));
+        assert_eq!(
+            config.get("VERSION_NAME"),
+            Some(&Value::String("1.2.3".to_string()))
+        );
+        assert_eq!(
+            config.get("API_URL"),
+            Some(&Value::String("https://example.com".to_string()))
+        );
+        assert!(config.fields.iter().all(|f| !f.implicit));
+        let mut strings = resolver.strings().to_vec();
+        strings.sort();
+        assert_eq!(resolver.strings(), strings.as_slice());
+
+        let mut dex = dex;
+        dex.set_static_value(class, "VERSION_NAME", &Value::Null)
+            .unwrap();
+        let dex = deserialize_vec(serialize(dex)).unwrap();
+        assert_eq!(static_values(&dex).len(), 3);
+        assert!(DexResolver::new(&dex)
+            .strings()
+            .iter()
+            .all(|s| s != "1.2.3"));
+
+        let mut dex = dex;
+        assert!(matches!(
+            dex.set_static_value(class, "DEBUG", &Value::Int(1)),
+            Err(EditError::InvalidValue(_))
+        ));
+        assert!(matches!(
+            dex.set_static_value(class, "unused", &Value::Long(1)),
+            Err(EditError::MemberNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_set_static_value_overflow() {
+        // const-string v0, string@0xffff; return-void
+        let extra = (0..0x10000)
+            .map(|i| format!("s{:05}", i))
+            .collect::<Vec<_>>();
+        let extra = extra.iter().map(String::as_str).collect::<Vec<_>>();
+        let mut dex = build_dex(
+            vec![TestClass::new("Lb/B;")
+                .static_field("NAME", "Ljava/lang/String;")
+                .method("run", &[], "V", &[0x001a, 0xffff, 0x000e])],
+            &extra,
+        );
+        let before = serialize(dex.clone());
+        // A new string sorting first pushes the operand out of 16 bits.
+        assert!(matches!(
+            dex.set_static_value("Lb/B;", "NAME", &Value::String("a".to_string())),
+            Err(EditError::IndexOverflow { .. })
+        ));
+        assert_eq!(serialize(dex), before);
+    }
+}
@@ -0,0 +1,1068 @@
+//! Builders for small, internally consistent `DexModel`s used by unit tests.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::{Cursor, Write},
+};
+
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+use crate::{
+    axml::{framework_attribute_id, Value, ANDROID_NAMESPACE},
+    dex_model::DexModel,
+    dex_structs::{
+        AnnotationElement, AnnotationItem, AnnotationOffItem, AnnotationSetItem,
+        AnnotationSetRefItem, AnnotationSetRefList, AnnotationsDirectoryItem, ClassDataItem,
+        ClassDefItem, CodeItem, DexStruct, EncodedAnnotation, EncodedArray, EncodedField,
+        EncodedMethod, EncodedValue, FieldAnnotation, FieldIdItem, Header, MapItem, MapList,
+        MethodAnnotation, MethodIdItem, ParameterAnnotation, ProtoIdItem, StringDataItem,
+        StringIdItem, TypeCode, TypeIdItem, TypeItem, TypeList,
+    },
+    instructions::{decode_insns, Instruction},
+    layout::{relayout_from, ItemOffsets},
+    resolver::{DexResolver, NO_INDEX},
+};
+
+pub(crate) const ACC_PUBLIC: u32 = 0x1;
+pub(crate) const ACC_STATIC: u32 = 0x8;
+pub(crate) const ACC_FINAL: u32 = 0x10;
+
+pub(crate) struct TestField {
+    pub(crate) name: &'static str,
+    pub(crate) field_type: &'static str,
+    pub(crate) access_flags: u32,
+}
+
+pub(crate) struct TestMethod {
+    pub(crate) name: &'static str,
+    pub(crate) parameters: Vec<&'static str>,
+    pub(crate) return_type: &'static str,
+    pub(crate) access_flags: u32,
+    /// Code units of the method body; `None` for abstract and native methods.
+    pub(crate) code: Option<Vec<u16>>,
+}
+
+pub(crate) struct TestClass {
+    pub(crate) descriptor: &'static str,
+    pub(crate) superclass: Option<&'static str>,
+    pub(crate) interfaces: Vec<&'static str>,
+    pub(crate) fields: Vec<TestField>,
+    pub(crate) methods: Vec<TestMethod>,
+}
+
+impl TestClass {
+    pub(crate) fn new(descriptor: &'static str) -> Self {
+        return Self {
+            descriptor,
+            superclass: Some("Ljava/lang/Object;"),
+            interfaces: vec![],
+            fields: vec![],
+            methods: vec![],
+        };
+    }
+
+    pub(crate) fn extends(mut self, superclass: &'static str) -> Self {
+        self.superclass = Some(superclass);
+        return self;
+    }
+
+    pub(crate) fn implements(mut self, interface: &'static str) -> Self {
+        self.interfaces.push(interface);
+        return self;
+    }
+
+    pub(crate) fn field(mut self, name: &'static str, field_type: &'static str) -> Self {
+        self.fields.push(TestField {
+            name,
+            field_type,
+            access_flags: ACC_PUBLIC,
+        });
+        return self;
+    }
+
+    /// A `public static final` field, as constants are declared.
+    pub(crate) fn static_field(mut self, name: &'static str, field_type: &'static str) -> Self {
+        self.fields.push(TestField {
+            name,
+            field_type,
+            access_flags: ACC_PUBLIC | ACC_STATIC | ACC_FINAL,
+        });
+        return self;
+    }
+
+    pub(crate) fn method(
+        mut self,
+        name: &'static str,
+        parameters: &[&'static str],
+        return_type: &'static str,
+        code: &[u16],
+    ) -> Self {
+        self.methods.push(TestMethod {
+            name,
+            parameters: parameters.to_vec(),
+            return_type,
+            access_flags: ACC_PUBLIC,
+            code: Some(code.to_vec()),
+        });
+        return self;
+    }
+}
+
+/// Decodes raw 16-bit code units into instructions.
+pub(crate) fn insns(code: &[u16]) -> Vec<Instruction> {
+    let bytes = code
+        .iter()
+        .flat_map(|unit| unit.to_le_bytes())
+        .collect::<Vec<u8>>();
+    return decode_insns(&mut Cursor::new(bytes), code.len());
+}
+
+fn shorty_char(descriptor: &str) -> char {
+    return match descriptor.chars().next().unwrap() {
+        'L' | '[' => 'L',
+        c => c,
+    };
+}
+
+fn shorty(parameters: &[&str], return_type: &str) -> String {
+    let mut shorty = String::new();
+    shorty.push(shorty_char(return_type));
+    for p in parameters {
+        shorty.push(shorty_char(p));
+    }
+    return shorty;
+}
+
+fn align(offset: u32, alignment: u32) -> u32 {
+    return offset.div_ceil(alignment) * alignment;
+}
+
+/// Builds a dex model defining `classes`, with every pool sorted as the dex
+/// format requires and every offset consistent with the map list.
+pub(crate) fn build_dex(classes: Vec<TestClass>, extra_strings: &[&str]) -> DexModel {
+    let mut strings = BTreeSet::new();
+    let mut types = BTreeSet::new();
+    for s in extra_strings {
+        strings.insert(s.to_string());
+    }
+    for class in classes.iter() {
+        types.insert(class.descriptor.to_string());
+        if let Some(superclass) = class.superclass {
+            types.insert(superclass.to_string());
+        }
+        for interface in class.interfaces.iter() {
+            types.insert(interface.to_string());
+        }
+        for field in class.fields.iter() {
+            strings.insert(field.name.to_string());
+            types.insert(field.field_type.to_string());
+        }
+        for method in class.methods.iter() {
+            strings.insert(method.name.to_string());
+            strings.insert(shorty(&method.parameters, method.return_type));
+            types.insert(method.return_type.to_string());
+            for p in method.parameters.iter() {
+                types.insert(p.to_string());
+            }
+        }
+    }
+    strings.extend(types.iter().cloned());
+    let strings = strings.into_iter().collect::<Vec<_>>();
+    let string_idx = |s: &str| strings.binary_search_by(|x| x.as_str().cmp(s)).unwrap() as u32;
+    let types = types.into_iter().collect::<Vec<_>>();
+    let type_idx = |s: &str| types.binary_search_by(|x| x.as_str().cmp(s)).unwrap() as u32;
+
+    // Protos, keyed by (return type, parameters) for dex ordering.
+    let mut protos = BTreeMap::new();
+    for method in classes.iter().flat_map(|c| c.methods.iter()) {
+        let key = (
+            type_idx(method.return_type),
+            method
+                .parameters
+                .iter()
+                .map(|p| type_idx(p))
+                .collect::<Vec<_>>(),
+        );
+        protos.insert(key, shorty(&method.parameters, method.return_type));
+    }
+    let protos = protos.into_iter().collect::<Vec<_>>();
+    let type_list_keys = protos
+        .iter()
+        .map(|((_, params), _)| params.clone())
+        .chain(
+            classes
+                .iter()
+                .map(|c| c.interfaces.iter().map(|i| type_idx(i)).collect::<Vec<_>>()),
+        )
+        .filter(|list| !list.is_empty())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect::<Vec<_>>();
+
+    let mut field_keys = BTreeSet::new();
+    let mut method_keys = BTreeSet::new();
+    for class in classes.iter() {
+        let class_idx = type_idx(class.descriptor);
+        for field in class.fields.iter() {
+            field_keys.insert((
+                class_idx,
+                string_idx(field.name),
+                type_idx(field.field_type),
+            ));
+        }
+        for method in class.methods.iter() {
+            let key = (
+                type_idx(method.return_type),
+                method
+                    .parameters
+                    .iter()
+                    .map(|p| type_idx(p))
+                    .collect::<Vec<_>>(),
+            );
+            let proto_idx = protos.iter().position(|(k, _)| *k == key).unwrap() as u32;
+            method_keys.insert((class_idx, string_idx(method.name), proto_idx));
+        }
+    }
+    let field_keys = field_keys.into_iter().collect::<Vec<_>>();
+    let method_keys = method_keys.into_iter().collect::<Vec<_>>();
+
+    // Fixed-size id sections follow the header.
+    let mut offset = 0x70u32;
+    let string_ids_off = offset;
+    offset += 4 * strings.len() as u32;
+    let type_ids_off = offset;
+    offset += 4 * types.len() as u32;
+    let proto_ids_off = offset;
+    offset += 12 * protos.len() as u32;
+    let field_ids_off = offset;
+    offset += 8 * field_keys.len() as u32;
+    let method_ids_off = offset;
+    offset += 8 * method_keys.len() as u32;
+    let class_defs_off = offset;
+    offset += 32 * classes.len() as u32;
+    let data_off = offset;
+
+    // Data sections, in the order they are laid out.
+    let type_lists = type_list_keys
+        .iter()
+        .map(|params| TypeList {
+            list: params
+                .iter()
+                .map(|t| TypeItem {
+                    type_idx: *t as u16,
+                })
+                .collect(),
+        })
+        .collect::<Vec<_>>();
+    offset = align(offset, 4);
+    let type_lists_off = offset;
+    let mut type_list_offs = vec![];
+    for list in type_lists.iter() {
+        offset = align(offset, 4);
+        type_list_offs.push(offset);
+        offset += list.size() as u32;
+    }
+
+    offset = align(offset, 4);
+    let code_items_off = offset;
+    let mut code_items = vec![];
+    let mut code_offs = BTreeMap::new();
+    for class in classes.iter() {
+        for method in class.methods.iter() {
+            if let Some(code) = &method.code {
+                let ins_size = method
+                    .parameters
+                    .iter()
+                    .map(|p| if *p == "J" || *p == "D" { 2 } else { 1 })
+                    .sum::<u16>()
+                    + if method.access_flags & ACC_STATIC == 0 {
+                        1
+                    } else {
+                        0
+                    };
+                let code_item = CodeItem {
+                    registers_size: ins_size + 4,
+                    ins_size,
+                    outs_size: 4,
+                    debug_info_off: 0,
+                    insns_size: code.len() as u32,
+                    insns: insns(code),
+                    tries: vec![],
+                    handlers: None,
+                };
+                offset = align(offset, 4);
+                code_offs.insert((class.descriptor, method.name), offset);
+                offset += code_item.size() as u32;
+                code_items.push(code_item);
+            }
+        }
+    }
+
+    let string_data_off = offset;
+    let mut string_ids = vec![];
+    let mut string_data_items = vec![];
+    for s in strings.iter() {
+        let mut data = mutf8::encode(s).into_owned();
+        data.push(0);
+        let item = StringDataItem {
+            utf16_size: s.encode_utf16().count() as u32,
+            data,
+        };
+        string_ids.push(StringIdItem {
+            string_data_off: offset,
+        });
+        offset += item.size() as u32;
+        string_data_items.push(item);
+    }
+
+    let class_data_off = offset;
+    let mut class_data_items = vec![];
+    let mut class_defs = vec![];
+    for class in classes.iter() {
+        let class_idx = type_idx(class.descriptor);
+        let field_idx = |f: &TestField| {
+            field_keys
+                .binary_search(&(class_idx, string_idx(f.name), type_idx(f.field_type)))
+                .unwrap() as u32
+        };
+        let encode_fields = |fields: Vec<&TestField>| {
+            let mut sorted = fields
+                .into_iter()
+                .map(|f| (field_idx(f), f.access_flags))
+                .collect::<Vec<_>>();
+            sorted.sort();
+            let mut prev = 0;
+            sorted
+                .into_iter()
+                .map(|(idx, access_flags)| {
+                    let field = EncodedField {
+                        field_idx_off: idx - prev,
+                        access_flags,
+                    };
+                    prev = idx;
+                    field
+                })
+                .collect::<Vec<_>>()
+        };
+        let method_idx = |m: &TestMethod| {
+            method_keys
+                .iter()
+                .position(|(c, n, p)| {
+                    *c == class_idx
+                        && *n == string_idx(m.name)
+                        && protos[*p as usize].0
+                            == (
+                                type_idx(m.return_type),
+                                m.parameters.iter().map(|p| type_idx(p)).collect::<Vec<_>>(),
+                            )
+                })
+                .unwrap() as u32
+        };
+        let encode_methods = |methods: Vec<&TestMethod>| {
+            let mut sorted = methods
+                .into_iter()
+                .map(|m| {
+                    let code_off = code_offs
+                        .get(&(class.descriptor, m.name))
+                        .copied()
+                        .unwrap_or(0);
+                    (method_idx(m), m.access_flags, code_off)
+                })
+                .collect::<Vec<_>>();
+            sorted.sort();
+            let mut prev = 0;
+            sorted
+                .into_iter()
+                .map(|(idx, access_flags, code_off)| {
+                    let method = EncodedMethod {
+                        method_idx_off: idx - prev,
+                        access_flags,
+                        code_off,
+                    };
+                    prev = idx;
+                    method
+                })
+                .collect::<Vec<_>>()
+        };
+        let is_direct = |m: &&TestMethod| m.access_flags & ACC_STATIC != 0 || m.name == "<init>";
+        let data = ClassDataItem {
+            static_fields: encode_fields(
+                class
+                    .fields
+                    .iter()
+                    .filter(|f| f.access_flags & ACC_STATIC != 0)
+                    .collect(),
+            ),
+            instance_fields: encode_fields(
+                class
+                    .fields
+                    .iter()
+                    .filter(|f| f.access_flags & ACC_STATIC == 0)
+                    .collect(),
+            ),
+            direct_methods: encode_methods(class.methods.iter().filter(is_direct).collect()),
+            virtual_methods: encode_methods(
+                class.methods.iter().filter(|m| !is_direct(m)).collect(),
+            ),
+        };
+        class_defs.push(ClassDefItem {
+            class_idx,
+            access_flags: ACC_PUBLIC,
+            superclass_idx: class.superclass.map_or(NO_INDEX, type_idx),
+            interfaces_off: type_list_keys
+                .iter()
+                .position(|list| {
+                    *list
+                        == class
+                            .interfaces
+                            .iter()
+                            .map(|i| type_idx(i))
+                            .collect::<Vec<_>>()
+                })
+                .filter(|_| !class.interfaces.is_empty())
+                .map_or(0, |i| type_list_offs[i]),
+            source_file_idx: NO_INDEX,
+            annotations_off: 0,
+            class_data_off: offset,
+            static_values_off: 0,
+        });
+        offset += data.size() as u32;
+        class_data_items.push(data);
+    }
+
+    let mut map_items = vec![
+        (TypeCode::TypeHeaderItem, 1, 0),
+        (TypeCode::TypeStringIdItem, strings.len(), string_ids_off),
+        (TypeCode::TypeTypeIdItem, types.len(), type_ids_off),
+        (TypeCode::TypeProtoIdItem, protos.len(), proto_ids_off),
+        (TypeCode::TypeFieldIdItem, field_keys.len(), field_ids_off),
+        (
+            TypeCode::TypeMethodIdItem,
+            method_keys.len(),
+            method_ids_off,
+        ),
+        (TypeCode::TypeClassDefItem, classes.len(), class_defs_off),
+        (TypeCode::TypeTypeList, type_lists.len(), type_lists_off),
+        (TypeCode::TypeCodeItem, code_items.len(), code_items_off),
+        (TypeCode::TypeStringDataItem, strings.len(), string_data_off),
+        (TypeCode::TypeClassDataItem, classes.len(), class_data_off),
+    ];
+    map_items.retain(|(_, size, _)| *size > 0);
+    offset = align(offset, 4);
+    let map_off = offset;
+    map_items.push((TypeCode::TypeMapList, 1, map_off));
+    let map_list = MapList {
+        list: map_items
+            .into_iter()
+            .map(|(type_code, size, offset)| MapItem {
+                type_code,
+                unused: 0,
+                size: size as u32,
+                offset,
+            })
+            .collect(),
+    };
+    offset += map_list.size() as u32;
+
+    let header = Header {
+        magic: *b"dex\n038\0",
+        checksum: 0,
+        signature: [0u8; 20],
+        file_size: offset,
+        header_size: 0x70,
+        endian_tag: 0x12345678,
+        link_size: 0,
+        link_off: 0,
+        map_off,
+        string_ids_size: strings.len() as u32,
+        string_ids_off,
+        type_ids_size: types.len() as u32,
+        type_ids_off,
+        proto_ids_size: protos.len() as u32,
+        proto_ids_off,
+        field_ids_size: field_keys.len() as u32,
+        field_ids_off,
+        method_ids_size: method_keys.len() as u32,
+        method_ids_off,
+        class_defs_size: classes.len() as u32,
+        class_defs_off,
+        data_size: offset - data_off,
+        data_off,
+    };
+
+    return DexModel {
+        header,
+        string_ids,
+        type_ids: types
+            .iter()
+            .map(|t| TypeIdItem {
+                descriptor_idx: string_idx(t),
+            })
+            .collect(),
+        proto_ids: protos
+            .iter()
+            .map(|((return_type_idx, params), shorty)| ProtoIdItem {
+                shorty_idx: string_idx(shorty),
+                return_type_idx: *return_type_idx,
+                parameters_off: match type_list_keys.iter().position(|p| p == params) {
+                    Some(i) => type_list_offs[i],
+                    None => 0,
+                },
+            })
+            .collect(),
+        field_ids: field_keys
+            .iter()
+            .map(|(class_idx, name_idx, type_idx)| FieldIdItem {
+                class_idx: *class_idx as u16,
+                type_idx: *type_idx as u16,
+                name_idx: *name_idx,
+            })
+            .collect(),
+        method_ids: method_keys
+            .iter()
+            .map(|(class_idx, name_idx, proto_idx)| MethodIdItem {
+                class_idx: *class_idx as u16,
+                proto_idx: *proto_idx as u16,
+                name_idx: *name_idx,
+            })
+            .collect(),
+        class_defs,
+        call_site_ids: vec![],
+        method_handles: vec![],
+        type_lists,
+        string_data_items,
+        annotation_set_ref_lists: vec![],
+        annotation_set_items: vec![],
+        annotation_items: vec![],
+        annotations_directory_items: vec![],
+        hiddenapi_class_data_items: vec![],
+        encoded_array_items: vec![],
+        class_data_items,
+        debug_info_items: vec![],
+        code_items,
+        link_data: vec![],
+        map_list,
+    };
+}
+
+fn string_idx(resolver: &DexResolver, s: &str) -> u32 {
+    return resolver.strings().iter().position(|x| x == s).unwrap() as u32;
+}
+
+/// A system annotation of type `descriptor`, whose type and element names
+/// must already be in the dex file.
+pub(crate) fn annotation_item(
+    resolver: &DexResolver,
+    descriptor: &str,
+    elements: Vec<(&str, EncodedValue)>,
+) -> AnnotationItem {
+    let type_idx = (0..resolver.dex.type_ids.len() as u32)
+        .find(|t| resolver.type_descriptor(*t) == descriptor)
+        .unwrap();
+    return AnnotationItem {
+        visibility: 2,
+        annotation: EncodedAnnotation {
+            type_idx,
+            elements: elements
+                .into_iter()
+                .map(|(name, value)| AnnotationElement {
+                    name_idx: string_idx(resolver, name),
+                    value,
+                })
+                .collect(),
+        },
+    };
+}
+
+/// A `String[]` value of strings already in the dex file.
+pub(crate) fn string_array(resolver: &DexResolver, values: &[&str]) -> EncodedValue {
+    return EncodedValue::ValueArray(EncodedArray {
+        values: values
+            .iter()
+            .map(|s| EncodedValue::ValueString(string_idx(resolver, s)))
+            .collect(),
+    });
+}
+
+/// Attaches annotations to `dex.class_defs[class]`: `class_annotations` to
+/// the class itself, and sets to fields and methods by index.
+pub(crate) fn add_annotations(
+    dex: &mut DexModel,
+    class: usize,
+    class_annotations: Vec<AnnotationItem>,
+    fields: Vec<(u32, Vec<AnnotationItem>)>,
+    methods: Vec<(u32, Vec<AnnotationItem>)>,
+    parameters: Vec<(u32, Vec<Vec<AnnotationItem>>)>,
+) {
+    let mut offsets = ItemOffsets::of(dex);
+    let mut add_set = |dex: &mut DexModel, items: Vec<AnnotationItem>| {
+        if items.is_empty() {
+            return 0;
+        }
+        let mut entries = vec![];
+        for item in items {
+            dex.annotation_items.push(item);
+            entries.push(AnnotationOffItem {
+                annotation_off: offsets.append(TypeCode::TypeAnnotationItem),
+            });
+        }
+        dex.annotation_set_items.push(AnnotationSetItem { entries });
+        return offsets.append(TypeCode::TypeAnnotationSetItem);
+    };
+    let class_annotations_off = add_set(dex, class_annotations);
+    let field_annotations = fields
+        .into_iter()
+        .map(|(field_idx, items)| FieldAnnotation {
+            field_idx,
+            annotations_off: add_set(dex, items),
+        })
+        .collect();
+    let method_annotations = methods
+        .into_iter()
+        .map(|(method_idx, items)| MethodAnnotation {
+            method_idx,
+            annotations_off: add_set(dex, items),
+        })
+        .collect();
+    let mut lists = vec![];
+    for (method_idx, sets) in parameters {
+        let list = sets
+            .into_iter()
+            .map(|items| AnnotationSetRefItem {
+                annotations_off: add_set(dex, items),
+            })
+            .collect();
+        lists.push((method_idx, AnnotationSetRefList { list }));
+    }
+    let parameter_annotations = lists
+        .into_iter()
+        .map(|(method_idx, list)| {
+            dex.annotation_set_ref_lists.push(list);
+            ParameterAnnotation {
+                method_idx,
+                annotations_off: offsets.append(TypeCode::TypeAnnotationSetRefList),
+            }
+        })
+        .collect();
+    dex.annotations_directory_items
+        .push(AnnotationsDirectoryItem {
+            class_annotations_off,
+            field_annotations,
+            method_annotations,
+            parameter_annotations,
+        });
+    dex.class_defs[class].annotations_off = offsets.append(TypeCode::TypeAnnotationsDirectoryItem);
+    relayout_from(dex, offsets);
+}
+
+enum AxmlEvent {
+    Start(String, Vec<(bool, String, Value)>),
+    End,
+}
+
+/// Builds binary XML documents with the `android` namespace declared on the
+/// root element.
+pub(crate) struct AxmlBuilder {
+    events: Vec<AxmlEvent>,
+}
+
+fn axml_chunk(chunk_type: u16, header: &[u8], body: &[u8]) -> Vec<u8> {
+    let mut chunk = vec![];
+    chunk.extend_from_slice(&chunk_type.to_le_bytes());
+    chunk.extend_from_slice(&(8 + header.len() as u16).to_le_bytes());
+    chunk.extend_from_slice(&((8 + header.len() + body.len()) as u32).to_le_bytes());
+    chunk.extend_from_slice(header);
+    chunk.extend_from_slice(body);
+    return chunk;
+}
+
+/// A UTF-16 `ResStringPool` chunk.
+pub(crate) fn string_pool_chunk(strings: &[String]) -> Vec<u8> {
+    let mut offsets = vec![];
+    let mut data = vec![];
+    for s in strings {
+        offsets.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        let units = s.encode_utf16().collect::<Vec<_>>();
+        data.extend_from_slice(&(units.len() as u16).to_le_bytes());
+        for unit in units.iter().chain([0u16].iter()) {
+            data.extend_from_slice(&unit.to_le_bytes());
+        }
+    }
+    while data.len() % 4 != 0 {
+        data.push(0);
+    }
+    let mut header = vec![];
+    for field in [strings.len() as u32, 0, 0, 28 + offsets.len() as u32, 0] {
+        header.extend_from_slice(&field.to_le_bytes());
+    }
+    offsets.extend(data);
+    return axml_chunk(0x0001, &header, &offsets);
+}
+
+/// `Res_value` type and data of a value; strings are interned in `strings`.
+pub(crate) fn res_value(value: &Value, strings: &mut Vec<String>) -> (u8, u32) {
+    return match value {
+        Value::Null => (0x00, 0),
+        Value::Reference(id) => (0x01, *id),
+        Value::Attribute(id) => (0x02, *id),
+        Value::String(s) => (0x03, intern(strings, s)),
+        Value::Float(f) => (0x04, f.to_bits()),
+        Value::Dimension(d) => (0x05, *d),
+        Value::Fraction(d) => (0x06, *d),
+        Value::Int(i) => (0x10, *i as u32),
+        Value::Hex(i) => (0x11, *i),
+        Value::Boolean(b) => (0x12, if *b { 0xffffffff } else { 0 }),
+        Value::Color(c) => (0x1c, *c),
+        Value::Other { data_type, data } => (*data_type, *data),
+    };
+}
+
+pub(crate) fn intern(strings: &mut Vec<String>, s: &str) -> u32 {
+    return match strings.iter().position(|x| x == s) {
+        Some(i) => i as u32,
+        None => {
+            strings.push(s.to_string());
+            strings.len() as u32 - 1
+        }
+    };
+}
+
+impl AxmlBuilder {
+    pub(crate) fn new() -> Self {
+        return Self { events: vec![] };
+    }
+
+    pub(crate) fn start(&mut self, name: &str) -> &mut Self {
+        self.events.push(AxmlEvent::Start(name.to_string(), vec![]));
+        return self;
+    }
+
+    fn push_attr(&mut self, android: bool, name: &str, value: Value) -> &mut Self {
+        match self.events.last_mut() {
+            Some(AxmlEvent::Start(_, attrs)) => attrs.push((android, name.to_string(), value)),
+            _ => panic!("attribute outside of a start element"),
+        }
+        return self;
+    }
+
+    /// Adds an attribute without namespace to the last started element.
+    pub(crate) fn attr(&mut self, name: &str, value: Value) -> &mut Self {
+        return self.push_attr(false, name, value);
+    }
+
+    /// Adds an `android:` attribute to the last started element.
+    pub(crate) fn android(&mut self, name: &str, value: Value) -> &mut Self {
+        return self.push_attr(true, name, value);
+    }
+
+    pub(crate) fn end(&mut self) -> &mut Self {
+        self.events.push(AxmlEvent::End);
+        return self;
+    }
+
+    pub(crate) fn build(&self) -> Vec<u8> {
+        // Attribute names with a resource id come first so that the resource
+        // map lines up with the string pool.
+        let mut strings = vec![];
+        let mut resource_ids = vec![];
+        for event in self.events.iter() {
+            if let AxmlEvent::Start(_, attrs) = event {
+                for (android, name, _) in attrs.iter() {
+                    if let Some(id) = framework_attribute_id(name).filter(|_| *android) {
+                        if !strings.contains(name) {
+                            strings.push(name.clone());
+                            resource_ids.push(id);
+                        }
+                    }
+                }
+            }
+        }
+        let prefix = intern(&mut strings, "android");
+        let uri = intern(&mut strings, ANDROID_NAMESPACE);
+
+        let node_header = [1u32.to_le_bytes(), 0xffffffffu32.to_le_bytes()].concat();
+        let namespace = [prefix.to_le_bytes(), uri.to_le_bytes()].concat();
+        let mut nodes = axml_chunk(0x0100, &node_header, &namespace);
+        let mut open = vec![];
+        for event in self.events.iter() {
+            match event {
+                AxmlEvent::Start(name, attrs) => {
+                    let name_idx = intern(&mut strings, name);
+                    open.push(name_idx);
+                    let mut body = vec![];
+                    body.extend_from_slice(&0xffffffffu32.to_le_bytes());
+                    body.extend_from_slice(&name_idx.to_le_bytes());
+                    for field in [20u16, 20, attrs.len() as u16, 0, 0, 0] {
+                        body.extend_from_slice(&field.to_le_bytes());
+                    }
+                    for (android, attr_name, value) in attrs.iter() {
+                        let ns = if *android { uri } else { 0xffffffff };
+                        let attr_name_idx = intern(&mut strings, attr_name);
+                        let (data_type, data) = res_value(value, &mut strings);
+                        let raw = if data_type == 0x03 { data } else { 0xffffffff };
+                        for field in [ns, attr_name_idx, raw] {
+                            body.extend_from_slice(&field.to_le_bytes());
+                        }
+                        body.extend_from_slice(&8u16.to_le_bytes());
+                        body.push(0);
+                        body.push(data_type);
+                        body.extend_from_slice(&data.to_le_bytes());
+                    }
+                    nodes.extend(axml_chunk(0x0102, &node_header, &body));
+                }
+                AxmlEvent::End => {
+                    let name_idx = open.pop().unwrap();
+                    let body = [0xffffffffu32.to_le_bytes(), name_idx.to_le_bytes()].concat();
+                    nodes.extend(axml_chunk(0x0103, &node_header, &body));
+                }
+            }
+        }
+        nodes.extend(axml_chunk(0x0101, &node_header, &namespace));
+
+        let mut body = string_pool_chunk(&strings);
+        let resource_map = resource_ids
+            .iter()
+            .flat_map(|id| id.to_le_bytes())
+            .collect::<Vec<_>>();
+        body.extend(axml_chunk(0x0180, &[], &resource_map));
+        body.extend(nodes);
+        return axml_chunk(0x0003, &[], &body);
+    }
+}
+
+/// A `ResTable_type` chunk with 16-byte simple string entries.
+fn arsc_type_chunk(type_id: u8, language: &[u8; 2], entries: &[(u32, u32)]) -> Vec<u8> {
+    let entry_count = entries
+        .iter()
+        .map(|(index, _)| *index + 1)
+        .max()
+        .unwrap_or(0);
+    let mut config = vec![0u8; 64];
+    config[0..4].copy_from_slice(&64u32.to_le_bytes());
+    config[8..10].copy_from_slice(language);
+    let header_size = 8 + 12 + config.len() as u32;
+
+    let mut header = vec![type_id, 0, 0, 0];
+    header.extend_from_slice(&entry_count.to_le_bytes());
+    header.extend_from_slice(&(header_size + 4 * entry_count).to_le_bytes());
+    header.extend(config);
+
+    let mut offsets = vec![0xffffffffu32; entry_count as usize];
+    let mut data = vec![];
+    for (index, string_idx) in entries.iter() {
+        offsets[*index as usize] = data.len() as u32;
+        data.extend_from_slice(&8u16.to_le_bytes());
+        data.extend_from_slice(&0u16.to_le_bytes());
+        // Entries are keyed by their position in the key pool.
+        data.extend_from_slice(&(*index + if type_id == 2 { 2 } else { 0 }).to_le_bytes());
+        data.extend_from_slice(&8u16.to_le_bytes());
+        data.push(0);
+        data.push(0x03);
+        data.extend_from_slice(&string_idx.to_le_bytes());
+    }
+    let mut body = offsets
+        .iter()
+        .flat_map(|o| o.to_le_bytes())
+        .collect::<Vec<_>>();
+    body.extend(data);
+    return axml_chunk(0x0201, &header, &body);
+}
+
+/// A resource table for package `com.example` (0x7f) defining
+/// `string/app_name` (default and `fr`), `string/second` and `layout/main`.
+pub(crate) fn arsc_table() -> Vec<u8> {
+    let strings = ["Example", "Exemple", "Second", "res/layout/main.xml"]
+        .map(String::from)
+        .to_vec();
+    let type_names = string_pool_chunk(&["string", "layout"].map(String::from));
+    let keys = string_pool_chunk(&["app_name", "second", "main"].map(String::from));
+
+    let mut package_body = type_names.clone();
+    package_body.extend(keys.iter());
+    let mut spec_header = vec![1u8, 0, 0, 0];
+    spec_header.extend_from_slice(&2u32.to_le_bytes());
+    package_body.extend(axml_chunk(0x0202, &spec_header, &[0u8; 8]));
+    package_body.extend(arsc_type_chunk(1, b"\0\0", &[(0, 0), (1, 2)]));
+    package_body.extend(arsc_type_chunk(1, b"fr", &[(0, 1)]));
+    package_body.extend(arsc_type_chunk(2, b"\0\0", &[(0, 3)]));
+
+    let mut package_header = vec![];
+    package_header.extend_from_slice(&0x7fu32.to_le_bytes());
+    let mut name = "com.example"
+        .encode_utf16()
+        .flat_map(|u| u.to_le_bytes())
+        .collect::<Vec<_>>();
+    name.resize(256, 0);
+    package_header.extend(name);
+    for field in [288, 0, 288 + type_names.len() as u32, 0, 0] {
+        package_header.extend_from_slice(&field.to_le_bytes());
+    }
+
+    let mut body = string_pool_chunk(&strings);
+    body.extend(axml_chunk(0x0200, &package_header, &package_body));
+    return axml_chunk(0x0002, &1u32.to_le_bytes(), &body);
+}
+
+/// Builds a zip archive with stored (uncompressed) entries.
+pub(crate) fn build_zip(entries: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut writer = ZipWriter::new(Cursor::new(vec![]));
+    let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+    for (name, data) in entries {
+        writer.start_file(*name, options).unwrap();
+        writer.write_all(data).unwrap();
+    }
+    return writer.finish().unwrap().into_inner();
+}
+
+fn der(tag: u8, value: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    if value.len() < 0x80 {
+        out.push(value.len() as u8);
+    } else {
+        out.push(0x82);
+        out.extend_from_slice(&(value.len() as u16).to_be_bytes());
+    }
+    out.extend_from_slice(value);
+    return out;
+}
+
+/// A self-signed X.509 certificate for `CN=Test` with a dummy RSA key and
+/// signature.
+pub(crate) fn test_certificate() -> Vec<u8> {
+    let rsa = der(
+        0x06,
+        &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01],
+    );
+    let sha256_rsa = der(
+        0x30,
+        &der(
+            0x06,
+            &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0b],
+        ),
+    );
+    let name = der(
+        0x30,
+        &der(
+            0x31,
+            &der(
+                0x30,
+                &[der(0x06, &[0x55, 0x04, 0x03]), der(0x0c, b"Test")].concat(),
+            ),
+        ),
+    );
+    let validity = der(
+        0x30,
+        &[der(0x17, b"230101000000Z"), der(0x17, b"330101000000Z")].concat(),
+    );
+    let public_key = der(
+        0x30,
+        &[der(0x30, &rsa), der(0x03, &[0, 0x30, 0x00])].concat(),
+    );
+    let tbs = der(
+        0x30,
+        &[
+            der(0xa0, &der(0x02, &[2])),
+            der(0x02, &[0x01, 0x23]),
+            sha256_rsa.clone(),
+            name.clone(),
+            validity,
+            name,
+            public_key,
+        ]
+        .concat(),
+    );
+    return der(0x30, &[tbs, sha256_rsa, der(0x03, &[0, 0xab])].concat());
+}
+
+/// Encodes protobuf messages field by field.
+#[derive(Default)]
+pub(crate) struct ProtoBuilder {
+    bytes: Vec<u8>,
+}
+
+fn encode_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+impl ProtoBuilder {
+    pub(crate) fn new() -> Self {
+        return Self::default();
+    }
+
+    pub(crate) fn varint(&mut self, number: u32, value: u64) -> &mut Self {
+        encode_varint(&mut self.bytes, (number as u64) << 3);
+        encode_varint(&mut self.bytes, value);
+        return self;
+    }
+
+    pub(crate) fn fixed32(&mut self, number: u32, value: u32) -> &mut Self {
+        encode_varint(&mut self.bytes, (number as u64) << 3 | 5);
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+        return self;
+    }
+
+    pub(crate) fn bytes(&mut self, number: u32, value: &[u8]) -> &mut Self {
+        encode_varint(&mut self.bytes, (number as u64) << 3 | 2);
+        encode_varint(&mut self.bytes, value.len() as u64);
+        self.bytes.extend_from_slice(value);
+        return self;
+    }
+
+    pub(crate) fn string(&mut self, number: u32, value: &str) -> &mut Self {
+        return self.bytes(number, value.as_bytes());
+    }
+
+    pub(crate) fn message(&mut self, number: u32, message: &ProtoBuilder) -> &mut Self {
+        return self.bytes(number, &message.bytes);
+    }
+
+    /// A repeated varint field in packed encoding.
+    pub(crate) fn packed(&mut self, number: u32, values: &[u64]) -> &mut Self {
+        let mut packed = vec![];
+        for value in values {
+            encode_varint(&mut packed, *value);
+        }
+        return self.bytes(number, &packed);
+    }
+
+    pub(crate) fn build(&self) -> Vec<u8> {
+        return self.bytes.clone();
+    }
+
+    /// The message prefixed with its length, as written by
+    /// `writeDelimitedTo`.
+    pub(crate) fn build_delimited(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        encode_varint(&mut bytes, self.bytes.len() as u64);
+        bytes.extend_from_slice(&self.bytes);
+        return bytes;
+    }
+}
+
+/// A little-endian ELF64 file whose only sections are the null section,
+/// the section name table and `extra`.
+pub(crate) fn elf64(extra: &str) -> Vec<u8> {
+    let names = format!("\0.shstrtab\0{}\0", extra);
+    let mut elf = vec![0u8; 0x40];
+    elf[..4].copy_from_slice(b"\x7fELF");
+    elf[4] = 2;
+    elf[5] = 1;
+    let names_offset = elf.len() as u64;
+    elf.extend_from_slice(names.as_bytes());
+    let headers = elf.len() as u64;
+    elf[0x28..0x30].copy_from_slice(&headers.to_le_bytes());
+    elf[0x3a..0x3c].copy_from_slice(&0x40u16.to_le_bytes());
+    elf[0x3c..0x3e].copy_from_slice(&3u16.to_le_bytes());
+    elf[0x3e..0x40].copy_from_slice(&1u16.to_le_bytes());
+    for name in [0u32, 1, 11] {
+        let mut header = vec![0u8; 0x40];
+        header[..4].copy_from_slice(&name.to_le_bytes());
+        header[0x18..0x20].copy_from_slice(&names_offset.to_le_bytes());
+        elf.extend(header);
+    }
+    return elf;
+}